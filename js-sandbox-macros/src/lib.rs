@@ -5,11 +5,28 @@ use proc_macro2::TokenStream as TokenStream2;
 use quote::{quote, ToTokens};
 use syn::spanned::Spanned as _;
 
+/// Generates a struct with the methods of a trait, each calling the JS function of the same name.
+///
+/// Use `#[js_api(register = "path/to/file.js")]` to bundle the API with its JS code. The file is embedded like with
+/// [`js_include!`], and loaded into a namespace named after the trait (see `Script::add_script()`) when the API is created with
+/// `Script::bind_api()` or the generated `register()` function. Methods then call the functions of that namespace:
+///
+/// ```ignore
+/// #[js_api(register = "plugins/shapes.js")]
+/// trait Shapes {
+///     fn area(&mut self, width: f64, height: f64) -> f64;
+/// }
+///
+/// let mut script = Script::from_string("")?;
+/// let mut shapes: Shapes = script.bind_api();
+/// assert_eq!(shapes.area(2.0, 3.0), 6.0);
+/// ```
 #[proc_macro_attribute]
-pub fn js_api(_attr: TokenStream, input: TokenStream) -> TokenStream {
+pub fn js_api(attr: TokenStream, input: TokenStream) -> TokenStream {
+	let args = syn::parse_macro_input!(attr as ApiArgs);
 	let item = syn::parse_macro_input!(input as syn::ItemTrait);
 
-	let stream2 = match generate_api(item) {
+	let stream2 = match generate_api(item, args) {
 		Ok(stream) => stream,
 		Err(err) => err.to_compile_error(),
 	};
@@ -17,40 +34,334 @@ pub fn js_api(_attr: TokenStream, input: TokenStream) -> TokenStream {
 	TokenStream::from(stream2)
 }
 
-fn generate_api(item: syn::ItemTrait) -> syn::Result<TokenStream2> {
+#[proc_macro_attribute]
+pub fn js_host_object(_attr: TokenStream, input: TokenStream) -> TokenStream {
+	let item = syn::parse_macro_input!(input as syn::ItemImpl);
+
+	let stream2 = match generate_host_object(item) {
+		Ok(stream) => stream,
+		Err(err) => err.to_compile_error(),
+	};
+
+	TokenStream::from(stream2)
+}
+
+/// Defines a Rust function that calls a single, inline JS function.
+///
+/// This is a lightweight alternative to `#[js_api]`, when a whole trait would be overkill:
+///
+/// ```ignore
+/// js_fn!(pub fn word_count(text: &str) -> usize = "(text) => text.split(/\\s+/).filter(Boolean).length");
+///
+/// assert_eq!(word_count("one two  three"), 3);
+/// ```
+///
+/// The source is a JS function declaration or expression (any `&str` expression, e.g. [`js_include!`]). It is loaded into a script
+/// that is created on first use, separately for every thread, and keeps its state across calls. Arguments and return values are
+/// converted like in `#[js_api]`: a return type `T` panics on failure, while `JsResult<T>` returns the error.
+#[proc_macro]
+pub fn js_fn(input: TokenStream) -> TokenStream {
+	let item = syn::parse_macro_input!(input as JsFnItem);
+
+	let stream2 = match generate_js_fn(item) {
+		Ok(stream) => stream,
+		Err(err) => err.to_compile_error(),
+	};
+
+	TokenStream::from(stream2)
+}
+
+/// Embeds a JS file as `&'static str`, like `include_str!`, after checking its syntax at compile time.
+///
+/// The path is relative to the crate's root directory (where `Cargo.toml` is located). Syntax errors are reported as Rust compile
+/// errors, rather than surfacing when the script is loaded at runtime. Pass `module` as second argument to parse the file as ES
+/// module, for use with `ScriptBuilder::as_module()`.
+///
+/// The syntax check requires the `syntax-check` feature, which pulls in a JS parser at build time. Without it, the file is
+/// embedded unchecked.
+///
+/// ```ignore
+/// let js_code: &'static str = js_include!("plugins/physics.js");
+/// let module_code: &'static str = js_include!("plugins/physics.mjs", module);
+/// ```
+///
+/// The check only covers syntax; references to undefined functions or variables are still detected at runtime.
+#[proc_macro]
+pub fn js_include(input: TokenStream) -> TokenStream {
+	let args = syn::parse_macro_input!(input as IncludeArgs);
+
+	let stream2 = match generate_include(args) {
+		Ok(stream) => stream,
+		Err(err) => err.to_compile_error(),
+	};
+
+	TokenStream::from(stream2)
+}
+
+/// Global object holding the namespaces of `Script::add_script()`; must match `namespace::REGISTRY_GLOBAL` in js-sandbox.
+const NAMESPACE_REGISTRY: &str = "__jsSandboxNamespaces";
+
+/// Arguments of `#[js_api]`.
+#[derive(Default)]
+struct ApiArgs {
+	/// JS file which is loaded into a namespace named after the trait, when the API is created from a script.
+	register: Option<syn::LitStr>,
+}
+
+impl syn::parse::Parse for ApiArgs {
+	fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+		let mut args = Self::default();
+		if input.is_empty() {
+			return Ok(args);
+		}
+
+		let key: syn::Ident = input.parse()?;
+		if key != "register" {
+			return Err(syn::Error::new(key.span(), "expected `register`"));
+		}
+		input.parse::<syn::Token![=]>()?;
+		args.register = Some(input.parse()?);
+		input.parse::<Option<syn::Token![,]>>()?;
+
+		if !input.is_empty() {
+			return Err(input.error("unexpected argument"));
+		}
+
+		Ok(args)
+	}
+}
+
+fn generate_api(item: syn::ItemTrait, args: ApiArgs) -> syn::Result<TokenStream2> {
 	let name = &item.ident;
+	let visibility = &item.vis;
+
+	// Registered APIs call the functions of their namespace, instead of global ones
+	let namespace = args.register.as_ref().map(|_| name);
+
 	let struct_ = generate_struct(&item)?;
-	let methods = generate_impl_methods(&item)?;
-	let marker_impl = generate_marker_trait_impl(&item)?;
+	let methods = generate_impl_methods(&item, namespace)?;
+	let marker_impl = generate_marker_trait_impl(&item, args.register.as_ref())?;
+	let declarations = generate_ts_declarations(&item);
 
 	Ok(quote! {
 		#struct_
-		impl<'a> #name<'a> {
+		impl<'a, E: js_sandbox::ScriptEngine> #name<'a, E> {
+			/// TypeScript declarations of the JS functions this API expects, for plugin authors (e.g. saved as `.d.ts` file).
+			#visibility const TS_DECLARATIONS: &'static str = #declarations;
+
+			/// Binds the API to `engine`, which can also be an engine other than [`Script`](js_sandbox::Script).
+			#visibility fn new(engine: &'a mut E) -> Self {
+				Self { script: engine }
+			}
+
 			#methods
 		}
 		#marker_impl
 	})
 }
 
+/// Declares each method as TypeScript function, with parameter and return types derived from the Rust signature.
+///
+/// JS functions may also be async, so results are declared as `T | Promise<T>`. Doc comments are carried over.
+fn generate_ts_declarations(item: &syn::ItemTrait) -> String {
+	let mut declarations = String::new();
+	for method in item.items.iter() {
+		let syn::TraitItem::Fn(method) = method else {
+			continue;
+		};
+
+		let docs: Vec<String> = method
+			.attrs
+			.iter()
+			.filter_map(|attr| match &attr.meta {
+				syn::Meta::NameValue(meta) if meta.path.is_ident("doc") => match &meta.value {
+					syn::Expr::Lit(syn::ExprLit {
+						lit: syn::Lit::Str(doc),
+						..
+					}) => Some(doc.value()),
+					_ => None,
+				},
+				_ => None,
+			})
+			.collect();
+		if !docs.is_empty() {
+			declarations += "/**\n";
+			for line in docs {
+				declarations += &format!(" *{}\n", line.trim_end());
+			}
+			declarations += " */\n";
+		}
+
+		let params: Vec<String> = method
+			.sig
+			.inputs
+			.iter()
+			.filter_map(|arg| match arg {
+				syn::FnArg::Typed(arg) => {
+					let name = arg.pat.to_token_stream().to_string();
+					Some(format!("{name}: {}", ts_type(&arg.ty)))
+				}
+				syn::FnArg::Receiver(_) => None,
+			})
+			.collect();
+
+		let result = match parse_return_type(&method.sig.output) {
+			Ok(ReturnType::Direct(ty) | ReturnType::ResultWrap(ty)) => ts_type(&ty),
+			_ => "void".to_string(),
+		};
+
+		declarations += &format!(
+			"declare function {}({}): {result} | Promise<{result}>;\n",
+			method.sig.ident,
+			params.join(", ")
+		);
+	}
+
+	declarations
+}
+
+/// TypeScript type of the JSON representation of `ty`. Types without an obvious representation (e.g. structs) become `unknown`.
+fn ts_type(ty: &syn::Type) -> String {
+	match ty {
+		syn::Type::Reference(ty) => ts_type(&ty.elem),
+		syn::Type::Paren(ty) => ts_type(&ty.elem),
+		syn::Type::Group(ty) => ts_type(&ty.elem),
+		syn::Type::Slice(ty) => format!("{}[]", ts_element_type(&ty.elem)),
+		syn::Type::Array(ty) => format!("{}[]", ts_element_type(&ty.elem)),
+		syn::Type::Tuple(ty) if ty.elems.is_empty() => "void".to_string(),
+		syn::Type::Tuple(ty) => {
+			let elems: Vec<String> = ty.elems.iter().map(ts_type).collect();
+			format!("[{}]", elems.join(", "))
+		}
+		syn::Type::Path(path) if path.qself.is_none() => {
+			let Some(segment) = path.path.segments.last() else {
+				return "unknown".to_string();
+			};
+			let generics: Vec<&syn::Type> = match &segment.arguments {
+				syn::PathArguments::AngleBracketed(args) => args
+					.args
+					.iter()
+					.filter_map(|arg| match arg {
+						syn::GenericArgument::Type(ty) => Some(ty),
+						_ => None,
+					})
+					.collect(),
+				_ => Vec::new(),
+			};
+
+			match (segment.ident.to_string().as_str(), generics.as_slice()) {
+				(
+					"i8" | "i16" | "i32" | "i64" | "isize" | "u8" | "u16" | "u32" | "u64" | "usize"
+					| "f32" | "f64",
+					_,
+				) => "number".to_string(),
+				("bool", _) => "boolean".to_string(),
+				("str" | "String" | "char", _) => "string".to_string(),
+				("Vec" | "VecDeque" | "HashSet" | "BTreeSet", [elem]) => {
+					format!("{}[]", ts_element_type(elem))
+				}
+				("Option", [inner]) => format!("{} | null", ts_type(inner)),
+				("Box" | "Rc" | "Arc" | "Cow", [.., inner]) => ts_type(inner),
+				("HashMap" | "BTreeMap", [_, value]) => {
+					format!("Record<string, {}>", ts_type(value))
+				}
+				_ => "unknown".to_string(),
+			}
+		}
+		_ => "unknown".to_string(),
+	}
+}
+
+/// Like [`ts_type()`], but parenthesized where needed to be followed by `[]`.
+fn ts_element_type(ty: &syn::Type) -> String {
+	let ty = ts_type(ty);
+	if ty.contains(' ') {
+		format!("({ty})")
+	} else {
+		ty
+	}
+}
+
 fn generate_struct(item: &syn::ItemTrait) -> syn::Result<TokenStream2> {
 	let name = &item.ident;
 	let visibility = &item.vis;
 
 	Ok(quote! {
-		#visibility struct #name<'a> {
-			script: &'a mut js_sandbox::Script,
+		#visibility struct #name<'a, E: js_sandbox::ScriptEngine = js_sandbox::Script> {
+			script: &'a mut E,
 		}
 	})
 }
 
-fn generate_marker_trait_impl(item: &syn::ItemTrait) -> syn::Result<TokenStream2> {
+fn generate_marker_trait_impl(
+	item: &syn::ItemTrait,
+	register: Option<&syn::LitStr>,
+) -> syn::Result<TokenStream2> {
 	let name = &item.ident;
 	let visibility = &item.vis;
+	let namespace = register.map(|_| name);
+
+	let functions = item.items.iter().filter_map(|item| match item {
+		syn::TraitItem::Fn(method) => {
+			let fn_name = js_function_name(namespace, &method.sig.ident);
+			let arity = method
+				.sig
+				.inputs
+				.iter()
+				.filter(|arg| matches!(arg, syn::FnArg::Typed(_)))
+				.count();
+			let optional = method.default.is_some();
+
+			Some(quote! {
+				js_sandbox::ApiFunction { name: #fn_name, arity: #arity, optional: #optional }
+			})
+		}
+		_ => None,
+	});
+
+	let mut from_script = quote! { Self { script } };
+	let mut register_fn = TokenStream2::new();
+	if let Some(path) = register {
+		let namespace = quote_token(name);
+		let source = generate_include(IncludeArgs {
+			path: path.clone(),
+			module: false,
+		})?;
+		let doc = format!(
+			"Loads `{}` into the namespace `{name}` of `script`, unless already present, and binds the API to it.",
+			path.value(),
+		);
+
+		from_script = quote! {
+			Self::register(script).unwrap_or_else(|e| panic!("cannot register namespace `{}`: {e}", #namespace))
+		};
+		register_fn = quote! {
+			impl<'a> #name<'a> {
+				#[doc = #doc]
+				///
+				/// Unlike [`JsApi::from_script()`](js_sandbox::JsApi::from_script), errors of the JS code are returned instead of
+				/// causing a panic.
+				#visibility fn register(script: &'a mut js_sandbox::Script) -> js_sandbox::JsResult<Self> {
+					if !script.list_namespaces().any(|namespace| namespace == #namespace) {
+						script.add_script(#namespace, #source)?;
+					}
+
+					Ok(Self { script })
+				}
+			}
+		};
+	}
 
 	Ok(quote! {
+		#register_fn
+
 		impl<'a> js_sandbox::JsApi<'a> for #name<'a> {
 			#visibility fn from_script(script: &'a mut js_sandbox::Script) -> Self {
-				Self { script }
+				#from_script
+			}
+
+			fn functions() -> &'static [js_sandbox::ApiFunction] {
+				&[#(#functions),*]
 			}
 		}
 	})
@@ -62,13 +373,19 @@ macro_rules! syntax_error {
 	)
 }
 
+/// Largest tuple implementing `CallArgs`.
+const MAX_TUPLE_ARGS: usize = 5;
+
 enum ReturnType {
 	Unit,
 	Direct(syn::Type),
 	ResultWrap(syn::Type),
 }
 
-fn generate_impl_methods(item: &syn::ItemTrait) -> syn::Result<TokenStream2> {
+fn generate_impl_methods(
+	item: &syn::ItemTrait,
+	namespace: Option<&syn::Ident>,
+) -> syn::Result<TokenStream2> {
 	let mut result = TokenStream2::new();
 	for item in item.items.iter() {
 		let method = match item {
@@ -81,82 +398,69 @@ fn generate_impl_methods(item: &syn::ItemTrait) -> syn::Result<TokenStream2> {
 		if let Some(tok) = &method.sig.asyncness {
 			syntax_error!(tok, "async functions are not supported");
 		}
-		if let Some(tok) = &method.default {
-			syntax_error!(tok, "cannot specify an implementation of methods");
-		}
-		if let Some(rcv) = method.sig.receiver() {
-			if rcv.mutability.is_none() {
-				syntax_error!(
-					rcv,
-					"receiver must be `&mut self`; values and shared references are not supported"
-				);
-			}
-		} else {
-			syntax_error!(
+
+		// `&self` methods are for functions that do not mutate host-visible state, and use the shared-reference call API
+		let fn_name = js_function_name(namespace, &method.sig.ident);
+		let shared = match method.sig.receiver() {
+			Some(rcv) if rcv.reference.is_none() => syntax_error!(
+				rcv,
+				"receiver must be `&mut self` or `&self`; values are not supported"
+			),
+			Some(rcv) => rcv.mutability.is_none(),
+			None => syntax_error!(
 				method.sig.ident,
-				"receiver must be `&mut self`; associated methods are not supported"
-			);
-		}
+				"receiver must be `&mut self` or `&self`; associated methods are not supported"
+			),
+		};
 
-		let mut args = Vec::new();
-		for arg in method.sig.inputs.iter() {
-			let arg = match arg {
-				syn::FnArg::Receiver(_) => continue,
-				syn::FnArg::Typed(arg) => arg,
+		let args = parse_args(&method.sig)?;
+		let sig = &method.sig;
+		let attrs = &method.attrs;
+		let (return_type, transform) = generate_return(&method.sig.output)?;
+
+		// Beyond the tuple sizes implemented by `CallArgs`, each argument is serialized separately
+		let (args, call) = if args.len() <= MAX_TUPLE_ARGS {
+			let call = if shared {
+				quote! { js_sandbox::ScriptEngine::call_ref(&*self.script, #fn_name, args) }
+			} else {
+				quote! { js_sandbox::ScriptEngine::call(&mut *self.script, #fn_name, args) }
 			};
-			let ident = match &*arg.pat {
-				syn::Pat::Ident(i) => i,
-				other => syntax_error!(other, "parameter must be a bare identifier"),
+			(quote! { (#(#args,)*) }, call)
+		} else {
+			let call = if shared {
+				quote! { js_sandbox::__private::call_spread_ref(&*self.script, #fn_name, args) }
+			} else {
+				quote! { js_sandbox::__private::call_spread(&mut *self.script, #fn_name, args) }
 			};
-			if let Some(tok) = &ident.by_ref {
-				syntax_error!(tok, "parameter must be a value; by-reference unsupported");
-			}
-			if let Some(tok) = &ident.mutability {
-				syntax_error!(tok, "parameter must not be mutable");
-			}
-			if let Some((_, tok)) = &ident.subpat {
-				syntax_error!(tok, "parameter cannot have destructured bindings");
-			}
+			(
+				quote! { vec![#(js_sandbox::__private::arg_value(&#args)),*] },
+				call,
+			)
+		};
 
-			let ident = &ident.ident;
-			args.push(ident);
-		}
+		let invocation = quote! {
+			let args = #args;
 
-		let sig = &method.sig;
-		let attrs = &method.attrs;
-		let fn_name = quote_token(&method.sig.ident);
-
-		let return_type: TokenStream2;
-		let transform: TokenStream2;
-		match parse_return_type(&method.sig.output)? {
-			ReturnType::Direct(ty) => {
-				return_type = ty.to_token_stream();
-				let ty_str = quote_token(&ty);
-				transform = quote! {
-					result.expect(concat!("cannot convert to type `", #ty_str, "`"))
-				}
-			}
-			ReturnType::ResultWrap(ty) => {
-				return_type = ty.to_token_stream();
-				transform = quote! { result };
-			}
-			ReturnType::Unit => {
-				return_type = quote! { () };
-				transform = quote! {
-					result.expect("JS function call failed");
+			let result: js_sandbox::JsResult<#return_type> = #call;
+			#transform
+		};
+
+		// A default implementation is the fallback for scripts that do not define the function
+		let body = match &method.default {
+			Some(fallback) => quote! {
+				if let Ok(false) = js_sandbox::ScriptEngine::has_function(&*self.script, #fn_name) {
+					#fallback
+				} else {
+					#invocation
 				}
-			}
+			},
+			None => invocation,
 		};
 
 		result.extend(quote! {
 			#(#attrs)*
 			#sig {
-				let args = (
-					#(#args,),*
-				);
-
-				let result: js_sandbox::JsResult<#return_type> = self.script.call(#fn_name, args);
-				#transform
+				#body
 			}
 		});
 	}
@@ -164,6 +468,56 @@ fn generate_impl_methods(item: &syn::ItemTrait) -> syn::Result<TokenStream2> {
 	Ok(result)
 }
 
+/// Names of the parameters, excluding the receiver.
+fn parse_args(sig: &syn::Signature) -> syn::Result<Vec<&syn::Ident>> {
+	let mut args = Vec::new();
+	for arg in sig.inputs.iter() {
+		let arg = match arg {
+			syn::FnArg::Receiver(_) => continue,
+			syn::FnArg::Typed(arg) => arg,
+		};
+		let ident = match &*arg.pat {
+			syn::Pat::Ident(i) => i,
+			other => syntax_error!(other, "parameter must be a bare identifier"),
+		};
+		if let Some(tok) = &ident.by_ref {
+			syntax_error!(tok, "parameter must be a value; by-reference unsupported");
+		}
+		if let Some(tok) = &ident.mutability {
+			syntax_error!(tok, "parameter must not be mutable");
+		}
+		if let Some((_, tok)) = &ident.subpat {
+			syntax_error!(tok, "parameter cannot have destructured bindings");
+		}
+
+		args.push(&ident.ident);
+	}
+
+	Ok(args)
+}
+
+/// Returns the type to deserialize the JS result into, and an expression that turns `result` into the declared return type.
+fn generate_return(tok: &syn::ReturnType) -> syn::Result<(TokenStream2, TokenStream2)> {
+	let generated = match parse_return_type(tok)? {
+		ReturnType::Direct(ty) => {
+			let ty_str = quote_token(&ty);
+			let transform = quote! {
+				result.expect(concat!("cannot convert to type `", #ty_str, "`"))
+			};
+			(ty.to_token_stream(), transform)
+		}
+		ReturnType::ResultWrap(ty) => (ty.to_token_stream(), quote! { result }),
+		ReturnType::Unit => {
+			let transform = quote! {
+				result.expect("JS function call failed");
+			};
+			(quote! { () }, transform)
+		}
+	};
+
+	Ok(generated)
+}
+
 fn parse_return_type(tok: &syn::ReturnType) -> syn::Result<ReturnType> {
 	match tok {
 		syn::ReturnType::Default => {
@@ -199,9 +553,285 @@ fn parse_return_type(tok: &syn::ReturnType) -> syn::Result<ReturnType> {
 	}
 }
 
+fn generate_host_object(item: syn::ItemImpl) -> syn::Result<TokenStream2> {
+	if let Some((_, path, _)) = &item.trait_ {
+		syntax_error!(
+			path,
+			"must be applied to an inherent impl block, not a trait impl"
+		);
+	}
+
+	let mut names = Vec::new();
+	let mut arms = Vec::new();
+	for impl_item in item.items.iter() {
+		let method = match impl_item {
+			syn::ImplItem::Fn(f) if matches!(f.vis, syn::Visibility::Public(_)) => f,
+			_ => continue,
+		};
+
+		let sig = &method.sig;
+		match sig.receiver() {
+			Some(rcv) if rcv.reference.is_some() => {}
+			Some(rcv) => syntax_error!(rcv, "receiver must be `&self` or `&mut self`"),
+			None => continue, // associated functions are not exposed
+		}
+		if let Some(tok) = &sig.asyncness {
+			syntax_error!(tok, "async methods are not supported");
+		}
+		if !sig.generics.params.is_empty() {
+			syntax_error!(sig.generics, "generic methods are not supported");
+		}
+
+		let mut arg_decls = Vec::new();
+		let mut arg_names = Vec::new();
+		for (i, arg) in sig.inputs.iter().enumerate() {
+			let arg = match arg {
+				syn::FnArg::Receiver(_) => continue,
+				syn::FnArg::Typed(arg) => arg,
+			};
+			let ident = quote::format_ident!("__arg{}", i);
+
+			// Shared references are passed to owned values: `&str` to a `String`, `&[T]` to a `Vec<T>`
+			match &*arg.ty {
+				syn::Type::Reference(ty) if ty.mutability.is_some() => syntax_error!(
+					ty,
+					"parameter must be an owned type or shared reference; `&mut` is not supported"
+				),
+				syn::Type::Reference(ty) => {
+					let owned = match &*ty.elem {
+						syn::Type::Path(path) if path.path.is_ident("str") => quote! { String },
+						syn::Type::Slice(slice) => {
+							let elem = &slice.elem;
+							quote! { Vec<#elem> }
+						}
+						elem => elem.to_token_stream(),
+					};
+
+					arg_decls.push(quote! {
+						let #ident: #owned = js_sandbox::__private::arg_from_json(args.next())?;
+					});
+					arg_names.push(quote! { &#ident });
+				}
+				ty => {
+					arg_decls.push(quote! {
+						let #ident: #ty = js_sandbox::__private::arg_from_json(args.next())?;
+					});
+					arg_names.push(ident.to_token_stream());
+				}
+			}
+		}
+
+		let ident = &sig.ident;
+		let name = quote_token(ident);
+		let invocation = if returns_result(&sig.output) {
+			quote! { self.#ident(#(#arg_names),*).map_err(js_sandbox::AnyError::from)? }
+		} else {
+			quote! { self.#ident(#(#arg_names),*) }
+		};
+
+		arms.push(quote! {
+			#name => {
+				#(#arg_decls)*
+				let result = #invocation;
+				js_sandbox::__private::result_to_json(result)
+			}
+		});
+		names.push(name);
+	}
+
+	let self_ty = &item.self_ty;
+	let (impl_generics, _, where_clause) = item.generics.split_for_impl();
+
+	Ok(quote! {
+		#item
+
+		impl #impl_generics js_sandbox::HostObject for #self_ty #where_clause {
+			fn method_names(&self) -> &'static [&'static str] {
+				&[#(#names),*]
+			}
+
+			fn call_method(
+				&mut self,
+				method: &str,
+				args: Vec<js_sandbox::JsValue>,
+			) -> Result<js_sandbox::JsValue, js_sandbox::AnyError> {
+				#[allow(unused_mut, unused_variables)]
+				let mut args = args.into_iter();
+
+				match method {
+					#(#arms)*
+					_ => Err(js_sandbox::__private::unknown_method(method)),
+				}
+			}
+		}
+	})
+}
+
+/// Whether a method returns `Result<T, E>` (or an alias such as `JsResult<T>`), whose error is propagated to JS.
+fn returns_result(tok: &syn::ReturnType) -> bool {
+	match tok {
+		syn::ReturnType::Default => false,
+		syn::ReturnType::Type(_, ty) => match ty.as_ref() {
+			syn::Type::Path(path) => path
+				.path
+				.segments
+				.last()
+				.is_some_and(|seg| seg.ident == "Result" || seg.ident == "JsResult"),
+			_ => false,
+		},
+	}
+}
+
+/// Name under which the JS function of `method` is called: a path into `namespace` for registered APIs, otherwise a global.
+fn js_function_name(namespace: Option<&syn::Ident>, method: &syn::Ident) -> syn::LitStr {
+	let name = match namespace {
+		Some(namespace) => format!("{NAMESPACE_REGISTRY}.{namespace}.{method}"),
+		None => method.to_string(),
+	};
+
+	syn::LitStr::new(&name, method.span())
+}
+
 fn quote_token(token: &dyn quote::ToTokens) -> syn::Lit {
 	syn::Lit::Str(syn::LitStr::new(
 		&token.to_token_stream().to_string(),
 		token.span(),
 	))
 }
+
+struct IncludeArgs {
+	path: syn::LitStr,
+	// Only affects the syntax check, but is accepted either way
+	#[cfg_attr(not(feature = "syntax-check"), allow(dead_code))]
+	module: bool,
+}
+
+impl syn::parse::Parse for IncludeArgs {
+	fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+		let path = input.parse()?;
+		let mut module = false;
+
+		if input.parse::<Option<syn::Token![,]>>()?.is_some() && !input.is_empty() {
+			let flag: syn::Ident = input.parse()?;
+			if flag != "module" {
+				return Err(syn::Error::new(flag.span(), "expected `module`"));
+			}
+
+			module = true;
+			input.parse::<Option<syn::Token![,]>>()?;
+		}
+
+		Ok(Self { path, module })
+	}
+}
+
+fn generate_include(args: IncludeArgs) -> syn::Result<TokenStream2> {
+	let manifest_dir = std::env::var("CARGO_MANIFEST_DIR")
+		.map_err(|e| syn::Error::new(args.path.span(), format!("CARGO_MANIFEST_DIR: {e}")))?;
+	let full_path = std::path::Path::new(&manifest_dir).join(args.path.value());
+
+	#[cfg(feature = "syntax-check")]
+	check_syntax(&full_path, &args)?;
+
+	// include_str! makes Cargo rebuild (and thus re-check) when the file changes
+	let full_path = full_path.to_string_lossy();
+	Ok(quote! {
+		::core::include_str!(#full_path)
+	})
+}
+
+#[cfg(feature = "syntax-check")]
+fn check_syntax(full_path: &std::path::Path, args: &IncludeArgs) -> syn::Result<()> {
+	let js_code = std::fs::read_to_string(full_path).map_err(|e| {
+		let message = format!("cannot read {}: {e}", full_path.display());
+		syn::Error::new(args.path.span(), message)
+	})?;
+
+	let mut interner = boa_interner::Interner::default();
+	let mut parser = boa_parser::Parser::new(boa_parser::Source::from_bytes(js_code.as_bytes()));
+	let parsed = if args.module {
+		parser.parse_module(&mut interner).map(drop)
+	} else {
+		parser.parse_script(&mut interner).map(drop)
+	};
+
+	parsed.map_err(|e| {
+		let message = format!("syntax error in {}: {e}", args.path.value());
+		syn::Error::new(args.path.span(), message)
+	})
+}
+
+struct JsFnItem {
+	attrs: Vec<syn::Attribute>,
+	vis: syn::Visibility,
+	sig: syn::Signature,
+	source: syn::Expr,
+}
+
+impl syn::parse::Parse for JsFnItem {
+	fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+		let attrs = input.call(syn::Attribute::parse_outer)?;
+		let vis = input.parse()?;
+		let sig = input.parse()?;
+		input.parse::<syn::Token![=]>()?;
+		let source = input.parse()?;
+		input.parse::<Option<syn::Token![;]>>()?;
+
+		Ok(Self {
+			attrs,
+			vis,
+			sig,
+			source,
+		})
+	}
+}
+
+fn generate_js_fn(item: JsFnItem) -> syn::Result<TokenStream2> {
+	let JsFnItem {
+		attrs,
+		vis,
+		sig,
+		source,
+	} = &item;
+
+	if let Some(tok) = &sig.constness {
+		syntax_error!(tok, "const functions are not supported");
+	}
+	if let Some(tok) = &sig.asyncness {
+		syntax_error!(tok, "async functions are not supported");
+	}
+	if let Some(rcv) = sig.receiver() {
+		syntax_error!(
+			rcv,
+			"js_fn! defines free functions; receivers are not supported"
+		);
+	}
+
+	let args = parse_args(sig)?;
+	let fn_name = quote_token(&sig.ident);
+	let (return_type, transform) = generate_return(&sig.output)?;
+
+	Ok(quote! {
+		#(#attrs)*
+		#vis #sig {
+			::std::thread_local! {
+				static SCRIPT: ::std::cell::RefCell<::std::option::Option<js_sandbox::Script>> =
+					::std::cell::RefCell::new(::std::option::Option::None);
+			}
+
+			let args = (
+				#(#args,)*
+			);
+
+			let result: js_sandbox::JsResult<#return_type> = SCRIPT.with(|script| {
+				let mut script = script.borrow_mut();
+				if script.is_none() {
+					*script = Some(js_sandbox::__private::js_fn_script(#fn_name, #source)?);
+				}
+
+				script.as_mut().expect("script is initialized").call(#fn_name, args)
+			});
+			#transform
+		}
+	})
+}
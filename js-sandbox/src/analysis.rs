@@ -0,0 +1,85 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use serde::Serialize;
+
+use crate::lexer::{self, TokenKind};
+
+/// Identifiers which untrusted scripts typically have no business accessing.
+///
+/// Can be passed to [`find_forbidden_apis()`] as-is, or extended with host-specific names.
+pub const DEFAULT_FORBIDDEN_APIS: &[&str] = &[
+	"Deno",
+	"eval",
+	"Function",
+	"import",
+	"WebAssembly",
+	"SharedArrayBuffer",
+	"Atomics",
+];
+
+/// Reference to a forbidden identifier, as reported by [`find_forbidden_apis()`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ForbiddenApiFinding {
+	/// The forbidden identifier that was referenced.
+	pub identifier: String,
+
+	/// 1-based line number of the reference.
+	pub line: usize,
+
+	/// 1-based column of the reference, counted in characters.
+	pub column: usize,
+}
+
+/// Scans JavaScript source code for references to forbidden identifiers, without executing it.
+///
+/// Useful to reject or flag suspicious plugins before loading them. Each occurrence of an identifier in `forbidden` is reported,
+/// in source order. Property accesses such as `obj.eval` are not reported, since they do not refer to the global; occurrences in
+/// string literals and comments are ignored as well. Dynamic `import(...)` is covered by forbidding `import`.
+///
+/// The scan is lexical and errs on the side of reporting: an identifier used as object key or local variable name is reported, too.
+/// Conversely, code can still reach forbidden APIs indirectly (e.g. through `globalThis["ev" + "al"]`), so this is a first line of
+/// defense, not a replacement for the sandbox itself.
+///
+/// ```rust
+/// use js_sandbox::{find_forbidden_apis, DEFAULT_FORBIDDEN_APIS};
+///
+/// let src = "function run(code) {\n\treturn eval(code); // not allowed\n}";
+/// let findings = find_forbidden_apis(src, DEFAULT_FORBIDDEN_APIS);
+///
+/// assert_eq!(findings.len(), 1);
+/// assert_eq!(findings[0].identifier, "eval");
+/// assert_eq!((findings[0].line, findings[0].column), (2, 9));
+/// ```
+pub fn find_forbidden_apis(js_code: &str, forbidden: &[&str]) -> Vec<ForbiddenApiFinding> {
+	let tokens = lexer::tokenize(js_code);
+	let mut findings = Vec::new();
+
+	for (i, token) in tokens.iter().enumerate() {
+		let TokenKind::Ident(ident) = token.kind else {
+			continue;
+		};
+
+		if !forbidden.contains(&ident) || is_property_access(&tokens[..i]) {
+			continue;
+		}
+
+		findings.push(ForbiddenApiFinding {
+			identifier: ident.to_string(),
+			line: token.line,
+			column: token.column,
+		});
+	}
+
+	findings
+}
+
+/// Whether the tokens preceding an identifier make it a property name (`a.b`, `a?.b`), as opposed to spread syntax (`...b`).
+fn is_property_access(preceding: &[lexer::Token]) -> bool {
+	match preceding {
+		[.., before, last] => {
+			last.kind == TokenKind::Punct('.') && before.kind != TokenKind::Punct('.')
+		}
+		[last] => last.kind == TokenKind::Punct('.'),
+		[] => false,
+	}
+}
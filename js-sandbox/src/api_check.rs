@@ -0,0 +1,95 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::fmt;
+
+use crate::{JsError, Script};
+
+/// A JS function expected by a `#[js_api]` trait, see [`Script::check_api()`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ApiFunction {
+	/// Name of the JS function.
+	pub name: &'static str,
+
+	/// Number of arguments passed by the Rust method.
+	pub arity: usize,
+
+	/// Whether the method has a default implementation, which is used when the script does not define the function.
+	pub optional: bool,
+}
+
+/// Difference between a script and the functions expected by a `#[js_api]` trait, see [`Script::check_api()`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ApiMismatch {
+	/// The script does not define the function.
+	Missing { function: String },
+
+	/// The name refers to a value which is not a function.
+	NotAFunction { function: String },
+
+	/// The function declares more parameters than the Rust method passes arguments, so some of them would be `undefined`.
+	Arity {
+		function: String,
+		expected: usize,
+		actual: usize,
+	},
+}
+
+impl fmt::Display for ApiMismatch {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			ApiMismatch::Missing { function } => write!(f, "function `{function}` is missing"),
+			ApiMismatch::NotAFunction { function } => write!(f, "`{function}` is not a function"),
+			ApiMismatch::Arity {
+				function,
+				expected,
+				actual,
+			} => write!(
+				f,
+				"function `{function}` declares {actual} parameters, but is called with {expected} arguments"
+			),
+		}
+	}
+}
+
+/// Compares the functions defined by `script` against `functions`.
+pub(crate) fn check(
+	script: &Script,
+	functions: &[ApiFunction],
+) -> Result<Vec<ApiMismatch>, JsError> {
+	// Per function: its number of declared parameters, -1 if undefined, -2 if not a function
+	let probes: Vec<String> = functions
+		.iter()
+		.map(|function| {
+			let name = function.name;
+			format!(
+				"(() => {{ try {{ return typeof {name} === 'function' ? {name}.length : typeof {name} === 'undefined' ? -1 : -2; }} \
+				catch {{ return -1; }} }})()"
+			)
+		})
+		.collect();
+	let lengths = script.evaluate(&format!("[{}]", probes.join(", ")))?;
+
+	let mut mismatches = Vec::new();
+	for (function, length) in functions
+		.iter()
+		.zip(lengths.as_array().into_iter().flatten())
+	{
+		let name = function.name.to_string();
+		match length.as_i64() {
+			Some(-1) if function.optional => {}
+			Some(-1) => mismatches.push(ApiMismatch::Missing { function: name }),
+			Some(-2) => mismatches.push(ApiMismatch::NotAFunction { function: name }),
+			Some(actual) if actual as usize > function.arity => {
+				mismatches.push(ApiMismatch::Arity {
+					function: name,
+					expected: function.arity,
+					actual: actual as usize,
+				})
+			}
+			_ => {}
+		}
+	}
+
+	Ok(mismatches)
+}
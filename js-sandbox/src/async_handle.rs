@@ -0,0 +1,134 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::thread;
+
+use deno_core::futures::channel::{mpsc, oneshot};
+use deno_core::futures::executor;
+use deno_core::futures::future::BoxFuture;
+use serde::de::DeserializeOwned;
+
+use crate::{call_args, AnyError, CallArgs, CallOptions, JsError, JsValue, Script};
+
+type Job = Box<dyn FnOnce(&mut Script) + Send>;
+
+/// Thread-safe handle to a [`Script`] running on its own thread.
+///
+/// A V8 isolate is bound to the thread it was created on, so `Script` itself cannot be sent to other threads. This handle owns a
+/// dedicated thread running the script, and forwards calls to it. It is `Send + Sync` and cheap to clone, so it can be shared across
+/// async tasks (e.g. `tokio::spawn`ed handlers) without a `LocalSet`.
+///
+/// Calls are executed one after another, in the order they were issued. The thread shuts down once all handles are dropped.
+///
+/// ```rust
+/// use js_sandbox::{AsyncScriptHandle, JsError};
+///
+/// # use deno_core::futures::executor::block_on;
+/// fn main() -> Result<(), JsError> {
+/// 	let handle = AsyncScriptHandle::from_string("function sub(a, b) { return a - b; }")?;
+///
+/// 	// Any executor works; inside an async task, simply .await the future
+/// 	let result: i32 = block_on(handle.call("sub", (7, 5)))?;
+///
+/// 	assert_eq!(result, 2);
+/// 	Ok(())
+/// }
+/// ```
+#[derive(Clone)]
+pub struct AsyncScriptHandle {
+	jobs: mpsc::UnboundedSender<Job>,
+}
+
+impl AsyncScriptHandle {
+	/// Initialize a script with the given JavaScript source code, on a new thread.
+	///
+	/// See [`Script::from_string()`].
+	pub fn from_string(js_code: impl Into<String>) -> Result<Self, JsError> {
+		let js_code = js_code.into();
+		Self::spawn(move || Script::from_string(&js_code))
+	}
+
+	/// Starts a new thread, on which `init` creates the script.
+	///
+	/// This allows to configure the script using [`Script::builder()`] or methods such as [`Script::with_timeout()`].
+	/// Blocks until initialization has completed, and returns its error if it fails.
+	pub fn spawn<F>(init: F) -> Result<Self, JsError>
+	where
+		F: FnOnce() -> Result<Script, JsError> + Send + 'static,
+	{
+		let (jobs_tx, jobs_rx) = mpsc::unbounded::<Job>();
+		let (init_tx, init_rx) = oneshot::channel::<Result<(), JsError>>();
+
+		thread::Builder::new()
+			.name("js-sandbox-script".to_string())
+			.spawn(move || {
+				let mut script = match init() {
+					Ok(script) => {
+						let _ = init_tx.send(Ok(()));
+						script
+					}
+					Err(e) => {
+						let _ = init_tx.send(Err(e));
+						return;
+					}
+				};
+
+				for job in executor::block_on_stream(jobs_rx) {
+					job(&mut script);
+				}
+			})
+			.map_err(AnyError::from)?;
+
+		executor::block_on(init_rx)
+			.map_err(|_| AnyError::msg("script thread panicked during initialization"))??;
+
+		Ok(Self { jobs: jobs_tx })
+	}
+
+	/// Invokes a JavaScript function on the script's thread.
+	///
+	/// Arguments are serialized on the calling thread, and the result is deserialized when the returned future is polled.
+	/// See [`Script::call()`] for details.
+	pub fn call<A, R>(&self, fn_name: &str, args_tuple: A) -> BoxFuture<'static, Result<R, JsError>>
+	where
+		A: CallArgs,
+		R: DeserializeOwned + Send + 'static,
+	{
+		let fn_name = fn_name.to_string();
+		let job_fn_name = fn_name.clone();
+		let sent = args_tuple
+			.into_args()
+			.map_err(|e| call_args::args_error(&fn_name, e))
+			.and_then(|args| {
+				self.send(move |script| {
+					script.call_impl(&job_fn_name, &args, &CallOptions::default())
+				})
+			});
+
+		Box::pin(async move {
+			let json_result = sent?.await.map_err(|_| thread_stopped())??;
+			let result: R = call_args::from_result(json_result)
+				.map_err(|e| call_args::result_error(&fn_name, e))?;
+
+			Ok(result)
+		})
+	}
+
+	fn send<F>(&self, job: F) -> Result<oneshot::Receiver<Result<JsValue, JsError>>, JsError>
+	where
+		F: FnOnce(&mut Script) -> Result<JsValue, JsError> + Send + 'static,
+	{
+		let (result_tx, result_rx) = oneshot::channel();
+
+		self.jobs
+			.unbounded_send(Box::new(move |script| {
+				let _ = result_tx.send(job(script));
+			}))
+			.map_err(|_| thread_stopped())?;
+
+		Ok(result_rx)
+	}
+}
+
+fn thread_stopped() -> JsError {
+	JsError::Runtime(AnyError::msg("script thread is no longer running"))
+}
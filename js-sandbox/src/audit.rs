@@ -0,0 +1,120 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use deno_core::{op, OpState};
+
+use crate::Provenance;
+
+/// Operation that a script attempted, but was not allowed to perform.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BlockedOperation {
+	/// Static `import` declaration, denied by the [`ImportPolicy`](crate::ImportPolicy).
+	Import,
+
+	/// Dynamic `import()`, denied by the [`ImportPolicy`](crate::ImportPolicy).
+	DynamicImport,
+
+	/// Import of a module type that is not allowed, see [`ScriptBuilder::with_import_types()`](crate::ScriptBuilder::with_import_types).
+	ImportType,
+
+	/// `host.require()` of a capability that was not granted, see [`CapabilityRegistry`](crate::CapabilityRegistry).
+	Capability,
+}
+
+/// Record of a blocked operation, retrieved with [`Script::take_audit_log()`](crate::Script::take_audit_log).
+///
+/// Scripts may catch the resulting errors and carry on, so the audit log is the only reliable way for the host to see what
+/// a (possibly hostile) script attempted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AuditEvent {
+	/// What was attempted.
+	pub operation: BlockedOperation,
+
+	/// The module specifier or capability name that was requested. For denied module types, the resolved module URL.
+	pub target: String,
+
+	/// Name of the script or module from which the operation was attempted, if known.
+	pub origin: Option<String>,
+
+	/// Innermost frames of the JS stack at the time of the attempt (like `at run (sandboxed.js:3:5)`), up to
+	/// [`AuditEvent::MAX_STACK_FRAMES`].
+	///
+	/// Empty for imports, which are resolved after the importing code has run.
+	pub stack: Vec<String>,
+
+	/// Provenance of the script, see [`ScriptBuilder::with_provenance()`](crate::ScriptBuilder::with_provenance).
+	pub provenance: Option<Provenance>,
+}
+
+impl AuditEvent {
+	/// Maximum number of stack frames recorded per event.
+	pub const MAX_STACK_FRAMES: usize = 8;
+}
+
+/// Blocked operations of a script, shared between the script, its op state and its module loader.
+#[derive(Clone, Default)]
+pub(crate) struct AuditLog(Rc<RefCell<VecDeque<AuditEvent>>>);
+
+impl AuditLog {
+	/// Events beyond this number displace the oldest ones, so that scripts cannot make the log grow without bounds.
+	pub const MAX_EVENTS: usize = 1000;
+
+	pub fn record(&self, event: AuditEvent) {
+		let mut events = self.0.borrow_mut();
+		if events.len() == Self::MAX_EVENTS {
+			events.pop_front();
+		}
+		events.push_back(event);
+	}
+
+	/// Removes all events, filling in the script's provenance.
+	pub fn take(&self, provenance: Option<&Provenance>) -> Vec<AuditEvent> {
+		let mut events: Vec<AuditEvent> = self.0.take().into();
+		for event in events.iter_mut() {
+			event.provenance = provenance.cloned();
+		}
+		events
+	}
+}
+
+/// Records a denied capability request. `stack` is the `stack` property of the error thrown to the script.
+#[op]
+pub(crate) fn op_audit_capability(state: &mut OpState, name: String, stack: String) {
+	let Some(log) = state.try_borrow::<AuditLog>() else {
+		return;
+	};
+
+	// The first line holds the error message
+	let stack: Vec<String> = stack
+		.lines()
+		.skip(1)
+		.map(|frame| frame.trim().to_string())
+		.take(AuditEvent::MAX_STACK_FRAMES)
+		.collect();
+
+	log.record(AuditEvent {
+		operation: BlockedOperation::Capability,
+		target: name,
+		origin: stack.first().and_then(|frame| frame_script(frame)),
+		stack,
+		provenance: None,
+	});
+}
+
+/// Script name of a V8 stack frame, such as `at run (sandboxed.js:3:5)` or `at sandboxed.js:3:5`.
+fn frame_script(frame: &str) -> Option<String> {
+	let location = frame.strip_prefix("at ")?;
+	let location = match location.rsplit_once(" (") {
+		Some((_function, location)) => location.strip_suffix(')')?,
+		None => location,
+	};
+
+	// Strip line and column
+	let mut parts = location.rsplitn(3, ':');
+	let (_column, _line, script) = (parts.next()?, parts.next()?, parts.next()?);
+	Some(script.to_string())
+}
@@ -0,0 +1,92 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use deno_core::v8;
+
+use crate::termination::{Termination, TerminationFlag};
+use crate::watchdog;
+
+/// Time-slice budget of a script, consumed by periodic interrupts while JS code runs.
+///
+/// Slices are measured in wall-clock time, so consumption varies between runs, see
+/// [`Script::with_time_slice_budget()`](crate::Script::with_time_slice_budget).
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct TimeSliceBudget {
+	pub slices: u64,
+	pub slice_interval: Duration,
+}
+
+impl TimeSliceBudget {
+	/// Starts metering a single call. Metering stops as soon as the returned guard is dropped.
+	/// Sets `terminated` when the budget is exhausted.
+	pub fn start(&self, handle: v8::IsolateHandle, terminated: TerminationFlag) -> BudgetGuard {
+		let meter = Arc::new(Meter {
+			remaining: AtomicU64::new(self.slices),
+			active: AtomicBool::new(true),
+			terminated,
+		});
+
+		let slice_interval = self.slice_interval;
+		let ticker_meter = meter.clone();
+
+		let ticker = watchdog::schedule(slice_interval, move || {
+			let data = Arc::into_raw(ticker_meter.clone()) as *mut c_void;
+
+			if handle.request_interrupt(consume_slice, data) {
+				Some(slice_interval)
+			} else {
+				// Isolate has been disposed, callback will never run
+				// SAFETY: pointer was obtained from Arc::into_raw() above and not passed on
+				unsafe { drop(Arc::from_raw(data as *const Meter)) };
+				None
+			}
+		});
+
+		BudgetGuard {
+			meter,
+			_ticker: ticker,
+		}
+	}
+}
+
+/// Keeps metering alive for the duration of a call.
+pub(crate) struct BudgetGuard {
+	meter: Arc<Meter>,
+	_ticker: watchdog::Scheduled,
+}
+
+impl Drop for BudgetGuard {
+	fn drop(&mut self) {
+		// Interrupts may still be queued in the isolate; they must not affect later calls
+		self.meter.active.store(false, Ordering::SeqCst);
+	}
+}
+
+struct Meter {
+	remaining: AtomicU64,
+	active: AtomicBool,
+	terminated: TerminationFlag,
+}
+
+// Runs on the isolate's thread, while JS code is executing
+extern "C" fn consume_slice(isolate: &mut v8::Isolate, data: *mut c_void) {
+	// SAFETY: pointer was obtained from Arc::into_raw() in TimeSliceBudget::start()
+	let meter = unsafe { Arc::from_raw(data as *const Meter) };
+
+	if !meter.active.load(Ordering::SeqCst) {
+		return;
+	}
+
+	let previous = meter
+		.remaining
+		.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |r| r.checked_sub(1));
+
+	if matches!(previous, Ok(1) | Err(_)) {
+		meter.terminated.set(Termination::Budget);
+		isolate.terminate_execution();
+	}
+}
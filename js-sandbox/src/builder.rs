@@ -0,0 +1,642 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::future::Future;
+use std::io::Write;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::Duration;
+
+use deno_core::{Extension, ModuleSpecifier, OpDecl};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::host_object::AsyncHostFn;
+use crate::integrity::ModuleVerifier;
+use crate::storage::QuotaStorage;
+use crate::{
+	AnyError, CapabilityRegistry, Clock, HostObject, ImportPolicy, ImportType, JsError, JsValue,
+	Policy, Provenance, Script, ScriptEnv, ScriptStorage,
+};
+
+/// Configures a [`Script`] before it is initialized.
+///
+/// Some settings are applied by V8 when the runtime is created, and cannot be changed later on.
+/// Settings that can be changed at any time (such as [`Script::with_timeout()`]) are available directly on `Script`.
+///
+/// ```rust
+/// use js_sandbox::{Script, JsError};
+///
+/// fn main() -> Result<(), JsError> {
+/// 	let js_code = "function add(a, b) { return a + b; }";
+/// 	let mut script = Script::builder()
+/// 		.with_max_stack_size(512)
+/// 		.build_from_string(js_code)?;
+///
+/// 	let result: i32 = script.call("add", (1, 2))?;
+/// 	assert_eq!(result, 3);
+/// 	Ok(())
+/// }
+/// ```
+#[derive(Default)]
+pub struct ScriptBuilder {
+	pub(crate) max_stack_size: Option<usize>,
+	pub(crate) recursion_budget: Option<usize>,
+	pub(crate) max_source_size: Option<usize>,
+	pub(crate) max_heap_size: Option<usize>,
+	pub(crate) max_nesting_depth: Option<usize>,
+	pub(crate) max_microtasks: Option<u64>,
+	pub(crate) max_event_loop_turns: Option<u64>,
+	pub(crate) max_string_length: Option<usize>,
+	pub(crate) max_array_length: Option<usize>,
+	pub(crate) host_objects: Vec<(String, Box<dyn HostObject>)>,
+	pub(crate) async_host_fns: Vec<(String, AsyncHostFn)>,
+	pub(crate) capabilities: Option<Vec<(String, Box<dyn HostObject>)>>,
+	pub(crate) output_sink: Option<Box<dyn Write>>,
+	pub(crate) context: Option<serde_json::Result<JsValue>>,
+	pub(crate) env: Option<ScriptEnv>,
+	pub(crate) storage: Option<QuotaStorage>,
+	pub(crate) ops: Vec<OpDecl>,
+	pub(crate) extensions: Vec<Extension>,
+	pub(crate) clock: Option<Box<dyn Clock>>,
+	pub(crate) web_apis: bool,
+	pub(crate) module: bool,
+	pub(crate) snapshot: Option<&'static [u8]>,
+	pub(crate) imports: ImportPolicy,
+	pub(crate) import_types: Option<Vec<ImportType>>,
+	pub(crate) module_verifier: Option<ModuleVerifier>,
+	pub(crate) provenance: Option<Provenance>,
+	pub(crate) filename: Option<String>,
+	// Set for modules loaded from a file, whose relative imports are resolved against the file's location
+	pub(crate) module_url: Option<ModuleSpecifier>,
+	pub(crate) deny_code_generation: bool,
+	pub(crate) deny_wasm: bool,
+	pub(crate) deny_timers: bool,
+	pub(crate) freeze_intrinsics: bool,
+	pub(crate) timeout: Option<Duration>,
+}
+
+impl ScriptBuilder {
+	/// V8's default stack size on 64-bit platforms, in KiB.
+	pub(crate) const DEFAULT_MAX_STACK_SIZE: usize = 984;
+
+	/// Creates a builder with default settings.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Limits the stack size (in KiB) that JavaScript code may use.
+	///
+	/// Deeply recursive code that exceeds this limit fails with a `RangeError: Maximum call stack size exceeded`, which can be caught
+	/// inside JS or is otherwise returned as a [`JsError`]. The limit must be lower than the native stack of the thread calling into the
+	/// script (by default 8 MiB for the main thread, 2 MiB for threads spawned by Rust), otherwise the process may crash instead.
+	///
+	/// The limit only applies to this script, and is counted from the point where the host calls into the script.
+	///
+	/// Panics if `size_kb` is zero.
+	pub fn with_max_stack_size(mut self, size_kb: usize) -> Self {
+		assert!(size_kb > 0);
+
+		self.max_stack_size = Some(size_kb);
+		self
+	}
+
+	/// Sizes the stack of JavaScript code for about `calls` nested calls of simple functions.
+	///
+	/// Runaway recursion that is not caught inside JS fails with [`JsError::RecursionLimit`], which names the function that hit
+	/// the limit, rather than with a plain `RangeError`. Within JS, the error is still a `RangeError` and can be caught.
+	///
+	/// This is a stack budget, not an exact call count: V8 does not count calls, so the budget is converted to a stack size of
+	/// roughly 96 bytes per call. Functions with many arguments or local variables use more stack per call and stop earlier.
+	/// The stack is never reduced below 128 KiB (about 1300 calls), which scripts need for initialization, so smaller budgets
+	/// are not honored. If [`Self::with_max_stack_size()`] is set as well, the lower of both limits applies.
+	///
+	/// Panics if `calls` is zero.
+	pub fn with_recursion_budget(mut self, calls: usize) -> Self {
+		assert!(calls > 0);
+
+		self.recursion_budget = Some(calls);
+		self
+	}
+
+	/// Limits the size (in bytes) of the JavaScript heap.
+	///
+	/// When a script gets close to the limit, the current call is aborted with [`JsError::MemoryLimit`]. The limit is then raised
+	/// temporarily, so that V8 can unwind; scripts which ran into the limit should be discarded and recreated.
+	///
+	/// External memory, such as array buffers backed by host memory, counts towards the limit as well. Array buffers are allocated
+	/// against the limit as they are created, so that a single huge allocation cannot exhaust host memory; in JS, allocations
+	/// beyond the limit throw a `RangeError`. The sum of heap and external memory (including memory reported with
+	/// [`Script::adjust_external_memory()`]) is only checked after every call: calls that leave the script above the limit fail.
+	///
+	/// Panics if `size_bytes` is zero.
+	pub fn with_max_heap_size(mut self, size_bytes: usize) -> Self {
+		assert!(size_bytes > 0);
+
+		self.max_heap_size = Some(size_bytes);
+		self
+	}
+
+	/// Limits the size of the source code (in bytes) that is accepted.
+	///
+	/// The limit is checked before the code is parsed, and for files before they are read into memory. This prevents hostile
+	/// multi-megabyte sources from stalling initialization, which is not covered by [`Script::with_timeout()`].
+	///
+	/// Panics if `size_bytes` is zero.
+	pub fn with_max_source_size(mut self, size_bytes: usize) -> Self {
+		assert!(size_bytes > 0);
+
+		self.max_source_size = Some(size_bytes);
+		self
+	}
+
+	/// Limits how deeply brackets (`()`, `[]`, `{}`) may be nested in the source code.
+	///
+	/// The check is a quick lexical scan that runs before the code is parsed, guarding against input designed to make the parser
+	/// slow or to exhaust its stack. String literals, comments and regex literals are ignored.
+	///
+	/// Panics if `depth` is zero.
+	pub fn with_max_nesting_depth(mut self, depth: usize) -> Self {
+		assert!(depth > 0);
+
+		self.max_nesting_depth = Some(depth);
+		self
+	}
+
+	/// Limits the number of microtasks (promise reactions and `queueMicrotask()` callbacks) that run during a single call.
+	///
+	/// Code that endlessly chains resolved promises never blocks and never returns to the event loop, so it would keep the host's
+	/// thread busy until a timeout (if any) fires. Once the limit is exceeded, the call is aborted with [`JsError::MicrotaskLimit`].
+	/// Microtasks are counted through V8 promise hooks, which makes promise-heavy code somewhat slower.
+	///
+	/// Panics if `count` is zero.
+	pub fn with_max_microtasks(mut self, count: u64) -> Self {
+		assert!(count > 0);
+
+		self.max_microtasks = Some(count);
+		self
+	}
+
+	/// Limits how often the event loop is polled during a single call, while waiting for async functions and their promises.
+	///
+	/// Each turn processes completed async ops and due timers once. A call whose promises have not settled after `count` turns is
+	/// aborted with [`JsError::EventLoopLimit`].
+	///
+	/// Panics if `count` is zero.
+	pub fn with_max_event_loop_turns(mut self, count: u64) -> Self {
+		assert!(count > 0);
+
+		self.max_event_loop_turns = Some(count);
+		self
+	}
+
+	/// Limits the length of strings (in UTF-16 code units, like JS `length`) in results returned to the host.
+	///
+	/// Results are checked before they are copied out of V8, so that e.g. `return "x".repeat(1e8)` fails with an error instead
+	/// of allocating host memory. This applies to strings nested in arrays and objects, too. Constructing large strings inside
+	/// the script is bounded by [`Self::with_max_heap_size()`], which should be set as well.
+	///
+	/// Panics if `length` is zero.
+	pub fn with_max_string_length(mut self, length: usize) -> Self {
+		assert!(length > 0);
+
+		self.max_string_length = Some(length);
+		self
+	}
+
+	/// Limits the length of arrays in results returned to the host, including nested ones.
+	///
+	/// Typed arrays count their elements, while `ArrayBuffer` and `DataView` count their bytes (as they become arrays of bytes).
+	/// Like [`Self::with_max_string_length()`], this is checked before results are copied out of V8, while constructing large
+	/// arrays inside the script is bounded by [`Self::with_max_heap_size()`].
+	///
+	/// Panics if `length` is zero.
+	pub fn with_max_array_length(mut self, length: usize) -> Self {
+		assert!(length > 0);
+
+		self.max_array_length = Some(length);
+		self
+	}
+
+	/// Attaches metadata about the script's origin, such as plugin id, version and author.
+	///
+	/// The provenance is included in errors produced while the script runs (as [`JsError::WithProvenance`]), in its audit events
+	/// (see [`Script::take_audit_log()`]) and as a prefix in every line of its console output, which simplifies diagnostics when
+	/// many plugins run side by side. Deno's console, enabled with web APIs, is not prefixed.
+	///
+	/// ```rust
+	/// use js_sandbox::{Provenance, Script, JsError};
+	///
+	/// let mut script = Script::builder()
+	/// 	.with_provenance(Provenance::new("geometry").with_version("1.2.0"))
+	/// 	.build_from_string("function area() { throw new Error('not implemented'); }")
+	/// 	.unwrap();
+	///
+	/// let err = script.call::<_, f64>("area", ()).unwrap_err();
+	/// assert_eq!(err.provenance().unwrap().id, "geometry");
+	/// assert!(matches!(err.inner(), JsError::Runtime(_)));
+	/// ```
+	pub fn with_provenance(mut self, provenance: Provenance) -> Self {
+		self.provenance = Some(provenance);
+		self
+	}
+
+	/// Makes a Rust object available to JavaScript as `host.<name>`.
+	///
+	/// The object is owned by the script. See [`HostObject`] for details.
+	///
+	/// Panics if an object or function with the same name has already been added.
+	pub fn with_host_object(mut self, name: &str, object: impl HostObject) -> Self {
+		self.assert_unused_host_name(name);
+
+		self.host_objects.push((name.to_string(), Box::new(object)));
+		self
+	}
+
+	/// Grants the script the capabilities named in `granted`, which it can obtain with `host.require(name)`.
+	///
+	/// Host objects are created for the granted capabilities only. See [`CapabilityRegistry`] for details.
+	///
+	/// Panics if capabilities have already been granted, if one of `granted` is not registered, or if a host object or function
+	/// named `require` has been added.
+	pub fn with_capabilities(mut self, registry: &CapabilityRegistry, granted: &[&str]) -> Self {
+		assert!(self.capabilities.is_none(), "capabilities granted twice");
+		self.assert_unused_host_name("require");
+
+		self.capabilities = Some(registry.instantiate(granted));
+		self
+	}
+
+	/// Makes an async Rust function available to JavaScript as `host.<name>`, which returns a promise.
+	///
+	/// `function` receives the arguments of the JS call as JSON values, and returns a future resolving to the JSON result (or to an
+	/// error, which rejects the promise). The future is driven by the script's event loop while a call is awaiting it:
+	///
+	/// ```rust
+	/// use js_sandbox::{Script, JsError, JsValue};
+	///
+	/// fn main() -> Result<(), JsError> {
+	/// 	let js_code = "async function describe(id) { const user = await host.lookup(id); return user.name; }";
+	/// 	let mut script = Script::builder()
+	/// 		.with_async_host_fn("lookup", |args| async move {
+	/// 			let id = args[0].as_u64().unwrap_or_default();
+	/// 			Ok(serde_json::json!({ "id": id, "name": format!("user{id}") }))
+	/// 		})
+	/// 		.build_from_string(js_code)?;
+	///
+	/// 	let name: String = script.call("describe", (7,))?;
+	/// 	assert_eq!(name, "user7");
+	/// 	Ok(())
+	/// }
+	/// ```
+	///
+	/// Futures are polled on the thread calling into the script, so they need not be `Send`. They must not depend on the reactor of
+	/// an async runtime (such as tokio's timers or sockets) unless the script is called from within that runtime.
+	///
+	/// Panics if an object or function with the same name has already been added.
+	pub fn with_async_host_fn<F, Fut>(mut self, name: &str, function: F) -> Self
+	where
+		F: Fn(Vec<JsValue>) -> Fut + 'static,
+		Fut: Future<Output = Result<JsValue, AnyError>> + 'static,
+	{
+		self.assert_unused_host_name(name);
+
+		let function: AsyncHostFn = Box::new(move |args| Box::pin(function(args)));
+		self.async_host_fns.push((name.to_string(), function));
+		self
+	}
+
+	/// Makes an async Rust closure with typed parameters available to JavaScript as `host.<name>`, which returns a promise.
+	///
+	/// This is a typed variant of [`Self::with_async_host_fn()`]: the JS arguments are deserialized into the tuple `Args` (use `(T,)`
+	/// for a single parameter), and the closure's result is serialized with serde. Calls with arguments that do not match reject
+	/// the promise without invoking the closure. This allows exposing I/O-bound host services without blocking the isolate's
+	/// thread while they do their work.
+	///
+	/// ```rust
+	/// use js_sandbox::{Script, JsError};
+	///
+	/// fn main() -> Result<(), JsError> {
+	/// 	let js_code = "async function total() { return await host.add(1, 2) + await host.add(3, 4); }";
+	/// 	let mut script = Script::builder()
+	/// 		.with_async_fn("add", |(a, b): (i32, i32)| async move { Ok(a + b) })
+	/// 		.build_from_string(js_code)?;
+	///
+	/// 	let result: i32 = script.call("total", ())?;
+	/// 	assert_eq!(result, 10);
+	/// 	Ok(())
+	/// }
+	/// ```
+	///
+	/// Panics if an object or function with the same name has already been added.
+	pub fn with_async_fn<Args, R, F, Fut>(self, name: &str, function: F) -> Self
+	where
+		Args: DeserializeOwned,
+		R: Serialize,
+		F: Fn(Args) -> Fut + 'static,
+		Fut: Future<Output = Result<R, AnyError>> + 'static,
+	{
+		let function = Rc::new(function);
+		self.with_async_host_fn(name, move |args| {
+			let function = function.clone();
+			async move {
+				let args: Args = serde_json::from_value(JsValue::Array(args))?;
+				let result = function(args).await?;
+				Ok(serde_json::to_value(result)?)
+			}
+		})
+	}
+
+	/// Makes `context` available to every call as the read-only global `context`.
+	///
+	/// Use this for data that stays the same for the lifetime of a script, such as plugin metadata, feature flags or configuration,
+	/// instead of passing it as an argument to every function. The value is converted to JSON once, when the script is built (which
+	/// fails with [`JsError::Json`] if that is not possible), and deeply frozen inside JS.
+	///
+	/// ```rust
+	/// use js_sandbox::{Script, JsError};
+	///
+	/// fn main() -> Result<(), JsError> {
+	/// 	let config = serde_json::json!({ "plugin": "greeter", "locale": "en" });
+	/// 	let mut script = Script::builder()
+	/// 		.with_context(&config)
+	/// 		.build_from_string("function locale() { return context.locale; }")?;
+	///
+	/// 	let locale: String = script.call("locale", ())?;
+	/// 	assert_eq!(locale, "en");
+	/// 	Ok(())
+	/// }
+	/// ```
+	///
+	/// Panics if a context has already been set.
+	pub fn with_context(mut self, context: &impl Serialize) -> Self {
+		assert!(self.context.is_none(), "context set twice");
+
+		self.context = Some(serde_json::to_value(context));
+		self
+	}
+
+	/// Provides the `env` global with the given variables, similar to `process.env` in Node.js.
+	///
+	/// See [`ScriptEnv`] for details.
+	pub fn with_env(mut self, env: ScriptEnv) -> Self {
+		self.env = Some(env);
+		self
+	}
+
+	/// Provides the `storage` global, backed by `storage`, for persistent state in the style of `localStorage`.
+	///
+	/// Writes which would make the total size of all keys and values exceed `max_size` bytes fail with an exception in JS. See
+	/// [`ScriptStorage`] for details.
+	///
+	/// Panics if a storage has already been set.
+	pub fn with_storage(mut self, storage: impl ScriptStorage, max_size: usize) -> Self {
+		assert!(self.storage.is_none(), "storage set twice");
+
+		self.storage = Some(QuotaStorage {
+			storage: Box::new(storage),
+			max_size,
+		});
+		self
+	}
+
+	/// Lets scripts stream output to `sink`, by calling `host.write(chunk)` with strings (written as UTF-8) or typed arrays.
+	///
+	/// Scripts that generate large reports can write them piece by piece, instead of building a single huge return value. Chunks
+	/// are passed on immediately; wrap the sink in a [`BufWriter`](std::io::BufWriter) to reduce the number of writes. I/O errors
+	/// are thrown as exceptions in JS. The sink is owned by the script and dropped along with it.
+	///
+	/// Panics if a sink has already been set, or if a host object or function named `write` has been added.
+	pub fn with_output_sink(mut self, sink: impl Write + 'static) -> Self {
+		assert!(self.output_sink.is_none(), "output sink set twice");
+		self.assert_unused_host_name("write");
+
+		self.output_sink = Some(Box::new(sink));
+		self
+	}
+
+	/// Lets scripts query `database` through `host.db.query(sql, params)`, returning an array of rows.
+	///
+	/// The database is exposed as the host object `db`. See [`SqlDatabase`](crate::SqlDatabase) for details.
+	///
+	/// Panics if a host object or function named `db` has already been added.
+	#[cfg(feature = "sql")]
+	pub fn with_database(self, database: impl crate::SqlDatabase) -> Self {
+		self.with_host_object("db", crate::sql::DatabaseObject(Box::new(database)))
+	}
+
+	/// Starts the script from a snapshot created with [`snapshot::Builder`](crate::snapshot::Builder).
+	///
+	/// The functions and state of the snapshot's sources are available right away, without parsing or evaluating them again. The
+	/// code passed to `build_from_*()` runs on top of that, and may be empty.
+	///
+	/// Snapshots cannot be combined with [`Self::with_ops()`], [`Self::with_extensions()`] or web APIs; building the script fails
+	/// in that case.
+	pub fn with_snapshot(mut self, snapshot: &'static [u8]) -> Self {
+		self.snapshot = Some(snapshot);
+		self
+	}
+
+	/// Registers additional Deno ops, which scripts can invoke to call into Rust.
+	///
+	/// This is a low-level extension point for capabilities not covered by [`HostObject`]. Ops are declared with the `#[op]` attribute
+	/// of `deno_core` (which must be a dependency of the same version as used by js-sandbox), and passed as `op_name::DECL`.
+	/// Inside JS, synchronous ops are available as `Deno.core.ops.op_name(...)`, and async ones via `Deno.core.opAsync("op_name", ...)`.
+	///
+	/// Unlike for host objects and functions, panics in these ops are not caught, and abort the process.
+	pub fn with_ops(mut self, ops: impl IntoIterator<Item = OpDecl>) -> Self {
+		self.ops.extend(ops);
+		self
+	}
+
+	/// Adds Deno extensions to the runtime, in addition to the one js-sandbox uses internally.
+	///
+	/// This allows plugging existing extension crates (or in-house ones) into the sandbox. Their ops and JS files are loaded before
+	/// the script's own code runs. Keep in mind that extensions may expose capabilities (file system, network, ...) that undermine
+	/// the sandbox; only add ones you trust.
+	pub fn with_extensions(mut self, extensions: impl IntoIterator<Item = Extension>) -> Self {
+		self.extensions.extend(extensions);
+		self
+	}
+
+	/// Makes all time inside the script come from the given clock: `Date`, `performance.now()` and timers.
+	///
+	/// Installs `setTimeout()`, `setInterval()` and their `clear*` counterparts, scheduled on this clock (replacing the ones from
+	/// [`with_web_apis()`](Self::with_web_apis), if enabled). With a [`VirtualClock`](crate::VirtualClock), time only moves when
+	/// the host advances it, which makes time-dependent code deterministic. See [`Clock`] for details.
+	pub fn with_clock(mut self, clock: impl Clock) -> Self {
+		self.clock = Some(Box::new(clock));
+		self
+	}
+
+	/// Provides common web APIs: `console`, `URL`, `URLSearchParams`, `TextEncoder`, `TextDecoder`, `atob`, `btoa` and timers
+	/// (`setTimeout`, `setInterval` and their `clear*` counterparts).
+	///
+	/// These are backed by Deno's standard extensions, configured without high-resolution time. No file system or network
+	/// access is granted. Pending timers are awaited like promises when calling a function.
+	///
+	/// Replaces the built-in `console` with Deno's full implementation.
+	#[cfg(feature = "web")]
+	pub fn with_web_apis(mut self) -> Self {
+		self.web_apis = true;
+		self
+	}
+
+	/// Determines which modules the script may import. By default, modules are loaded from the file system.
+	///
+	/// With [`ImportPolicy::Deny`], both static `import` declarations and dynamic `import()` fail, while the script itself can still be
+	/// loaded as a module (see [`Self::as_module()`]). Denied imports are recorded in the audit log, see [`Script::take_audit_log()`].
+	pub fn with_imports(mut self, policy: ImportPolicy) -> Self {
+		self.imports = policy;
+		self
+	}
+
+	/// Restricts which kinds of non-JavaScript modules the script may import. By default, all supported types are allowed.
+	///
+	/// Import attributes like `with { type: "json" }` expose the raw contents of loaded resources to the script. Types not in
+	/// `allowed` are rejected regardless of the [`ImportPolicy`], and recorded in the audit log (see [`Script::take_audit_log()`]).
+	/// Pass an empty slice to allow only JavaScript modules.
+	pub fn with_import_types(mut self, allowed: &[ImportType]) -> Self {
+		self.import_types = Some(allowed.to_vec());
+		self
+	}
+
+	/// Verifies the contents of every file and module before it is executed, e.g. by checking a signature or hash.
+	///
+	/// The `verifier` receives the location (file path or module URL) and the raw contents. If it returns an error, the file is not
+	/// executed: building the script fails, or the import throws in JS. This applies to files loaded with [`Self::build_from_file()`]
+	/// and to all imported modules, but not to code passed as string.
+	pub fn with_module_verifier<F>(mut self, verifier: F) -> Self
+	where
+		F: Fn(&str, &[u8]) -> Result<(), AnyError> + 'static,
+	{
+		self.module_verifier = Some(Rc::new(verifier));
+		self
+	}
+
+	/// Whether `eval()`, `new Function()` and similar APIs may compile code from strings. Allowed by default.
+	///
+	/// When disabled, these throw an `EvalError`. This does not affect code loaded by the host.
+	pub fn with_code_generation(mut self, allowed: bool) -> Self {
+		self.deny_code_generation = !allowed;
+		self
+	}
+
+	/// Whether the `WebAssembly` API is available. Available by default.
+	pub fn with_wasm(mut self, allowed: bool) -> Self {
+		self.deny_wasm = !allowed;
+		self
+	}
+
+	/// Whether timers (`setTimeout()`, `setInterval()` and their `clear*` counterparts) are available, if installed by
+	/// [`Self::with_clock()`] or [`Self::with_web_apis()`]. Available by default.
+	pub fn with_timers(mut self, allowed: bool) -> Self {
+		self.deny_timers = !allowed;
+		self
+	}
+
+	/// Freezes built-in objects such as `Object.prototype` and `Array.prototype` once the script's code has been evaluated.
+	///
+	/// This prevents calls from polluting prototypes and thereby altering the behavior of subsequent calls, while the script's
+	/// own initialization may still install polyfills. All standard constructors, their prototypes and everything reachable
+	/// from them are frozen; globals declared by the script remain writable.
+	///
+	/// Assignments that would shadow an inherited built-in property fail on frozen prototypes (the "override mistake"). For
+	/// `constructor`, `toString`, `valueOf`, `toLocaleString`, `toJSON`, `name` and `message` on the prototypes of `Object`,
+	/// `Function`, `Array` and the error types, such assignments still create an own property, so e.g. `error.name = "..."`
+	/// keeps working.
+	pub fn with_frozen_intrinsics(mut self) -> Self {
+		self.freeze_intrinsics = true;
+		self
+	}
+
+	/// Applies all settings of a [`Policy`], such as [`Policy::strict()`], replacing those configured before.
+	///
+	/// The policy's timeout is set on the script as if by [`Script::with_timeout()`], which then must not be called again.
+	/// Settings not covered by the policy can be combined freely, and individual ones can be overridden afterwards:
+	///
+	/// ```rust
+	/// use js_sandbox::{Policy, Script, JsError};
+	///
+	/// fn main() -> Result<(), JsError> {
+	/// 	let mut script = Script::builder()
+	/// 		.with_policy(Policy::standard())
+	/// 		.with_max_heap_size(32 * 1024 * 1024)
+	/// 		.build_from_string("function add(a, b) { return a + b; }")?;
+	///
+	/// 	let result: i32 = script.call("add", (1, 2))?;
+	/// 	assert_eq!(result, 3);
+	/// 	Ok(())
+	/// }
+	/// ```
+	pub fn with_policy(mut self, policy: Policy) -> Self {
+		self.imports = policy.imports;
+		self.deny_code_generation = !policy.code_generation;
+		self.deny_wasm = !policy.wasm;
+		self.deny_timers = !policy.timers;
+		self.freeze_intrinsics = policy.freeze_intrinsics;
+		self.timeout = policy.timeout;
+		self.max_heap_size = policy.max_heap_size;
+		self.max_stack_size = policy.max_stack_size;
+		self.max_source_size = policy.max_source_size;
+		self.max_nesting_depth = policy.max_nesting_depth;
+		self.max_microtasks = policy.max_microtasks;
+		self.max_event_loop_turns = policy.max_event_loop_turns;
+		self.max_string_length = policy.max_string_length;
+		self.max_array_length = policy.max_array_length;
+		self
+	}
+
+	/// Sets the file name under which the code appears in stack traces and errors.
+	///
+	/// Scripts loaded from a file use the file's name by default, scripts built from strings `sandboxed.js`. For modules, this is
+	/// also the URL path (below `file:///`) that relative imports are resolved against. The exception are modules loaded from a
+	/// file with [`ImportPolicy::FileSystem`] and without a custom name, which resolve imports against the file's actual location.
+	pub fn with_filename(mut self, filename: &str) -> Self {
+		self.filename = Some(filename.to_string());
+		self
+	}
+
+	/// Loads the code as an ES module instead of a classic script.
+	///
+	/// Modules may use `export`, `import` and top-level `await`. Their declarations are not globals, so functions must be exported
+	/// to be accessible from Rust; see [`Script::module_exports()`].
+	pub fn as_module(mut self) -> Self {
+		self.module = true;
+		self
+	}
+
+	/// Initialize the script with the given JavaScript source code.
+	///
+	/// See [`Script::from_string()`].
+	pub fn build_from_string(self, js_code: &str) -> Result<Script, JsError> {
+		Script::create_from_string(js_code, self)
+	}
+
+	/// Initialize the script by loading it from a .js file.
+	///
+	/// See [`Script::from_file()`].
+	pub fn build_from_file(self, file: impl AsRef<Path>) -> Result<Script, JsError> {
+		Script::create_from_file(file.as_ref(), None, self)
+	}
+
+	/// Initialize the script by loading it from a .js file, after checking its SHA-256 hash.
+	///
+	/// See [`Script::from_file_verified()`].
+	pub fn build_from_file_verified(
+		self,
+		file: impl AsRef<Path>,
+		expected_sha256: &str,
+	) -> Result<Script, JsError> {
+		Script::create_from_file(file.as_ref(), Some(expected_sha256), self)
+	}
+
+	fn assert_unused_host_name(&self, name: &str) {
+		assert!(
+			self.host_objects.iter().all(|(n, _)| n != name)
+				&& self.async_host_fns.iter().all(|(n, _)| n != name)
+				&& !(self.output_sink.is_some() && name == "write")
+				&& !(self.capabilities.is_some() && name == "require"),
+			"host object or function `{name}` added twice"
+		);
+	}
+}
@@ -1,7 +1,21 @@
 // Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
 
-use crate::AnyError;
+#[cfg(feature = "v8")]
+use std::{fmt, io};
+
+#[cfg(any(feature = "v8", feature = "quickjs"))]
+use serde::de::DeserializeOwned;
+#[cfg(feature = "v8")]
+use serde::ser::{self, Impossible, Serializer};
 use serde::Serialize;
+#[cfg(feature = "v8")]
+use serde_json::ser::{CharEscape, CompactFormatter, Formatter};
+
+#[cfg(any(feature = "v8", feature = "quickjs"))]
+use crate::lexer::is_identifier;
+use crate::AnyError;
+#[cfg(any(feature = "v8", feature = "quickjs"))]
+use crate::{JsError, JsValue};
 
 /// Sealing token
 mod private {
@@ -14,7 +28,124 @@ mod private {
 /// Use structs or arrays inside a one-element tuple if you need more flexibility.
 pub trait CallArgs: private::Sealed {
 	/// Convert the arguments into a JSON string
+	///
+	/// If an argument cannot be serialized, the error mentions its position, starting at `#1`.
 	fn into_arg_string(self) -> Result<String, AnyError>;
+
+	/// Convert the arguments into the representation passed to the script, see [`SerializedArgs`].
+	#[cfg(feature = "v8")]
+	#[doc(hidden)]
+	fn into_args(self) -> Result<SerializedArgs, AnyError>;
+}
+
+#[cfg(feature = "v8")]
+/// Strings of at least this many bytes are passed to V8 as they are, instead of being escaped into JSON and parsed again.
+const LARGE_STRING_LEN: usize = 4 * 1024;
+
+#[cfg(feature = "v8")]
+/// Arguments of a call, serialized for the script.
+///
+/// Large string arguments and byte arrays (serialized with `serialize_bytes()`, e.g. through `serde_bytes`) are detached from
+/// the JSON text. Strings are copied into V8 directly, which avoids escaping and parsing them, as well as holding them in
+/// memory twice. Byte arrays arrive in JS as `Uint8Array` instead of arrays of numbers.
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct SerializedArgs {
+	// Comma-separated JSON values, with `null` in place of detached values
+	json: String,
+	detached: Vec<DetachedArg>,
+}
+
+#[cfg(feature = "v8")]
+/// A value passed to the script outside of the JSON text.
+#[derive(Debug)]
+pub(crate) struct DetachedArg {
+	/// Position among the arguments, starting at 0.
+	pub position: u32,
+	/// Location inside the argument; empty if the argument itself is detached.
+	pub path: Vec<PathSegment>,
+	pub value: Detached,
+}
+
+#[cfg(feature = "v8")]
+#[derive(Clone, Debug)]
+pub(crate) enum PathSegment {
+	Key(String),
+	Index(u32),
+}
+
+#[cfg(feature = "v8")]
+#[derive(Debug)]
+pub(crate) enum Detached {
+	String(String),
+	Bytes(Vec<u8>),
+}
+
+#[cfg(feature = "v8")]
+impl Detached {
+	fn to_json(&self) -> JsValue {
+		match self {
+			Detached::String(string) => JsValue::from(string.as_str()),
+			Detached::Bytes(bytes) => JsValue::from(bytes.as_slice()),
+		}
+	}
+
+	fn len(&self) -> usize {
+		match self {
+			Detached::String(string) => string.len(),
+			Detached::Bytes(bytes) => bytes.len(),
+		}
+	}
+}
+
+#[cfg(feature = "v8")]
+impl SerializedArgs {
+	/// Arguments that are fully contained in comma-separated JSON values.
+	pub(crate) fn from_json(json: String) -> Self {
+		Self {
+			json,
+			detached: Vec::new(),
+		}
+	}
+
+	/// Arguments given as individual JSON values.
+	pub(crate) fn from_values(values: &[JsValue]) -> Self {
+		let json: Vec<String> = values.iter().map(JsValue::to_string).collect();
+		Self::from_json(json.join(","))
+	}
+
+	/// Comma-separated JSON values, with `null` in place of the [`Self::detached()`] values.
+	pub(crate) fn json(&self) -> &str {
+		&self.json
+	}
+
+	pub(crate) fn detached(&self) -> &[DetachedArg] {
+		&self.detached
+	}
+
+	/// Size of the arguments in bytes, for usage tracking.
+	pub(crate) fn size(&self) -> usize {
+		let detached: usize = self.detached.iter().map(|arg| arg.value.len()).sum();
+		self.json.len() + detached
+	}
+
+	/// All arguments as JSON array.
+	pub(crate) fn to_value(&self) -> Result<JsValue, serde_json::Error> {
+		let mut values: JsValue = serde_json::from_str(&format!("[{}]", self.json))?;
+		for arg in &self.detached {
+			let mut target = &mut values[arg.position as usize];
+			for segment in &arg.path {
+				target = match segment {
+					PathSegment::Key(key) => &mut target[key.as_str()],
+					PathSegment::Index(index) => &mut target[*index as usize],
+				};
+			}
+
+			*target = arg.value.to_json();
+		}
+
+		Ok(values)
+	}
 }
 
 impl private::Sealed for () {}
@@ -22,6 +153,11 @@ impl CallArgs for () {
 	fn into_arg_string(self) -> Result<String, AnyError> {
 		Ok(String::new())
 	}
+
+	#[cfg(feature = "v8")]
+	fn into_args(self) -> Result<SerializedArgs, AnyError> {
+		Ok(SerializedArgs::from_json(String::new()))
+	}
 }
 
 macro_rules! impl_call_args {
@@ -37,12 +173,45 @@ macro_rules! impl_call_args {
 				let ($($param),+,) = self;
 				let args = [
 					$(
-						serde_json::to_value($param)?.to_string()
+						serde_json::to_value($param)
 					),+
 				];
 
+				let args = args
+					.into_iter()
+					.enumerate()
+					.map(|(i, arg)| match arg {
+						Ok(value) => Ok(value.to_string()),
+						Err(e) => Err(AnyError::msg(format!("arg #{}: {e}", i + 1))),
+					})
+					.collect::<Result<Vec<String>, AnyError>>()?;
+
 				Ok(args.join(","))
 			}
+
+			#[cfg(feature = "v8")]
+			fn into_args(self) -> Result<SerializedArgs, AnyError> {
+				let ($($param),+,) = self;
+
+				// Like into_arg_string(), but detaching large strings and byte arrays
+				let mut args = Vec::new();
+				let mut detached = Vec::new();
+				let mut position = 0;
+				$(
+					position += 1;
+					if position > 1 {
+						args.push(b',');
+					}
+
+					serialize_arg(&mut args, &mut detached, position, &$param)
+						.map_err(|e| AnyError::msg(format!("arg #{position}: {e}")))?;
+				)+
+
+				Ok(SerializedArgs {
+					json: String::from_utf8(args).expect("serde_json writes UTF-8"),
+					detached,
+				})
+			}
 		}
 	}
 }
@@ -52,3 +221,348 @@ impl_call_args!(P0, P1);
 impl_call_args!(P0, P1, P2);
 impl_call_args!(P0, P1, P2, P3);
 impl_call_args!(P0, P1, P2, P3, P4);
+
+#[cfg(feature = "v8")]
+/// Appends the argument at `position` (starting at 1) to the JSON text in `args`, detaching large strings and byte arrays.
+fn serialize_arg<T: Serialize>(
+	args: &mut Vec<u8>,
+	detached: &mut Vec<DetachedArg>,
+	position: u32,
+	value: &T,
+) -> Result<(), serde_json::Error> {
+	if let Ok(string) = value.serialize(LargeStringDetector) {
+		args.extend_from_slice(b"null");
+		detached.push(DetachedArg {
+			position: position - 1,
+			path: Vec::new(),
+			value: Detached::String(string),
+		});
+		return Ok(());
+	}
+
+	let formatter = BytesFormatter {
+		position: position - 1,
+		detached,
+		path: Vec::new(),
+		key: None,
+	};
+	value.serialize(&mut serde_json::Serializer::with_formatter(args, formatter))
+}
+
+#[cfg(any(feature = "v8", feature = "quickjs"))]
+/// Error for arguments of `fn_name` that could not be serialized, as returned by [`CallArgs::into_arg_string()`].
+pub(crate) fn args_error(fn_name: &str, error: AnyError) -> JsError {
+	json_error(format!("call(\"{fn_name}\") {error}"))
+}
+
+#[cfg(any(feature = "v8", feature = "quickjs"))]
+/// Checks that `fn_name` can be spliced into JS code as the function to call.
+///
+/// `fn_name` may be a dotted path such as `utils.math.add`, in which case the function is called as a method of its parent
+/// object. Fails unless every segment is an identifier, so arbitrary code cannot be injected through the name.
+pub(crate) fn check_fn_path(fn_name: &str) -> Result<(), JsError> {
+	if fn_name.split('.').all(is_identifier) {
+		Ok(())
+	} else {
+		Err(JsError::Runtime(AnyError::msg(format!(
+			"call(\"{fn_name}\"): not a valid function name or path"
+		))))
+	}
+}
+
+#[cfg(feature = "v8")]
+/// Converts the arguments of `fn_name` into one JSON value per argument.
+pub(crate) fn into_values<A: CallArgs>(
+	fn_name: &str,
+	args_tuple: A,
+) -> Result<Vec<JsValue>, JsError> {
+	let args = args_tuple.into_args().map_err(|e| args_error(fn_name, e))?;
+
+	match args.to_value()? {
+		JsValue::Array(values) => Ok(values),
+		_ => unreachable!("arguments are serialized as array"),
+	}
+}
+
+#[cfg(any(feature = "v8", feature = "quickjs"))]
+/// Deserializes the return value of a call.
+///
+/// With the `path-to-error` feature, errors name the location of the offending value within the result, such as
+/// `items[3].name: invalid type: integer `5`, expected a string`.
+pub(crate) fn from_result<R: DeserializeOwned>(value: JsValue) -> Result<R, serde_json::Error> {
+	#[cfg(feature = "path-to-error")]
+	{
+		serde_path_to_error::deserialize(value).map_err(|error| {
+			let path = error.path().to_string();
+			let error = error.into_inner();
+
+			// The path of the result itself is "."
+			match path.as_str() {
+				"." => error,
+				_ => serde::de::Error::custom(format!("{path}: {error}")),
+			}
+		})
+	}
+
+	#[cfg(not(feature = "path-to-error"))]
+	serde_json::from_value(value)
+}
+
+#[cfg(any(feature = "v8", feature = "quickjs"))]
+/// Error for a return value of `fn_name` that could not be deserialized.
+pub(crate) fn result_error(fn_name: &str, error: serde_json::Error) -> JsError {
+	json_error(format!("call(\"{fn_name}\") return value: {error}"))
+}
+
+#[cfg(feature = "v8")]
+/// Error for a return value of `fn_name` that could not be converted with [`FromJs`](crate::FromJs).
+pub(crate) fn conversion_error(fn_name: &str, error: AnyError) -> JsError {
+	json_error(format!("call(\"{fn_name}\") return value: {error}"))
+}
+
+#[cfg(any(feature = "v8", feature = "quickjs"))]
+fn json_error(message: String) -> JsError {
+	JsError::Json(<serde_json::Error as serde::de::Error>::custom(message))
+}
+
+#[cfg(feature = "v8")]
+/// Serializer that only accepts strings of at least [`LARGE_STRING_LEN`] bytes, and fails immediately for any other value.
+struct LargeStringDetector;
+
+#[cfg(feature = "v8")]
+#[derive(Debug)]
+struct NotLargeString;
+
+#[cfg(feature = "v8")]
+impl fmt::Display for NotLargeString {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str("not a large string")
+	}
+}
+
+#[cfg(feature = "v8")]
+impl std::error::Error for NotLargeString {}
+
+#[cfg(feature = "v8")]
+impl ser::Error for NotLargeString {
+	fn custom<T: fmt::Display>(_msg: T) -> Self {
+		NotLargeString
+	}
+}
+
+#[cfg(feature = "v8")]
+macro_rules! reject {
+	($($method:ident($($arg:ty),*) -> $ret:ty;)+) => {
+		$(
+			fn $method(self, $(_: $arg),*) -> Result<$ret, NotLargeString> {
+				Err(NotLargeString)
+			}
+		)+
+	};
+}
+
+#[cfg(feature = "v8")]
+impl Serializer for LargeStringDetector {
+	type Ok = String;
+	type Error = NotLargeString;
+	type SerializeSeq = Impossible<String, NotLargeString>;
+	type SerializeTuple = Impossible<String, NotLargeString>;
+	type SerializeTupleStruct = Impossible<String, NotLargeString>;
+	type SerializeTupleVariant = Impossible<String, NotLargeString>;
+	type SerializeMap = Impossible<String, NotLargeString>;
+	type SerializeStruct = Impossible<String, NotLargeString>;
+	type SerializeStructVariant = Impossible<String, NotLargeString>;
+
+	fn serialize_str(self, v: &str) -> Result<String, NotLargeString> {
+		if v.len() >= LARGE_STRING_LEN {
+			Ok(v.to_string())
+		} else {
+			Err(NotLargeString)
+		}
+	}
+
+	fn serialize_newtype_struct<T: ?Sized + Serialize>(
+		self,
+		_name: &'static str,
+		value: &T,
+	) -> Result<String, NotLargeString> {
+		value.serialize(self)
+	}
+
+	fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<String, NotLargeString> {
+		value.serialize(self)
+	}
+
+	reject! {
+		serialize_bool(bool) -> String;
+		serialize_i8(i8) -> String;
+		serialize_i16(i16) -> String;
+		serialize_i32(i32) -> String;
+		serialize_i64(i64) -> String;
+		serialize_u8(u8) -> String;
+		serialize_u16(u16) -> String;
+		serialize_u32(u32) -> String;
+		serialize_u64(u64) -> String;
+		serialize_f32(f32) -> String;
+		serialize_f64(f64) -> String;
+		serialize_char(char) -> String;
+		serialize_bytes(&[u8]) -> String;
+		serialize_none() -> String;
+		serialize_unit() -> String;
+		serialize_unit_struct(&'static str) -> String;
+		serialize_unit_variant(&'static str, u32, &'static str) -> String;
+		serialize_seq(Option<usize>) -> Self::SerializeSeq;
+		serialize_tuple(usize) -> Self::SerializeTuple;
+		serialize_tuple_struct(&'static str, usize) -> Self::SerializeTupleStruct;
+		serialize_tuple_variant(&'static str, u32, &'static str, usize) -> Self::SerializeTupleVariant;
+		serialize_map(Option<usize>) -> Self::SerializeMap;
+		serialize_struct(&'static str, usize) -> Self::SerializeStruct;
+		serialize_struct_variant(&'static str, u32, &'static str, usize) -> Self::SerializeStructVariant;
+	}
+
+	fn serialize_newtype_variant<T: ?Sized + Serialize>(
+		self,
+		_name: &'static str,
+		_variant_index: u32,
+		_variant: &'static str,
+		_value: &T,
+	) -> Result<String, NotLargeString> {
+		Err(NotLargeString)
+	}
+}
+
+#[cfg(feature = "v8")]
+/// JSON formatter that writes `null` in place of byte arrays, and detaches them together with their location.
+///
+/// The location is tracked through the formatter calls: the current index for arrays, and the key for objects.
+struct BytesFormatter<'a> {
+	position: u32,
+	detached: &'a mut Vec<DetachedArg>,
+	path: Vec<PathSegment>,
+	// Set while an object key is being written
+	key: Option<String>,
+}
+
+#[cfg(feature = "v8")]
+macro_rules! capture_key {
+	($($method:ident($ty:ty);)+) => {
+		$(
+			fn $method<W: ?Sized + io::Write>(&mut self, writer: &mut W, value: $ty) -> io::Result<()> {
+				if let Some(key) = &mut self.key {
+					key.push_str(&value.to_string());
+				}
+				CompactFormatter.$method(writer, value)
+			}
+		)+
+	};
+}
+
+#[cfg(feature = "v8")]
+impl Formatter for BytesFormatter<'_> {
+	fn write_byte_array<W: ?Sized + io::Write>(
+		&mut self,
+		writer: &mut W,
+		value: &[u8],
+	) -> io::Result<()> {
+		self.detached.push(DetachedArg {
+			position: self.position,
+			path: self.path.clone(),
+			value: Detached::Bytes(value.to_vec()),
+		});
+		CompactFormatter.write_null(writer)
+	}
+
+	fn begin_array<W: ?Sized + io::Write>(&mut self, writer: &mut W) -> io::Result<()> {
+		self.path.push(PathSegment::Index(0));
+		CompactFormatter.begin_array(writer)
+	}
+
+	fn begin_array_value<W: ?Sized + io::Write>(
+		&mut self,
+		writer: &mut W,
+		first: bool,
+	) -> io::Result<()> {
+		if let (false, Some(PathSegment::Index(index))) = (first, self.path.last_mut()) {
+			*index += 1;
+		}
+		CompactFormatter.begin_array_value(writer, first)
+	}
+
+	fn end_array<W: ?Sized + io::Write>(&mut self, writer: &mut W) -> io::Result<()> {
+		self.path.pop();
+		CompactFormatter.end_array(writer)
+	}
+
+	fn begin_object<W: ?Sized + io::Write>(&mut self, writer: &mut W) -> io::Result<()> {
+		self.path.push(PathSegment::Key(String::new()));
+		CompactFormatter.begin_object(writer)
+	}
+
+	fn begin_object_key<W: ?Sized + io::Write>(
+		&mut self,
+		writer: &mut W,
+		first: bool,
+	) -> io::Result<()> {
+		self.key = Some(String::new());
+		CompactFormatter.begin_object_key(writer, first)
+	}
+
+	fn end_object_key<W: ?Sized + io::Write>(&mut self, writer: &mut W) -> io::Result<()> {
+		if let (Some(key), Some(segment)) = (self.key.take(), self.path.last_mut()) {
+			*segment = PathSegment::Key(key);
+		}
+		CompactFormatter.end_object_key(writer)
+	}
+
+	fn end_object<W: ?Sized + io::Write>(&mut self, writer: &mut W) -> io::Result<()> {
+		self.path.pop();
+		CompactFormatter.end_object(writer)
+	}
+
+	fn write_string_fragment<W: ?Sized + io::Write>(
+		&mut self,
+		writer: &mut W,
+		fragment: &str,
+	) -> io::Result<()> {
+		if let Some(key) = &mut self.key {
+			key.push_str(fragment);
+		}
+		CompactFormatter.write_string_fragment(writer, fragment)
+	}
+
+	fn write_char_escape<W: ?Sized + io::Write>(
+		&mut self,
+		writer: &mut W,
+		char_escape: CharEscape,
+	) -> io::Result<()> {
+		if let Some(key) = &mut self.key {
+			key.push(match char_escape {
+				CharEscape::Quote => '"',
+				CharEscape::ReverseSolidus => '\\',
+				CharEscape::Solidus => '/',
+				CharEscape::Backspace => '\u{8}',
+				CharEscape::FormFeed => '\u{c}',
+				CharEscape::LineFeed => '\n',
+				CharEscape::CarriageReturn => '\r',
+				CharEscape::Tab => '\t',
+				CharEscape::AsciiControl(byte) => char::from(byte),
+			});
+		}
+		CompactFormatter.write_char_escape(writer, char_escape)
+	}
+
+	// Non-string keys, such as integers, are written as numbers inside the key's quotes
+	capture_key! {
+		write_bool(bool);
+		write_i8(i8);
+		write_i16(i16);
+		write_i32(i32);
+		write_i64(i64);
+		write_i128(i128);
+		write_u8(u8);
+		write_u16(u16);
+		write_u32(u32);
+		write_u64(u64);
+		write_u128(u128);
+	}
+}
@@ -0,0 +1,249 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use deno_core::{op, OpState};
+use serde::Serialize;
+
+use crate::console::ConsoleOutput;
+use crate::{JsError, JsValue};
+
+/// Settings for a single function call, passed to [`Script::call_with_options()`](crate::Script::call_with_options).
+///
+/// Unlike settings on the [`Script`](crate::Script) or [`ScriptBuilder`](crate::ScriptBuilder), these only apply to the call they
+/// are passed to. Settings that are not specified keep the script's behavior.
+///
+/// ```rust
+/// use std::time::Duration;
+/// use js_sandbox::{CallOptions, Script};
+///
+/// let mut script = Script::from_string("function roll() { return Math.floor(Math.random() * 6) + 1; }").unwrap();
+/// let options = CallOptions::new()
+/// 	.with_timeout(Duration::from_millis(100))
+/// 	.with_seed(42);
+///
+/// let first: u32 = script.call_with_options("roll", (), &options).unwrap();
+/// let second: u32 = script.call_with_options("roll", (), &options).unwrap();
+/// assert_eq!(first, second);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CallOptions {
+	pub(crate) timeout: Option<Duration>,
+	pub(crate) max_result_size: Option<usize>,
+	pub(crate) capture_console: bool,
+	pub(crate) seed: Option<u64>,
+	pub(crate) retries: u32,
+	pub(crate) backoff: Duration,
+	pub(crate) retry_on: Vec<RetryOn>,
+	pub(crate) call_context: Option<Result<JsValue, Arc<serde_json::Error>>>,
+}
+
+/// Kind of failure after which a call is retried, see [`CallOptions::with_retries()`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RetryOn {
+	/// The call exceeded its timeout.
+	Timeout,
+
+	/// The call was forcibly stopped by a time-slice budget or preemption callback.
+	Termination,
+
+	/// Any error, including exceptions thrown by the function and invalid results.
+	Error,
+}
+
+impl RetryOn {
+	fn matches(self, error: &JsError) -> bool {
+		let error = error.inner();
+		match self {
+			RetryOn::Timeout => matches!(error, JsError::Timeout { .. }),
+			RetryOn::Termination => {
+				matches!(
+					error,
+					JsError::Preempted { .. } | JsError::BudgetExhausted { .. }
+				)
+			}
+			// Scripts which ran out of heap should be recreated, not called again
+			RetryOn::Error => !matches!(error, JsError::MemoryLimit { .. }),
+		}
+	}
+}
+
+impl CallOptions {
+	/// Creates options that keep the script's behavior.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Aborts the call after the specified duration, replacing the timeout set with
+	/// [`Script::with_timeout()`](crate::Script::with_timeout) (if any).
+	///
+	/// Panics if `timeout` is zero.
+	pub fn with_timeout(mut self, timeout: Duration) -> Self {
+		assert!(timeout > Duration::ZERO);
+
+		self.timeout = Some(timeout);
+		self
+	}
+
+	/// Limits the size (in bytes) of the result in JSON representation. Larger results fail the call.
+	///
+	/// Panics if `size_bytes` is zero.
+	pub fn with_max_result_size(mut self, size_bytes: usize) -> Self {
+		assert!(size_bytes > 0);
+
+		self.max_result_size = Some(size_bytes);
+		self
+	}
+
+	/// Captures console output during the call, instead of printing it to stdout/stderr.
+	///
+	/// Captured lines are collected by the script until retrieved with
+	/// [`Script::take_console_output()`](crate::Script::take_console_output).
+	pub fn with_console_capture(mut self, capture: bool) -> Self {
+		self.capture_console = capture;
+		self
+	}
+
+	/// Makes `Math.random()` deterministic during the call, producing the same sequence for the same seed.
+	///
+	/// The generator is not cryptographically secure, and is restarted with every call.
+	pub fn with_seed(mut self, seed: u64) -> Self {
+		self.seed = Some(seed);
+		self
+	}
+
+	/// Makes `context` available as the read-only global `callContext` during this call, e.g. a request ID or user information.
+	///
+	/// This lets hosts pass data along with a call without adding a parameter to every JS function. Outside of calls with a context,
+	/// `callContext` is `null`. Each access returns a fresh copy of the value, so modifications inside JS have no effect.
+	///
+	/// If `context` cannot be converted to JSON, calls with these options fail with [`JsError::Json`] before running.
+	pub fn with_call_context(mut self, context: &impl Serialize) -> Self {
+		self.call_context = Some(serde_json::to_value(context).map_err(Arc::new));
+		self
+	}
+
+	/// Fails if a setting could not be applied, see [`Self::with_call_context()`].
+	pub(crate) fn check(&self) -> Result<(), JsError> {
+		match &self.call_context {
+			Some(Err(e)) => Err(JsError::Json(serde::ser::Error::custom(e))),
+			_ => Ok(()),
+		}
+	}
+
+	/// Invokes the function again if it fails because of a timeout or termination, up to `retries` additional times.
+	///
+	/// Waits for `backoff` before the first retry, doubling the delay for every following one. Retries happen on the calling thread,
+	/// which is blocked while waiting. Hooks such as [`Script::on_result()`](crate::Script::on_result) see every attempt.
+	/// Use [`Self::with_retry_on()`] to retry other kinds of failures.
+	///
+	/// Keep in mind that failed attempts may have changed the script's state before they were aborted.
+	pub fn with_retries(mut self, retries: u32, backoff: Duration) -> Self {
+		self.retries = retries;
+		self.backoff = backoff;
+		if self.retry_on.is_empty() {
+			self.retry_on = vec![RetryOn::Timeout, RetryOn::Termination];
+		}
+		self
+	}
+
+	/// Replaces the kinds of failures that are retried, see [`Self::with_retries()`].
+	pub fn with_retry_on(mut self, retry_on: &[RetryOn]) -> Self {
+		self.retry_on = retry_on.to_vec();
+		self
+	}
+}
+
+/// Tracks the attempts of a single call, according to its retry settings.
+pub(crate) struct Retries<'a> {
+	options: &'a CallOptions,
+	attempt: u32,
+}
+
+impl<'a> Retries<'a> {
+	pub fn new(options: &'a CallOptions) -> Self {
+		Self {
+			options,
+			attempt: 0,
+		}
+	}
+
+	/// Returns whether a failed attempt is retried; if so, waits for the backoff first.
+	pub fn retry(&mut self, error: &JsError) -> bool {
+		let options = self.options;
+		if self.attempt >= options.retries || !options.retry_on.iter().any(|on| on.matches(error)) {
+			return false;
+		}
+
+		let backoff = options.backoff.saturating_mul(1 << self.attempt.min(31));
+		thread::sleep(backoff);
+
+		self.attempt += 1;
+		true
+	}
+}
+
+/// Applies the settings that are handled inside the runtime, until `end()` is called.
+pub(crate) fn begin(state: &Rc<RefCell<OpState>>, options: &CallOptions) {
+	let mut state = state.borrow_mut();
+	state.borrow_mut::<ConsoleOutput>().capturing = options.capture_console;
+
+	if let Some(seed) = options.seed {
+		state.put(SeededRandom(seed));
+	}
+	if let Some(Ok(context)) = &options.call_context {
+		state.put(CallContext(context.clone()));
+	}
+}
+
+/// Restores the script's behavior after a call.
+pub(crate) fn end(state: &Rc<RefCell<OpState>>) {
+	let mut state = state.borrow_mut();
+	state.borrow_mut::<ConsoleOutput>().capturing = false;
+	state.try_take::<SeededRandom>();
+	state.try_take::<CallContext>();
+}
+
+/// Routes `Math.random()` through the seeded generator while one is active, and defines the `callContext` global.
+pub(crate) const INSTALL_JS: &str = r#"
+Math.random = ((random) => () => Deno.core.ops.op_seeded_random() ?? random())(Math.random);
+Object.defineProperty(globalThis, "callContext", { get: () => Deno.core.ops.op_call_context() });
+"#;
+
+/// Context of the current call, see [`CallOptions::with_call_context()`].
+struct CallContext(JsValue);
+
+#[op]
+pub(crate) fn op_call_context(state: &mut OpState) -> Option<JsValue> {
+	state
+		.try_borrow::<CallContext>()
+		.map(|context| context.0.clone())
+}
+
+/// Random number generator of a call with a seed (SplitMix64).
+struct SeededRandom(u64);
+
+impl SeededRandom {
+	fn next_f64(&mut self) -> f64 {
+		self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+
+		let mut z = self.0;
+		z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+		z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+		z ^= z >> 31;
+
+		// Upper 53 bits, uniformly distributed in [0, 1)
+		(z >> 11) as f64 / (1u64 << 53) as f64
+	}
+}
+
+#[op]
+pub(crate) fn op_seeded_random(state: &mut OpState) -> Option<f64> {
+	state
+		.try_borrow_mut::<SeededRandom>()
+		.map(SeededRandom::next_f64)
+}
@@ -0,0 +1,120 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::HostObject;
+
+type Factory = Rc<dyn Fn() -> Box<dyn HostObject>>;
+
+/// Named capabilities that the host can grant to individual scripts.
+///
+/// Each capability (e.g. `"http"` or `"storage"`) is a [`HostObject`], created separately for every script it is granted to.
+/// Scripts obtain granted capabilities with `host.require(name)`; requiring any other capability throws an error with
+/// `name === "CapabilityError"`, which scripts can catch to degrade gracefully. Such requests are recorded in the script's
+/// audit log, see [`Script::take_audit_log()`](crate::Script::take_audit_log).
+///
+/// ```rust
+/// use js_sandbox::{js_host_object, CapabilityRegistry, Script, JsError};
+///
+/// struct Clock;
+///
+/// #[js_host_object]
+/// impl Clock {
+/// 	pub fn year(&self) -> i32 {
+/// 		2023
+/// 	}
+/// }
+///
+/// fn main() -> Result<(), JsError> {
+/// 	let registry = CapabilityRegistry::new()
+/// 		.with_capability("clock", || Clock)
+/// 		.with_capability("http", || Clock); // stand-in
+///
+/// 	let js_code = r#"function info() {
+/// 		let http;
+/// 		try { http = host.require("http"); } catch (e) { http = e.name; }
+/// 		return host.require("clock").year() + " " + http;
+/// 	}"#;
+///
+/// 	let mut script = Script::builder()
+/// 		.with_capabilities(&registry, &["clock"])
+/// 		.build_from_string(js_code)?;
+///
+/// 	let info: String = script.call("info", ())?;
+/// 	assert_eq!(info, "2023 CapabilityError");
+/// 	Ok(())
+/// }
+/// ```
+#[derive(Clone, Default)]
+pub struct CapabilityRegistry {
+	factories: HashMap<String, Factory>,
+}
+
+impl CapabilityRegistry {
+	/// Creates an empty registry.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Registers a capability, whose host object is created by `factory` for every script it is granted to.
+	///
+	/// Panics if a capability with the same name has already been registered.
+	pub fn with_capability<T, F>(mut self, name: &str, factory: F) -> Self
+	where
+		T: HostObject,
+		F: Fn() -> T + 'static,
+	{
+		assert!(
+			!self.factories.contains_key(name),
+			"capability `{name}` registered twice"
+		);
+
+		let factory: Factory = Rc::new(move || Box::new(factory()));
+		self.factories.insert(name.to_string(), factory);
+		self
+	}
+
+	/// Names of all registered capabilities, in unspecified order.
+	pub fn names(&self) -> impl Iterator<Item = &str> {
+		self.factories.keys().map(String::as_str)
+	}
+
+	/// Creates the host objects of the `granted` capabilities.
+	///
+	/// Panics if one of them has not been registered.
+	pub(crate) fn instantiate(&self, granted: &[&str]) -> Vec<(String, Box<dyn HostObject>)> {
+		granted
+			.iter()
+			.map(|name| {
+				let Some(factory) = self.factories.get(*name) else {
+					panic!("capability `{name}` is not registered");
+				};
+
+				(name.to_string(), factory())
+			})
+			.collect()
+	}
+}
+
+/// Key under which a capability's host object is stored, distinct from the names of regular host objects.
+pub(crate) fn object_key(name: &str) -> String {
+	format!("capability:{name}")
+}
+
+/// Returns JS code that defines `host.require()`, given the comma-separated `name: object` pairs of granted capabilities.
+pub(crate) fn install_code(granted: &str) -> String {
+	format!(
+		r#"host.require = ((granted) => function require(name) {{
+	if (Object.hasOwn(granted, name)) {{
+		return granted[name];
+	}}
+	const error = new Error(`capability "${{name}}" is not granted`);
+	error.name = "CapabilityError";
+	Error.captureStackTrace(error, require);
+	Deno.core.ops.op_audit_capability(String(name), error.stack);
+	throw error;
+}})(Object.freeze({{ {granted} }}));
+"#
+	)
+}
@@ -0,0 +1,219 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use deno_core::{op, v8, FastString, JsRuntime, OpState};
+
+use crate::invoke::{self, Completion};
+use crate::{panic_guard, AnyError, JsValue, Script};
+
+/// Source of all time observed inside a script.
+///
+/// A clock configured with [`ScriptBuilder::with_clock()`](crate::ScriptBuilder::with_clock) backs `Date.now()`, `new Date()`,
+/// `performance.now()` and the scheduling of `setTimeout()`/`setInterval()`. Timers fire once their due time has been reached on
+/// this clock; this is checked at the end of each call, after the function and all promises have completed. Tests can move time
+/// forward with [`Script::advance_time()`].
+pub trait Clock: 'static {
+	/// Current wall-clock time, as seen by `Date`.
+	fn now(&self) -> SystemTime;
+
+	/// Monotonic time since an arbitrary origin, as seen by `performance.now()` and timers.
+	///
+	/// Must never decrease.
+	fn elapsed(&self) -> Duration;
+}
+
+/// Clock following real time, used by default.
+///
+/// Monotonic time is coarsened to milliseconds, to mitigate timing attacks.
+#[derive(Clone, Debug)]
+pub struct SystemClock {
+	origin: Instant,
+}
+
+impl SystemClock {
+	/// Creates a clock whose monotonic time starts now.
+	pub fn new() -> Self {
+		Self {
+			origin: Instant::now(),
+		}
+	}
+}
+
+impl Default for SystemClock {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Clock for SystemClock {
+	fn now(&self) -> SystemTime {
+		SystemTime::now()
+	}
+
+	fn elapsed(&self) -> Duration {
+		Duration::from_millis(self.origin.elapsed().as_millis() as u64)
+	}
+}
+
+/// Clock under control of the host, for deterministic time inside scripts.
+///
+/// The clock starts at zero (and by default at the Unix epoch for `Date`), and only moves when [`advance()`](Self::advance) is
+/// called. Clones share the same time, so the host can keep a handle after passing the clock to
+/// [`ScriptBuilder::with_clock()`](crate::ScriptBuilder::with_clock).
+///
+/// ```rust
+/// use std::time::Duration;
+/// use js_sandbox::{Script, VirtualClock};
+///
+/// let clock = VirtualClock::new();
+/// let mut script = Script::builder()
+/// 	.with_clock(clock.clone())
+/// 	.build_from_string("function now() { return [performance.now(), Date.now()]; }")
+/// 	.unwrap();
+///
+/// clock.advance(Duration::from_millis(250));
+/// let now: (f64, f64) = script.call("now", ()).unwrap();
+/// assert_eq!(now, (250.0, 250.0));
+/// ```
+#[derive(Clone, Debug)]
+pub struct VirtualClock {
+	start: SystemTime,
+	elapsed: Arc<Mutex<Duration>>,
+}
+
+impl VirtualClock {
+	/// Creates a clock at the Unix epoch.
+	pub fn new() -> Self {
+		Self::starting_at(UNIX_EPOCH)
+	}
+
+	/// Creates a clock whose wall-clock time starts at `start`.
+	pub fn starting_at(start: SystemTime) -> Self {
+		Self {
+			start,
+			elapsed: Arc::default(),
+		}
+	}
+
+	/// Moves the clock forward by `duration`.
+	pub fn advance(&self, duration: Duration) {
+		*self.lock() += duration;
+	}
+
+	fn lock(&self) -> MutexGuard<'_, Duration> {
+		// A plain Duration cannot be left in an inconsistent state
+		self.elapsed.lock().unwrap_or_else(PoisonError::into_inner)
+	}
+}
+
+impl Default for VirtualClock {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Clock for VirtualClock {
+	fn now(&self) -> SystemTime {
+		self.start + self.elapsed()
+	}
+
+	/// Time that has passed on this clock, i.e. the sum of all advances.
+	fn elapsed(&self) -> Duration {
+		*self.lock()
+	}
+}
+
+/// Clock of a script, stored in the op state.
+pub(crate) struct ScriptClock(pub Box<dyn Clock>);
+
+/// Defines the `performance` global.
+pub(crate) const INSTALL_JS: &str =
+	"globalThis.performance = Object.freeze({ now: () => Deno.core.ops.op_performance_now() });";
+
+/// Controller of the script's timers, as returned by `js/clock.js`.
+///
+/// Only the host holds a reference to it, so scripts cannot fire timers or move time themselves.
+struct ClockController(v8::Global<v8::Object>);
+
+/// Routes `Date` and timers through the script's clock.
+pub(crate) fn install(runtime: &mut JsRuntime) -> Result<(), AnyError> {
+	let controller = runtime.execute_script(
+		Script::DEFAULT_FILENAME,
+		FastString::from_static(include_str!("js/clock.js")),
+	)?;
+
+	let controller = {
+		let scope = &mut runtime.handle_scope();
+		let controller = v8::Local::<v8::Object>::try_from(v8::Local::new(scope, controller))?;
+		v8::Global::new(scope, controller)
+	};
+
+	runtime
+		.op_state()
+		.borrow_mut()
+		.put(ClockController(controller));
+	Ok(())
+}
+
+/// Fires the next timer that has become due. Returns whether there was one, in which case the event loop needs to run again.
+pub(crate) fn run_next_timer(runtime: &mut JsRuntime) -> Result<bool, AnyError> {
+	call_controller(runtime, "runDue", None)
+}
+
+/// Shifts the time of the script forward by `millis`. Timers in between fire while running the event loop.
+pub(crate) fn advance(runtime: &mut JsRuntime, millis: f64) -> Result<Completion, AnyError> {
+	call_controller(runtime, "advance", Some(millis))?;
+	Ok(Completion::Value(JsValue::Null))
+}
+
+/// Invokes `method` of the controller, and returns whether its result is `true`.
+fn call_controller(
+	runtime: &mut JsRuntime,
+	method: &str,
+	arg: Option<f64>,
+) -> Result<bool, AnyError> {
+	let controller = runtime
+		.op_state()
+		.borrow()
+		.borrow::<ClockController>()
+		.0
+		.clone();
+
+	let scope = &mut runtime.handle_scope();
+	let scope = &mut v8::TryCatch::new(scope);
+	let controller = v8::Local::new(scope, controller);
+	let key = v8::String::new(scope, method).expect("method name is a valid V8 string");
+	let function = controller
+		.get(scope, key.into())
+		.and_then(|function| v8::Local::<v8::Function>::try_from(function).ok())
+		.expect("clock controller defines the method");
+
+	let args: Vec<v8::Local<v8::Value>> = arg
+		.into_iter()
+		.map(|arg| v8::Number::new(scope, arg).into())
+		.collect();
+	match function.call(scope, controller.into(), &args) {
+		Some(result) => Ok(result.is_true()),
+		None => Err(invoke::exception_error(scope)),
+	}
+}
+
+#[op]
+pub(crate) fn op_performance_now(state: &mut OpState) -> Result<f64, AnyError> {
+	let elapsed = panic_guard::catch(state, |state| Ok(state.borrow::<ScriptClock>().0.elapsed()))?;
+
+	Ok(elapsed.as_secs_f64() * 1000.0)
+}
+
+#[op]
+pub(crate) fn op_clock_now(state: &mut OpState) -> Result<f64, AnyError> {
+	let now = panic_guard::catch(state, |state| Ok(state.borrow::<ScriptClock>().0.now()))?;
+
+	// Like Date, represent times before the epoch as negative
+	match now.duration_since(UNIX_EPOCH) {
+		Ok(after) => Ok(after.as_millis() as f64),
+		Err(e) => Ok(-(e.duration().as_millis() as f64)),
+	}
+}
@@ -0,0 +1,58 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+#[cfg(feature = "v8")]
+use deno_core::{op, OpState};
+
+/// Source of the built-in console: `log`, `info`, `debug`, `warn`, `error`, `dir` and `table`, with depth-limited object formatting.
+const CONSOLE_JS: &str = include_str!("js/console.js");
+
+/// Returns JS code that defines the `console` global.
+///
+/// `print_fn` is a JS expression evaluating to a function `(line, isError) => void`, which outputs a single line (without trailing
+/// newline).
+pub(crate) fn install_code(print_fn: &str) -> String {
+	format!("{CONSOLE_JS}({print_fn});")
+}
+
+/// Destination of console output, stored in the op state.
+#[cfg(feature = "v8")]
+#[derive(Default)]
+pub(crate) struct ConsoleOutput {
+	/// Whether output is currently collected in `captured` rather than printed.
+	pub capturing: bool,
+	pub captured: Vec<String>,
+	/// Prepended to every line, identifying the script's provenance.
+	pub prefix: Option<String>,
+}
+
+/// Prints `text` (which includes line breaks) to stdout or stderr, unless console output is captured.
+#[cfg(feature = "v8")]
+#[op]
+pub(crate) fn op_console_print(state: &mut OpState, text: String, is_error: bool) {
+	let Some(output) = state.try_borrow_mut::<ConsoleOutput>() else {
+		return print_text(&text, is_error);
+	};
+
+	let lines = text.strip_suffix('\n').unwrap_or(&text).split('\n');
+	let prefix = output.prefix.as_deref().unwrap_or_default();
+
+	if output.capturing {
+		output
+			.captured
+			.extend(lines.map(|line| format!("{prefix}{line}")));
+	} else if output.prefix.is_some() {
+		let text: String = lines.map(|line| format!("{prefix}{line}\n")).collect();
+		print_text(&text, is_error);
+	} else {
+		print_text(&text, is_error);
+	}
+}
+
+/// Prints `text` to stderr for errors and warnings, and to stdout otherwise.
+pub(crate) fn print_text(text: &str, is_error: bool) {
+	if is_error {
+		eprint!("{text}");
+	} else {
+		print!("{text}");
+	}
+}
@@ -0,0 +1,19 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use crate::JsValue;
+
+/// Returns JS code that defines the read-only `context` global, deeply frozen.
+pub(crate) fn install_code(context: &JsValue) -> String {
+	format!(
+		r#"((value) => {{
+	const freeze = (v) => {{
+		if (typeof v === "object" && v !== null) {{
+			Object.values(v).forEach(freeze);
+			Object.freeze(v);
+		}}
+		return v;
+	}};
+	Object.defineProperty(globalThis, "context", {{ value: freeze(value), enumerable: true }});
+}})({context});"#
+	)
+}
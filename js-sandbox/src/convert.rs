@@ -0,0 +1,291 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use deno_core::{serde_v8, v8};
+
+use crate::{AnyError, JsValue};
+
+/// Types that can be converted directly into a V8 value, to be passed to [`Script::call_js()`](crate::Script::call_js).
+///
+/// Unlike [`Serialize`](serde::Serialize) types, which are converted to JSON text first, implementors create the V8 value
+/// themselves. This avoids intermediate representations for types with a natural JS counterpart, e.g. vectors that map to typed
+/// arrays or interned strings.
+///
+/// Implementations are provided for `bool`, `i32`, `u32`, `f64`, `String`, `&str`, [`JsValue`], as well as `Option<T>` (`None`
+/// becomes `null`) and `Vec<T>` (an array) of implementing types.
+pub trait ToJs {
+	/// Creates the V8 value representing `self`.
+	fn to_js<'s>(
+		&self,
+		scope: &mut v8::HandleScope<'s>,
+	) -> Result<v8::Local<'s, v8::Value>, AnyError>;
+}
+
+/// Types that can be created directly from a V8 value, to be returned from [`Script::call_js()`](crate::Script::call_js).
+///
+/// The counterpart of [`ToJs`]. Implementations are provided for the same types, and for `()`, which accepts any value.
+/// Conversions are strict: a JS number is not accepted as `String`, and only integral numbers in range are accepted as `i32`
+/// and `u32`.
+pub trait FromJs: Sized {
+	/// Converts `value` into `Self`, failing if it has the wrong type.
+	fn from_js(scope: &mut v8::HandleScope, value: v8::Local<v8::Value>) -> Result<Self, AnyError>;
+}
+
+/// Sealing token
+mod private {
+	pub trait Sealed {}
+}
+
+/// Arguments of [`Script::call_js()`](crate::Script::call_js): tuples of size 0..=5, whose elements implement [`ToJs`].
+pub trait ToJsArgs: private::Sealed {
+	/// Converts each element into a V8 value.
+	#[doc(hidden)]
+	fn to_js_args<'s>(
+		&self,
+		scope: &mut v8::HandleScope<'s>,
+	) -> Result<Vec<v8::Local<'s, v8::Value>>, AnyError>;
+}
+
+impl private::Sealed for () {}
+impl ToJsArgs for () {
+	fn to_js_args<'s>(
+		&self,
+		_scope: &mut v8::HandleScope<'s>,
+	) -> Result<Vec<v8::Local<'s, v8::Value>>, AnyError> {
+		Ok(Vec::new())
+	}
+}
+
+macro_rules! impl_to_js_args {
+	($($param:ident),+) => {
+		impl<$($param),+> private::Sealed for ($($param),+,) {}
+
+		#[allow(non_snake_case)] // use generic params as variable names
+		impl<$($param),+> ToJsArgs for ($($param),+,)
+			where $($param : ToJs),+
+		{
+			fn to_js_args<'s>(
+				&self,
+				scope: &mut v8::HandleScope<'s>,
+			) -> Result<Vec<v8::Local<'s, v8::Value>>, AnyError> {
+				let ($($param),+,) = self;
+
+				let mut args = Vec::new();
+				$(
+					let position = args.len() + 1;
+					let arg = $param
+						.to_js(scope)
+						.map_err(|e| AnyError::msg(format!("arg #{position}: {e}")))?;
+					args.push(arg);
+				)+
+
+				Ok(args)
+			}
+		}
+	}
+}
+
+impl_to_js_args!(P0);
+impl_to_js_args!(P0, P1);
+impl_to_js_args!(P0, P1, P2);
+impl_to_js_args!(P0, P1, P2, P3);
+impl_to_js_args!(P0, P1, P2, P3, P4);
+
+fn type_mismatch(
+	expected: &str,
+	scope: &mut v8::HandleScope,
+	value: v8::Local<v8::Value>,
+) -> AnyError {
+	let actual = value.type_of(scope).to_rust_string_lossy(scope);
+	AnyError::msg(format!("expected {expected}, found {actual}"))
+}
+
+impl ToJs for bool {
+	fn to_js<'s>(
+		&self,
+		scope: &mut v8::HandleScope<'s>,
+	) -> Result<v8::Local<'s, v8::Value>, AnyError> {
+		Ok(v8::Boolean::new(scope, *self).into())
+	}
+}
+
+impl FromJs for bool {
+	fn from_js(scope: &mut v8::HandleScope, value: v8::Local<v8::Value>) -> Result<Self, AnyError> {
+		if !value.is_boolean() {
+			return Err(type_mismatch("boolean", scope, value));
+		}
+		Ok(value.is_true())
+	}
+}
+
+impl ToJs for i32 {
+	fn to_js<'s>(
+		&self,
+		scope: &mut v8::HandleScope<'s>,
+	) -> Result<v8::Local<'s, v8::Value>, AnyError> {
+		Ok(v8::Integer::new(scope, *self).into())
+	}
+}
+
+impl FromJs for i32 {
+	fn from_js(scope: &mut v8::HandleScope, value: v8::Local<v8::Value>) -> Result<Self, AnyError> {
+		if value.is_int32() {
+			Ok(value.int32_value(scope).expect("value is an int32"))
+		} else {
+			Err(type_mismatch("i32", scope, value))
+		}
+	}
+}
+
+impl ToJs for u32 {
+	fn to_js<'s>(
+		&self,
+		scope: &mut v8::HandleScope<'s>,
+	) -> Result<v8::Local<'s, v8::Value>, AnyError> {
+		Ok(v8::Integer::new_from_unsigned(scope, *self).into())
+	}
+}
+
+impl FromJs for u32 {
+	fn from_js(scope: &mut v8::HandleScope, value: v8::Local<v8::Value>) -> Result<Self, AnyError> {
+		if value.is_uint32() {
+			Ok(value.uint32_value(scope).expect("value is an uint32"))
+		} else {
+			Err(type_mismatch("u32", scope, value))
+		}
+	}
+}
+
+impl ToJs for f64 {
+	fn to_js<'s>(
+		&self,
+		scope: &mut v8::HandleScope<'s>,
+	) -> Result<v8::Local<'s, v8::Value>, AnyError> {
+		Ok(v8::Number::new(scope, *self).into())
+	}
+}
+
+impl FromJs for f64 {
+	fn from_js(scope: &mut v8::HandleScope, value: v8::Local<v8::Value>) -> Result<Self, AnyError> {
+		if value.is_number() {
+			Ok(value.number_value(scope).expect("value is a number"))
+		} else {
+			Err(type_mismatch("number", scope, value))
+		}
+	}
+}
+
+impl ToJs for str {
+	fn to_js<'s>(
+		&self,
+		scope: &mut v8::HandleScope<'s>,
+	) -> Result<v8::Local<'s, v8::Value>, AnyError> {
+		v8::String::new(scope, self)
+			.map(Into::into)
+			.ok_or_else(|| AnyError::msg("string too long for V8"))
+	}
+}
+
+impl<T: ToJs + ?Sized> ToJs for &T {
+	fn to_js<'s>(
+		&self,
+		scope: &mut v8::HandleScope<'s>,
+	) -> Result<v8::Local<'s, v8::Value>, AnyError> {
+		(**self).to_js(scope)
+	}
+}
+
+impl ToJs for String {
+	fn to_js<'s>(
+		&self,
+		scope: &mut v8::HandleScope<'s>,
+	) -> Result<v8::Local<'s, v8::Value>, AnyError> {
+		self.as_str().to_js(scope)
+	}
+}
+
+impl FromJs for String {
+	fn from_js(scope: &mut v8::HandleScope, value: v8::Local<v8::Value>) -> Result<Self, AnyError> {
+		if value.is_string() {
+			Ok(value.to_rust_string_lossy(scope))
+		} else {
+			Err(type_mismatch("string", scope, value))
+		}
+	}
+}
+
+impl<T: ToJs> ToJs for Option<T> {
+	fn to_js<'s>(
+		&self,
+		scope: &mut v8::HandleScope<'s>,
+	) -> Result<v8::Local<'s, v8::Value>, AnyError> {
+		match self {
+			Some(value) => value.to_js(scope),
+			None => Ok(v8::null(scope).into()),
+		}
+	}
+}
+
+impl<T: FromJs> FromJs for Option<T> {
+	fn from_js(scope: &mut v8::HandleScope, value: v8::Local<v8::Value>) -> Result<Self, AnyError> {
+		if value.is_null_or_undefined() {
+			Ok(None)
+		} else {
+			T::from_js(scope, value).map(Some)
+		}
+	}
+}
+
+impl<T: ToJs> ToJs for Vec<T> {
+	fn to_js<'s>(
+		&self,
+		scope: &mut v8::HandleScope<'s>,
+	) -> Result<v8::Local<'s, v8::Value>, AnyError> {
+		let elements = self
+			.iter()
+			.map(|element| element.to_js(scope))
+			.collect::<Result<Vec<_>, _>>()?;
+
+		Ok(v8::Array::new_with_elements(scope, &elements).into())
+	}
+}
+
+impl<T: FromJs> FromJs for Vec<T> {
+	fn from_js(scope: &mut v8::HandleScope, value: v8::Local<v8::Value>) -> Result<Self, AnyError> {
+		let Ok(array) = v8::Local::<v8::Array>::try_from(value) else {
+			return Err(type_mismatch("array", scope, value));
+		};
+
+		(0..array.length())
+			.map(|i| {
+				let element = array
+					.get_index(scope, i)
+					.ok_or_else(|| AnyError::msg(format!("[{i}]: element cannot be read")))?;
+				T::from_js(scope, element).map_err(|e| AnyError::msg(format!("[{i}]: {e}")))
+			})
+			.collect()
+	}
+}
+
+impl ToJs for JsValue {
+	fn to_js<'s>(
+		&self,
+		scope: &mut v8::HandleScope<'s>,
+	) -> Result<v8::Local<'s, v8::Value>, AnyError> {
+		Ok(serde_v8::to_v8(scope, self)?)
+	}
+}
+
+impl FromJs for JsValue {
+	fn from_js(scope: &mut v8::HandleScope, value: v8::Local<v8::Value>) -> Result<Self, AnyError> {
+		Ok(serde_v8::from_v8(scope, value)?)
+	}
+}
+
+impl FromJs for () {
+	fn from_js(
+		_scope: &mut v8::HandleScope,
+		_value: v8::Local<v8::Value>,
+	) -> Result<Self, AnyError> {
+		Ok(())
+	}
+}
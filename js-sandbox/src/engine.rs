@@ -0,0 +1,232 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use deno_core::futures::future::{self, LocalBoxFuture};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::call_args::{self, SerializedArgs};
+use crate::{AnyError, CallArgs, CallOptions, JsError, JsValue, Script};
+
+/// Abstraction over the engine executing JS functions, as used by APIs generated with `#[js_api]`.
+///
+/// [`Script`] is the engine used in production. Implementing this trait for other types allows running the same generated API
+/// against e.g. a test double or an alternative backend:
+///
+/// ```rust
+/// use js_sandbox::{js_api, Script};
+///
+/// #[js_api]
+/// trait Greeter {
+/// 	fn greet(&mut self, name: &str) -> String;
+/// }
+///
+/// fn welcome<E: js_sandbox::ScriptEngine>(api: &mut Greeter<'_, E>) -> String {
+/// 	api.greet("world")
+/// }
+///
+/// let mut script = Script::from_string("function greet(name) { return `Hello ${name}`; }").unwrap();
+/// assert_eq!(welcome(&mut Greeter::new(&mut script)), "Hello world");
+/// ```
+///
+/// For unit tests of host code, [`MockScript`](crate::MockScript) answers calls with scripted responses, without starting V8.
+///
+/// The trait is object-safe: engines only implement the methods passing arguments and results as [`JsValue`], while the typed
+/// methods such as [`Self::call()`] are provided on top. This allows mixing engines in one collection:
+///
+/// ```rust
+/// use js_sandbox::{MockScript, Script, ScriptEngine};
+///
+/// let mut engines: Vec<Box<dyn ScriptEngine>> = vec![
+/// 	Box::new(Script::from_string("function version() { return 2; }").unwrap()),
+/// 	Box::new(MockScript::new().returning("version", 1)),
+/// ];
+///
+/// let versions: Vec<u32> = engines.iter_mut().map(|e| e.call("version", ()).unwrap()).collect();
+/// assert_eq!(versions, vec![2, 1]);
+/// ```
+pub trait ScriptEngine {
+	/// Invokes a JS function with one JSON value per argument, and returns its result as JSON.
+	fn call_values(&mut self, fn_name: &str, args: Vec<JsValue>) -> Result<JsValue, JsError>;
+
+	/// Invokes a JS function through a shared reference, like [`Self::call_values()`].
+	fn call_values_ref(&self, fn_name: &str, args: Vec<JsValue>) -> Result<JsValue, JsError>;
+
+	/// Invokes a JS function, returning a future which resolves to its result as JSON.
+	///
+	/// The default implementation runs [`Self::call_values()`] to completion, including any promise returned by the function,
+	/// and returns a ready future. Engines which can make progress concurrently may override it.
+	fn call_values_async(
+		&mut self,
+		fn_name: &str,
+		args: Vec<JsValue>,
+	) -> LocalBoxFuture<'_, Result<JsValue, JsError>> {
+		Box::pin(future::ready(self.call_values(fn_name, args)))
+	}
+
+	/// Whether `fn_name` refers to a function, see [`Script::has_function()`].
+	fn has_function(&self, fn_name: &str) -> Result<bool, JsError>;
+
+	/// Invokes a JS function, see [`Script::call()`].
+	fn call<A, R>(&mut self, fn_name: &str, args_tuple: A) -> Result<R, JsError>
+	where
+		A: CallArgs,
+		R: DeserializeOwned,
+		Self: Sized,
+	{
+		let args = call_args::into_values(fn_name, args_tuple)?;
+		let result = self.call_values(fn_name, args)?;
+		call_args::from_result(result).map_err(|e| call_args::result_error(fn_name, e))
+	}
+
+	/// Invokes a JS function through a shared reference, see [`Script::call_ref()`].
+	fn call_ref<A, R>(&self, fn_name: &str, args_tuple: A) -> Result<R, JsError>
+	where
+		A: CallArgs,
+		R: DeserializeOwned,
+		Self: Sized,
+	{
+		let args = call_args::into_values(fn_name, args_tuple)?;
+		let result = self.call_values_ref(fn_name, args)?;
+		call_args::from_result(result).map_err(|e| call_args::result_error(fn_name, e))
+	}
+
+	/// Invokes a JS function, returning a future which resolves to its result. See [`Self::call_values_async()`].
+	fn call_async<A, R>(
+		&mut self,
+		fn_name: &str,
+		args_tuple: A,
+	) -> LocalBoxFuture<'_, Result<R, JsError>>
+	where
+		A: CallArgs,
+		R: DeserializeOwned + 'static,
+		Self: Sized,
+	{
+		let args = match call_args::into_values(fn_name, args_tuple) {
+			Ok(args) => args,
+			Err(e) => return Box::pin(future::ready(Err(e))),
+		};
+
+		let fn_name = fn_name.to_string();
+		let result = self.call_values_async(&fn_name, args);
+		Box::pin(async move {
+			call_args::from_result(result.await?).map_err(|e| call_args::result_error(&fn_name, e))
+		})
+	}
+}
+
+impl ScriptEngine for Script {
+	fn call_values(&mut self, fn_name: &str, args: Vec<JsValue>) -> Result<JsValue, JsError> {
+		self.call_values_ref(fn_name, args)
+	}
+
+	fn call_values_ref(&self, fn_name: &str, args: Vec<JsValue>) -> Result<JsValue, JsError> {
+		let args = SerializedArgs::from_values(&args);
+		self.call_impl(fn_name, &args, &CallOptions::default())
+	}
+
+	fn has_function(&self, fn_name: &str) -> Result<bool, JsError> {
+		Script::has_function(self, fn_name)
+	}
+
+	// Typed calls skip the intermediate JSON values
+
+	fn call<A, R>(&mut self, fn_name: &str, args_tuple: A) -> Result<R, JsError>
+	where
+		A: CallArgs,
+		R: DeserializeOwned,
+	{
+		Script::call(self, fn_name, args_tuple)
+	}
+
+	fn call_ref<A, R>(&self, fn_name: &str, args_tuple: A) -> Result<R, JsError>
+	where
+		A: CallArgs,
+		R: DeserializeOwned,
+	{
+		Script::call_ref(self, fn_name, args_tuple)
+	}
+
+	fn call_async<A, R>(
+		&mut self,
+		fn_name: &str,
+		args_tuple: A,
+	) -> LocalBoxFuture<'_, Result<R, JsError>>
+	where
+		A: CallArgs,
+		R: DeserializeOwned + 'static,
+	{
+		Box::pin(future::ready(Script::call(self, fn_name, args_tuple)))
+	}
+}
+
+impl<E: ScriptEngine + ?Sized> ScriptEngine for Box<E> {
+	fn call_values(&mut self, fn_name: &str, args: Vec<JsValue>) -> Result<JsValue, JsError> {
+		(**self).call_values(fn_name, args)
+	}
+
+	fn call_values_ref(&self, fn_name: &str, args: Vec<JsValue>) -> Result<JsValue, JsError> {
+		(**self).call_values_ref(fn_name, args)
+	}
+
+	fn call_values_async(
+		&mut self,
+		fn_name: &str,
+		args: Vec<JsValue>,
+	) -> LocalBoxFuture<'_, Result<JsValue, JsError>> {
+		(**self).call_values_async(fn_name, args)
+	}
+
+	fn has_function(&self, fn_name: &str) -> Result<bool, JsError> {
+		(**self).has_function(fn_name)
+	}
+}
+
+// Calls with more arguments than CallArgs tuples support, as generated by #[js_api]
+
+/// Serializes a single argument for [`call_spread()`].
+pub fn arg_value<T: Serialize + ?Sized>(value: &T) -> Result<JsValue, serde_json::Error> {
+	serde_json::to_value(value)
+}
+
+/// Invokes `fn_name` with each element of `args` as a distinct argument.
+pub fn call_spread<E, R>(
+	engine: &mut E,
+	fn_name: &str,
+	args: Vec<Result<JsValue, serde_json::Error>>,
+) -> Result<R, JsError>
+where
+	E: ScriptEngine,
+	R: DeserializeOwned,
+{
+	let result = engine.call_values(fn_name, spread_args(fn_name, args)?)?;
+	call_args::from_result(result).map_err(|e| call_args::result_error(fn_name, e))
+}
+
+/// Like [`call_spread()`], through a shared reference.
+pub fn call_spread_ref<E, R>(
+	engine: &E,
+	fn_name: &str,
+	args: Vec<Result<JsValue, serde_json::Error>>,
+) -> Result<R, JsError>
+where
+	E: ScriptEngine,
+	R: DeserializeOwned,
+{
+	let result = engine.call_values_ref(fn_name, spread_args(fn_name, args)?)?;
+	call_args::from_result(result).map_err(|e| call_args::result_error(fn_name, e))
+}
+
+fn spread_args(
+	fn_name: &str,
+	args: Vec<Result<JsValue, serde_json::Error>>,
+) -> Result<Vec<JsValue>, JsError> {
+	args.into_iter()
+		.enumerate()
+		.map(|(i, arg)| {
+			arg.map_err(|e| {
+				let position = i + 1;
+				call_args::args_error(fn_name, AnyError::msg(format!("arg #{position}: {e}")))
+			})
+		})
+		.collect()
+}
@@ -0,0 +1,166 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::collections::HashMap;
+
+use deno_core::{op, OpState};
+
+use crate::AnyError;
+
+/// Environment variables of a script, available as the `env` global.
+///
+/// Scripts ported from Node.js often read their configuration from `process.env`. Passing a `ScriptEnv` to
+/// [`ScriptBuilder::with_env()`](crate::ScriptBuilder::with_env) provides the same through an `env` object, without exposing the
+/// host's actual environment:
+///
+/// ```rust
+/// use js_sandbox::{Script, ScriptEnv, JsError};
+///
+/// fn main() -> Result<(), JsError> {
+/// 	let env = ScriptEnv::new([("API_URL", "https://example.com")]);
+/// 	let mut script = Script::builder()
+/// 		.with_env(env)
+/// 		.build_from_string("function url() { return env.API_URL; }")?;
+///
+/// 	let url: String = script.call("url", ())?;
+/// 	assert_eq!(url, "https://example.com");
+/// 	Ok(())
+/// }
+/// ```
+///
+/// Variables are read-only by default; assigning or deleting them throws an error. With [`Self::writable()`], scripts can change
+/// them within quotas, and the host can read the result back with [`Script::env()`](crate::Script::env). Like in Node.js, assigned
+/// values are converted to strings.
+#[derive(Clone, Debug, Default)]
+pub struct ScriptEnv {
+	vars: HashMap<String, String>,
+	writable: bool,
+	max_vars: usize,
+	max_size: usize,
+}
+
+impl ScriptEnv {
+	/// Creates read-only variables from name/value pairs.
+	pub fn new<K, V>(vars: impl IntoIterator<Item = (K, V)>) -> Self
+	where
+		K: Into<String>,
+		V: Into<String>,
+	{
+		Self {
+			vars: vars
+				.into_iter()
+				.map(|(name, value)| (name.into(), value.into()))
+				.collect(),
+			..Self::default()
+		}
+	}
+
+	/// Lets scripts set and delete variables, as long as there are at most `max_vars` of them, taking up at most `max_size` bytes
+	/// (sum of the lengths of all names and values).
+	///
+	/// The quotas only restrict changes by the script; initial variables may exceed them.
+	pub fn writable(mut self, max_vars: usize, max_size: usize) -> Self {
+		self.writable = true;
+		self.max_vars = max_vars;
+		self.max_size = max_size;
+		self
+	}
+
+	pub(crate) fn vars(&self) -> &HashMap<String, String> {
+		&self.vars
+	}
+
+	fn check_writable(&self) -> Result<(), AnyError> {
+		if self.writable {
+			Ok(())
+		} else {
+			Err(AnyError::msg("env is read-only"))
+		}
+	}
+
+	fn set(&mut self, name: String, value: String) -> Result<(), AnyError> {
+		self.check_writable()?;
+
+		let previous = self.vars.get(&name);
+		let added_vars = usize::from(previous.is_none());
+		let removed_size = previous.map_or(0, |previous| name.len() + previous.len());
+
+		if self.vars.len() + added_vars > self.max_vars {
+			return Err(AnyError::msg(format!(
+				"env quota exceeded: more than {} variables",
+				self.max_vars
+			)));
+		}
+
+		if self.size() - removed_size + name.len() + value.len() > self.max_size {
+			return Err(AnyError::msg(format!(
+				"env quota exceeded: more than {} bytes",
+				self.max_size
+			)));
+		}
+
+		self.vars.insert(name, value);
+		Ok(())
+	}
+
+	fn remove(&mut self, name: &str) -> Result<(), AnyError> {
+		self.check_writable()?;
+		self.vars.remove(name);
+		Ok(())
+	}
+
+	fn size(&self) -> usize {
+		self.vars
+			.iter()
+			.map(|(name, value)| name.len() + value.len())
+			.sum()
+	}
+}
+
+/// Defines the `env` global, a proxy forwarding to the ops below.
+pub(crate) const INSTALL_JS: &str = r#"
+Object.defineProperty(globalThis, "env", {
+	value: new Proxy(Object.create(null), {
+		get: (_, name) => typeof name === "string" ? Deno.core.ops.op_env_get(name) ?? undefined : undefined,
+		set: (_, name, value) => { Deno.core.ops.op_env_set(String(name), String(value)); return true; },
+		deleteProperty: (_, name) => { Deno.core.ops.op_env_delete(String(name)); return true; },
+		has: (_, name) => typeof name === "string" && Deno.core.ops.op_env_get(name) !== null,
+		ownKeys: () => Deno.core.ops.op_env_keys(),
+		getOwnPropertyDescriptor: (_, name) => {
+			const value = typeof name === "string" ? Deno.core.ops.op_env_get(name) : null;
+			return value === null ? undefined : { value, writable: true, enumerable: true, configurable: true };
+		},
+		defineProperty: () => { throw new TypeError("env does not support defineProperty()"); },
+	}),
+	enumerable: true,
+});
+"#;
+
+// The ops are registered for every script, but only scripts built with an environment have the state
+
+fn script_env(state: &mut OpState) -> Result<&mut ScriptEnv, AnyError> {
+	state
+		.try_borrow_mut::<ScriptEnv>()
+		.ok_or_else(|| AnyError::msg("script has no environment"))
+}
+
+#[op]
+pub(crate) fn op_env_get(state: &mut OpState, name: String) -> Result<Option<String>, AnyError> {
+	Ok(script_env(state)?.vars.get(&name).cloned())
+}
+
+#[op]
+pub(crate) fn op_env_set(state: &mut OpState, name: String, value: String) -> Result<(), AnyError> {
+	script_env(state)?.set(name, value)
+}
+
+#[op]
+pub(crate) fn op_env_delete(state: &mut OpState, name: String) -> Result<(), AnyError> {
+	script_env(state)?.remove(&name)
+}
+
+#[op]
+pub(crate) fn op_env_keys(state: &mut OpState) -> Result<Vec<String>, AnyError> {
+	let mut keys: Vec<String> = script_env(state)?.vars.keys().cloned().collect();
+	keys.sort();
+	Ok(keys)
+}
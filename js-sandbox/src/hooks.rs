@@ -0,0 +1,79 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::call_args::SerializedArgs;
+use crate::{JsError, JsValue};
+
+type OnCall = Box<dyn FnMut(&str, &JsValue)>;
+type OnResult = Box<dyn FnMut(&str, Result<&JsValue, &JsError>, Duration)>;
+type Validator = Box<dyn Fn(&JsValue) -> Result<(), String>>;
+
+/// Callbacks invoked around function calls, registered via `Script::on_call()`, `Script::on_result()` and
+/// `Script::validate_result()`.
+#[derive(Default)]
+pub(crate) struct CallHooks {
+	pub on_call: Vec<OnCall>,
+	pub on_result: Vec<OnResult>,
+	pub validators: HashMap<String, Validator>,
+}
+
+impl CallHooks {
+	pub fn before_call(&mut self, fn_name: &str, args: &SerializedArgs) -> Result<(), JsError> {
+		if self.on_call.is_empty() {
+			return Ok(());
+		}
+
+		// Only parse arguments if someone is interested
+		let args = args.to_value()?;
+		self.before_call_with(fn_name, || args);
+
+		Ok(())
+	}
+
+	/// Like `before_call()`, for arguments that are already available as an array of values.
+	pub fn before_call_with(&mut self, fn_name: &str, args: impl FnOnce() -> JsValue) {
+		if self.on_call.is_empty() {
+			return;
+		}
+
+		let args = args();
+		for hook in self.on_call.iter_mut() {
+			hook(fn_name, &args);
+		}
+	}
+
+	/// Validates the result (if a validator is registered for `fn_name`) and passes it to the result hooks.
+	pub fn after_call(
+		&mut self,
+		fn_name: &str,
+		result: Result<JsValue, JsError>,
+		duration: Duration,
+	) -> Result<JsValue, JsError> {
+		let result = self.validate(fn_name, result);
+		for hook in self.on_result.iter_mut() {
+			hook(fn_name, result.as_ref(), duration);
+		}
+
+		result
+	}
+
+	fn validate(
+		&self,
+		fn_name: &str,
+		result: Result<JsValue, JsError>,
+	) -> Result<JsValue, JsError> {
+		let (Ok(value), Some(validator)) = (&result, self.validators.get(fn_name)) else {
+			return result;
+		};
+
+		match validator(value) {
+			Ok(()) => result,
+			Err(reason) => Err(JsError::InvalidResult {
+				function: fn_name.to_string(),
+				reason,
+			}),
+		}
+	}
+}
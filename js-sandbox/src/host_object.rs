@@ -0,0 +1,205 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use deno_core::futures::future::LocalBoxFuture;
+use deno_core::{op, JsRuntime, OpState};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::{capability, panic_guard, AnyError, JsError, JsValue, Script};
+
+/// Rust object whose methods can be called from JavaScript.
+///
+/// Usually implemented through the [`js_host_object`](crate::js_host_object) attribute, which exposes all `pub` methods
+/// taking `&self` or `&mut self` of an `impl` block:
+///
+/// ```rust
+/// use js_sandbox::{js_host_object, Script, JsError};
+///
+/// struct Counter {
+/// 	count: i32,
+/// }
+///
+/// #[js_host_object]
+/// impl Counter {
+/// 	pub fn add(&mut self, amount: i32) -> i32 {
+/// 		self.count += amount;
+/// 		self.count
+/// 	}
+/// }
+///
+/// fn main() -> Result<(), JsError> {
+/// 	let js_code = "function addTwice(n) { host.counter.add(n); return host.counter.add(n); }";
+/// 	let mut script = Script::builder()
+/// 		.with_host_object("counter", Counter { count: 0 })
+/// 		.build_from_string(js_code)?;
+///
+/// 	let result: i32 = script.call("addTwice", (5,))?;
+/// 	assert_eq!(result, 10);
+/// 	Ok(())
+/// }
+/// ```
+///
+/// Objects are accessible from JS as `host.<name>`, where `<name>` is the name under which they were registered.
+/// Arguments and return values are converted using serde_json. A method that panics throws an exception in JS, instead of unwinding
+/// into the JS engine.
+pub trait HostObject: 'static {
+	/// Names of all methods which are exposed to JavaScript.
+	fn method_names(&self) -> &'static [&'static str];
+
+	/// Invokes the method `method` with JSON arguments, returning its JSON result.
+	fn call_method(&mut self, method: &str, args: Vec<JsValue>) -> Result<JsValue, AnyError>;
+}
+
+/// Async function registered with [`ScriptBuilder::with_async_host_fn()`](crate::ScriptBuilder::with_async_host_fn).
+pub(crate) type AsyncHostFn =
+	Box<dyn Fn(Vec<JsValue>) -> LocalBoxFuture<'static, Result<JsValue, AnyError>>>;
+
+/// Stored in Deno's op state.
+pub(crate) struct HostObjects {
+	objects: HashMap<String, Box<dyn HostObject>>,
+}
+
+/// Stored in Deno's op state.
+struct AsyncHostFns {
+	functions: HashMap<String, Rc<AsyncHostFn>>,
+}
+
+/// Registers `objects`, `async_fns` and granted `capabilities` in the runtime and makes them available in JS under the `host` global.
+pub(crate) fn install(
+	runtime: &mut JsRuntime,
+	mut objects: Vec<(String, Box<dyn HostObject>)>,
+	async_fns: Vec<(String, AsyncHostFn)>,
+	capabilities: Option<Vec<(String, Box<dyn HostObject>)>>,
+) -> Result<(), JsError> {
+	if objects.is_empty() && async_fns.is_empty() && capabilities.is_none() {
+		return Ok(());
+	}
+
+	let mut js_code = "globalThis.host = globalThis.host ?? {};\n".to_string();
+	for (name, object) in objects.iter() {
+		let name_json = JsValue::from(name.as_str());
+		let object_code = object_code(name, object.as_ref());
+		js_code += &format!("host[{name_json}] = {object_code};\n");
+	}
+
+	for (name, _) in async_fns.iter() {
+		let name_json = JsValue::from(name.as_str());
+		js_code += &format!(
+			"host[{name_json}] = (...args) => Deno.core.opAsync(\"op_host_call_async\", {name_json}, args);\n"
+		);
+	}
+
+	if let Some(capabilities) = capabilities {
+		let granted: Vec<String> = capabilities
+			.iter()
+			.map(|(name, object)| {
+				// Stored separately from host objects of the same name
+				let key = capability::object_key(name);
+				let name_json = JsValue::from(name.as_str());
+				format!("{name_json}: {}", object_code(&key, object.as_ref()))
+			})
+			.collect();
+
+		js_code += &capability::install_code(&granted.join(", "));
+		objects.extend(
+			capabilities
+				.into_iter()
+				.map(|(name, object)| (capability::object_key(&name), object)),
+		);
+	}
+
+	let state_rc = runtime.op_state();
+	let mut state = state_rc.borrow_mut();
+	state.put(HostObjects {
+		objects: objects.into_iter().collect(),
+	});
+	state.put(AsyncHostFns {
+		functions: async_fns
+			.into_iter()
+			.map(|(name, function)| (name, Rc::new(function)))
+			.collect(),
+	});
+	drop(state);
+
+	runtime.execute_script(Script::DEFAULT_FILENAME, js_code.into())?;
+	Ok(())
+}
+
+/// Returns a JS expression for a frozen object, whose methods call the host object stored under `key`.
+fn object_code(key: &str, object: &dyn HostObject) -> String {
+	let key_json = JsValue::from(key);
+	let methods: Vec<String> = object
+		.method_names()
+		.iter()
+		.map(|method| {
+			let method_json = JsValue::from(*method);
+			format!("{method_json}: (...args) => Deno.core.ops.op_host_call({key_json}, {method_json}, args)")
+		})
+		.collect();
+
+	format!("Object.freeze({{ {} }})", methods.join(", "))
+}
+
+#[op]
+pub(crate) fn op_host_call(
+	state: &mut OpState,
+	object: String,
+	method: String,
+	args: Vec<JsValue>,
+) -> Result<JsValue, AnyError> {
+	panic_guard::catch(state, |state| {
+		// Scripts without host objects have no HostObjects
+		let host_object = state
+			.try_borrow_mut::<HostObjects>()
+			.and_then(|host_objects| host_objects.objects.get_mut(&object));
+
+		let Some(object) = host_object else {
+			return Err(AnyError::msg(format!("no host object `{object}`")));
+		};
+
+		object.call_method(&method, args)
+	})
+}
+
+#[op]
+pub(crate) async fn op_host_call_async(
+	state: Rc<RefCell<OpState>>,
+	name: String,
+	args: Vec<JsValue>,
+) -> Result<JsValue, AnyError> {
+	// The op state must not stay borrowed while the future runs. Scripts without host functions have no AsyncHostFns.
+	let function = state
+		.borrow()
+		.try_borrow::<AsyncHostFns>()
+		.and_then(|fns| fns.functions.get(&name).cloned());
+
+	let Some(function) = function else {
+		return Err(AnyError::msg(format!("no host function `{name}`")));
+	};
+
+	// Also covers panics while creating the future
+	panic_guard::catch_async(&state, async move { function(args).await }).await
+}
+
+// ----------------------------------------------------------------------------------------------------------------------------------------------
+// Support functions for code generated by #[js_host_object]
+
+#[doc(hidden)]
+pub fn arg_from_json<T: DeserializeOwned>(arg: Option<JsValue>) -> Result<T, AnyError> {
+	// Missing arguments are treated like `undefined`, which maps to null in JSON
+	Ok(serde_json::from_value(arg.unwrap_or(JsValue::Null))?)
+}
+
+#[doc(hidden)]
+pub fn result_to_json<T: Serialize>(result: T) -> Result<JsValue, AnyError> {
+	Ok(serde_json::to_value(result)?)
+}
+
+#[doc(hidden)]
+pub fn unknown_method(method: &str) -> AnyError {
+	AnyError::msg(format!("no host method `{method}`"))
+}
@@ -0,0 +1,31 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::fmt::Write;
+use std::rc::Rc;
+
+use sha2::{Digest, Sha256};
+
+use crate::{AnyError, JsError};
+
+/// Callback verifying the contents of a script file or module before it is executed, see
+/// [`ScriptBuilder::with_module_verifier()`](crate::ScriptBuilder::with_module_verifier).
+pub(crate) type ModuleVerifier = Rc<dyn Fn(&str, &[u8]) -> Result<(), AnyError>>;
+
+/// Fails with [`JsError::IntegrityMismatch`] unless the SHA-256 hash of `code` equals `expected` (hex-encoded).
+pub(crate) fn check_sha256(code: &[u8], expected: &str) -> Result<(), JsError> {
+	let actual = Sha256::digest(code)
+		.iter()
+		.fold(String::with_capacity(64), |mut hex, byte| {
+			let _ = write!(hex, "{byte:02x}");
+			hex
+		});
+
+	if actual.eq_ignore_ascii_case(expected.trim()) {
+		Ok(())
+	} else {
+		Err(JsError::IntegrityMismatch {
+			expected: expected.to_string(),
+			actual,
+		})
+	}
+}
@@ -0,0 +1,742 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use deno_core::{serde_v8, v8, JsRuntime};
+
+use crate::call_args::{Detached, PathSegment, SerializedArgs};
+use crate::js_error::ThrownValue;
+use crate::module::NAMESPACE_GLOBAL;
+use crate::{AnyError, JsValue};
+
+/// Maximum nesting of arrays and objects in results, which also stops at cyclic references.
+const MAX_RESULT_DEPTH: usize = 128;
+
+/// Caps on the strings and arrays in results, see [`ScriptBuilder::with_max_string_length()`](crate::ScriptBuilder::with_max_string_length)
+/// and [`ScriptBuilder::with_max_array_length()`](crate::ScriptBuilder::with_max_array_length).
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct ResultLimits {
+	pub max_string_length: Option<usize>,
+	pub max_array_length: Option<usize>,
+}
+
+impl ResultLimits {
+	/// Fails if `value` is a string or array (including typed arrays and binary data, which become arrays) beyond the limits.
+	fn check(&self, value: v8::Local<v8::Value>) -> Result<(), AnyError> {
+		let (length, max_length, kind) =
+			if let Ok(string) = v8::Local::<v8::String>::try_from(value) {
+				(string.length(), self.max_string_length, "string")
+			} else if let Ok(array) = v8::Local::<v8::Array>::try_from(value) {
+				(array.length() as usize, self.max_array_length, "array")
+			} else if let Ok(array) = v8::Local::<v8::TypedArray>::try_from(value) {
+				(array.length(), self.max_array_length, "array")
+			} else if let Ok(view) = v8::Local::<v8::ArrayBufferView>::try_from(value) {
+				(view.byte_length(), self.max_array_length, "array")
+			} else if let Ok(buffer) = v8::Local::<v8::ArrayBuffer>::try_from(value) {
+				(buffer.byte_length(), self.max_array_length, "array")
+			} else {
+				return Ok(());
+			};
+
+		match max_length {
+			Some(max_length) if length > max_length => Err(AnyError::msg(format!(
+				"result contains {kind} of length {length}, exceeding the limit of {max_length}"
+			))),
+			_ => Ok(()),
+		}
+	}
+}
+
+/// Arguments of a direct function call.
+pub(crate) enum Args<'a> {
+	/// Arguments produced by [`CallArgs`](crate::CallArgs).
+	Serialized(&'a SerializedArgs),
+	/// Values passed to [`Script::call_values()`](crate::Script::call_values).
+	Values(Vec<JsValue>),
+	/// Values converted by [`ToJs`](crate::ToJs), see [`Script::call_js()`](crate::Script::call_js).
+	V8(Vec<v8::Global<v8::Value>>),
+}
+
+/// Function invoked by a direct call.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Callee<'a> {
+	/// Global function or dotted path, validated with `check_fn_path()`.
+	Function(&'a str),
+	/// Export of a module script, which may have any name (including ones that are not identifiers).
+	Export(&'a str),
+}
+
+impl<'a> Callee<'a> {
+	/// Name of the function, as used in errors and hooks.
+	pub fn name(self) -> &'a str {
+		match self {
+			Callee::Function(name) | Callee::Export(name) => name,
+		}
+	}
+}
+
+/// What a call or wrapper script evaluated to.
+pub(crate) enum Completion {
+	/// Result of a synchronous function, converted right away so that pending work cannot modify it.
+	Value(JsValue),
+	/// Promise returned by an async function or wrapper script, which settles with the result.
+	Promise(v8::Global<v8::Promise>),
+}
+
+/// Like [`Completion`], but leaving the conversion of the result to the caller.
+pub(crate) enum RawCompletion {
+	Value(v8::Global<v8::Value>),
+	Promise(v8::Global<v8::Promise>),
+}
+
+/// Identifies a call started with [`Script::start_call()`](crate::Script::start_call), until its result is collected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CallId(pub(crate) u64);
+
+/// Iterator over the chunks of a result, see [`Script::call_chunked()`](crate::Script::call_chunked).
+pub(crate) struct ChunkIterator {
+	iterator: v8::Global<v8::Object>,
+	next: v8::Global<v8::Function>,
+}
+
+/// A call that was started, but whose result has not been collected yet.
+pub(crate) struct InFlightCall {
+	pub fn_name: String,
+	pub completion: Completion,
+	/// Size of the arguments and time spent starting the call, for usage tracking once it is finished.
+	pub bytes_in: usize,
+	pub start_time: Duration,
+}
+
+/// Lookups of top-level `let`, `const` and `class` bindings, keyed by identifier.
+///
+/// Unlike functions and `var` declarations, these are not properties of the global object, so they can only be accessed from
+/// compiled code. Each lookup consists of just the identifier, and is compiled on first use.
+#[derive(Default)]
+pub(crate) struct LexicalLookups {
+	scripts: HashMap<String, v8::Global<v8::UnboundScript>>,
+}
+
+impl LexicalLookups {
+	/// Bounds the memory used by hosts calling many different functions; the cache is simply reset once full.
+	const MAX_ENTRIES: usize = 1024;
+
+	fn lookup<'s>(
+		&mut self,
+		scope: &mut v8::TryCatch<v8::HandleScope<'s>>,
+		identifier: &str,
+	) -> Option<v8::Local<'s, v8::Value>> {
+		let unbound = match self.scripts.get(identifier) {
+			Some(unbound) => v8::Local::new(scope, unbound),
+			None => {
+				let code = v8::String::new(scope, identifier)?;
+				let unbound = v8::Script::compile(scope, code, None)?.get_unbound_script(scope);
+
+				if self.scripts.len() >= Self::MAX_ENTRIES {
+					self.scripts.clear();
+				}
+				self.scripts
+					.insert(identifier.to_string(), v8::Global::new(scope, unbound));
+				unbound
+			}
+		};
+
+		unbound.bind_to_current_context(scope).run(scope)
+	}
+}
+
+/// Invokes `callee`, whose path must have been validated as identifier or dotted path.
+///
+/// The function is looked up on every call, so functions that are replaced later on are picked up. Functions in dotted paths
+/// are called as methods of their parent object, and module exports with the namespace as receiver. Async functions complete
+/// with a promise, while the results of other functions (including promises they return) are passed on as they are.
+pub(crate) fn call(
+	runtime: &mut JsRuntime,
+	lookups: &mut LexicalLookups,
+	callee: Callee,
+	args: Args,
+	limits: ResultLimits,
+) -> Result<Completion, AnyError> {
+	let scope = &mut runtime.handle_scope();
+	let scope = &mut v8::TryCatch::new(scope);
+	let (function, result) = invoke_function(scope, lookups, callee, args)?;
+
+	match v8::Local::<v8::Promise>::try_from(result) {
+		Ok(promise) if function.is_async_function() => {
+			Ok(Completion::Promise(v8::Global::new(scope, promise)))
+		}
+		_ => Ok(Completion::Value(to_json(scope, result, limits)?)),
+	}
+}
+
+/// Invokes the function at `fn_name` like [`call()`], but returns its result as V8 value.
+pub(crate) fn call_raw(
+	runtime: &mut JsRuntime,
+	lookups: &mut LexicalLookups,
+	fn_name: &str,
+	args: Args,
+) -> Result<RawCompletion, AnyError> {
+	let scope = &mut runtime.handle_scope();
+	let scope = &mut v8::TryCatch::new(scope);
+	let (function, result) = invoke_function(scope, lookups, Callee::Function(fn_name), args)?;
+
+	match v8::Local::<v8::Promise>::try_from(result) {
+		Ok(promise) if function.is_async_function() => {
+			Ok(RawCompletion::Promise(v8::Global::new(scope, promise)))
+		}
+		_ => Ok(RawCompletion::Value(v8::Global::new(scope, result))),
+	}
+}
+
+/// Invokes the function at `fn_name` like [`call()`], and starts iterating over the iterable or async iterable it returns.
+pub(crate) fn call_iterable(
+	runtime: &mut JsRuntime,
+	lookups: &mut LexicalLookups,
+	fn_name: &str,
+	args: Args,
+) -> Result<ChunkIterator, AnyError> {
+	let scope = &mut runtime.handle_scope();
+	let scope = &mut v8::TryCatch::new(scope);
+	let (_, result) = invoke_function(scope, lookups, Callee::Function(fn_name), args)?;
+
+	let Ok(iterable) = v8::Local::<v8::Object>::try_from(result) else {
+		return Err(type_error(
+			scope,
+			&format!("{fn_name} did not return an iterable"),
+		)?);
+	};
+
+	// Like `for await` loops, prefer async iteration
+	let symbols = [
+		v8::Symbol::get_async_iterator(scope),
+		v8::Symbol::get_iterator(scope),
+	];
+	for symbol in symbols {
+		let Some(method) = iterable.get(scope, symbol.into()) else {
+			return Err(exception_error(scope));
+		};
+		let Ok(method) = v8::Local::<v8::Function>::try_from(method) else {
+			continue;
+		};
+
+		let Some(iterator) = method.call(scope, iterable.into(), &[]) else {
+			return Err(exception_error(scope));
+		};
+		let key = v8_string(scope, "next")?;
+		let iterator = v8::Local::<v8::Object>::try_from(iterator).ok();
+		let next = iterator.and_then(|iterator| iterator.get(scope, key.into()));
+
+		return match (iterator, next.map(v8::Local::<v8::Function>::try_from)) {
+			(Some(iterator), Some(Ok(next))) => Ok(ChunkIterator {
+				iterator: v8::Global::new(scope, iterator),
+				next: v8::Global::new(scope, next),
+			}),
+			_ => Err(type_error(
+				scope,
+				&format!("{fn_name} returned an invalid iterator"),
+			)?),
+		};
+	}
+
+	Err(type_error(
+		scope,
+		&format!("{fn_name} did not return an iterable"),
+	)?)
+}
+
+/// Requests the next chunk from an iterator. Completes with the iterator result, an object with `done` and `value` fields.
+pub(crate) fn next_chunk(
+	runtime: &mut JsRuntime,
+	chunks: &ChunkIterator,
+	limits: ResultLimits,
+) -> Result<Completion, AnyError> {
+	let scope = &mut runtime.handle_scope();
+	let scope = &mut v8::TryCatch::new(scope);
+	let iterator = v8::Local::new(scope, &chunks.iterator);
+	let next = v8::Local::new(scope, &chunks.next);
+
+	let Some(result) = next.call(scope, iterator.into(), &[]) else {
+		return Err(exception_error(scope));
+	};
+
+	match v8::Local::<v8::Promise>::try_from(result) {
+		Ok(promise) => Ok(Completion::Promise(v8::Global::new(scope, promise))),
+		Err(_) => Ok(Completion::Value(to_json(scope, result, limits)?)),
+	}
+}
+
+/// Closes an iterator that has not been exhausted, so that e.g. `finally` blocks of generators run. Errors are ignored.
+pub(crate) fn close_iterator(runtime: &mut JsRuntime, chunks: &ChunkIterator) {
+	let scope = &mut runtime.handle_scope();
+	let scope = &mut v8::TryCatch::new(scope);
+	let iterator = v8::Local::new(scope, &chunks.iterator);
+
+	let Ok(key) = v8_string(scope, "return") else {
+		return;
+	};
+	let method = iterator.get(scope, key.into());
+	let Some(Ok(method)) = method.map(v8::Local::<v8::Function>::try_from) else {
+		return;
+	};
+
+	if let Some(result) = method.call(scope, iterator.into(), &[]) {
+		if let Ok(promise) = v8::Local::<v8::Promise>::try_from(result) {
+			ignore_rejection(scope, promise);
+		}
+	}
+}
+
+/// Resolves the function at `fn_name` and calls it, returning the function and its result.
+fn invoke_function<'s>(
+	scope: &mut v8::TryCatch<v8::HandleScope<'s>>,
+	lookups: &mut LexicalLookups,
+	callee: Callee,
+	args: Args,
+) -> Result<(v8::Local<'s, v8::Function>, v8::Local<'s, v8::Value>), AnyError> {
+	// Resolve the function and its receiver
+	let global = scope.get_current_context().global(scope);
+	let mut receiver: v8::Local<v8::Value> = v8::undefined(scope).into();
+	let mut target;
+
+	match callee {
+		Callee::Function(fn_name) => {
+			let mut segments = fn_name.split('.');
+			let first = segments.next().unwrap_or_default();
+			let key = v8_string(scope, first)?;
+
+			target = match global.has(scope, key.into()) {
+				Some(true) => global.get(scope, key.into()),
+				_ => lookups.lookup(scope, first),
+			};
+
+			for segment in segments {
+				let Some(object) = target.and_then(|value| value.to_object(scope)) else {
+					return Err(exception_error(scope));
+				};
+
+				let key = v8_string(scope, segment)?;
+				receiver = object.into();
+				target = object.get(scope, key.into());
+			}
+		}
+		Callee::Export(export_name) => {
+			// Looked up as a property, so any export name works and none is evaluated as code
+			let key = v8_string(scope, NAMESPACE_GLOBAL)?;
+			let Some(namespace) = global
+				.get(scope, key.into())
+				.and_then(|value| value.to_object(scope))
+			else {
+				return Err(exception_error(scope));
+			};
+
+			let key = v8_string(scope, export_name)?;
+			receiver = namespace.into();
+			target = namespace.get(scope, key.into());
+		}
+	}
+
+	let Some(target) = target else {
+		return Err(exception_error(scope));
+	};
+	let Ok(function) = v8::Local::<v8::Function>::try_from(target) else {
+		let fn_name = callee.name();
+		return Err(type_error(scope, &format!("{fn_name} is not a function"))?);
+	};
+
+	// Convert the arguments straight into V8 values
+	let args: Vec<v8::Local<v8::Value>> = match args {
+		// Calls without arguments are frequent, and need not go through the JSON parser
+		Args::Serialized(serialized) if serialized.json().is_empty() => Vec::new(),
+		Args::Serialized(serialized) => {
+			let json = v8_string(scope, &format!("[{}]", serialized.json()))?;
+			let Some(array) = v8::json::parse(scope, json) else {
+				return Err(exception_error(scope));
+			};
+
+			let array = v8::Local::<v8::Array>::try_from(array)?;
+			let mut args: Vec<_> = (0..array.length())
+				.map(|i| array.get_index(scope, i).expect("array element exists"))
+				.collect();
+
+			// Large strings and byte arrays are copied into V8 as they are, instead of going through JSON
+			for detached in serialized.detached() {
+				let value = match &detached.value {
+					Detached::String(string) => v8_string(scope, string)?.into(),
+					Detached::Bytes(bytes) => uint8_array(scope, bytes)?.into(),
+				};
+
+				let target = &mut args[detached.position as usize];
+				match detached.path.split_last() {
+					None => *target = value,
+					Some((last, parents)) => {
+						let mut container = *target;
+						for segment in parents {
+							container = get_property(scope, container, segment)?;
+						}
+						set_property(scope, container, last, value)?;
+					}
+				}
+			}
+			args
+		}
+		Args::Values(values) => values
+			.iter()
+			.map(|value| serde_v8::to_v8(scope, value))
+			.collect::<Result<_, _>>()?,
+		Args::V8(values) => values
+			.iter()
+			.map(|value| v8::Local::new(scope, value))
+			.collect(),
+	};
+
+	let Some(result) = function.call(scope, receiver, &args) else {
+		return Err(exception_error(scope));
+	};
+
+	Ok((function, result))
+}
+
+/// Executes `js_code` like `JsRuntime::execute_script()`, but with line numbers in stack traces shifted by `line_offset`.
+///
+/// Wrappers embedding user code pass the negated number of lines preceding it, so that errors point to the user's own lines.
+pub(crate) fn execute_at_line(
+	runtime: &mut JsRuntime,
+	name: &str,
+	js_code: &str,
+	line_offset: i32,
+) -> Result<v8::Global<v8::Value>, AnyError> {
+	let scope = &mut runtime.handle_scope();
+	let scope = &mut v8::TryCatch::new(scope);
+	let source = v8_string(scope, js_code)?;
+	let name = v8_string(scope, name)?;
+	let source_map_url = v8::undefined(scope);
+	let origin = v8::ScriptOrigin::new(
+		scope,
+		name.into(),
+		line_offset,
+		0,
+		false,
+		0,
+		source_map_url.into(),
+		false,
+		false,
+		false,
+	);
+
+	let Some(script) = v8::Script::compile(scope, source, Some(&origin)) else {
+		return Err(exception_error(scope));
+	};
+	let Some(value) = script.run(scope) else {
+		return Err(exception_error(scope));
+	};
+
+	Ok(v8::Global::new(scope, value))
+}
+
+/// Completion of a wrapper script, which evaluates to a promise of its result.
+pub(crate) fn wrapper_completion(
+	runtime: &mut JsRuntime,
+	value: v8::Global<v8::Value>,
+) -> Result<Completion, AnyError> {
+	let scope = &mut runtime.handle_scope();
+	let value = v8::Local::new(scope, value);
+	let promise = v8::Local::<v8::Promise>::try_from(value)?;
+
+	Ok(Completion::Promise(v8::Global::new(scope, promise)))
+}
+
+/// Marks a promise as handled, so that its rejection is not reported by the event loop, but only when its result is read.
+pub(crate) fn mark_handled(runtime: &mut JsRuntime, promise: &v8::Global<v8::Promise>) {
+	let scope = &mut runtime.handle_scope();
+	let promise = v8::Local::new(scope, promise);
+	ignore_rejection(scope, promise);
+}
+
+fn ignore_rejection(scope: &mut v8::HandleScope, promise: v8::Local<v8::Promise>) {
+	let ignore = v8::Function::new(
+		scope,
+		|_: &mut v8::HandleScope, _: v8::FunctionCallbackArguments, _: v8::ReturnValue| {},
+	)
+	.expect("function can be created");
+
+	promise.catch(scope, ignore);
+}
+
+/// Whether a promise has been fulfilled or rejected.
+pub(crate) fn is_settled(runtime: &mut JsRuntime, promise: &v8::Global<v8::Promise>) -> bool {
+	let scope = &mut runtime.handle_scope();
+	!matches!(
+		v8::Local::new(scope, promise).state(),
+		v8::PromiseState::Pending
+	)
+}
+
+/// Returns the value a settled promise was fulfilled with, or the error it was rejected with. `None` if it is still pending.
+pub(crate) fn settled_result(
+	runtime: &mut JsRuntime,
+	promise: &v8::Global<v8::Promise>,
+	limits: ResultLimits,
+) -> Option<Result<JsValue, AnyError>> {
+	let scope = &mut runtime.handle_scope();
+	let promise = v8::Local::new(scope, promise);
+
+	match promise.state() {
+		v8::PromiseState::Pending => None,
+		v8::PromiseState::Fulfilled => {
+			let value = promise.result(scope);
+			Some(to_json(scope, value, limits))
+		}
+		v8::PromiseState::Rejected => {
+			let exception = promise.result(scope);
+			Some(Err(thrown_error(scope, exception)))
+		}
+	}
+}
+
+/// Like [`settled_result()`], but returning the value a promise was fulfilled with as it is.
+pub(crate) fn settled_raw(
+	runtime: &mut JsRuntime,
+	promise: &v8::Global<v8::Promise>,
+) -> Option<Result<v8::Global<v8::Value>, AnyError>> {
+	let scope = &mut runtime.handle_scope();
+	let promise = v8::Local::new(scope, promise);
+
+	match promise.state() {
+		v8::PromiseState::Pending => None,
+		v8::PromiseState::Fulfilled => {
+			let value = promise.result(scope);
+			Some(Ok(v8::Global::new(scope, value)))
+		}
+		v8::PromiseState::Rejected => {
+			let exception = promise.result(scope);
+			Some(Err(thrown_error(scope, exception)))
+		}
+	}
+}
+
+/// Converts a result to JSON, treating `undefined` as `null`.
+///
+/// Binary data (`ArrayBuffer` and `DataView`) becomes arrays of bytes, typed arrays become arrays of their elements. This also
+/// applies inside arrays and objects, so that e.g. `Uint8Array` fields can be deserialized with `serde_bytes`.
+///
+/// Strings and arrays are checked against `limits` before they are copied out of V8.
+fn to_json(
+	scope: &mut v8::HandleScope,
+	value: v8::Local<v8::Value>,
+	limits: ResultLimits,
+) -> Result<JsValue, AnyError> {
+	to_json_nested(scope, value, limits, 0)
+}
+
+fn to_json_nested(
+	scope: &mut v8::HandleScope,
+	value: v8::Local<v8::Value>,
+	limits: ResultLimits,
+	depth: usize,
+) -> Result<JsValue, AnyError> {
+	if depth > MAX_RESULT_DEPTH {
+		return Err(AnyError::msg(format!(
+			"result is nested deeper than {MAX_RESULT_DEPTH} levels (or cyclic)"
+		)));
+	}
+
+	limits.check(value)?;
+
+	if value.is_undefined() {
+		return Ok(JsValue::Null);
+	} else if let Some(elements) = typed_array_elements(value) {
+		return Ok(elements);
+	} else if let Some(bytes) = binary_contents(scope, value) {
+		return Ok(JsValue::Array(
+			bytes.into_iter().map(JsValue::from).collect(),
+		));
+	}
+
+	// Arrays and plain objects are traversed here, to find binary data inside; everything else is left to serde_v8
+	if let Ok(array) = v8::Local::<v8::Array>::try_from(value) {
+		let mut elements = Vec::with_capacity(array.length() as usize);
+		for i in 0..array.length() {
+			let element = array.get_index(scope, i).ok_or_else(property_error)?;
+			elements.push(to_json_nested(scope, element, limits, depth + 1)?);
+		}
+
+		Ok(JsValue::Array(elements))
+	} else if value.is_object() && !value.is_function() && !value.is_map() && !value.is_set() {
+		let object = value.to_object(scope).ok_or_else(property_error)?;
+		let names_args = v8::GetPropertyNamesArgs {
+			key_conversion: v8::KeyConversionMode::ConvertToString,
+			..Default::default()
+		};
+		let names = object
+			.get_own_property_names(scope, names_args)
+			.ok_or_else(property_error)?;
+
+		let mut map = serde_json::Map::new();
+		for i in 0..names.length() {
+			let name = names.get_index(scope, i).ok_or_else(property_error)?;
+			let field = object.get(scope, name).ok_or_else(property_error)?;
+			let key = name.to_rust_string_lossy(scope);
+			map.insert(key, to_json_nested(scope, field, limits, depth + 1)?);
+		}
+
+		Ok(JsValue::Object(map))
+	} else {
+		Ok(serde_v8::from_v8(scope, value)?)
+	}
+}
+
+fn property_error() -> AnyError {
+	AnyError::msg("failed to read property of result")
+}
+
+/// Copies the elements of a typed array out of V8 in one go, instead of reading them one by one.
+///
+/// Returns `None` for other values, and for typed arrays whose elements have no Rust counterpart.
+fn typed_array_elements(value: v8::Local<v8::Value>) -> Option<JsValue> {
+	let array = v8::Local::<v8::TypedArray>::try_from(value).ok()?;
+	let mut bytes = vec![0; array.byte_length()];
+	array.copy_contents(&mut bytes);
+
+	// Elements are stored in native byte order
+	macro_rules! elements {
+		($ty:ty) => {{
+			bytes
+				.chunks_exact(std::mem::size_of::<$ty>())
+				.map(|chunk| {
+					JsValue::from(<$ty>::from_ne_bytes(
+						chunk.try_into().expect("chunk has element size"),
+					))
+				})
+				.collect()
+		}};
+	}
+
+	let elements = if value.is_uint8_array() || value.is_uint8_clamped_array() {
+		elements!(u8)
+	} else if value.is_int8_array() {
+		elements!(i8)
+	} else if value.is_uint16_array() {
+		elements!(u16)
+	} else if value.is_int16_array() {
+		elements!(i16)
+	} else if value.is_uint32_array() {
+		elements!(u32)
+	} else if value.is_int32_array() {
+		elements!(i32)
+	} else if value.is_float32_array() {
+		elements!(f32)
+	} else if value.is_float64_array() {
+		elements!(f64)
+	} else if value.is_big_uint64_array() {
+		elements!(u64)
+	} else if value.is_big_int64_array() {
+		elements!(i64)
+	} else {
+		return None;
+	};
+
+	Some(JsValue::Array(elements))
+}
+
+/// Copies the bytes of an `ArrayBuffer` or `DataView` out of V8 in one go, instead of reading them one by one.
+fn binary_contents(scope: &mut v8::HandleScope, value: v8::Local<v8::Value>) -> Option<Vec<u8>> {
+	let view: v8::Local<v8::ArrayBufferView> =
+		if let Ok(buffer) = v8::Local::<v8::ArrayBuffer>::try_from(value) {
+			v8::Uint8Array::new(scope, buffer, 0, buffer.byte_length())?.into()
+		} else if value.is_data_view() {
+			v8::Local::<v8::ArrayBufferView>::try_from(value).ok()?
+		} else {
+			return None;
+		};
+
+	let mut bytes = vec![0; view.byte_length()];
+	view.copy_contents(&mut bytes);
+	Some(bytes)
+}
+
+/// Copies `bytes` into a new `Uint8Array`.
+fn uint8_array<'s>(
+	scope: &mut v8::HandleScope<'s>,
+	bytes: &[u8],
+) -> Result<v8::Local<'s, v8::Uint8Array>, AnyError> {
+	let store = v8::ArrayBuffer::new_backing_store_from_vec(bytes.to_vec()).make_shared();
+	let buffer = v8::ArrayBuffer::with_backing_store(scope, &store);
+
+	v8::Uint8Array::new(scope, buffer, 0, bytes.len())
+		.ok_or_else(|| AnyError::msg("failed to create Uint8Array"))
+}
+
+fn property_key<'s>(
+	scope: &mut v8::HandleScope<'s>,
+	segment: &PathSegment,
+) -> Result<v8::Local<'s, v8::Value>, AnyError> {
+	match segment {
+		PathSegment::Key(key) => Ok(v8_string(scope, key)?.into()),
+		PathSegment::Index(index) => Ok(v8::Integer::new_from_unsigned(scope, *index).into()),
+	}
+}
+
+/// Reads a property of a parsed argument.
+fn get_property<'s>(
+	scope: &mut v8::HandleScope<'s>,
+	container: v8::Local<'s, v8::Value>,
+	segment: &PathSegment,
+) -> Result<v8::Local<'s, v8::Value>, AnyError> {
+	let key = property_key(scope, segment)?;
+	container
+		.to_object(scope)
+		.and_then(|object| object.get(scope, key))
+		.ok_or_else(|| AnyError::msg("detached argument has no parent"))
+}
+
+/// Writes a property of a parsed argument.
+fn set_property<'s>(
+	scope: &mut v8::HandleScope<'s>,
+	container: v8::Local<'s, v8::Value>,
+	segment: &PathSegment,
+	value: v8::Local<'s, v8::Value>,
+) -> Result<(), AnyError> {
+	let key = property_key(scope, segment)?;
+	container
+		.to_object(scope)
+		.and_then(|object| object.set(scope, key, value))
+		.map(|_| ())
+		.ok_or_else(|| AnyError::msg("detached argument has no parent"))
+}
+
+fn v8_string<'s>(
+	scope: &mut v8::HandleScope<'s>,
+	s: &str,
+) -> Result<v8::Local<'s, v8::String>, AnyError> {
+	v8::String::new(scope, s).ok_or_else(|| AnyError::msg("string too long for V8"))
+}
+
+/// Error for an exception. Values other than `Error` objects are kept, to be reported as [`JsError::Thrown`](crate::JsError::Thrown).
+fn thrown_error(scope: &mut v8::HandleScope, exception: v8::Local<v8::Value>) -> AnyError {
+	let error = deno_core::error::JsError::from_v8_exception(scope, exception);
+	if exception.is_native_error() {
+		return error.into();
+	}
+
+	match to_json(scope, exception, ResultLimits::default()) {
+		Ok(value) => ThrownValue { value, error }.into(),
+		Err(_) => error.into(),
+	}
+}
+
+/// Error for a `TypeError` with the given message, as if it had been thrown in JS.
+fn type_error(scope: &mut v8::HandleScope, message: &str) -> Result<AnyError, AnyError> {
+	let message = v8_string(scope, message)?;
+	let exception = v8::Exception::type_error(scope, message);
+
+	Ok(deno_core::error::JsError::from_v8_exception(scope, exception).into())
+}
+
+/// Converts the exception caught by `scope` to an error, like `JsRuntime::execute_script()` does.
+pub(crate) fn exception_error(scope: &mut v8::TryCatch<v8::HandleScope>) -> AnyError {
+	match scope.exception() {
+		Some(exception) if !scope.has_terminated() => thrown_error(scope, exception),
+		_ => AnyError::msg("execution terminated"),
+	}
+}
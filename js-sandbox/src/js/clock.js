@@ -0,0 +1,105 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+// Routes Date and timers through the clock configured by the host, installed when ScriptBuilder::with_clock() is used.
+// Evaluates to a controller object, which the host keeps to itself:
+// - runDue(): fires the next timer whose due time has been reached, returns whether there was one.
+// - advance(ms): shifts the time of the script forward; timers in between fire on subsequent runDue() calls.
+
+(() => {
+	const ops = Deno.core.ops;
+	const OriginalDate = globalThis.Date;
+
+	// Time shifted by Script::advance_time(), relative to the host clock
+	let offset = 0;
+	// Time to reach while advancing; timers up to here are due
+	let target;
+
+	const now = () => ops.op_performance_now() + offset;
+	const dateNow = () => ops.op_clock_now() + offset;
+
+	function Date(...args) {
+		if (new.target === undefined) {
+			return new OriginalDate(dateNow()).toString();
+		}
+		return Reflect.construct(OriginalDate, args.length === 0 ? [dateNow()] : args, new.target);
+	}
+
+	Object.setPrototypeOf(Date, OriginalDate);
+	Date.prototype = OriginalDate.prototype;
+	Date.now = dateNow;
+	Object.defineProperty(OriginalDate.prototype, "constructor", { value: Date, writable: true, configurable: true });
+
+	// Pending timers by ID; ordered by due time, then by the order in which they were scheduled
+	const timers = new Map();
+	let nextId = 1;
+	let nextSeq = 1;
+
+	function schedule(callback, delay, args, repeat) {
+		if (typeof callback !== "function") {
+			throw new TypeError("timer callback must be a function");
+		}
+
+		delay = Math.max(0, Number(delay) || 0);
+		if (repeat) {
+			// Otherwise, an interval would fire forever without time passing
+			delay = Math.max(1, delay);
+		}
+
+		const id = nextId++;
+		timers.set(id, { callback, args, delay, repeat, due: now() + delay, seq: nextSeq++ });
+		return id;
+	}
+
+	function nextDue(limit) {
+		let next;
+		for (const [id, timer] of timers) {
+			if (timer.due <= limit && (next === undefined || timer.due < next[1].due || (timer.due === next[1].due && timer.seq < next[1].seq))) {
+				next = [id, timer];
+			}
+		}
+		return next;
+	}
+
+	// Fires a single timer, so that promises settle before the next one
+	function runDue() {
+		const next = nextDue(target ?? now());
+		if (next === undefined) {
+			if (target !== undefined) {
+				offset += Math.max(0, target - now());
+				target = undefined;
+			}
+			return false;
+		}
+
+		const [id, timer] = next;
+		const due = timer.due;
+		if (timer.repeat) {
+			timer.due += timer.delay;
+			timer.seq = nextSeq++;
+		} else {
+			timers.delete(id);
+		}
+
+		// While advancing, the timer observes its own due time
+		offset += Math.max(0, due - now());
+		timer.callback(...timer.args);
+		return true;
+	}
+
+	function advance(ms) {
+		target = Math.max(target ?? now(), now() + ms);
+	}
+
+	const clear = (id) => void timers.delete(id);
+
+	Object.assign(globalThis, {
+		Date,
+		performance: Object.freeze({ now }),
+		setTimeout: (callback, delay, ...args) => schedule(callback, delay, args, false),
+		setInterval: (callback, delay, ...args) => schedule(callback, delay, args, true),
+		clearTimeout: clear,
+		clearInterval: clear,
+	});
+
+	return { runDue, advance };
+})()
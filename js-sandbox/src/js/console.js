@@ -0,0 +1,158 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+// Minimal console, installed when the web APIs are not enabled.
+// Evaluates to a function that receives `print(line, isError)` and defines the `console` global.
+
+((print) => {
+	// Nesting levels expanded by default; deeper objects are abbreviated as [Object] or [Array]
+	const DEFAULT_DEPTH = 2;
+	const IDENTIFIER = /^[A-Za-z_$][\w$]*$/;
+
+	function formatKey(key) {
+		if (typeof key === "symbol") {
+			return `[${key.toString()}]`;
+		}
+		return IDENTIFIER.test(key) ? key : JSON.stringify(key);
+	}
+
+	function formatEntries(open, entries, close) {
+		return entries.length === 0 ? `${open}${close}` : `${open} ${entries.join(", ")} ${close}`;
+	}
+
+	function inspect(value, depth, maxDepth, seen) {
+		switch (typeof value) {
+			case "string":
+				return depth === 0 ? value : JSON.stringify(value);
+			case "bigint":
+				return `${value}n`;
+			case "symbol":
+				return value.toString();
+			case "function":
+				return `[Function: ${value.name || "(anonymous)"}]`;
+			case "object":
+				break;
+			default:
+				return String(value);
+		}
+
+		if (value === null) {
+			return "null";
+		}
+		if (value instanceof Error) {
+			return value.stack ?? String(value);
+		}
+		if (value instanceof Date) {
+			return isNaN(value.getTime()) ? "Invalid Date" : value.toISOString();
+		}
+		if (value instanceof RegExp) {
+			return String(value);
+		}
+		if (seen.includes(value)) {
+			return "[Circular]";
+		}
+
+		const isArray = Array.isArray(value);
+		if (depth > maxDepth) {
+			return isArray ? "[Array]" : `[${value.constructor?.name || "Object"}]`;
+		}
+
+		const nested = [...seen, value];
+		const format = (v) => inspect(v, depth + 1, maxDepth, nested);
+
+		if (isArray) {
+			return formatEntries("[", value.map(format), "]");
+		}
+		if (value instanceof Map) {
+			const entries = [...value].map(([k, v]) => `${format(k)} => ${format(v)}`);
+			return formatEntries(`Map(${value.size}) {`, entries, "}");
+		}
+		if (value instanceof Set) {
+			return formatEntries(`Set(${value.size}) {`, [...value].map(format), "}");
+		}
+
+		const entries = Reflect.ownKeys(value)
+			.filter((key) => Object.prototype.propertyIsEnumerable.call(value, key))
+			.map((key) => `${formatKey(key)}: ${format(value[key])}`);
+
+		const name = value.constructor?.name;
+		const prefix = name && name !== "Object" ? `${name} ` : "";
+		return formatEntries(`${prefix}{`, entries, "}");
+	}
+
+	function formatArgs(args) {
+		return args.map((arg) => inspect(arg, 0, DEFAULT_DEPTH, [])).join(" ");
+	}
+
+	function formatTable(data, columns) {
+		const indexHeader = "(index)";
+		const valuesHeader = "Values";
+		const rows = data instanceof Map ? [...data] : Object.entries(data);
+		const cell = (v) => inspect(v, 1, 0, []);
+
+		const keys = [];
+		let hasValues = false;
+		for (const [, row] of rows) {
+			if (row !== null && typeof row === "object") {
+				for (const key of Object.keys(row)) {
+					if (!keys.includes(key)) {
+						keys.push(key);
+					}
+				}
+			} else {
+				hasValues = true;
+			}
+		}
+
+		const header = [indexHeader, ...(columns ?? keys)];
+		if (hasValues) {
+			header.push(valuesHeader);
+		}
+
+		const body = rows.map(([index, row]) => {
+			const isObject = row !== null && typeof row === "object";
+			const line = [String(index)];
+			for (const key of header.slice(1)) {
+				if (key === valuesHeader && hasValues) {
+					line.push(isObject ? "" : cell(row));
+				} else {
+					line.push(isObject && key in row ? cell(row[key]) : "");
+				}
+			}
+			return line;
+		});
+
+		const widths = header.map((h, i) => Math.max(h.length, ...body.map((line) => line[i].length)) + 2);
+		const separator = (left, middle, right) => left + widths.map((w) => "─".repeat(w)).join(middle) + right;
+		const formatLine = (line) => "│" + line.map((text, i) => ` ${text.padEnd(widths[i] - 1)}`).join("│") + "│";
+
+		return [
+			separator("┌", "┬", "┐"),
+			formatLine(header),
+			separator("├", "┼", "┤"),
+			...body.map(formatLine),
+			separator("└", "┴", "┘"),
+		].join("\n");
+	}
+
+	const log = (...args) => print(formatArgs(args), false);
+	const error = (...args) => print(formatArgs(args), true);
+
+	globalThis.console = {
+		log,
+		info: log,
+		debug: log,
+		warn: error,
+		error,
+		dir(value, options) {
+			const depth = options?.depth ?? DEFAULT_DEPTH;
+			print(typeof value === "string" ? JSON.stringify(value) : inspect(value, 0, depth, []), false);
+		},
+		table(data, columns) {
+			if (data === null || typeof data !== "object") {
+				log(data);
+			} else {
+				print(formatTable(data, columns), false);
+			}
+		},
+	};
+})
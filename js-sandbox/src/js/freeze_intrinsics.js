@@ -0,0 +1,90 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+// Freezes the built-in constructors, their prototypes and everything reachable from them, installed when
+// ScriptBuilder::with_frozen_intrinsics() is used. Runs after the script's own code has been evaluated.
+//
+// Frozen prototypes make assignments like `error.name = "..."` fail, since they would shadow a read-only inherited property.
+// For the properties most commonly shadowed, such assignments define an own property on the instance instead.
+
+(() => {
+	const names = [
+		"Object", "Function", "Array", "String", "Number", "Boolean", "Symbol", "BigInt", "Math", "JSON", "Reflect", "Proxy",
+		"Promise", "RegExp", "Date", "Map", "Set", "WeakMap", "WeakSet", "WeakRef", "FinalizationRegistry", "ArrayBuffer",
+		"SharedArrayBuffer", "DataView", "Atomics", "Intl", "WebAssembly", "Error", "AggregateError", "EvalError", "RangeError",
+		"ReferenceError", "SyntaxError", "TypeError", "URIError", "Int8Array", "Uint8Array", "Uint8ClampedArray", "Int16Array",
+		"Uint16Array", "Int32Array", "Uint32Array", "Float32Array", "Float64Array", "BigInt64Array", "BigUint64Array",
+		"escape", "unescape", "eval", "isFinite", "isNaN", "parseFloat", "parseInt", "decodeURI", "decodeURIComponent",
+		"encodeURI", "encodeURIComponent",
+	];
+
+	// Intrinsics that are not reachable through globals
+	const hidden = [
+		Object.getPrototypeOf(function* () {}),
+		Object.getPrototypeOf(async function () {}),
+		Object.getPrototypeOf(async function* () {}),
+		Object.getPrototypeOf([][Symbol.iterator]()),
+		Object.getPrototypeOf(new Map()[Symbol.iterator]()),
+		Object.getPrototypeOf(new Set()[Symbol.iterator]()),
+		Object.getPrototypeOf(""[Symbol.iterator]()),
+		Object.getPrototypeOf("".matchAll(/./g)),
+	];
+
+	const overridable = ["constructor", "toString", "valueOf", "toLocaleString", "toJSON", "name", "message"];
+
+	const enableOverride = (object, key) => {
+		const descriptor = Object.getOwnPropertyDescriptor(object, key);
+		if (!descriptor || !("value" in descriptor) || !descriptor.configurable) {
+			return;
+		}
+
+		const value = descriptor.value;
+		Object.defineProperty(object, key, {
+			get() {
+				return value;
+			},
+			set(newValue) {
+				if (this === object) {
+					throw new TypeError(`Cannot assign to property '${key}' of a frozen intrinsic`);
+				}
+				Object.defineProperty(this, key, { value: newValue, writable: true, enumerable: true, configurable: true });
+			},
+			enumerable: descriptor.enumerable,
+			configurable: false,
+		});
+	};
+
+	for (const name of ["Object", "Function", "Array", "Error", "AggregateError", "EvalError", "RangeError", "ReferenceError",
+		"SyntaxError", "TypeError", "URIError"]) {
+		for (const key of overridable) {
+			enableOverride(globalThis[name].prototype, key);
+		}
+	}
+
+	const frozen = new Set();
+	const freeze = (value) => {
+		if ((typeof value !== "object" || value === null) && typeof value !== "function") {
+			return;
+		}
+		if (frozen.has(value)) {
+			return;
+		}
+
+		frozen.add(value);
+		Object.freeze(value);
+		freeze(Object.getPrototypeOf(value));
+
+		for (const key of Reflect.ownKeys(value)) {
+			const descriptor = Object.getOwnPropertyDescriptor(value, key);
+			freeze(descriptor.value);
+			freeze(descriptor.get);
+			freeze(descriptor.set);
+		}
+	};
+
+	for (const name of names) {
+		freeze(globalThis[name]);
+	}
+	for (const intrinsic of hidden) {
+		freeze(intrinsic);
+	}
+})();
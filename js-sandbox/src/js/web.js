@@ -0,0 +1,23 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+// Installs the web APIs provided by the Deno extensions as globals.
+
+import * as console from "ext:deno_console/01_console.js";
+import * as url from "ext:deno_url/00_url.js";
+import * as timers from "ext:deno_web/02_timers.js";
+import * as base64 from "ext:deno_web/05_base64.js";
+import * as encoding from "ext:deno_web/08_text_encoding.js";
+
+Object.assign(globalThis, {
+	console: new console.Console((msg, level) => Deno.core.ops.op_console_print(msg, level > 1)),
+	URL: url.URL,
+	URLSearchParams: url.URLSearchParams,
+	TextEncoder: encoding.TextEncoder,
+	TextDecoder: encoding.TextDecoder,
+	atob: base64.atob,
+	btoa: base64.btoa,
+	setTimeout: timers.setTimeout,
+	clearTimeout: timers.clearTimeout,
+	setInterval: timers.setInterval,
+	clearInterval: timers.clearInterval,
+});
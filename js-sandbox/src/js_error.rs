@@ -1,9 +1,12 @@
 use std::{
 	error::Error,
 	fmt::{self, Display},
+	time::Duration,
 };
 
-use crate::AnyError;
+use serde::de::DeserializeOwned;
+
+use crate::{AnyError, JsValue, Provenance};
 
 /// Represents an error ocurring during script execution
 #[derive(Debug)]
@@ -13,6 +16,138 @@ pub enum JsError {
 
 	/// Runtime errors occuring within a JS script
 	Runtime(AnyError),
+
+	/// A value other than an `Error` object was thrown, e.g. `throw { code: 404 }` or `throw "not found"`
+	///
+	/// Scripts can use this to report structured failure data, see [`JsError::thrown_as()`].
+	Thrown {
+		/// The thrown value, converted like a return value.
+		value: JsValue,
+
+		/// The exception as reported by the JS engine, including where it was thrown.
+		error: AnyError,
+	},
+
+	/// Call was aborted because it exceeded the timeout set with [`Script::with_timeout()`](crate::Script::with_timeout)
+	Timeout {
+		/// Time from the start of the call until it was aborted.
+		elapsed: Duration,
+	},
+
+	/// Execution was aborted because the JS heap reached the limit set with
+	/// [`ScriptBuilder::with_max_heap_size()`](crate::ScriptBuilder::with_max_heap_size)
+	MemoryLimit {
+		/// Used heap size in bytes (including external memory), when execution was aborted.
+		used: usize,
+
+		/// Configured maximum heap size in bytes.
+		limit: usize,
+	},
+
+	/// Call was aborted because it used up the time slices granted by
+	/// [`Script::with_time_slice_budget()`](crate::Script::with_time_slice_budget)
+	BudgetExhausted {
+		/// Configured number of time slices per call.
+		slices: u64,
+	},
+
+	/// Call was aborted by the callback set with [`Script::with_preemption()`](crate::Script::with_preemption)
+	Preempted {
+		/// Time from the start of the call until it was aborted.
+		elapsed: Duration,
+	},
+
+	/// Promise returned by the function did not settle within the timeout set with
+	/// [`Script::with_settle_timeout()`](crate::Script::with_settle_timeout), or can never settle because no work is pending
+	PendingPromise {
+		/// Time from the start of the call until it was aborted.
+		elapsed: Duration,
+	},
+
+	/// Call was aborted because it ran more microtasks than allowed by
+	/// [`ScriptBuilder::with_max_microtasks()`](crate::ScriptBuilder::with_max_microtasks)
+	MicrotaskLimit {
+		/// Configured maximum number of microtasks per call.
+		limit: u64,
+	},
+
+	/// Call was aborted because its promises did not settle within the event loop turns allowed by
+	/// [`ScriptBuilder::with_max_event_loop_turns()`](crate::ScriptBuilder::with_max_event_loop_turns)
+	EventLoopLimit {
+		/// Configured maximum number of event loop turns per call.
+		limit: u64,
+	},
+
+	/// Call was aborted because JS functions were nested deeper than the stack allowed by
+	/// [`ScriptBuilder::with_recursion_budget()`](crate::ScriptBuilder::with_recursion_budget)
+	RecursionLimit {
+		/// Name of the innermost function when the limit was hit, usually the one recursing. `None` for anonymous functions.
+		function: Option<String>,
+
+		/// Configured recursion budget, in approximate calls.
+		limit: usize,
+	},
+
+	/// Script file did not match the hash passed to [`Script::from_file_verified()`](crate::Script::from_file_verified)
+	IntegrityMismatch {
+		/// Expected SHA-256 hash, as passed by the host.
+		expected: String,
+
+		/// SHA-256 hash of the file's contents, hex-encoded.
+		actual: String,
+	},
+
+	/// Error of a script with provenance, see [`ScriptBuilder::with_provenance()`](crate::ScriptBuilder::with_provenance)
+	///
+	/// Wraps errors that occur while the script's code runs or its results are converted. Use [`JsError::inner()`] to match on the
+	/// wrapped error.
+	WithProvenance {
+		/// Provenance of the script that produced the error.
+		provenance: Provenance,
+
+		/// The actual error.
+		error: Box<JsError>,
+	},
+
+	/// Return value was rejected by the validator registered with
+	/// [`Script::validate_result()`](crate::Script::validate_result)
+	InvalidResult {
+		/// Name of the called function.
+		function: String,
+
+		/// Description of what is wrong with the value, as reported by the validator.
+		reason: String,
+	},
+}
+
+impl JsError {
+	/// Deserializes the value of a [`JsError::Thrown`] error into `T`.
+	///
+	/// Returns `None` for other errors, or if the thrown value does not match `T`.
+	pub fn thrown_as<T: DeserializeOwned>(&self) -> Option<T> {
+		match self.inner() {
+			JsError::Thrown { value, .. } => serde_json::from_value(value.clone()).ok(),
+			_ => None,
+		}
+	}
+}
+
+impl JsError {
+	/// The error itself, or the one wrapped by [`JsError::WithProvenance`].
+	pub fn inner(&self) -> &JsError {
+		match self {
+			JsError::WithProvenance { error, .. } => error.inner(),
+			_ => self,
+		}
+	}
+
+	/// Provenance of the script that produced this error, if any.
+	pub fn provenance(&self) -> Option<&Provenance> {
+		match self {
+			JsError::WithProvenance { provenance, .. } => Some(provenance),
+			_ => None,
+		}
+	}
 }
 
 impl Error for JsError {}
@@ -22,11 +157,77 @@ impl Display for JsError {
 		match self {
 			JsError::Json(e) => write!(f, "{}", e),
 			JsError::Runtime(e) => write!(f, "{}", e),
+			JsError::Thrown { error, .. } => write!(f, "{}", error),
+			JsError::Timeout { elapsed } => {
+				write!(f, "execution timed out after {}ms", elapsed.as_millis())
+			}
+			JsError::MemoryLimit { used, limit } => {
+				write!(
+					f,
+					"heap limit of {limit} bytes exceeded ({used} bytes used)"
+				)
+			}
+			JsError::BudgetExhausted { slices } => {
+				write!(f, "time-slice budget of {slices} slices per call exhausted")
+			}
+			JsError::Preempted { elapsed } => {
+				write!(
+					f,
+					"execution aborted by preemption callback after {}ms",
+					elapsed.as_millis()
+				)
+			}
+			JsError::PendingPromise { elapsed } => {
+				write!(
+					f,
+					"returned promise still pending after {}ms",
+					elapsed.as_millis()
+				)
+			}
+			JsError::MicrotaskLimit { limit } => {
+				write!(f, "microtask limit of {limit} per call exceeded")
+			}
+			JsError::EventLoopLimit { limit } => {
+				write!(f, "event loop turn limit of {limit} per call exceeded")
+			}
+			JsError::RecursionLimit { function, limit } => match function {
+				Some(function) => write!(
+					f,
+					"recursion limit of {limit} calls exceeded in function `{function}`"
+				),
+				None => write!(f, "recursion limit of {limit} calls exceeded"),
+			},
+			JsError::IntegrityMismatch { expected, actual } => {
+				write!(
+					f,
+					"integrity check failed: expected SHA-256 {expected}, got {actual}"
+				)
+			}
+			JsError::WithProvenance { provenance, error } => write!(f, "[{provenance}] {error}"),
+			JsError::InvalidResult { function, reason } => {
+				write!(
+					f,
+					"call(\"{function}\"): script returned invalid shape: {reason}"
+				)
+			}
 		}
 	}
 }
 
 impl From<AnyError> for JsError {
+	#[cfg(feature = "v8")]
+	fn from(e: AnyError) -> JsError {
+		match e.downcast::<ThrownValue>() {
+			Ok(thrown) => JsError::Thrown {
+				value: thrown.value,
+				error: thrown.error.into(),
+			},
+			Err(e) => JsError::Runtime(e),
+		}
+	}
+
+	// Thrown values are only detected by the V8 runtime
+	#[cfg(not(feature = "v8"))]
 	fn from(e: AnyError) -> JsError {
 		JsError::Runtime(e)
 	}
@@ -37,3 +238,21 @@ impl From<serde_json::Error> for JsError {
 		JsError::Json(e)
 	}
 }
+
+/// Exception with a thrown value other than an `Error`, which becomes [`JsError::Thrown`] once converted.
+#[cfg(feature = "v8")]
+#[derive(Debug)]
+pub(crate) struct ThrownValue {
+	pub value: JsValue,
+	pub error: deno_core::error::JsError,
+}
+
+#[cfg(feature = "v8")]
+impl Error for ThrownValue {}
+
+#[cfg(feature = "v8")]
+impl Display for ThrownValue {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.error)
+	}
+}
@@ -0,0 +1,243 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+/// Lexical token, as far as needed for lightweight source checks.
+///
+/// String literals, comments, numbers and regex literals are skipped. Like in JS parsers, whether a `/` starts a regex or is a
+/// division operator is decided by the preceding token.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum TokenKind<'a> {
+	Ident(&'a str),
+	Punct(char),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Token<'a> {
+	pub kind: TokenKind<'a>,
+	/// 1-based line number.
+	pub line: usize,
+	/// 1-based column, counted in characters.
+	pub column: usize,
+}
+
+/// Splits JS source code into identifiers and punctuation.
+pub(crate) fn tokenize(src: &str) -> Vec<Token<'_>> {
+	let mut lexer = Lexer {
+		src,
+		chars: src.char_indices().peekable(),
+		line: 1,
+		column: 1,
+		brace_depth: 0,
+		template_depths: Vec::new(),
+		regex_allowed: true,
+		tokens: Vec::new(),
+	};
+
+	lexer.run();
+	lexer.tokens
+}
+
+struct Lexer<'a> {
+	src: &'a str,
+	chars: Peekable<CharIndices<'a>>,
+	line: usize,
+	column: usize,
+	brace_depth: usize,
+	// Brace depths at which `${` expressions inside template literals were entered
+	template_depths: Vec<usize>,
+	// Whether a `/` at this point starts a regex literal rather than a division, i.e. an expression is expected
+	regex_allowed: bool,
+	tokens: Vec<Token<'a>>,
+}
+
+impl<'a> Lexer<'a> {
+	fn run(&mut self) {
+		while let Some((start, c)) = self.peek() {
+			let (line, column) = (self.line, self.column);
+			self.bump();
+
+			match c {
+				'"' | '\'' => {
+					self.skip_string(c);
+					self.regex_allowed = false;
+				}
+				'`' => self.skip_template(),
+				'/' if self.peek_char() == Some('/') => self.skip_until(|c| c == '\n'),
+				'/' if self.peek_char() == Some('*') => self.skip_block_comment(),
+				'/' if self.regex_allowed => {
+					self.skip_regex();
+					self.regex_allowed = false;
+				}
+				'}' if self.template_depths.last() == Some(&self.brace_depth) => {
+					// End of `${...}` expression, continue with the template literal
+					self.template_depths.pop();
+					self.skip_template();
+				}
+				c if c.is_ascii_digit() => {
+					self.skip_while(|c| c.is_alphanumeric() || c == '.' || c == '_');
+					self.regex_allowed = false;
+				}
+				c if is_ident_start(c) => {
+					self.skip_while(is_ident_continue);
+					let end = self.peek().map_or(self.src.len(), |(i, _)| i);
+					let ident = &self.src[start..end];
+
+					self.regex_allowed = precedes_expression(ident);
+					self.push(TokenKind::Ident(ident), line, column);
+				}
+				c if c.is_whitespace() => {}
+				c => {
+					match c {
+						'{' => self.brace_depth += 1,
+						'}' => self.brace_depth = self.brace_depth.saturating_sub(1),
+						_ => {}
+					}
+
+					// After `)` or `]`, an operand has ended. `}` usually ends a block, after which a new statement begins
+					self.regex_allowed = !matches!(c, ')' | ']');
+					self.push(TokenKind::Punct(c), line, column);
+				}
+			}
+		}
+	}
+
+	fn push(&mut self, kind: TokenKind<'a>, line: usize, column: usize) {
+		self.tokens.push(Token { kind, line, column });
+	}
+
+	fn peek(&mut self) -> Option<(usize, char)> {
+		self.chars.peek().copied()
+	}
+
+	fn peek_char(&mut self) -> Option<char> {
+		self.peek().map(|(_, c)| c)
+	}
+
+	fn bump(&mut self) -> Option<char> {
+		let (_, c) = self.chars.next()?;
+		if c == '\n' {
+			self.line += 1;
+			self.column = 1;
+		} else {
+			self.column += 1;
+		}
+		Some(c)
+	}
+
+	fn skip_while(&mut self, pred: impl Fn(char) -> bool) {
+		while self.peek_char().is_some_and(&pred) {
+			self.bump();
+		}
+	}
+
+	fn skip_until(&mut self, pred: impl Fn(char) -> bool) {
+		self.skip_while(|c| !pred(c));
+	}
+
+	fn skip_string(&mut self, quote: char) {
+		while let Some(c) = self.bump() {
+			match c {
+				'\\' => {
+					self.bump();
+				}
+				'\n' => break, // unterminated
+				_ if c == quote => break,
+				_ => {}
+			}
+		}
+	}
+
+	fn skip_block_comment(&mut self) {
+		self.bump(); // '*'
+		let mut prev = '\0';
+		while let Some(c) = self.bump() {
+			if prev == '*' && c == '/' {
+				break;
+			}
+			prev = c;
+		}
+	}
+
+	/// Skips template literal content, up to the closing backtick or the start of a `${...}` expression.
+	fn skip_template(&mut self) {
+		self.regex_allowed = false;
+		while let Some(c) = self.bump() {
+			match c {
+				'\\' => {
+					self.bump();
+				}
+				'`' => break,
+				'$' if self.peek_char() == Some('{') => {
+					self.bump();
+					self.template_depths.push(self.brace_depth);
+					self.regex_allowed = true;
+					break;
+				}
+				_ => {}
+			}
+		}
+	}
+
+	/// Skips a regex literal after its opening `/`, including its flags.
+	fn skip_regex(&mut self) {
+		let mut in_class = false;
+		while let Some(c) = self.peek_char() {
+			if c == '\n' {
+				break; // unterminated
+			}
+
+			self.bump();
+			match c {
+				'\\' => {
+					self.bump();
+				}
+				'[' => in_class = true,
+				']' => in_class = false,
+				'/' if !in_class => {
+					self.skip_while(is_ident_continue);
+					break;
+				}
+				_ => {}
+			}
+		}
+	}
+}
+
+/// Whether `ident` is a keyword after which an expression follows, such as `return`. After other identifiers, `/` is a division.
+fn precedes_expression(ident: &str) -> bool {
+	const KEYWORDS: &[&str] = &[
+		"return",
+		"typeof",
+		"instanceof",
+		"in",
+		"of",
+		"new",
+		"delete",
+		"void",
+		"throw",
+		"case",
+		"do",
+		"else",
+		"yield",
+		"await",
+	];
+
+	KEYWORDS.contains(&ident)
+}
+
+#[cfg(any(feature = "v8", feature = "quickjs"))]
+/// Whether `name` can be used as JS identifier (restricted to letters, digits, `_` and `$`).
+pub(crate) fn is_identifier(name: &str) -> bool {
+	let mut chars = name.chars();
+	chars.next().is_some_and(is_ident_start) && chars.all(is_ident_continue)
+}
+
+fn is_ident_start(c: char) -> bool {
+	c.is_alphabetic() || c == '_' || c == '$'
+}
+
+fn is_ident_continue(c: char) -> bool {
+	c.is_alphanumeric() || c == '_' || c == '$'
+}
@@ -81,7 +81,9 @@
 //! JavaScript files can be loaded from any `Path` at runtime (e.g. 3rd party mods).
 //!
 //! If you want to statically embed UTF-8 encoded files in the Rust binary, you can alternatively use the
-//! [`std::include_str`](https://doc.rust-lang.org/std/macro.include_str.html) macro.
+//! [`std::include_str`](https://doc.rust-lang.org/std/macro.include_str.html) macro. Its counterpart [`js_include!`] additionally
+//! checks the syntax at compile time when the `syntax-check` feature is enabled, turning errors in embedded scripts into compile
+//! errors.
 //!
 //! ```rust,no_run
 //! # macro_rules! include_str { ( $($tt:tt)* ) => { "" } }
@@ -140,10 +142,7 @@
 //!
 //! 	let result: Result<String, JsError> = script.call("run_forever", ());
 //!
-//! 	assert_eq!(
-//! 		result.unwrap_err().to_string(),
-//! 		"Uncaught Error: execution terminated".to_string()
-//! 	);
+//! 	assert!(matches!(result, Err(JsError::Timeout { .. })));
 //!
 //! 	Ok(())
 //! }
@@ -152,10 +151,64 @@
 //! [Deno]: https://deno.land
 //! [serde_json]: https://docs.serde.rs/serde_json
 
+pub use analysis::{find_forbidden_apis, ForbiddenApiFinding, DEFAULT_FORBIDDEN_APIS};
+#[cfg(feature = "v8")]
+pub use api_check::{ApiFunction, ApiMismatch};
+#[cfg(feature = "v8")]
+pub use async_handle::AsyncScriptHandle;
+#[cfg(feature = "v8")]
+pub use audit::{AuditEvent, BlockedOperation};
+#[cfg(feature = "v8")]
+pub use builder::ScriptBuilder;
 pub use call_args::CallArgs;
-pub use js_sandbox_macros::js_api;
+#[cfg(feature = "v8")]
+pub use call_options::{CallOptions, RetryOn};
+#[cfg(feature = "v8")]
+pub use capability::CapabilityRegistry;
+#[cfg(feature = "v8")]
+pub use clock::{Clock, SystemClock, VirtualClock};
+#[cfg(feature = "v8")]
+pub use convert::{FromJs, ToJs, ToJsArgs};
+#[cfg(feature = "v8")]
+pub use engine::ScriptEngine;
+#[cfg(feature = "v8")]
+pub use env::ScriptEnv;
+#[cfg(feature = "v8")]
+pub use host_object::HostObject;
+#[cfg(feature = "v8")]
+pub use invoke::CallId;
+pub use js_sandbox_macros::{js_api, js_fn, js_host_object, js_include};
+#[cfg(feature = "v8")]
+pub use loader::{ImportPolicy, ImportType};
+#[cfg(feature = "v8")]
+pub use manager::{SandboxManager, TenantLimits};
+#[cfg(feature = "v8")]
+pub use mock::{MockCall, MockScript};
+#[cfg(feature = "v8")]
+pub use module::ModuleExport;
+#[cfg(feature = "v8")]
+pub use pipeline::Pipeline;
+#[cfg(feature = "v8")]
+pub use platform::init_platform;
+#[cfg(feature = "v8")]
+pub use policy::Policy;
+#[cfg(feature = "v8")]
+pub use preemption::{Checkpoint, Preemption};
+pub use provenance::Provenance;
+#[cfg(feature = "v8")]
 pub use script::*;
-pub use util::eval_json;
+#[cfg(feature = "sql")]
+pub use sql::SqlDatabase;
+#[cfg(feature = "v8")]
+pub use storage::ScriptStorage;
+#[cfg(feature = "v8")]
+pub use supervisor::SupervisedScript;
+#[cfg(feature = "v8")]
+pub use termination::FatalCondition;
+#[cfg(feature = "v8")]
+pub use usage::UsageReport;
+#[cfg(feature = "v8")]
+pub use util::{eval_json, global};
 
 /// Represents a value passed to or from JavaScript.
 ///
@@ -168,13 +221,105 @@ pub use js_error::JsError;
 /// Polymorphic error type able to represent different error domains.
 ///
 /// Currently reusing [anyhow::Error](../anyhow/enum.Error.html), this type may change slightly in the future depending on js-sandbox's needs.
-// use through deno_core, to make sure same version of anyhow crate is used
-pub type AnyError = deno_core::error::AnyError;
+// same anyhow version as used by deno_core, so that errors of ops and the runtime convert without wrapping
+pub type AnyError = anyhow::Error;
 
 /// Wrapper type representing a result that can result in a JS runtime error
 pub type JsResult<T> = Result<T, JsError>;
 
+#[doc(hidden)]
+#[cfg(feature = "v8")]
+pub mod __private {
+	pub use crate::engine::{arg_value, call_spread, call_spread_ref};
+	pub use crate::host_object::{arg_from_json, result_to_json, unknown_method};
+	pub use crate::util::js_fn_script;
+}
+
+#[cfg(feature = "quickjs")]
+pub mod quickjs;
+#[cfg(feature = "v8")]
+pub mod snapshot;
+
+mod analysis;
+#[cfg(feature = "v8")]
+mod api_check;
+#[cfg(feature = "v8")]
+mod async_handle;
+#[cfg(feature = "v8")]
+mod audit;
+#[cfg(feature = "v8")]
+mod budget;
+#[cfg(feature = "v8")]
+mod builder;
 mod call_args;
+#[cfg(feature = "v8")]
+mod call_options;
+#[cfg(feature = "v8")]
+mod capability;
+#[cfg(feature = "v8")]
+mod clock;
+#[cfg(any(feature = "v8", feature = "quickjs"))]
+mod console;
+#[cfg(feature = "v8")]
+mod context;
+#[cfg(feature = "v8")]
+mod convert;
+#[cfg(feature = "v8")]
+mod engine;
+#[cfg(feature = "v8")]
+mod env;
+#[cfg(feature = "v8")]
+mod hooks;
+#[cfg(feature = "v8")]
+mod host_object;
+#[cfg(feature = "v8")]
+mod integrity;
+#[cfg(feature = "v8")]
+mod invoke;
 mod js_error;
+mod lexer;
+#[cfg(feature = "v8")]
+mod limits;
+#[cfg(feature = "v8")]
+mod loader;
+#[cfg(feature = "v8")]
+mod manager;
+#[cfg(feature = "v8")]
+mod mock;
+#[cfg(feature = "v8")]
+mod module;
+#[cfg(feature = "v8")]
+mod namespace;
+#[cfg(feature = "v8")]
+mod panic_guard;
+#[cfg(feature = "v8")]
+mod pipeline;
+#[cfg(feature = "v8")]
+mod platform;
+#[cfg(feature = "v8")]
+mod policy;
+#[cfg(feature = "v8")]
+mod preemption;
+mod provenance;
+#[cfg(feature = "v8")]
 mod script;
+#[cfg(feature = "v8")]
+mod sink;
+#[cfg(feature = "sql")]
+mod sql;
+#[cfg(feature = "v8")]
+mod storage;
+#[cfg(feature = "v8")]
+mod supervisor;
+#[cfg(feature = "v8")]
+mod task_limits;
+#[cfg(feature = "v8")]
+mod termination;
+#[cfg(feature = "v8")]
+mod usage;
+#[cfg(feature = "v8")]
 mod util;
+#[cfg(feature = "v8")]
+mod watchdog;
+#[cfg(feature = "web")]
+mod web;
@@ -0,0 +1,245 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::alloc::{self, Layout};
+use std::ffi::c_void;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use deno_core::v8;
+
+use crate::lexer::{self, TokenKind};
+use crate::{usage, AnyError, JsError, JsValue};
+
+/// Fails if `size` bytes of source code exceed the configured maximum.
+pub(crate) fn check_source_size(size: u64, max_size: Option<usize>) -> Result<(), JsError> {
+	match max_size {
+		Some(max) if size > max as u64 => Err(limit_error(format!(
+			"source code has {size} bytes, exceeding the limit of {max} bytes"
+		))),
+		_ => Ok(()),
+	}
+}
+
+/// Reads the source code in `file`, failing if it exceeds the configured maximum.
+///
+/// At most one byte more than the maximum is read, so the check holds even if the file grows while it is being loaded.
+pub(crate) fn read_source(file: &Path, max_size: Option<usize>) -> Result<Vec<u8>, JsError> {
+	let mut file = File::open(file).map_err(AnyError::from)?;
+	let mut js_code = Vec::new();
+	match max_size {
+		Some(max) => {
+			file.take(max as u64 + 1)
+				.read_to_end(&mut js_code)
+				.map_err(AnyError::from)?;
+			if js_code.len() > max {
+				return Err(limit_error(format!(
+					"source code exceeds the limit of {max} bytes"
+				)));
+			}
+		}
+		None => {
+			file.read_to_end(&mut js_code).map_err(AnyError::from)?;
+		}
+	}
+
+	Ok(js_code)
+}
+
+/// Fails if the JSON representation of a call's result exceeds `max_size` bytes.
+pub(crate) fn check_result_size(value: &JsValue, max_size: usize) -> Result<(), JsError> {
+	let size = usage::json_size(value);
+	if size > max_size as u64 {
+		return Err(limit_error(format!(
+			"result has {size} bytes, exceeding the limit of {max_size} bytes"
+		)));
+	}
+
+	Ok(())
+}
+
+/// Fails if brackets in the source code are nested deeper than the configured maximum.
+///
+/// This is a lexical approximation, which runs before V8's parser ever sees the code: string literals (including the text of
+/// template literals), comments and regex literals are skipped. It is meant as a coarse guard against pathological input, not
+/// as a validator.
+pub(crate) fn check_nesting_depth(js_code: &str, max_depth: Option<usize>) -> Result<(), JsError> {
+	let Some(max_depth) = max_depth else {
+		return Ok(());
+	};
+
+	let mut depth = 0usize;
+	for token in lexer::tokenize(js_code) {
+		match token.kind {
+			TokenKind::Punct('(' | '[' | '{') => {
+				depth += 1;
+				if depth > max_depth {
+					return Err(limit_error(format!(
+						"source code nests brackets deeper than the limit of {max_depth}"
+					)));
+				}
+			}
+			TokenKind::Punct(')' | ']' | '}') => depth = depth.saturating_sub(1),
+			_ => {}
+		}
+	}
+
+	Ok(())
+}
+
+/// Approximate stack usage of a JS call, in bytes. Frames of simple interpreted functions take roughly this much.
+const CALL_FRAME_SIZE: usize = 96;
+
+/// Smallest stack (in KiB) that is known to suffice for initializing a script.
+const MIN_STACK_SIZE: usize = 128;
+
+/// Stack size in KiB that allows about `budget` nested calls, see
+/// [`ScriptBuilder::with_recursion_budget()`](crate::ScriptBuilder::with_recursion_budget).
+pub(crate) fn recursion_stack_size(budget: usize) -> usize {
+	(budget.saturating_mul(CALL_FRAME_SIZE) / 1024).max(MIN_STACK_SIZE)
+}
+
+/// Converts an uncaught stack overflow to [`JsError::RecursionLimit`]. Returns other errors unchanged.
+pub(crate) fn recursion_error(error: AnyError, budget: usize) -> Result<JsError, AnyError> {
+	let Some(js_error) = error.downcast_ref::<deno_core::error::JsError>() else {
+		return Err(error);
+	};
+
+	let is_overflow = js_error.name.as_deref() == Some("RangeError")
+		&& js_error
+			.message
+			.as_deref()
+			.is_some_and(|message| message.starts_with("Maximum call stack size exceeded"));
+	if !is_overflow {
+		return Err(error);
+	}
+
+	let function = js_error
+		.frames
+		.first()
+		.and_then(|frame| frame.function_name.clone());
+
+	Ok(JsError::RecursionLimit {
+		function,
+		limit: budget,
+	})
+}
+
+/// Array buffer allocator which fails allocations once the buffers of a script would exceed `limit` bytes in total, see
+/// [`ScriptBuilder::with_max_heap_size()`](crate::ScriptBuilder::with_max_heap_size).
+///
+/// V8 does not count array buffers towards its heap limit, so the cap has to be enforced while they are allocated: a single
+/// `new ArrayBuffer(8e9)` would otherwise be served from host memory before any check could run. In JS, failed allocations
+/// throw a `RangeError`.
+pub(crate) struct BoundedAllocator {
+	limit: usize,
+	allocated: AtomicUsize,
+}
+
+impl BoundedAllocator {
+	/// Creates an allocator for a single isolate.
+	pub fn new_shared(limit: usize) -> v8::SharedRef<v8::Allocator> {
+		let allocator = Box::into_raw(Box::new(Self {
+			limit,
+			allocated: AtomicUsize::new(0),
+		}));
+
+		// SAFETY: the pointer remains valid until V8 invokes `drop`, which frees it
+		unsafe { v8::new_rust_allocator(allocator, &BOUNDED_ALLOCATOR_VTABLE) }.make_shared()
+	}
+
+	/// Accounts for `len` more bytes, unless that exceeds the limit.
+	fn reserve(&self, len: usize) -> bool {
+		self.allocated
+			.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |allocated| {
+				allocated
+					.checked_add(len)
+					.filter(|&total| total <= self.limit)
+			})
+			.is_ok()
+	}
+
+	fn release(&self, len: usize) {
+		self.allocated.fetch_sub(len, Ordering::SeqCst);
+	}
+}
+
+static BOUNDED_ALLOCATOR_VTABLE: v8::RustAllocatorVtable<BoundedAllocator> =
+	v8::RustAllocatorVtable {
+		allocate: bounded_allocate,
+		allocate_uninitialized: bounded_allocate_uninitialized,
+		free: bounded_free,
+		reallocate: bounded_reallocate,
+		drop: bounded_drop,
+	};
+
+/// Layout of a buffer with `len` bytes. Empty buffers still get an allocation, so that every buffer has a distinct address.
+fn buffer_layout(len: usize) -> Option<Layout> {
+	Layout::from_size_align(len.max(1), 16).ok()
+}
+
+unsafe fn bounded_allocate_with(
+	allocator: &BoundedAllocator,
+	len: usize,
+	alloc: unsafe fn(Layout) -> *mut u8,
+) -> *mut c_void {
+	let Some(layout) = buffer_layout(len) else {
+		return ptr::null_mut();
+	};
+	if !allocator.reserve(len) {
+		return ptr::null_mut();
+	}
+
+	let data = alloc(layout);
+	if data.is_null() {
+		allocator.release(len);
+	}
+	data.cast()
+}
+
+unsafe extern "C" fn bounded_allocate(allocator: &BoundedAllocator, len: usize) -> *mut c_void {
+	bounded_allocate_with(allocator, len, alloc::alloc_zeroed)
+}
+
+unsafe extern "C" fn bounded_allocate_uninitialized(
+	allocator: &BoundedAllocator,
+	len: usize,
+) -> *mut c_void {
+	bounded_allocate_with(allocator, len, alloc::alloc)
+}
+
+unsafe extern "C" fn bounded_free(allocator: &BoundedAllocator, data: *mut c_void, len: usize) {
+	if let Some(layout) = buffer_layout(len) {
+		alloc::dealloc(data.cast(), layout);
+		allocator.release(len);
+	}
+}
+
+unsafe extern "C" fn bounded_reallocate(
+	allocator: &BoundedAllocator,
+	data: *mut c_void,
+	old_len: usize,
+	new_len: usize,
+) -> *mut c_void {
+	// Like V8's default: allocate a zeroed buffer, copy the contents, and free the old one
+	let new_data = bounded_allocate(allocator, new_len);
+	if !new_data.is_null() {
+		ptr::copy_nonoverlapping(
+			data.cast::<u8>(),
+			new_data.cast::<u8>(),
+			old_len.min(new_len),
+		);
+		bounded_free(allocator, data, old_len);
+	}
+	new_data
+}
+
+unsafe extern "C" fn bounded_drop(allocator: *const BoundedAllocator) {
+	drop(Box::from_raw(allocator as *mut BoundedAllocator));
+}
+
+fn limit_error(message: String) -> JsError {
+	JsError::Runtime(AnyError::msg(message))
+}
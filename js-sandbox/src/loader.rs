@@ -0,0 +1,219 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::path::{Component, Path, PathBuf};
+use std::pin::Pin;
+
+use deno_core::futures::{future, FutureExt};
+use deno_core::{
+	FsModuleLoader, ModuleLoader, ModuleSource, ModuleSourceFuture, ModuleSpecifier, ModuleType,
+	ResolutionKind,
+};
+
+use crate::audit::{AuditEvent, AuditLog, BlockedOperation};
+use crate::integrity::ModuleVerifier;
+use crate::AnyError;
+
+/// Which modules scripts may import, with static `import` declarations or dynamic `import()`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ImportPolicy {
+	/// Imports are rejected. Only the script's own code is loaded.
+	Deny,
+
+	/// Modules are loaded from the file system, with the permissions of the host process.
+	#[default]
+	FileSystem,
+
+	/// Modules are loaded from the given directory only, which acts as the root of the file system for imports.
+	///
+	/// Specifiers are resolved like URLs below the root: `/lib/util.js`, and `./util.js` from a module in `/lib`, both refer to
+	/// `<root>/lib/util.js`. Other URL schemes are denied, and so are files that lie outside the root once symbolic links are
+	/// resolved. The root must exist when modules are loaded.
+	Directory(PathBuf),
+}
+
+/// Kind of module other than JavaScript, imported with an import attribute such as `with { type: "json" }`.
+///
+/// Text and bytes imports (`type: "text"` and `type: "bytes"`) are not supported by the engine, and therefore always fail.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ImportType {
+	/// JSON modules, imported with `type: "json"`.
+	Json,
+}
+
+/// Module loader enforcing an [`ImportPolicy`] and the allowed [`ImportType`]s, recording denied imports in the audit log.
+/// Loaded modules are passed to the verifier, if any.
+pub(crate) struct SandboxLoader {
+	policy: ImportPolicy,
+	// None if all types are allowed
+	import_types: Option<Vec<ImportType>>,
+	verifier: Option<ModuleVerifier>,
+	audit: AuditLog,
+}
+
+impl SandboxLoader {
+	pub fn new(
+		policy: ImportPolicy,
+		import_types: Option<Vec<ImportType>>,
+		verifier: Option<ModuleVerifier>,
+		audit: AuditLog,
+	) -> Self {
+		Self {
+			policy,
+			import_types,
+			verifier,
+			audit,
+		}
+	}
+
+	/// Records a denied import in the audit log, and returns the error for it.
+	fn deny(
+		&self,
+		is_dyn_import: bool,
+		specifier: &str,
+		referrer: Option<&str>,
+		reason: &str,
+	) -> AnyError {
+		let operation = if is_dyn_import {
+			BlockedOperation::DynamicImport
+		} else {
+			BlockedOperation::Import
+		};
+		self.audit.record(AuditEvent {
+			operation,
+			target: specifier.to_string(),
+			origin: referrer.map(str::to_string),
+			stack: Vec::new(),
+			provenance: None,
+		});
+
+		AnyError::msg(format!("import of `{specifier}` denied: {reason}"))
+	}
+
+	fn allows(&self, import_type: ImportType) -> bool {
+		match &self.import_types {
+			Some(types) => types.contains(&import_type),
+			None => true,
+		}
+	}
+}
+
+impl ModuleLoader for SandboxLoader {
+	fn resolve(
+		&self,
+		specifier: &str,
+		referrer: &str,
+		kind: ResolutionKind,
+	) -> Result<ModuleSpecifier, AnyError> {
+		let is_dyn_import = matches!(kind, ResolutionKind::DynamicImport);
+
+		// The script itself is resolved as main module, which is always allowed
+		let reason = match &self.policy {
+			ImportPolicy::Deny if !matches!(kind, ResolutionKind::MainModule) => {
+				"imports are disabled"
+			}
+			ImportPolicy::Directory(_) => {
+				let resolved = FsModuleLoader.resolve(specifier, referrer, kind)?;
+				if resolved.scheme() == "file" {
+					return Ok(resolved);
+				}
+				"only files below the root directory can be imported"
+			}
+			_ => return FsModuleLoader.resolve(specifier, referrer, kind),
+		};
+
+		Err(self.deny(is_dyn_import, specifier, Some(referrer), reason))
+	}
+
+	fn load(
+		&self,
+		module_specifier: &ModuleSpecifier,
+		maybe_referrer: Option<&ModuleSpecifier>,
+		is_dyn_import: bool,
+	) -> Pin<Box<ModuleSourceFuture>> {
+		// Only reachable for resolved specifiers
+		let load = match &self.policy {
+			ImportPolicy::Directory(root) => {
+				let result = read_below_root(root, module_specifier).map_err(|error| match error {
+					RootError::Escape => self.deny(
+						is_dyn_import,
+						module_specifier.as_str(),
+						maybe_referrer.map(ModuleSpecifier::as_str),
+						"file lies outside the root directory",
+					),
+					RootError::Io(error) => error,
+				});
+				future::ready(result).boxed_local()
+			}
+			_ => FsModuleLoader.load(module_specifier, maybe_referrer, is_dyn_import),
+		};
+		if self.allows(ImportType::Json) && self.verifier.is_none() {
+			return load;
+		}
+
+		let json_allowed = self.allows(ImportType::Json);
+		let verifier = self.verifier.clone();
+		let specifier = module_specifier.clone();
+		let origin = maybe_referrer.map(ToString::to_string);
+		let audit = self.audit.clone();
+		async move {
+			let source = load.await?;
+			if let Some(verifier) = verifier {
+				verifier(specifier.as_str(), source.code.as_bytes())?;
+			}
+
+			// The import attribute is checked against the module type by the engine, so the type of the loaded module decides
+			if json_allowed || source.module_type != ModuleType::Json {
+				return Ok(source);
+			}
+
+			audit.record(AuditEvent {
+				operation: BlockedOperation::ImportType,
+				target: specifier.to_string(),
+				origin,
+				stack: Vec::new(),
+				provenance: None,
+			});
+			Err(AnyError::msg(format!(
+				"import of `{specifier}` denied: JSON modules are disabled"
+			)))
+		}
+		.boxed_local()
+	}
+}
+
+/// Failure to read a module below the root directory.
+enum RootError {
+	Escape,
+	Io(AnyError),
+}
+
+/// Reads the module at `specifier`, a `file:` URL whose path is interpreted relative to `root`.
+fn read_below_root(root: &Path, specifier: &ModuleSpecifier) -> Result<ModuleSource, RootError> {
+	let io_error = |error: std::io::Error| RootError::Io(error.into());
+	let root = root.canonicalize().map_err(io_error)?;
+
+	// URLs are already normalized, so only plain names remain besides the leading `/`
+	let path = specifier
+		.to_file_path()
+		.map_err(|()| RootError::Io(AnyError::msg(format!("invalid file URL `{specifier}`"))))?;
+	let relative: PathBuf = path
+		.components()
+		.filter(|component| matches!(component, Component::Normal(_)))
+		.collect();
+
+	// Symbolic links are resolved, so that they cannot point outside the root
+	let path = root.join(relative).canonicalize().map_err(io_error)?;
+	if !path.starts_with(&root) {
+		return Err(RootError::Escape);
+	}
+
+	let code = std::fs::read_to_string(&path).map_err(io_error)?;
+	let module_type = match path.extension() {
+		Some(extension) if extension.eq_ignore_ascii_case("json") => ModuleType::Json,
+		_ => ModuleType::JavaScript,
+	};
+
+	Ok(ModuleSource::new(module_type, code.into(), specifier))
+}
@@ -0,0 +1,285 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use serde::de::DeserializeOwned;
+
+use crate::{AnyError, CallArgs, JsError, Script, UsageReport};
+
+/// Resource limits applying to a single tenant of a [`SandboxManager`].
+///
+/// All limits are optional; `TenantLimits::default()` does not restrict the tenant.
+#[derive(Clone, Debug, Default)]
+pub struct TenantLimits {
+	/// Maximum size of the JS heap in bytes, see [`ScriptBuilder::with_max_heap_size()`](crate::ScriptBuilder::with_max_heap_size).
+	pub max_heap_size: Option<usize>,
+
+	/// Maximum duration of a single call, see [`Script::with_timeout()`].
+	pub timeout: Option<Duration>,
+
+	/// Time-slice budget per call as `(slices, slice_interval)`, see [`Script::with_time_slice_budget()`].
+	pub time_slice_budget: Option<(u64, Duration)>,
+
+	/// Maximum number of calls within a time window, as `(max_calls, window)`.
+	pub call_rate: Option<(u32, Duration)>,
+
+	/// Number of calls after which the tenant's script is replaced by a fresh one.
+	pub recycle_after_calls: Option<u64>,
+
+	/// Heap size in bytes, observed after a call, beyond which the tenant's script is replaced by a fresh one.
+	///
+	/// Unlike `max_heap_size`, this never aborts a call. It keeps long-lived scripts from slowly bloating, e.g. due to plugin code
+	/// that leaks memory into global state.
+	pub recycle_heap_size: Option<usize>,
+}
+
+/// Owns multiple named scripts (tenants), each with their own resource limits.
+///
+/// Typical for multi-tenant hosts, where every customer or plugin runs in its own isolated sandbox. The manager takes care of:
+/// * lookup of scripts by tenant ID,
+/// * enforcing per-tenant limits (heap, time, time-slice budget, call rate),
+/// * recycling tenants whose script was forcibly stopped, by recreating it from its source code,
+/// * recycling tenants periodically, after a number of calls or once their heap has grown too much,
+/// * evicting tenants that have been idle for a while.
+///
+/// ```rust
+/// use js_sandbox::{SandboxManager, TenantLimits, JsError};
+/// use std::time::Duration;
+///
+/// fn main() -> Result<(), JsError> {
+/// 	let mut manager = SandboxManager::new();
+///
+/// 	let limits = TenantLimits {
+/// 		timeout: Some(Duration::from_millis(500)),
+/// 		..Default::default()
+/// 	};
+/// 	manager.add_tenant("alice", "function greet() { return 'Hi from Alice'; }", limits)?;
+///
+/// 	let result: String = manager.call("alice", "greet", ())?;
+/// 	assert_eq!(result, "Hi from Alice");
+/// 	Ok(())
+/// }
+/// ```
+#[derive(Default)]
+pub struct SandboxManager {
+	tenants: HashMap<String, Tenant>,
+}
+
+struct Tenant {
+	script: Script,
+	js_code: String,
+	limits: TenantLimits,
+	// Usage of scripts that have been recycled
+	retired_usage: UsageReport,
+	// Why the script could not be recreated the last time it was due for recycling
+	recycle_error: Option<JsError>,
+	last_used: Instant,
+	window_start: Instant,
+	calls_in_window: u32,
+}
+
+impl SandboxManager {
+	/// Creates a manager without tenants.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Creates a script for a new tenant, identified by `tenant_id`.
+	///
+	/// Fails if the code cannot be initialized, or a tenant with the same ID already exists.
+	pub fn add_tenant(
+		&mut self,
+		tenant_id: &str,
+		js_code: &str,
+		limits: TenantLimits,
+	) -> Result<(), JsError> {
+		if self.tenants.contains_key(tenant_id) {
+			return Err(JsError::Runtime(AnyError::msg(format!(
+				"tenant `{tenant_id}` already exists"
+			))));
+		}
+
+		let script = create_script(js_code, &limits)?;
+		let now = Instant::now();
+
+		self.tenants.insert(
+			tenant_id.to_string(),
+			Tenant {
+				script,
+				js_code: js_code.to_string(),
+				limits,
+				retired_usage: UsageReport::default(),
+				recycle_error: None,
+				last_used: now,
+				window_start: now,
+				calls_in_window: 0,
+			},
+		);
+
+		Ok(())
+	}
+
+	/// Removes a tenant and disposes its script. Returns whether the tenant existed.
+	pub fn remove_tenant(&mut self, tenant_id: &str) -> bool {
+		self.tenants.remove(tenant_id).is_some()
+	}
+
+	/// Whether a tenant with the given ID exists.
+	pub fn contains_tenant(&self, tenant_id: &str) -> bool {
+		self.tenants.contains_key(tenant_id)
+	}
+
+	/// IDs of all tenants, in unspecified order.
+	pub fn tenant_ids(&self) -> impl Iterator<Item = &str> {
+		self.tenants.keys().map(String::as_str)
+	}
+
+	/// Gives direct access to the script of a tenant, bypassing call rate limits.
+	pub fn script_mut(&mut self, tenant_id: &str) -> Option<&mut Script> {
+		self.tenants.get_mut(tenant_id).map(|t| &mut t.script)
+	}
+
+	/// Invokes a JavaScript function in the script of a tenant.
+	///
+	/// Fails if the tenant does not exist or exceeded its call rate; otherwise behaves like [`Script::call()`].
+	/// If the call is forcibly stopped (due to timeout, time-slice budget or heap limit), or leaves the script unhealthy (see
+	/// [`Script::is_healthy()`]), the tenant's script is recreated from its source code, so that subsequent calls start from a
+	/// fresh state. The same happens once the script is due for recycling according to [`TenantLimits::recycle_after_calls`] and
+	/// [`TenantLimits::recycle_heap_size`]; if calls started through [`Self::script_mut()`] are still in flight, the script is
+	/// only replaced after they have been collected.
+	///
+	/// The result is always that of the call itself. If the script cannot be recreated, the tenant keeps its current script and
+	/// the failure is available through [`Self::recycle_error()`].
+	pub fn call<A, R>(
+		&mut self,
+		tenant_id: &str,
+		fn_name: &str,
+		args_tuple: A,
+	) -> Result<R, JsError>
+	where
+		A: CallArgs,
+		R: DeserializeOwned,
+	{
+		let Some(tenant) = self.tenants.get_mut(tenant_id) else {
+			return Err(JsError::Runtime(AnyError::msg(format!(
+				"no tenant `{tenant_id}`"
+			))));
+		};
+
+		let now = Instant::now();
+		tenant.check_call_rate(now)?;
+		tenant.last_used = now;
+
+		let result = tenant.script.call(fn_name, args_tuple);
+		let terminated = result.is_err() && tenant.script.was_terminated();
+		if terminated || !tenant.script.is_healthy() || tenant.recycle_due() {
+			tenant.recycle_error = tenant.recycle().err();
+		}
+
+		result
+	}
+
+	/// Why the script of a tenant could not be recreated when it was last due for recycling, see [`Self::call()`].
+	///
+	/// `None` if the tenant does not exist, or its script has been recreated successfully since.
+	pub fn recycle_error(&self, tenant_id: &str) -> Option<&JsError> {
+		self.tenants.get(tenant_id)?.recycle_error.as_ref()
+	}
+
+	/// Returns the resources used by a tenant so far, including scripts that have been recycled.
+	pub fn usage(&self, tenant_id: &str) -> Option<UsageReport> {
+		self.tenants.get(tenant_id).map(|tenant| {
+			let mut usage = tenant.retired_usage.clone();
+			usage.merge(&tenant.script.usage());
+			usage
+		})
+	}
+
+	/// Returns usage reports of all tenants, by tenant ID.
+	pub fn usage_reports(&self) -> HashMap<String, UsageReport> {
+		self.tenant_ids()
+			.filter_map(|id| Some((id.to_string(), self.usage(id)?)))
+			.collect()
+	}
+
+	/// Removes all tenants which have not been called for at least `max_idle`, and returns their IDs.
+	pub fn evict_idle(&mut self, max_idle: Duration) -> Vec<String> {
+		let now = Instant::now();
+		let idle: Vec<String> = self
+			.tenants
+			.iter()
+			.filter(|(_, t)| now.duration_since(t.last_used) >= max_idle)
+			.map(|(id, _)| id.clone())
+			.collect();
+
+		for id in idle.iter() {
+			self.tenants.remove(id);
+		}
+
+		idle
+	}
+}
+
+impl Tenant {
+	/// Whether the script should be replaced according to the recycling policy, and has no more calls in flight.
+	fn recycle_due(&self) -> bool {
+		let usage = self.script.usage();
+		let calls_due = self
+			.limits
+			.recycle_after_calls
+			.is_some_and(|max_calls| usage.calls >= max_calls);
+		let heap_due = self
+			.limits
+			.recycle_heap_size
+			.is_some_and(|max_size| usage.peak_heap_after_call > max_size);
+
+		(calls_due || heap_due) && !self.script.has_calls_in_flight()
+	}
+
+	/// Replaces the script by a fresh one, created from the tenant's source code.
+	fn recycle(&mut self) -> Result<(), JsError> {
+		let new_script = create_script(&self.js_code, &self.limits)?;
+		let old_script = std::mem::replace(&mut self.script, new_script);
+		self.retired_usage.merge(&old_script.usage());
+		Ok(())
+	}
+
+	fn check_call_rate(&mut self, now: Instant) -> Result<(), JsError> {
+		let Some((max_calls, window)) = self.limits.call_rate else {
+			return Ok(());
+		};
+
+		if now.duration_since(self.window_start) >= window {
+			self.window_start = now;
+			self.calls_in_window = 0;
+		}
+
+		if self.calls_in_window >= max_calls {
+			return Err(JsError::Runtime(AnyError::msg(format!(
+				"call rate exceeded: at most {max_calls} calls per {}ms",
+				window.as_millis()
+			))));
+		}
+
+		self.calls_in_window += 1;
+		Ok(())
+	}
+}
+
+fn create_script(js_code: &str, limits: &TenantLimits) -> Result<Script, JsError> {
+	let mut builder = Script::builder();
+	if let Some(max_heap_size) = limits.max_heap_size {
+		builder = builder.with_max_heap_size(max_heap_size);
+	}
+
+	let mut script = builder.build_from_string(js_code)?;
+	if let Some(timeout) = limits.timeout {
+		script = script.with_timeout(timeout);
+	}
+	if let Some((slices, slice_interval)) = limits.time_slice_budget {
+		script = script.with_time_slice_budget(slices, slice_interval);
+	}
+
+	Ok(script)
+}
@@ -0,0 +1,120 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::{AnyError, JsError, JsValue, ScriptEngine};
+
+type Responder = Box<dyn Fn(&[JsValue]) -> Result<JsValue, JsError>>;
+
+/// A call received by a [`MockScript`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct MockCall {
+	/// Name of the called function.
+	pub fn_name: String,
+
+	/// Arguments, as they would be passed to the script.
+	pub args: Vec<JsValue>,
+}
+
+/// Test double for [`Script`](crate::Script), which answers calls with scripted responses instead of running JS.
+///
+/// Host code written against [`ScriptEngine`] -- including APIs generated with `#[js_api]` -- can be unit-tested with a
+/// `MockScript`, without starting V8. Every call is recorded and can be inspected with [`Self::calls()`].
+///
+/// ```rust
+/// use js_sandbox::{js_api, MockScript};
+///
+/// #[js_api]
+/// trait Pricing {
+/// 	fn discount(&mut self, total: f64) -> f64;
+/// }
+///
+/// let mut script = MockScript::new().returning("discount", 5.0);
+/// assert_eq!(Pricing::new(&mut script).discount(100.0), 5.0);
+///
+/// assert_eq!(script.calls()[0].fn_name, "discount");
+/// assert_eq!(script.calls()[0].args, vec![serde_json::json!(100.0)]);
+/// ```
+///
+/// Calls to functions without a response fail with [`JsError::Runtime`], like calls to undefined functions in a script.
+#[derive(Default)]
+pub struct MockScript {
+	responders: HashMap<String, Responder>,
+	calls: RefCell<Vec<MockCall>>,
+}
+
+impl MockScript {
+	/// Creates a mock which defines no functions.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Defines the function `fn_name`, which returns `value` on every call.
+	///
+	/// # Panics
+	/// If `value` cannot be serialized.
+	pub fn returning(self, fn_name: &str, value: impl Serialize) -> Self {
+		let value = serde_json::to_value(value).expect("response must be serializable");
+		self.responding(fn_name, move |_args| Ok(value.clone()))
+	}
+
+	/// Defines the function `fn_name`, which fails with a JS exception carrying `message` on every call.
+	pub fn throwing(self, fn_name: &str, message: &str) -> Self {
+		let message = message.to_string();
+		self.responding(fn_name, move |_args| {
+			Err(JsError::Runtime(AnyError::msg(format!(
+				"Uncaught Error: {message}"
+			))))
+		})
+	}
+
+	/// Defines the function `fn_name`, which computes its result from the arguments by invoking `responder`.
+	///
+	/// Replaces previous responses for the same function.
+	pub fn responding<F>(mut self, fn_name: &str, responder: F) -> Self
+	where
+		F: Fn(&[JsValue]) -> Result<JsValue, JsError> + 'static,
+	{
+		self.responders
+			.insert(fn_name.to_string(), Box::new(responder));
+		self
+	}
+
+	/// All calls received so far, in order.
+	pub fn calls(&self) -> Vec<MockCall> {
+		self.calls.borrow().clone()
+	}
+
+	/// Forgets the calls received so far.
+	pub fn clear_calls(&mut self) {
+		self.calls.get_mut().clear();
+	}
+}
+
+impl ScriptEngine for MockScript {
+	fn call_values(&mut self, fn_name: &str, args: Vec<JsValue>) -> Result<JsValue, JsError> {
+		self.call_values_ref(fn_name, args)
+	}
+
+	fn call_values_ref(&self, fn_name: &str, args: Vec<JsValue>) -> Result<JsValue, JsError> {
+		self.calls.borrow_mut().push(MockCall {
+			fn_name: fn_name.to_string(),
+			args: args.clone(),
+		});
+
+		let Some(responder) = self.responders.get(fn_name) else {
+			return Err(JsError::Runtime(AnyError::msg(format!(
+				"Uncaught ReferenceError: {fn_name} is not defined"
+			))));
+		};
+
+		responder(&args)
+	}
+
+	fn has_function(&self, fn_name: &str) -> Result<bool, JsError> {
+		Ok(self.responders.contains_key(fn_name))
+	}
+}
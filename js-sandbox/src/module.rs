@@ -0,0 +1,49 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use deno_core::futures::executor::block_on;
+use deno_core::{v8, FastString, JsRuntime, ModuleSpecifier};
+
+use crate::AnyError;
+
+/// Global under which the namespace object of a module script is stored.
+pub(crate) const NAMESPACE_GLOBAL: &str = "__jsSandboxModule";
+
+/// Export of a script loaded as ES module, see [`Script::module_exports()`](crate::Script::module_exports).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ModuleExport {
+	/// Name of the export; `default` for the default export.
+	pub name: String,
+
+	/// Whether the exported value is a function (including async functions and classes).
+	pub is_function: bool,
+}
+
+/// Loads and evaluates `js_code` as the main module at `specifier`, and stores its namespace object in [`NAMESPACE_GLOBAL`].
+pub(crate) fn load(
+	runtime: &mut JsRuntime,
+	specifier: &ModuleSpecifier,
+	js_code: FastString,
+) -> Result<(), AnyError> {
+	let id = block_on(runtime.load_main_module(specifier, Some(js_code)))?;
+
+	// Top-level await is driven by the event loop
+	let evaluation = runtime.mod_evaluate(id);
+	block_on(runtime.run_event_loop(false))?;
+	block_on(evaluation)??;
+
+	let namespace = runtime.get_module_namespace(id)?;
+	let scope = &mut runtime.handle_scope();
+	let namespace = v8::Local::new(scope, namespace);
+	let key = v8::String::new(scope, NAMESPACE_GLOBAL).expect("key is a valid V8 string");
+	let global = scope.get_current_context().global(scope);
+	global.set(scope, key.into(), namespace.into());
+
+	Ok(())
+}
+
+/// JS expression listing the module's exports as `[name, isFunction]` pairs.
+pub(crate) fn exports_expr() -> String {
+	format!(
+		"Object.keys({NAMESPACE_GLOBAL}).map(name => [name, typeof {NAMESPACE_GLOBAL}[name] === 'function'])"
+	)
+}
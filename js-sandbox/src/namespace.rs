@@ -0,0 +1,116 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::collections::BTreeSet;
+
+use crate::lexer::{self, TokenKind};
+use crate::JsValue;
+
+/// Hidden global holding the namespaces added with [`Script::add_script()`](crate::Script::add_script).
+pub(crate) const REGISTRY_GLOBAL: &str = "__jsSandboxNamespaces";
+
+/// Path of the function `fn_name` in `namespace`, which can be called like a function name.
+pub(crate) fn function_path(namespace: &str, fn_name: &str) -> String {
+	format!("{REGISTRY_GLOBAL}.{namespace}.{fn_name}")
+}
+
+/// Names of all functions and generators declared or named anywhere in `source`.
+///
+/// These are only candidates: which of them are actually declared at the top level is decided by the evaluated script, see
+/// [`register_code()`]. Nested functions and named function expressions are not in scope there, so they are filtered out.
+fn function_candidates(source: &str) -> BTreeSet<&str> {
+	let kinds: Vec<TokenKind> = lexer::tokenize(source)
+		.into_iter()
+		.map(|token| token.kind)
+		.collect();
+
+	let mut names = BTreeSet::new();
+	for (i, kind) in kinds.iter().enumerate() {
+		if *kind != TokenKind::Ident("function") {
+			continue;
+		}
+
+		match kinds[i + 1..] {
+			[TokenKind::Ident(name), ..] | [TokenKind::Punct('*'), TokenKind::Ident(name), ..] => {
+				names.insert(name);
+			}
+			_ => {}
+		}
+	}
+
+	names
+}
+
+/// Returns a wrapper script that evaluates `source` in its own scope, and registers its top-level functions under `namespace`.
+///
+/// Like call wrappers, it evaluates to a promise, which settles once the functions are registered. Also returns the number of lines
+/// preceding `source` in the wrapper, see [`register_code()`].
+pub(crate) fn add_code(namespace: &str, source: &str) -> (String, i32) {
+	let (register_code, line_offset) = register_code(namespace, source);
+	let js_code = format!("(async () => {{\n{register_code}\n}})()");
+
+	(js_code, line_offset + 1)
+}
+
+/// Returns statements that evaluate `source` in its own scope, and register its top-level functions under `namespace`.
+///
+/// Each candidate name is resolved in the scope of `source` once it has run. Names which are not functions there, or which only
+/// resolve to a global of the script, are not registered.
+///
+/// Also returns the number of lines preceding `source`, by which positions in stack traces need to be corrected.
+pub(crate) fn register_code(namespace: &str, source: &str) -> (String, i32) {
+	let namespace_json = JsValue::from(namespace);
+	let exports: String = function_candidates(source)
+		.into_iter()
+		.map(|name| {
+			format!("{name}: typeof {name} === \"function\" && {name} !== globalThis.{name} ? {name} : undefined, ")
+		})
+		.collect();
+
+	let prefix = format!(
+		r#"{{
+	if (!Object.hasOwn(globalThis, "{REGISTRY_GLOBAL}")) {{
+		Object.defineProperty(globalThis, "{REGISTRY_GLOBAL}", {{ value: Object.create(null) }});
+	}}
+
+	const exports = (() => {{
+"#
+	);
+	let suffix = format!(
+		r#"
+;
+		return {{ {exports}}};
+	}})();
+
+	for (const [name, value] of Object.entries(exports)) {{
+		if (value === undefined) {{
+			delete exports[name];
+		}}
+	}}
+
+	if (Object.keys(exports).length === 0) {{
+		throw new TypeError("script does not declare any top-level functions");
+	}}
+
+	{REGISTRY_GLOBAL}[{namespace_json}] = Object.freeze(exports);
+}}"#
+	);
+
+	let line_offset = prefix.lines().count() as i32;
+	(format!("{prefix}{source}{suffix}"), line_offset)
+}
+
+/// JS expression listing the names of all registered namespaces.
+pub(crate) fn names_expr() -> String {
+	format!("Object.keys(globalThis.{REGISTRY_GLOBAL} ?? {{}})")
+}
+
+/// Returns a wrapper script that deletes `namespace`, so its functions are no longer reachable.
+pub(crate) fn remove_code(namespace: &str) -> String {
+	let namespace_json = JsValue::from(namespace);
+
+	format!(
+		r#"(async () => {{
+	delete {REGISTRY_GLOBAL}[{namespace_json}];
+}})()"#
+	)
+}
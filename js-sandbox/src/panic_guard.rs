@@ -0,0 +1,62 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+
+use deno_core::futures::FutureExt;
+use deno_core::OpState;
+
+use crate::AnyError;
+
+/// Set once a host callback has panicked; shared between the script and its op state.
+#[derive(Clone, Default)]
+pub(crate) struct PanicFlag(Rc<Cell<bool>>);
+
+impl PanicFlag {
+	pub fn is_set(&self) -> bool {
+		self.0.get()
+	}
+}
+
+/// Runs a host callback from within an op, turning a panic into an error which is thrown as exception in JS.
+///
+/// Ops are called from V8 through the FFI boundary, which panics must not unwind across.
+pub(crate) fn catch<T>(
+	state: &mut OpState,
+	callback: impl FnOnce(&mut OpState) -> Result<T, AnyError>,
+) -> Result<T, AnyError> {
+	match panic::catch_unwind(AssertUnwindSafe(|| callback(state))) {
+		Ok(result) => result,
+		Err(payload) => Err(panic_error(state, payload)),
+	}
+}
+
+/// Like [`catch()`], for host callbacks of async ops.
+pub(crate) async fn catch_async<T>(
+	state: &Rc<RefCell<OpState>>,
+	future: impl Future<Output = Result<T, AnyError>>,
+) -> Result<T, AnyError> {
+	match AssertUnwindSafe(future).catch_unwind().await {
+		Ok(result) => result,
+		Err(payload) => Err(panic_error(&state.borrow(), payload)),
+	}
+}
+
+fn panic_error(state: &OpState, payload: Box<dyn Any + Send>) -> AnyError {
+	if let Some(flag) = state.try_borrow::<PanicFlag>() {
+		flag.0.set(true);
+	}
+
+	let message = match payload.downcast::<String>() {
+		Ok(message) => *message,
+		Err(payload) => match payload.downcast::<&'static str>() {
+			Ok(message) => message.to_string(),
+			Err(_) => "unknown cause".to_string(),
+		},
+	};
+
+	AnyError::msg(format!("host callback panicked: {message}"))
+}
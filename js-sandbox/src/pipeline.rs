@@ -0,0 +1,122 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::time::Instant;
+
+use serde::de::DeserializeOwned;
+
+use crate::{call_args, usage, AnyError, CallArgs, JsError, Script};
+
+/// Chain of function calls, where each function receives the result of the previous one.
+///
+/// Created by [`Script::pipeline()`]. Intermediate results stay inside JavaScript; only the arguments of each step and the final
+/// result are converted to/from JSON.
+///
+/// ```rust
+/// use js_sandbox::{Script, JsError};
+///
+/// fn main() -> Result<(), JsError> {
+/// 	let src = r#"
+/// 		function parse(text) { return text.split(","); }
+/// 		function scale(list, factor) { return list.map(x => x * factor); }
+/// 		function sum(list) { return list.reduce((a, b) => a + b, 0); }"#;
+///
+/// 	let mut script = Script::from_string(src)?;
+///
+/// 	let result: i32 = script
+/// 		.pipeline()
+/// 		.call("parse", ("1,2,3",))
+/// 		.call("scale", (10,))
+/// 		.call("sum", ())
+/// 		.run()?;
+///
+/// 	assert_eq!(result, 60);
+/// 	Ok(())
+/// }
+/// ```
+pub struct Pipeline<'a> {
+	script: &'a mut Script,
+	steps: Vec<(String, String)>,
+	error: Option<JsError>,
+}
+
+impl<'a> Pipeline<'a> {
+	pub(crate) fn new(script: &'a mut Script) -> Self {
+		Self {
+			script,
+			steps: Vec::new(),
+			error: None,
+		}
+	}
+
+	/// Adds a function call to the pipeline.
+	///
+	/// The first function is invoked with `args_tuple`. Every following function receives the previous result as its first argument,
+	/// followed by the elements of `args_tuple`.
+	pub fn call<A>(mut self, fn_name: &str, args_tuple: A) -> Self
+	where
+		A: CallArgs,
+	{
+		match args_tuple.into_arg_string() {
+			Ok(json_args) => self.steps.push((fn_name.to_string(), json_args)),
+			Err(e) => {
+				// Report the first error in run()
+				self.error.get_or_insert(call_args::args_error(fn_name, e));
+			}
+		}
+
+		self
+	}
+
+	/// Executes all calls of the pipeline, and returns the result of the last one.
+	///
+	/// Fails if any of the arguments cannot be serialized, if any function throws, or if the pipeline is empty.
+	pub fn run<R>(self) -> Result<R, JsError>
+	where
+		R: DeserializeOwned,
+	{
+		if let Some(e) = self.error {
+			return Err(e);
+		}
+
+		if self.steps.is_empty() {
+			return Err(JsError::Runtime(AnyError::msg("pipeline has no calls")));
+		}
+
+		let mut invocations = String::new();
+		let mut bytes_in = 0;
+		for (i, (fn_name, json_args)) in self.steps.iter().enumerate() {
+			bytes_in += json_args.len();
+			let all_args = match (i, json_args.is_empty()) {
+				(0, _) => json_args.clone(),
+				(_, true) => "__rust_result".to_string(),
+				(_, false) => format!("__rust_result, {json_args}"),
+			};
+
+			let invocation = Script::invocation_expr(fn_name, &all_args)?;
+			invocations += &format!("__rust_result = {invocation};\n");
+		}
+
+		let js_code = format!(
+			"(async () => {{
+				let __rust_result;
+				{invocations}
+				return __rust_result;
+			}})()"
+		);
+
+		let start = Instant::now();
+		let json_result = self.script.execute_returning(js_code);
+		self.script.record_call(
+			start.elapsed(),
+			bytes_in,
+			json_result.as_ref().ok().map(usage::json_size),
+		);
+
+		let json_result = json_result?;
+		let (last_fn_name, _) = self.steps.last().expect("pipeline is not empty");
+		let result: R = call_args::from_result(json_result)
+			.map_err(|e| call_args::result_error(last_fn_name, e))?;
+
+		Ok(result)
+	}
+}
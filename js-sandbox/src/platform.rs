@@ -0,0 +1,95 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::ops::{Deref, DerefMut};
+
+use deno_core::{JsRuntime, RuntimeOptions};
+
+/// Initializes the V8 platform, which is shared by all scripts in the process.
+///
+/// This happens automatically when the first script is created, so calling this function is optional. It can be used to pay the
+/// initialization cost upfront (e.g. during server startup), or to make sure the platform is set up on a specific thread before
+/// worker threads start creating scripts.
+///
+/// Idempotent and thread-safe: only the first call has an effect, concurrent callers block until initialization has completed.
+pub fn init_platform() {
+	// Guarded by a `Once` inside deno_core
+	JsRuntime::init_platform(None);
+}
+
+/// JS runtime whose isolate is only entered while it is in use.
+///
+/// V8 requires isolates to be exited in the reverse order in which they were entered on a thread. `rusty_v8` enters an isolate when
+/// it is created, and exits it when it is dropped, which breaks as soon as several scripts on the same thread are dropped in a
+/// different order (e.g. when recycling scripts), and can crash the process. Instead, the isolate is entered for the duration of
+/// each operation, see [`Entered`].
+pub(crate) struct Runtime {
+	js_runtime: JsRuntime,
+	// Stack in bytes that JS code may use, counted from where the isolate is entered
+	stack_size: usize,
+}
+
+impl Runtime {
+	/// Creates a runtime whose isolate uses a stack limit of `stack_size_kb`.
+	///
+	/// The limit only applies to this isolate. It is set whenever the isolate is entered, since V8 expects it as an address on
+	/// the native stack of the thread running JS.
+	pub fn new(options: RuntimeOptions, stack_size_kb: usize) -> Self {
+		init_platform();
+
+		let mut js_runtime = JsRuntime::new(options);
+
+		// SAFETY: the isolate has just been entered by its constructor, so it is the current one on this thread
+		unsafe { js_runtime.v8_isolate().exit() };
+
+		Self {
+			js_runtime,
+			stack_size: stack_size_kb.saturating_mul(1024),
+		}
+	}
+}
+
+impl Drop for Runtime {
+	fn drop(&mut self) {
+		// Balances the exit that rusty_v8 performs when the isolate is dropped
+		// SAFETY: the isolate is valid and exited again right afterwards, by the JsRuntime destructor
+		unsafe { self.js_runtime.v8_isolate().enter() };
+	}
+}
+
+/// Keeps the isolate of a [`Runtime`] entered on the current thread, until dropped.
+pub(crate) struct Entered<R: DerefMut<Target = Runtime>>(R);
+
+impl<R: DerefMut<Target = Runtime>> Entered<R> {
+	pub fn new(mut runtime: R) -> Self {
+		// SAFETY: the isolate is valid as long as the runtime; the matching exit happens in drop()
+		unsafe { runtime.js_runtime.v8_isolate().enter() };
+
+		// The stack grows downwards from here; like V8, use the address of a local variable as the current position
+		let stack_position = &runtime as *const R as usize;
+		let stack_limit = stack_position.saturating_sub(runtime.stack_size);
+		runtime.js_runtime.v8_isolate().set_stack_limit(stack_limit);
+
+		Self(runtime)
+	}
+}
+
+impl<R: DerefMut<Target = Runtime>> Deref for Entered<R> {
+	type Target = JsRuntime;
+
+	fn deref(&self) -> &JsRuntime {
+		&self.0.js_runtime
+	}
+}
+
+impl<R: DerefMut<Target = Runtime>> DerefMut for Entered<R> {
+	fn deref_mut(&mut self) -> &mut JsRuntime {
+		&mut self.0.js_runtime
+	}
+}
+
+impl<R: DerefMut<Target = Runtime>> Drop for Entered<R> {
+	fn drop(&mut self) {
+		// SAFETY: entered in new(); guards are scoped, so entering and exiting happens in reverse order
+		unsafe { self.0.js_runtime.v8_isolate().exit() };
+	}
+}
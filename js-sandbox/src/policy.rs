@@ -0,0 +1,159 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::time::Duration;
+
+use crate::ImportPolicy;
+
+/// Bundle of security-relevant settings, applied with [`ScriptBuilder::with_policy()`](crate::ScriptBuilder::with_policy).
+///
+/// The presets [`Policy::strict()`], [`Policy::standard()`] and [`Policy::permissive()`] give sensible defaults with one line:
+///
+/// ```rust
+/// use js_sandbox::{Policy, Script, JsError};
+///
+/// fn main() -> Result<(), JsError> {
+/// 	let mut script = Script::builder()
+/// 		.with_policy(Policy::strict())
+/// 		.build_from_string("function run(code) { return eval(code); }")?;
+///
+/// 	// Code generation from strings is disabled
+/// 	let result: Result<i32, JsError> = script.call("run", ("1 + 2",));
+/// 	assert!(result.is_err());
+/// 	Ok(())
+/// }
+/// ```
+///
+/// Fields can be adjusted individually, e.g. `Policy { timeout: None, ..Policy::standard() }`. Each field corresponds to a
+/// setting of [`ScriptBuilder`](crate::ScriptBuilder) (or [`Script`](crate::Script) for the timeout); `None` means no limit.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Policy {
+	/// Which modules may be imported, see [`ScriptBuilder::with_imports()`](crate::ScriptBuilder::with_imports).
+	pub imports: ImportPolicy,
+
+	/// Whether `eval()` and `new Function()` are available, see
+	/// [`ScriptBuilder::with_code_generation()`](crate::ScriptBuilder::with_code_generation).
+	pub code_generation: bool,
+
+	/// Whether the `WebAssembly` API is available, see [`ScriptBuilder::with_wasm()`](crate::ScriptBuilder::with_wasm).
+	pub wasm: bool,
+
+	/// Whether timers are available, see [`ScriptBuilder::with_timers()`](crate::ScriptBuilder::with_timers).
+	pub timers: bool,
+
+	/// Whether built-in objects are frozen after initialization, see
+	/// [`ScriptBuilder::with_frozen_intrinsics()`](crate::ScriptBuilder::with_frozen_intrinsics).
+	pub freeze_intrinsics: bool,
+
+	/// Maximum duration of a single call, see [`Script::with_timeout()`](crate::Script::with_timeout).
+	pub timeout: Option<Duration>,
+
+	/// See [`ScriptBuilder::with_max_heap_size()`](crate::ScriptBuilder::with_max_heap_size).
+	pub max_heap_size: Option<usize>,
+
+	/// See [`ScriptBuilder::with_max_stack_size()`](crate::ScriptBuilder::with_max_stack_size).
+	pub max_stack_size: Option<usize>,
+
+	/// See [`ScriptBuilder::with_max_source_size()`](crate::ScriptBuilder::with_max_source_size).
+	pub max_source_size: Option<usize>,
+
+	/// See [`ScriptBuilder::with_max_nesting_depth()`](crate::ScriptBuilder::with_max_nesting_depth).
+	pub max_nesting_depth: Option<usize>,
+
+	/// See [`ScriptBuilder::with_max_microtasks()`](crate::ScriptBuilder::with_max_microtasks).
+	pub max_microtasks: Option<u64>,
+
+	/// See [`ScriptBuilder::with_max_event_loop_turns()`](crate::ScriptBuilder::with_max_event_loop_turns).
+	pub max_event_loop_turns: Option<u64>,
+
+	/// See [`ScriptBuilder::with_max_string_length()`](crate::ScriptBuilder::with_max_string_length).
+	pub max_string_length: Option<usize>,
+
+	/// See [`ScriptBuilder::with_max_array_length()`](crate::ScriptBuilder::with_max_array_length).
+	pub max_array_length: Option<usize>,
+}
+
+impl Policy {
+	/// For untrusted code: no imports, code generation, WebAssembly or timers, frozen intrinsics and tight resource limits.
+	///
+	/// Calls may take up to 1 second and use up to 64 MiB of heap.
+	pub fn strict() -> Self {
+		Self {
+			imports: ImportPolicy::Deny,
+			code_generation: false,
+			wasm: false,
+			timers: false,
+			freeze_intrinsics: true,
+			timeout: Some(Duration::from_secs(1)),
+			max_heap_size: Some(64 * 1024 * 1024),
+			max_stack_size: Some(512),
+			max_source_size: Some(1024 * 1024),
+			max_nesting_depth: Some(256),
+			max_microtasks: Some(1_000_000),
+			max_event_loop_turns: Some(10_000),
+			max_string_length: Some(1024 * 1024),
+			max_array_length: Some(100_000),
+		}
+	}
+
+	/// For plugins of moderate trust: no imports, code generation or WebAssembly, but timers and generous resource limits.
+	///
+	/// Calls may take up to 10 seconds and use up to 256 MiB of heap.
+	pub fn standard() -> Self {
+		Self {
+			imports: ImportPolicy::Deny,
+			code_generation: false,
+			wasm: false,
+			timers: true,
+			freeze_intrinsics: false,
+			timeout: Some(Duration::from_secs(10)),
+			max_heap_size: Some(256 * 1024 * 1024),
+			max_stack_size: None,
+			max_source_size: Some(16 * 1024 * 1024),
+			max_nesting_depth: Some(1024),
+			max_microtasks: Some(100_000_000),
+			max_event_loop_turns: Some(1_000_000),
+			max_string_length: Some(16 * 1024 * 1024),
+			max_array_length: Some(1_000_000),
+		}
+	}
+
+	/// For trusted code: everything is allowed and no limits apply, like a builder without any settings.
+	pub fn permissive() -> Self {
+		Self {
+			imports: ImportPolicy::FileSystem,
+			code_generation: true,
+			wasm: true,
+			timers: true,
+			freeze_intrinsics: false,
+			timeout: None,
+			max_heap_size: None,
+			max_stack_size: None,
+			max_source_size: None,
+			max_nesting_depth: None,
+			max_microtasks: None,
+			max_event_loop_turns: None,
+			max_string_length: None,
+			max_array_length: None,
+		}
+	}
+}
+
+impl Default for Policy {
+	/// Same as [`Policy::permissive()`], which matches the defaults of [`ScriptBuilder`](crate::ScriptBuilder).
+	fn default() -> Self {
+		Self::permissive()
+	}
+}
+
+/// Removes the `WebAssembly` API, see [`ScriptBuilder::with_wasm()`](crate::ScriptBuilder::with_wasm).
+pub(crate) const REMOVE_WASM_JS: &str = "delete globalThis.WebAssembly;";
+
+/// Freezes built-in objects, see [`ScriptBuilder::with_frozen_intrinsics()`](crate::ScriptBuilder::with_frozen_intrinsics).
+pub(crate) const FREEZE_INTRINSICS_JS: &str = include_str!("js/freeze_intrinsics.js");
+
+/// Removes timers, see [`ScriptBuilder::with_timers()`](crate::ScriptBuilder::with_timers).
+pub(crate) const REMOVE_TIMERS_JS: &str = r#"(() => {
+	for (const name of ["setTimeout", "setInterval", "clearTimeout", "clearInterval"]) {
+		delete globalThis[name];
+	}
+})();"#;
@@ -0,0 +1,160 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::cell::{Cell, RefCell};
+use std::ffi::c_void;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use deno_core::v8;
+
+use crate::termination::{Termination, TerminationFlag};
+use crate::watchdog;
+
+/// Decision of a preemption callback, see [`Script::with_preemption()`](crate::Script::with_preemption).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Preemption {
+	/// Resume the script immediately.
+	Continue,
+
+	/// Suspend the script's thread for the given duration, then resume.
+	Pause(Duration),
+
+	/// Abort the call with [`JsError::Preempted`](crate::JsError::Preempted).
+	Abort,
+}
+
+/// Progress of a call, passed to the preemption callback at every checkpoint.
+#[derive(Clone, Copy, Debug)]
+pub struct Checkpoint {
+	/// Time since the call started, including pauses.
+	pub elapsed: Duration,
+
+	/// Number of checkpoints reached during this call, starting at 1.
+	pub count: u64,
+}
+
+type Callback = Box<dyn FnMut(&Checkpoint) -> Preemption>;
+
+/// Periodic interrupts of a script, deciding whether a call may continue.
+pub(crate) struct Preemptor {
+	interval: Duration,
+	callback: RefCell<Callback>,
+}
+
+impl Preemptor {
+	pub fn new(interval: Duration, callback: Callback) -> Self {
+		Self {
+			interval,
+			callback: RefCell::new(callback),
+		}
+	}
+
+	/// Starts interrupting a single call. Interrupts stop as soon as the returned guard is dropped.
+	/// Sets `terminated` when the callback aborts the call.
+	pub fn start(
+		self: &Rc<Self>,
+		handle: v8::IsolateHandle,
+		terminated: TerminationFlag,
+	) -> PreemptionGuard {
+		let state = Box::new(CallState {
+			preemptor: self.clone(),
+			start: Instant::now(),
+			count: Cell::new(0),
+			terminated,
+		});
+
+		let ticket = Arc::new(Ticket {
+			active: AtomicBool::new(true),
+			state: &*state as *const CallState as usize,
+		});
+
+		let interval = self.interval;
+		let interrupt_ticket = ticket.clone();
+
+		let interrupter = watchdog::schedule(interval, move || {
+			let data = Arc::into_raw(interrupt_ticket.clone()) as *mut c_void;
+
+			if handle.request_interrupt(checkpoint, data) {
+				Some(interval)
+			} else {
+				// Isolate has been disposed, callback will never run
+				// SAFETY: pointer was obtained from Arc::into_raw() above and not passed on
+				unsafe { drop(Arc::from_raw(data as *const Ticket)) };
+				None
+			}
+		});
+
+		PreemptionGuard {
+			ticket,
+			_state: state,
+			_interrupter: interrupter,
+		}
+	}
+}
+
+/// Keeps preemption alive for the duration of a call.
+pub(crate) struct PreemptionGuard {
+	ticket: Arc<Ticket>,
+	_state: Box<CallState>,
+	_interrupter: watchdog::Scheduled,
+}
+
+impl Drop for PreemptionGuard {
+	fn drop(&mut self) {
+		// Interrupts may still be queued in the isolate; they must not access the state after it is freed
+		self.ticket.active.store(false, Ordering::SeqCst);
+	}
+}
+
+/// State of the current call; only accessed on the isolate's thread.
+struct CallState {
+	preemptor: Rc<Preemptor>,
+	start: Instant,
+	count: Cell<u64>,
+	terminated: TerminationFlag,
+}
+
+/// Reference to the call state that can be sent to the watchdog thread.
+struct Ticket {
+	active: AtomicBool,
+	// Address of CallState, which must only be dereferenced while `active` is set
+	state: usize,
+}
+
+// Runs on the isolate's thread, while JS code is executing
+extern "C" fn checkpoint(isolate: &mut v8::Isolate, data: *mut c_void) {
+	// SAFETY: pointer was obtained from Arc::into_raw() in Preemptor::start()
+	let ticket = unsafe { Arc::from_raw(data as *const Ticket) };
+
+	if !ticket.active.load(Ordering::SeqCst) {
+		return;
+	}
+
+	// SAFETY: the guard owning the state clears `active` before freeing it, on this same thread
+	let state = unsafe { &*(ticket.state as *const CallState) };
+
+	// Guards against re-entrance, in case the callback causes interrupts to be processed; skip this checkpoint
+	let Ok(mut callback) = state.preemptor.callback.try_borrow_mut() else {
+		return;
+	};
+
+	let count = state.count.get() + 1;
+	state.count.set(count);
+
+	let checkpoint = Checkpoint {
+		elapsed: state.start.elapsed(),
+		count,
+	};
+
+	match callback(&checkpoint) {
+		Preemption::Continue => {}
+		Preemption::Pause(duration) => thread::sleep(duration),
+		Preemption::Abort => {
+			state.terminated.set(Termination::Preempted);
+			isolate.terminate_execution();
+		}
+	}
+}
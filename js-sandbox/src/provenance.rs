@@ -0,0 +1,56 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::fmt::{self, Display};
+
+/// Identifies where a script comes from, e.g. which plugin, so that diagnostics can be attributed to it.
+///
+/// Attached with [`ScriptBuilder::with_provenance()`](crate::ScriptBuilder::with_provenance), it is included in errors
+/// (see [`JsError::WithProvenance`](crate::JsError::WithProvenance)), audit events and console output of the script.
+///
+/// Displayed as `id@version by author`, omitting the parts that are not set.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Provenance {
+	/// Identifier of the plugin, e.g. its package name.
+	pub id: String,
+
+	/// Version of the plugin.
+	pub version: Option<String>,
+
+	/// Author or publisher of the plugin.
+	pub author: Option<String>,
+}
+
+impl Provenance {
+	/// Creates provenance with the given plugin identifier.
+	pub fn new(id: impl Into<String>) -> Self {
+		Self {
+			id: id.into(),
+			..Self::default()
+		}
+	}
+
+	/// Sets the plugin's version.
+	pub fn with_version(mut self, version: impl Into<String>) -> Self {
+		self.version = Some(version.into());
+		self
+	}
+
+	/// Sets the plugin's author.
+	pub fn with_author(mut self, author: impl Into<String>) -> Self {
+		self.author = Some(author.into());
+		self
+	}
+}
+
+impl Display for Provenance {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.id)?;
+		if let Some(version) = &self.version {
+			write!(f, "@{version}")?;
+		}
+		if let Some(author) = &self.author {
+			write!(f, " by {author}")?;
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,215 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+//! Lightweight alternative backend based on [QuickJS](https://bellard.org/quickjs).
+//!
+//! Available with the `quickjs` feature. [`Script`] mirrors the core API of the V8-based [`crate::Script`]: loading code, calling
+//! functions with serde arguments, and timeouts. It is meant for targets where building V8 is impractical, and raw performance is
+//! less important than portability.
+//!
+//! Features that depend on Deno (ops, extensions, host objects, web APIs, ...) are not available with this backend.
+//! The V8 backend is part of the default `v8` feature; disable default features to build without it:
+//!
+//! ```toml
+//! js-sandbox = { version = "0.2", default-features = false, features = ["quickjs"] }
+//! ```
+
+use std::cell::Cell;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use rquickjs::{CatchResultExt, CaughtError, Context, Function, Runtime};
+use serde::de::DeserializeOwned;
+
+use crate::{call_args, console, AnyError, CallArgs, JsError};
+
+/// Represents a single JavaScript file that can be executed, using the QuickJS engine.
+///
+/// See [`crate::Script`] for the V8 counterpart.
+pub struct Script {
+	runtime: Runtime,
+	context: Context,
+	timeout: Option<Duration>,
+	deadline: Rc<Cell<Option<Instant>>>,
+}
+
+impl Script {
+	// ----------------------------------------------------------------------------------------------------------------------------------------------
+	// Constructors and builders
+
+	/// Initialize a script with the given JavaScript source code.
+	///
+	/// Returns a new object on success, and an error in case of syntax or initialization error with the code.
+	pub fn from_string(js_code: &str) -> Result<Self, JsError> {
+		let runtime = Runtime::new().map_err(quickjs_error)?;
+		let context = Context::full(&runtime).map_err(quickjs_error)?;
+
+		// Interrupt handler is polled regularly during execution; abort once the deadline of the current call has passed
+		let deadline: Rc<Cell<Option<Instant>>> = Rc::new(Cell::new(None));
+		let handler_deadline = deadline.clone();
+		runtime.set_interrupt_handler(Some(Box::new(move || {
+			handler_deadline
+				.get()
+				.is_some_and(|deadline| Instant::now() >= deadline)
+		})));
+
+		context.with(|ctx| -> Result<(), JsError> {
+			// console is not available by default -- install the same one as the V8 backend
+			let print = Function::new(ctx.clone(), |text: String, is_error: bool| {
+				console::print_text(&text, is_error)
+			})
+			.catch(&ctx)
+			.map_err(caught_error)?;
+			ctx.globals()
+				.set("__rust_print", print)
+				.catch(&ctx)
+				.map_err(caught_error)?;
+
+			ctx.eval::<(), _>(console::install_code(
+				"(line, isError) => __rust_print(line + '\\n', isError)",
+			))
+			.catch(&ctx)
+			.map_err(caught_error)?;
+			ctx.eval::<(), _>(js_code)
+				.catch(&ctx)
+				.map_err(caught_error)?;
+
+			Ok(())
+		})?;
+
+		Ok(Self {
+			runtime,
+			context,
+			timeout: None,
+			deadline,
+		})
+	}
+
+	/// Initialize a script by loading it from a .js file.
+	///
+	/// Returns a new object on success. Fails if the file cannot be opened or in case of syntax or initialization error with the code.
+	pub fn from_file(file: impl AsRef<Path>) -> Result<Self, JsError> {
+		match std::fs::read_to_string(file) {
+			Ok(js_code) => Self::from_string(&js_code),
+			Err(e) => Err(JsError::Runtime(AnyError::from(e))),
+		}
+	}
+
+	/// Equips this script with a timeout, meaning that any function call is aborted after the specified duration.
+	///
+	/// QuickJS polls an interrupt handler during execution, so no extra thread is needed.
+	///
+	/// Panics with invalid timeouts or if this script already has a timeout set.
+	pub fn with_timeout(mut self, timeout: Duration) -> Self {
+		assert!(self.timeout.is_none());
+		assert!(timeout > Duration::ZERO);
+
+		self.timeout = Some(timeout);
+		self
+	}
+
+	// ----------------------------------------------------------------------------------------------------------------------------------------------
+	// Call API
+
+	/// Invokes a JavaScript function.
+	///
+	/// Blocks on asynchronous functions until completion.
+	///
+	/// `args_tuple` needs to be a tuple. Each tuple element is converted to JSON (using serde_json) and passed as a distinct argument
+	/// to the JS function.
+	pub fn call<A, R>(&mut self, fn_name: &str, args_tuple: A) -> Result<R, JsError>
+	where
+		A: CallArgs,
+		R: DeserializeOwned,
+	{
+		let json_args = args_tuple
+			.into_arg_string()
+			.map_err(|e| call_args::args_error(fn_name, e))?;
+		call_args::check_fn_path(fn_name)?;
+
+		// 'undefined' will cause JSON serialization error, so it needs to be treated as null
+		let js_code = format!(
+			"globalThis.__rust_result = undefined;
+			globalThis.__rust_error = undefined;
+			Promise.resolve({fn_name}({json_args}))
+				.then(r => JSON.stringify(typeof r === 'undefined' ? null : r))
+				.then(
+					json => {{ globalThis.__rust_result = json; }},
+					e => {{ globalThis.__rust_error = String(e); }}
+				);"
+		);
+
+		let start = Instant::now();
+		let deadline = self.timeout.map(|timeout| start + timeout);
+		self.deadline.set(deadline);
+
+		let result = self.run(&js_code);
+		self.deadline.set(None);
+
+		let json_result = match result {
+			Err(_) if deadline.is_some_and(|deadline| Instant::now() >= deadline) => {
+				return Err(JsError::Timeout {
+					elapsed: start.elapsed(),
+				});
+			}
+			result => result?,
+		};
+		let result: R = serde_json::from_str(&json_result)
+			.and_then(call_args::from_result)
+			.map_err(|e| call_args::result_error(fn_name, e))?;
+
+		Ok(result)
+	}
+
+	fn run(&mut self, js_code: &str) -> Result<String, JsError> {
+		self.context
+			.with(|ctx| ctx.eval::<(), _>(js_code).catch(&ctx).map_err(caught_error))?;
+
+		// Settle promises of async functions
+		while self.runtime.is_job_pending() {
+			let Err(exception) = self.runtime.execute_pending_job() else {
+				continue;
+			};
+
+			let timed_out = self
+				.deadline
+				.get()
+				.is_some_and(|deadline| Instant::now() >= deadline);
+			if timed_out {
+				return Err(JsError::Runtime(AnyError::msg("execution terminated")));
+			}
+
+			// Exception thrown by the job itself, e.g. when the result cannot be converted to JSON
+			return Err(exception.0.with(|ctx| {
+				caught_error(CaughtError::from_error(&ctx, rquickjs::Error::Exception))
+			}));
+		}
+
+		self.context.with(|ctx| {
+			let globals = ctx.globals();
+
+			if let Some(error) = globals
+				.get::<_, Option<String>>("__rust_error")
+				.map_err(quickjs_error)?
+			{
+				return Err(JsError::Runtime(AnyError::msg(format!("Uncaught {error}"))));
+			}
+
+			match globals
+				.get::<_, Option<String>>("__rust_result")
+				.map_err(quickjs_error)?
+			{
+				Some(json) => Ok(json),
+				None => Err(JsError::Runtime(AnyError::msg("function did not settle"))),
+			}
+		})
+	}
+}
+
+fn quickjs_error(e: rquickjs::Error) -> JsError {
+	JsError::Runtime(AnyError::msg(e.to_string()))
+}
+
+fn caught_error(e: rquickjs::CaughtError) -> JsError {
+	JsError::Runtime(AnyError::msg(e.to_string()))
+}
@@ -1,20 +1,60 @@
 // Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
 
 use std::borrow::Cow;
+use std::cell::{Cell, RefCell, RefMut};
+use std::collections::{BTreeSet, HashMap};
+use std::ops::DerefMut;
 use std::path::Path;
 use std::rc::Rc;
-use std::{thread, time::Duration};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
 
-use deno_core::{op, Extension, FastString, JsBuffer, JsRuntime, Op, OpState};
+use deno_core::{v8, Extension, FastString, JsRuntime, ModuleSpecifier, Op, OpDecl, Snapshot};
 use serde::de::DeserializeOwned;
+use serde::Serialize;
 
-use crate::{AnyError, CallArgs, JsError, JsValue};
+use crate::audit::{self, AuditLog};
+use crate::budget::TimeSliceBudget;
+use crate::call_args::{check_fn_path, SerializedArgs};
+use crate::call_options::Retries;
+use crate::clock::{self, ScriptClock};
+use crate::console::ConsoleOutput;
+use crate::hooks::CallHooks;
+use crate::invoke::{
+	self, Args, Callee, ChunkIterator, Completion, InFlightCall, LexicalLookups, RawCompletion,
+	ResultLimits,
+};
+use crate::lexer::is_identifier;
+use crate::loader::SandboxLoader;
+use crate::panic_guard::PanicFlag;
+use crate::platform::{Entered, Runtime};
+use crate::preemption::Preemptor;
+use crate::sink::{self, OutputSink};
+use crate::task_limits::{self, MicrotaskCounter, SettleTimer};
+use crate::termination::{Termination, TerminationFlag};
+use crate::{
+	api_check, call_args, call_options, console, context, env, host_object, integrity, limits,
+	module, namespace, policy, storage, usage, watchdog,
+};
+use crate::{
+	AnyError, ApiFunction, ApiMismatch, AuditEvent, CallArgs, CallId, CallOptions, Checkpoint,
+	FatalCondition, FromJs, ImportPolicy, JsError, JsValue, ModuleExport, Pipeline, Preemption,
+	Provenance, ScriptBuilder, ScriptEnv, SystemClock, ToJsArgs, UsageReport,
+};
 
 pub trait JsApi<'a> {
 	/// Generate an API from a script
 	fn from_script(script: &'a mut Script) -> Self
 	where
 		Self: Sized;
+
+	/// JS functions the API calls, see [`Script::check_api()`].
+	fn functions() -> &'static [ApiFunction]
+	where
+		Self: Sized,
+	{
+		&[]
+	}
 }
 
 /// Represents a single JavaScript file that can be executed.
@@ -22,13 +62,46 @@ pub trait JsApi<'a> {
 /// The code can be loaded from a file or from a string in memory.
 /// A typical usage pattern is to load a file with one or more JS function definitions, and then call those functions from Rust.
 pub struct Script {
-	runtime: JsRuntime,
-	last_rid: u32,
+	// Interior mutability allows calls through &self, see call_ref()
+	runtime: RefCell<Runtime>,
 	timeout: Option<Duration>,
+	settle_timeout: Option<Duration>,
+	budget: Option<TimeSliceBudget>,
+	preemption: Option<Rc<Preemptor>>,
+	max_heap_size: Option<usize>,
+	max_microtasks: Option<u64>,
+	max_event_loop_turns: Option<u64>,
+	recursion_budget: Option<usize>,
+	result_limits: ResultLimits,
+	// Whether timers are scheduled on a custom clock, and need to be fired after each call
+	clock_timers: bool,
+	// Whether the code was loaded as ES module
+	module: bool,
+	// Namespaces added with add_script()
+	namespaces: BTreeSet<String>,
+	// Set when execution is forcibly stopped by a timeout, budget, preemption or one of the limits
+	terminated: TerminationFlag,
+	usage: RefCell<UsageReport>,
+	hooks: RefCell<CallHooks>,
+	lexical_lookups: RefCell<LexicalLookups>,
+	// Calls started with start_call(), until finish_call() collects their results
+	in_flight: HashMap<CallId, InFlightCall>,
+	next_call_id: u64,
+	// Classes registered with register_error_class()
+	error_classes: BTreeSet<String>,
+	// Conditions the script cannot recover from, see is_healthy()
+	heap_exhausted: Cell<bool>,
+	host_panicked: PanicFlag,
+	audit: AuditLog,
+	provenance: Option<Provenance>,
 }
 
 impl Script {
-	const DEFAULT_FILENAME: &'static str = "sandboxed.js";
+	pub(crate) const DEFAULT_FILENAME: &'static str = "sandboxed.js";
+
+	/// Fraction of the configured heap limit below which the heap must fall, before a limit raised to unwind an out-of-memory call
+	/// is restored.
+	const HEAP_RESTORE_THRESHOLD: f64 = 0.5;
 
 	// ----------------------------------------------------------------------------------------------------------------------------------------------
 	// Constructors and builders
@@ -37,12 +110,7 @@ impl Script {
 	///
 	/// Returns a new object on success, and an error in case of syntax or initialization error with the code.
 	pub fn from_string(js_code: &str) -> Result<Self, JsError> {
-		// console.log() is not available by default -- add the most basic version with single argument (and no warn/info/... variants)
-		let all_code =
-			"const console = { log: function(expr) { Deno.core.print(expr + '\\n', false); } };"
-				.to_string() + js_code;
-
-		Self::create_script(all_code)
+		ScriptBuilder::new().build_from_string(js_code)
 	}
 
 	/// Initialize a script by loading it from a .js file.
@@ -52,24 +120,65 @@ impl Script {
 	///
 	/// Returns a new object on success. Fails if the file cannot be opened or in case of syntax or initialization error with the code.
 	pub fn from_file(file: impl AsRef<Path>) -> Result<Self, JsError> {
-		// let filename = file
-		// 	.as_ref()
-		// 	.file_name()
-		// 	.and_then(|s| s.to_str())
-		// 	.unwrap_or(Self::DEFAULT_FILENAME)
-		// 	.to_owned();
+		ScriptBuilder::new().build_from_file(file)
+	}
+
+	/// Initialize a script by loading it from a .js file, after checking that it has not been tampered with.
+	///
+	/// `expected_sha256` is the hex-encoded SHA-256 hash of the file's contents, e.g. as published alongside a third-party plugin.
+	/// If the file does not match, it is not executed and [`JsError::IntegrityMismatch`] is returned.
+	///
+	/// Otherwise behaves like [`Self::from_file()`]. The builder equivalent is [`ScriptBuilder::build_from_file_verified()`].
+	pub fn from_file_verified(
+		file: impl AsRef<Path>,
+		expected_sha256: &str,
+	) -> Result<Self, JsError> {
+		ScriptBuilder::new().build_from_file_verified(file, expected_sha256)
+	}
+
+	/// Checks JavaScript source code for syntax errors, without executing it.
+	///
+	/// The code is compiled in a temporary runtime, but none of its top-level statements run. This allows rejecting broken scripts
+	/// (e.g. uploads to a plugin marketplace) without executing untrusted initialization code.
+	///
+	/// Returns an error describing the first syntax error, if any.
+	pub fn validate(js_code: &str) -> Result<(), JsError> {
+		let mut runtime = Runtime::new(Default::default(), ScriptBuilder::DEFAULT_MAX_STACK_SIZE);
+		let mut runtime = Entered::new(&mut runtime);
+		let scope = &mut runtime.handle_scope();
 
-		match std::fs::read_to_string(file) {
-			Ok(js_code) => Self::create_script(js_code),
-			Err(e) => Err(JsError::Runtime(AnyError::from(e))),
+		let source = v8::String::new(scope, js_code)
+			.ok_or_else(|| AnyError::msg("source code exceeds V8's maximum string length"))?;
+
+		let scope = &mut v8::TryCatch::new(scope);
+		if v8::Script::compile(scope, source, None).is_some() {
+			return Ok(());
 		}
+
+		let exception = scope
+			.exception()
+			.expect("failed compilation must throw exception");
+		let js_error = deno_core::error::JsError::from_v8_exception(scope, exception);
+
+		Err(JsError::Runtime(js_error.into()))
+	}
+
+	/// Returns a builder, to configure settings that must be known before the script is initialized.
+	///
+	/// See [`ScriptBuilder`] for the available options.
+	pub fn builder() -> ScriptBuilder {
+		ScriptBuilder::new()
 	}
 
 	/// Equips this script with a timeout, meaning that any function call is aborted after the specified duration.
 	///
-	/// This requires creating a separate thread for each function call, which tracks time and pulls the plug
-	/// if the JS function does not return in time. Use this for untrusted 3rd-party code, not if you know that
-	/// your functions always return.
+	/// Time is tracked by a single background thread shared by all scripts, which pulls the plug if the JS function
+	/// does not return in time. Use this for untrusted 3rd-party code, not if you know that your functions always return.
+	///
+	/// The script remains usable after a call has been aborted, without re-running its initialization. All global state survives,
+	/// including changes that the aborted call made before it was stopped (which may leave data half-updated). Promises awaited by
+	/// the aborted call never settle, while timers it scheduled stay active and may fire during later calls. The same applies to
+	/// calls stopped by a time-slice budget or preemption. After exceeding the heap limit, the script should be recreated instead.
 	///
 	/// Panics with invalid timeouts or if this script already has a timeout set.
 	pub fn with_timeout(mut self, timeout: Duration) -> Self {
@@ -80,6 +189,89 @@ impl Script {
 		self
 	}
 
+	/// Limits how long a call may wait for the promise returned by an async function to settle.
+	///
+	/// The timeout starts once the function has returned its promise, and covers the time spent in the event loop (awaiting
+	/// timers, async ops and their callbacks). When it expires, the call fails with [`JsError::PendingPromise`]. This allows a short
+	/// limit for async work which does not apply to the synchronous part of a call, e.g. to fail quickly on promises which never
+	/// resolve. Calls whose promise cannot settle because no work is left pending fail with the same error, regardless of this timeout.
+	///
+	/// The overall timeout set with [`Self::with_timeout()`] still applies to the entire call.
+	///
+	/// Panics with invalid timeouts or if this script already has a settle timeout set.
+	pub fn with_settle_timeout(mut self, timeout: Duration) -> Self {
+		assert!(self.settle_timeout.is_none());
+		assert!(timeout > Duration::ZERO);
+
+		self.settle_timeout = Some(timeout);
+		self
+	}
+
+	/// Equips this script with a budget of time slices, which are consumed while JavaScript code is running.
+	///
+	/// During each function call, the isolate is interrupted every `slice_interval`, and each interrupt consumes one slice. Once all
+	/// `slices` are used up, the call is aborted with [`JsError::BudgetExhausted`]. The budget is replenished for every call.
+	///
+	/// This is a wall-clock budget, not a measure of work: V8 does not provide instruction counting, so the same call may consume a
+	/// different number of slices from run to run, depending on machine load and scheduling. Unlike [`Self::with_timeout()`],
+	/// interrupts are only processed while JS code executes, so time spent in the host (e.g. in ops) does not count towards the budget.
+	///
+	/// The budget is therefore **not** deterministic gas metering, and must not decide outcomes that several machines have to agree
+	/// on (e.g. in consensus protocols). js-sandbox does not offer such metering; the counts limited with
+	/// [`ScriptBuilder::with_max_microtasks()`](crate::ScriptBuilder::with_max_microtasks) and
+	/// [`ScriptBuilder::with_max_event_loop_turns()`](crate::ScriptBuilder::with_max_event_loop_turns) are reproducible, but do not
+	/// bound synchronous code.
+	///
+	/// Panics with invalid arguments or if this script already has a budget set.
+	pub fn with_time_slice_budget(mut self, slices: u64, slice_interval: Duration) -> Self {
+		assert!(self.budget.is_none());
+		assert!(slices > 0);
+		assert!(slice_interval > Duration::ZERO);
+
+		self.budget = Some(TimeSliceBudget {
+			slices,
+			slice_interval,
+		});
+		self
+	}
+
+	/// Periodically interrupts long-running calls at checkpoints, and lets `callback` decide how to proceed.
+	///
+	/// During each function call, the isolate is interrupted every `interval` while JS code runs. The callback is invoked on the
+	/// script's thread with the progress of the call, and returns whether to continue, to pause (suspending the thread, e.g. to give
+	/// other threads a turn), or to abort the call with [`JsError::Preempted`]. Since checkpoints occur in the middle of JS execution,
+	/// the callback can also perform other host work, which allows fair scheduling of many plugins on one thread.
+	///
+	/// ```rust
+	/// use std::time::Duration;
+	/// use js_sandbox::{JsError, Preemption, Script};
+	///
+	/// let mut script = Script::from_string("function spin() { for(;;){} }")
+	/// 	.unwrap()
+	/// 	.with_preemption(Duration::from_millis(10), |checkpoint| {
+	/// 		if checkpoint.count < 5 {
+	/// 			Preemption::Continue
+	/// 		} else {
+	/// 			Preemption::Abort
+	/// 		}
+	/// 	});
+	///
+	/// let result: Result<(), JsError> = script.call("spin", ());
+	/// assert!(matches!(result, Err(JsError::Preempted { .. })));
+	/// ```
+	///
+	/// Panics if `interval` is zero or if this script already has preemption set.
+	pub fn with_preemption<F>(mut self, interval: Duration, callback: F) -> Self
+	where
+		F: FnMut(&Checkpoint) -> Preemption + 'static,
+	{
+		assert!(self.preemption.is_none());
+		assert!(interval > Duration::ZERO);
+
+		self.preemption = Some(Rc::new(Preemptor::new(interval, Box::new(callback))));
+		self
+	}
+
 	// ----------------------------------------------------------------------------------------------------------------------------------------------
 	// Call API
 
@@ -90,126 +282,1986 @@ impl Script {
 	/// `args_tuple` needs to be a tuple.
 	///
 	/// Each tuple element is converted to JSON (using serde_json) and passed as a distinct argument to the JS function.
+	///
+	/// `fn_name` can also be a dotted path like `"utils.math.add"`, to call functions nested in objects. These are invoked as
+	/// methods, so `this` refers to the enclosing object.
+	///
+	/// The result is converted to JSON and deserialized into `R`. Functions can also return binary data as `ArrayBuffer` or
+	/// `DataView`, which arrives as an array of bytes, e.g. to be deserialized into `Vec<u8>`. Typed arrays arrive as arrays of
+	/// their elements, so a `Float64Array` can be deserialized into `Vec<f64>`.
+	///
+	/// Fields serialized with [`serde_bytes`](https://docs.rs/serde_bytes) are passed to JS as `Uint8Array`, instead of arrays of
+	/// numbers. In the other direction, `Uint8Array` values nested in arrays or objects can be deserialized with `serde_bytes`.
 	pub fn call<A, R>(&mut self, fn_name: &str, args_tuple: A) -> Result<R, JsError>
 	where
 		A: CallArgs,
 		R: DeserializeOwned,
 	{
-		let json_args = args_tuple.into_arg_string()?;
-		let json_result = self.call_impl(fn_name, json_args)?;
-		let result: R = serde_json::from_value(json_result)?;
+		self.call_ref(fn_name, args_tuple)
+	}
 
-		Ok(result)
+	/// Invokes a JavaScript function through a shared reference.
+	///
+	/// Behaves like [`Self::call()`], but allows sharing the script behind `&` references, e.g. in bindings that cannot provide
+	/// `&mut Script`. This is intended for pure/stateless scripts: nothing prevents a function from changing global JS state, which
+	/// then becomes visible to all holders of the reference.
+	///
+	/// Fails if the script is already executing a call, for example when invoked from a hook of that call.
+	pub fn call_ref<A, R>(&self, fn_name: &str, args_tuple: A) -> Result<R, JsError>
+	where
+		A: CallArgs,
+		R: DeserializeOwned,
+	{
+		self.call_deserialized(
+			Callee::Function(fn_name),
+			args_tuple,
+			&CallOptions::default(),
+		)
 	}
 
-	pub fn bind_api<'a, A>(&'a mut self) -> A
+	/// Whether `fn_name` refers to a function that can be invoked with [`Self::call()`].
+	///
+	/// Like in `call()`, `fn_name` can be a dotted path. This allows hosts to probe for optional hooks of a plugin, without treating
+	/// every error of a call as a missing function.
+	pub fn has_function(&self, fn_name: &str) -> Result<bool, JsError> {
+		check_fn_path(fn_name)?;
+
+		let expr = format!("(() => {{ try {{ return typeof {fn_name} === 'function'; }} catch {{ return false; }} }})()");
+		Ok(self.evaluate(&expr)? == JsValue::Bool(true))
+	}
+
+	/// Invokes an exported function of a script loaded as ES module (see [`ScriptBuilder::as_module()`]).
+	///
+	/// `export_name` is the name of a named export, or `"default"` for the default export, which is how most bundlers emit the entry
+	/// point of a plugin. Arguments and results are handled like in [`Self::call()`].
+	///
+	/// ```rust
+	/// use js_sandbox::Script;
+	///
+	/// let js_code = "export default function (a, b) { return a + b; } export const double = (x) => 2 * x;";
+	/// let mut script = Script::builder().as_module().build_from_string(js_code).unwrap();
+	///
+	/// let sum: i32 = script.call_export("default", (1, 2)).unwrap();
+	/// let doubled: i32 = script.call_export("double", (21,)).unwrap();
+	/// assert_eq!((sum, doubled), (3, 42));
+	/// ```
+	///
+	/// Fails if the script was loaded as a classic script.
+	pub fn call_export<A, R>(&mut self, export_name: &str, args_tuple: A) -> Result<R, JsError>
 	where
-		A: JsApi<'a>,
+		A: CallArgs,
+		R: DeserializeOwned,
 	{
-		A::from_script(self)
+		if !self.module {
+			return Err(JsError::Runtime(AnyError::msg(
+				"script was not loaded as a module",
+			)));
+		}
+
+		self.call_deserialized(
+			Callee::Export(export_name),
+			args_tuple,
+			&CallOptions::default(),
+		)
+	}
+
+	/// Evaluates additional JS code in its own scope, and makes all its top-level function declarations available under `namespace`.
+	///
+	/// This allows loading several plugins into the same script, without their top-level declarations clashing with each other
+	/// or with the main code. The functions can access the script's globals, and are called with [`Self::call_namespace()`]:
+	///
+	/// ```rust
+	/// use js_sandbox::{Script, JsError};
+	///
+	/// fn main() -> Result<(), JsError> {
+	/// 	let mut script = Script::from_string("const prefix = '> ';")?;
+	///
+	/// 	let src = "const sep = ', '; function greet(a, b) { return prefix + a + sep + b; } function sep_len() { return sep.length; }";
+	/// 	script.add_script("greeter", src)?;
+	///
+	/// 	let result: String = script.call_namespace("greeter", "greet", ("Hello", "world"))?;
+	/// 	assert_eq!(result, "> Hello, world");
+	/// 	let len: usize = script.call_namespace("greeter", "sep_len", ())?;
+	/// 	assert_eq!(len, 2);
+	/// 	Ok(())
+	/// }
+	/// ```
+	///
+	/// Other top-level declarations (variables, classes, ...) remain private to the namespace.
+	///
+	/// Fails if the namespace already exists or is not a valid identifier, or if the code throws or declares no functions.
+	pub fn add_script(&mut self, namespace: &str, source: &str) -> Result<(), JsError> {
+		if !is_identifier(namespace) {
+			return Err(namespace_error(
+				"add_script",
+				namespace,
+				"not a valid identifier",
+			));
+		}
+
+		if self.namespaces.contains(namespace) {
+			return Err(namespace_error(
+				"add_script",
+				namespace,
+				"namespace already exists",
+			));
+		}
+
+		self.load_namespace(namespace, source)?;
+		self.namespaces.insert(namespace.to_string());
+		Ok(())
+	}
+
+	/// Replaces the functions of an existing namespace with those of a new version of its code.
+	///
+	/// The new code is evaluated like in [`Self::add_script()`], in a fresh scope. If that fails, the previous functions remain in
+	/// place, so a broken update does not take down a running plugin.
+	pub fn replace_namespace(&mut self, namespace: &str, source: &str) -> Result<(), JsError> {
+		if !self.namespaces.contains(namespace) {
+			return Err(namespace_error(
+				"replace_namespace",
+				namespace,
+				"namespace does not exist",
+			));
+		}
+
+		self.load_namespace(namespace, source)
 	}
 
-	pub(crate) fn call_json(&mut self, fn_name: &str, args: &JsValue) -> Result<JsValue, JsError> {
-		self.call_impl(fn_name, args.to_string())
+	/// Removes a namespace added with [`Self::add_script()`], returning whether it existed.
+	///
+	/// Afterwards, its functions and any state only they refer to can be garbage-collected.
+	pub fn remove_namespace(&mut self, namespace: &str) -> Result<bool, JsError> {
+		if !self.namespaces.contains(namespace) {
+			return Ok(false);
+		}
+
+		self.execute_returning(namespace::remove_code(namespace))?;
+		self.namespaces.remove(namespace);
+		Ok(true)
+	}
+
+	/// Names of all namespaces added with [`Self::add_script()`], in alphabetical order.
+	pub fn list_namespaces(&self) -> impl Iterator<Item = &str> {
+		self.namespaces.iter().map(String::as_str)
+	}
+
+	/// Invokes a JavaScript function of a namespace added with [`Self::add_script()`].
+	///
+	/// Arguments, results and errors are handled the same way as in [`Self::call()`].
+	pub fn call_namespace<A, R>(
+		&mut self,
+		namespace: &str,
+		fn_name: &str,
+		args_tuple: A,
+	) -> Result<R, JsError>
+	where
+		A: CallArgs,
+		R: DeserializeOwned,
+	{
+		if !self.namespaces.contains(namespace) {
+			return Err(namespace_error(
+				"call_namespace",
+				namespace,
+				"namespace does not exist",
+			));
+		}
+
+		let path = namespace::function_path(namespace, fn_name);
+		self.call_deserialized(Callee::Function(&path), args_tuple, &CallOptions::default())
+	}
+
+	fn load_namespace(&mut self, namespace: &str, source: &str) -> Result<(), JsError> {
+		let (js_code, line_offset) = namespace::add_code(namespace, source);
+		self.execute_embedding(&js_code, line_offset)?;
+		Ok(())
 	}
 
-	fn call_impl(&mut self, fn_name: &str, json_args: String) -> Result<JsValue, JsError> {
-		// Note: ops() is required to initialize internal state
-		// Wrap everything in scoped block
+	/// Replaces the global function `fn_name` with a new implementation, keeping all other state of the script.
+	///
+	/// `source` is a function declaration or expression, such as `"function onTick(dt) { ... }"` or `"(dt) => { ... }"`. It is
+	/// evaluated in the global scope, so it can access the same globals as the code it replaces. This enables live-editing
+	/// individual functions of a running script:
+	///
+	/// ```rust
+	/// use js_sandbox::{Script, JsError};
+	///
+	/// fn main() -> Result<(), JsError> {
+	/// 	let mut script = Script::from_string("let total = 0; function onTick(dt) { total += dt; return total; }")?;
+	/// 	script.call::<_, u32>("onTick", (5,))?;
+	///
+	/// 	script.redefine_function("onTick", "function onTick(dt) { total += 2 * dt; return total; }")?;
+	/// 	let total: u32 = script.call("onTick", (5,))?;
+	/// 	assert_eq!(total, 15);
+	/// 	Ok(())
+	/// }
+	/// ```
+	///
+	/// Fails if `fn_name` does not refer to an existing function (declared with `function`, `var` or `let`), if `source` does not
+	/// evaluate to a function, or if the script was loaded as a module.
+	pub fn redefine_function(&mut self, fn_name: &str, source: &str) -> Result<(), JsError> {
+		if self.module {
+			return Err(JsError::Runtime(AnyError::msg(
+				"redefine_function() is not supported for modules",
+			)));
+		}
+
+		if !is_identifier(fn_name) {
+			return Err(JsError::Runtime(AnyError::msg(format!(
+				"redefine_function(\"{fn_name}\"): not a valid function name"
+			))));
+		}
 
-		// 'undefined' will cause JSON serialization error, so it needs to be treated as null
+		// Parenthesized, so that declarations are evaluated as expressions. The line break ends trailing // comments.
+		let source = source.trim().trim_end_matches(';');
+		// Keep the number of lines before `source` in sync with the offset below
 		let js_code = format!(
 			"(async () => {{
-				let __rust_result = {fn_name}.constructor.name === 'AsyncFunction'
-					? await {fn_name}({json_args})
-					: {fn_name}({json_args});
+				if (typeof {fn_name} !== 'function')
+					throw new TypeError('{fn_name} is not a function');
 
-				if (typeof __rust_result === 'undefined')
-					__rust_result = null;
+				const __rust_function = ({source}
+				);
+				if (typeof __rust_function !== 'function')
+					throw new TypeError('new source of {fn_name} is not a function');
 
-				Deno.core.ops.op_return(__rust_result);
+				{fn_name} = __rust_function;
 			}})()"
-		)
-		.into();
+		);
 
-		if let Some(timeout) = self.timeout {
-			let handle = self.runtime.v8_isolate().thread_safe_handle();
+		self.execute_embedding(&js_code, 4)?;
+		Ok(())
+	}
 
-			thread::spawn(move || {
-				thread::sleep(timeout);
-				handle.terminate_execution();
-			});
+	/// Invokes a JavaScript function with settings that apply to this call only.
+	///
+	/// Behaves like [`Self::call()`]. See [`CallOptions`] for the available settings.
+	pub fn call_with_options<A, R>(
+		&mut self,
+		fn_name: &str,
+		args_tuple: A,
+		options: &CallOptions,
+	) -> Result<R, JsError>
+	where
+		A: CallArgs,
+		R: DeserializeOwned,
+	{
+		self.call_deserialized(Callee::Function(fn_name), args_tuple, options)
+	}
+
+	/// Returns the console output captured so far (see [`CallOptions::with_console_capture()`]), one entry per line.
+	///
+	/// The captured output is cleared.
+	pub fn take_console_output(&mut self) -> Vec<String> {
+		let mut runtime = Entered::new(self.runtime.get_mut());
+		let state_rc = runtime.op_state();
+		let mut state = state_rc.borrow_mut();
+
+		std::mem::take(&mut state.borrow_mut::<ConsoleOutput>().captured)
+	}
+
+	/// Returns the operations that were blocked since the last call of this method, oldest first.
+	///
+	/// Denied imports and capability requests are recorded even if the script catches the resulting error. Only the most recent
+	/// 1000 events are kept.
+	pub fn take_audit_log(&mut self) -> Vec<AuditEvent> {
+		self.audit.take(self.provenance.as_ref())
+	}
+
+	/// Returns the current variables of the `env` global (see [`ScriptBuilder::with_env()`]), including changes by the script.
+	///
+	/// Empty if the script has no environment.
+	pub fn env(&mut self) -> HashMap<String, String> {
+		let mut runtime = Entered::new(self.runtime.get_mut());
+		let state_rc = runtime.op_state();
+		let state = state_rc.borrow();
+
+		state
+			.try_borrow::<ScriptEnv>()
+			.map(|env| env.vars().clone())
+			.unwrap_or_default()
+	}
+
+	/// Invokes a JavaScript function with a single options object, the common JS convention for named arguments.
+	///
+	/// `kwargs` can be a `serde_json::json!({...})` literal, a map or a struct deriving `Serialize`; it must serialize to a JSON object.
+	/// This is a shorthand for `call(fn_name, (kwargs,))`, which additionally rejects non-object values.
+	///
+	/// ```rust
+	/// use js_sandbox::{Script, JsError};
+	/// use serde_json::json;
+	///
+	/// fn main() -> Result<(), JsError> {
+	/// 	let js_code = "function area({ width, height }) { return width * height; }";
+	/// 	let mut script = Script::from_string(js_code)?;
+	///
+	/// 	let result: i32 = script.call_kwargs("area", json!({ "width": 10, "height": 20 }))?;
+	/// 	assert_eq!(result, 200);
+	/// 	Ok(())
+	/// }
+	/// ```
+	pub fn call_kwargs<K, R>(&mut self, fn_name: &str, kwargs: K) -> Result<R, JsError>
+	where
+		K: Serialize,
+		R: DeserializeOwned,
+	{
+		let kwargs = serde_json::to_value(kwargs)
+			.map_err(|e| call_args::args_error(fn_name, AnyError::msg(format!("kwargs: {e}"))))?;
+
+		if !kwargs.is_object() {
+			return Err(call_args::args_error(
+				fn_name,
+				AnyError::msg("kwargs: expected a JSON object"),
+			));
 		}
 
-		// syncing ops is required cause they sometimes change while preparing the engine
-		// self.runtime.sync_ops_cache();
+		self.call(fn_name, (kwargs,))
+	}
 
-		// TODO use strongly typed JsError here (downcast)
-		self.runtime
-			.execute_script(Self::DEFAULT_FILENAME, js_code)?;
-		deno_core::futures::executor::block_on(self.runtime.run_event_loop(false))?;
+	/// Invokes a JavaScript function with arguments that are already JSON values.
+	///
+	/// Each element of `args` is passed as a distinct argument. Unlike [`Self::call()`] with [`JsValue`] arguments, the values are
+	/// converted directly to V8 values, skipping the round-trip through JSON text. This pays off for hosts that build arguments
+	/// dynamically (e.g. rule engines), especially for large values.
+	pub fn call_values<R>(&mut self, fn_name: &str, args: Vec<JsValue>) -> Result<R, JsError>
+	where
+		R: DeserializeOwned,
+	{
+		self.hooks()?
+			.before_call_with(fn_name, || JsValue::Array(args.clone()));
 
-		let state_rc = self.runtime.op_state();
-		let mut state = state_rc.borrow_mut();
-		let table = &mut state.resource_table;
+		let start = Instant::now();
+		let result = self.call_values_unhooked(fn_name, args);
+		let result = self
+			.hooks
+			.borrow_mut()
+			.after_call(fn_name, result, start.elapsed());
 
-		// Get resource, and free slot (no longer needed)
-		let entry: Rc<ResultResource> = table
-			.take(self.last_rid)
-			.expect("Resource entry must be present");
-		let extracted =
-			Rc::try_unwrap(entry).expect("Rc must hold single strong ref to resource entry");
-		self.last_rid += 1;
+		let result: R = call_args::from_result(result?)
+			.map_err(|e| self.attributed(call_args::result_error(fn_name, e)))?;
 
-		Ok(extracted.json_value)
+		Ok(result)
 	}
 
-	fn create_script<S>(js_code: S) -> Result<Self, JsError>
+	/// Invokes a JavaScript function, converting arguments and result directly from and to V8 values.
+	///
+	/// Instead of serde, this uses the [`ToJs`] and [`FromJs`] traits, which types can implement to define their own V8
+	/// representation (e.g. interned strings, bit sets or math vectors). [`Self::call()`] keeps using serde, as Rust cannot pick
+	/// a conversion based on whether a trait is implemented.
+	///
+	/// Limits and settings of the script apply as with [`Self::call()`], except for the result size limits. As the arguments have
+	/// no JSON form, hooks registered with [`Self::on_call()`] and [`Self::on_result()`] are not invoked, and usage reports count no bytes for these calls.
+	///
+	/// ```rust
+	/// use js_sandbox::{JsError, Script};
+	///
+	/// fn main() -> Result<(), JsError> {
+	/// 	let mut script = Script::from_string("function scale(v, f) { return v.map(x => x * f); }")?;
+	///
+	/// 	let scaled: Vec<f64> = script.call_js("scale", (vec![1.0, 2.5], 2.0))?;
+	/// 	assert_eq!(scaled, vec![2.0, 5.0]);
+	/// 	Ok(())
+	/// }
+	/// ```
+	pub fn call_js<A, R>(&mut self, fn_name: &str, args_tuple: A) -> Result<R, JsError>
 	where
-		S: Into<FastString>,
+		A: ToJsArgs,
+		R: FromJs,
 	{
-		let ext = Extension {
-			ops: Cow::Owned(vec![op_return::DECL]),
-			..Default::default()
+		check_fn_path(fn_name)?;
+		let mut runtime = self.runtime()?;
+
+		let args = {
+			let scope = &mut runtime.handle_scope();
+			let args = args_tuple
+				.to_js_args(scope)
+				.map_err(|e| call_args::args_error(fn_name, e))?;
+			args.iter().map(|arg| v8::Global::new(scope, arg)).collect()
 		};
 
-		let mut runtime = JsRuntime::new(deno_core::RuntimeOptions {
-			module_loader: Some(Rc::new(deno_core::FsModuleLoader)),
-			extensions: vec![ext],
-			..Default::default()
+		let start = Instant::now();
+		let completion = self.run_guarded(&mut runtime, &CallOptions::default(), |runtime| {
+			let lookups = &mut self.lexical_lookups.borrow_mut();
+			let completion = invoke::call_raw(runtime, lookups, fn_name, Args::V8(args))?;
+
+			self.settle(runtime, None)?;
+			Ok(completion)
+		});
+
+		let value = completion.and_then(|completion| match completion {
+			RawCompletion::Value(value) => Ok(value),
+			RawCompletion::Promise(promise) => match invoke::settled_raw(&mut runtime, &promise) {
+				Some(result) => Ok(result?),
+				None => Err(JsError::PendingPromise {
+					elapsed: start.elapsed(),
+				}),
+			},
 		});
 
-		// We cannot provide a dynamic filename because execute_script() requires a &'static str
-		runtime.execute_script(Self::DEFAULT_FILENAME, js_code.into())?;
+		let result = value.and_then(|value| {
+			let scope = &mut runtime.handle_scope();
+			let value = v8::Local::new(scope, value);
+			R::from_js(scope, value)
+				.map_err(|e| self.attributed(call_args::conversion_error(fn_name, e)))
+		});
+		drop(runtime);
 
-		Ok(Script {
-			runtime,
-			last_rid: 0,
-			timeout: None,
-		})
+		self.record_call(start.elapsed(), 0, result.as_ref().ok().map(|_| 0));
+		result
 	}
-}
 
-#[derive(Debug)]
-struct ResultResource {
-	json_value: JsValue,
-}
+	/// Registers a JS class whose instances represent expected failures, see [`Self::call_expecting_errors()`].
+	///
+	/// `class_name` is an identifier, referring to a global or top-level class. It is resolved on each call, so the class can be
+	/// defined after registration. Instances of subclasses match as well.
+	pub fn register_error_class(&mut self, class_name: &str) -> Result<(), JsError> {
+		if !is_identifier(class_name) {
+			return Err(JsError::Runtime(AnyError::msg(format!(
+				"register_error_class(\"{class_name}\"): not a valid class name"
+			))));
+		}
+
+		self.error_classes.insert(class_name.to_string());
+		Ok(())
+	}
+
+	/// Invokes a JavaScript function, separating expected failures from other errors.
+	///
+	/// If the function throws an instance of a class registered with [`Self::register_error_class()`], the error is deserialized
+	/// into `E` and returned as `Ok(Err(e))`. The error object's own properties are available, as well as its `name` and `message`.
+	/// Everything else that is thrown remains a [`JsError`]:
+	///
+	/// ```rust
+	/// use serde::Deserialize;
+	/// use js_sandbox::{Script, JsError};
+	///
+	/// #[derive(Deserialize)]
+	/// struct ValidationError {
+	/// 	field: String,
+	/// 	message: String,
+	/// }
+	///
+	/// fn main() -> Result<(), JsError> {
+	/// 	let js_code = r#"
+	/// 		class ValidationError extends Error {
+	/// 			constructor(field) { super(`${field} is required`); this.field = field; }
+	/// 		}
+	/// 		function register(user) {
+	/// 			if (!user.name) throw new ValidationError("name");
+	/// 			return user.name.length;
+	/// 		}"#;
+	/// 	let mut script = Script::from_string(js_code)?;
+	/// 	script.register_error_class("ValidationError")?;
+	///
+	/// 	let user = serde_json::json!({ "name": "" });
+	/// 	let result = script.call_expecting_errors::<u32, ValidationError>("register", (user,))?;
+	///
+	/// 	let error = result.unwrap_err();
+	/// 	assert_eq!(error.field, "name");
+	/// 	assert_eq!(error.message, "name is required");
+	/// 	Ok(())
+	/// }
+	/// ```
+	///
+	/// Arguments are passed like in [`Self::call()`]. Call hooks are not invoked.
+	pub fn call_expecting_errors<T, E>(
+		&mut self,
+		fn_name: &str,
+		args_tuple: impl CallArgs,
+	) -> Result<Result<T, E>, JsError>
+	where
+		T: DeserializeOwned,
+		E: DeserializeOwned,
+	{
+		let json_args = args_tuple
+			.into_arg_string()
+			.map_err(|e| call_args::args_error(fn_name, e))?;
+		let invocation = Self::invocation_expr(fn_name, &json_args)?;
+
+		let mut expected = self
+			.error_classes
+			.iter()
+			.map(|class| {
+				format!("(typeof {class} === 'function' && __rust_error instanceof {class})")
+			})
+			.collect::<Vec<_>>()
+			.join(" || ");
+		if expected.is_empty() {
+			expected = "false".to_string();
+		}
+
+		let js_code = format!(
+			"(async () => {{
+				try {{
+					return {{ ok: {invocation} }};
+				}} catch (__rust_error) {{
+					if ({expected}) {{
+						return {{ err: {{ ...__rust_error, name: __rust_error.name, message: __rust_error.message }} }};
+					}}
+					throw __rust_error;
+				}}
+			}})()"
+		);
+
+		let start = Instant::now();
+		let outcome = self.execute_returning(js_code);
+		self.record_call(
+			start.elapsed(),
+			json_args.len(),
+			outcome.as_ref().ok().map(usage::json_size),
+		);
+
+		let mut outcome = match outcome? {
+			JsValue::Object(outcome) => outcome,
+			other => unreachable!("wrapper returns object, got {other}"),
+		};
+
+		if let Some(error) = outcome.remove("err") {
+			let error: E = call_args::from_result(error)
+				.map_err(|e| self.attributed(call_args::result_error(fn_name, e)))?;
+			return Ok(Err(error));
+		}
+
+		let result = outcome.remove("ok").unwrap_or(JsValue::Null);
+		let result: T = call_args::from_result(result)
+			.map_err(|e| self.attributed(call_args::result_error(fn_name, e)))?;
+		Ok(Ok(result))
+	}
+
+	/// Starts a JavaScript function call, without waiting for the returned promise to settle.
+	///
+	/// The function runs until it returns, which for async functions is typically their first `await`. Its result is collected
+	/// later with [`Self::finish_call()`], using the returned ID. Meanwhile, other calls can be made or started, so that several
+	/// independent operations (e.g. awaiting async host calls) are in flight at the same time:
+	///
+	/// ```rust
+	/// use js_sandbox::{Script, JsError};
+	///
+	/// fn main() -> Result<(), JsError> {
+	/// 	let js_code = "
+	/// 		let provide;
+	/// 		const data = new Promise(resolve => provide = resolve);
+	/// 		async function total() { return (await data).reduce((a, b) => a + b, 0); }
+	/// 		async function count() { return (await data).length; }
+	/// 		function setData(values) { provide(values); }";
+	/// 	let mut script = Script::from_string(js_code)?;
+	///
+	/// 	let total = script.start_call("total", ())?;
+	/// 	let count = script.start_call("count", ())?;
+	/// 	script.call::<_, ()>("setData", (vec![1, 2, 3],))?;
+	///
+	/// 	assert_eq!(script.finish_call::<u32>(count)?, 3);
+	/// 	assert_eq!(script.finish_call::<u32>(total)?, 6);
+	/// 	Ok(())
+	/// }
+	/// ```
+	///
+	/// Arguments are passed like in [`Self::call()`]. Errors thrown before the function returns are reported here, later
+	/// ones by `finish_call()`. Call hooks are not invoked for started calls.
+	pub fn start_call<A>(&mut self, fn_name: &str, args_tuple: A) -> Result<CallId, JsError>
+	where
+		A: CallArgs,
+	{
+		let args = args_tuple
+			.into_args()
+			.map_err(|e| call_args::args_error(fn_name, e))?;
+		check_fn_path(fn_name)?;
+
+		let mut runtime = self.runtime()?;
+		let start = Instant::now();
+		let completion = self.run_guarded(&mut runtime, &CallOptions::default(), |runtime| {
+			let lookups = &mut self.lexical_lookups.borrow_mut();
+			let completion = invoke::call(
+				runtime,
+				lookups,
+				fn_name,
+				Args::Serialized(&args),
+				self.result_limits,
+			)?;
+
+			// Let the function progress as far as possible without blocking, e.g. run microtasks
+			task_limits::poll_once(runtime)?;
+			Ok(completion)
+		});
+
+		let completion = match completion {
+			Ok(completion) => completion,
+			Err(e) => {
+				drop(runtime);
+				self.record_call(start.elapsed(), args.size(), None);
+				return Err(e);
+			}
+		};
+
+		// Rejections are reported by finish_call(), not by whichever call happens to run the event loop
+		if let Completion::Promise(promise) = &completion {
+			invoke::mark_handled(&mut runtime, promise);
+		}
+		drop(runtime);
+
+		let id = CallId(self.next_call_id);
+		self.next_call_id += 1;
+		self.in_flight.insert(
+			id,
+			InFlightCall {
+				fn_name: fn_name.to_string(),
+				completion,
+				bytes_in: args.size(),
+				start_time: start.elapsed(),
+			},
+		);
+
+		Ok(id)
+	}
+
+	/// Waits for a call started with [`Self::start_call()`] to complete, and returns its result.
+	///
+	/// Runs the event loop until the call's promise has settled, which also lets other calls in flight progress. Results can be
+	/// collected in any order; each ID can be used once. Fails with [`JsError::PendingPromise`] if the promise cannot settle,
+	/// because no more work is pending.
+	pub fn finish_call<R>(&mut self, id: CallId) -> Result<R, JsError>
+	where
+		R: DeserializeOwned,
+	{
+		let Some(call) = self.in_flight.remove(&id) else {
+			return Err(JsError::Runtime(AnyError::msg(format!(
+				"finish_call(): no call in flight with {id:?}"
+			))));
+		};
+
+		let mut runtime = self.runtime()?;
+		let start = Instant::now();
+		let result = match &call.completion {
+			Completion::Promise(promise) if !invoke::is_settled(&mut runtime, promise) => self
+				.run_guarded(&mut runtime, &CallOptions::default(), |runtime| {
+					self.settle(runtime, Some(promise))
+				}),
+			_ => Ok(()),
+		};
+		let result =
+			result.and_then(|()| self.completion_result(&mut runtime, call.completion, start));
+		drop(runtime);
+
+		// Counted as one call, including the time spent in start_call()
+		self.record_call(
+			call.start_time + start.elapsed(),
+			call.bytes_in,
+			result.as_ref().ok().map(usage::json_size),
+		);
+
+		let result: R = call_args::from_result(result?)
+			.map_err(|e| self.attributed(call_args::result_error(&call.fn_name, e)))?;
+
+		Ok(result)
+	}
+
+	/// Invokes a JavaScript function which produces its result in chunks, and passes each chunk to `on_chunk`.
+	///
+	/// The function returns an iterable or async iterable, typically by being a generator (`function*`) or an async generator
+	/// (`async function*`). Each value it yields is converted like the result of [`Self::call()`] and deserialized into `R`. The
+	/// generator only continues once `on_chunk` has returned, so just one chunk is held in memory at a time. This allows processing
+	/// results that are too large to be materialized as a whole, e.g. in ETL jobs:
+	///
+	/// ```rust
+	/// use js_sandbox::{Script, JsError};
+	///
+	/// fn main() -> Result<(), JsError> {
+	/// 	let js_code = "
+	/// 		function* rows(count) {
+	/// 			for (let i = 0; i < count; i += 1000) {
+	/// 				yield Array.from({ length: Math.min(1000, count - i) }, (_, j) => i + j);
+	/// 			}
+	/// 		}";
+	/// 	let mut script = Script::from_string(js_code)?;
+	///
+	/// 	let mut sum = 0;
+	/// 	script.call_chunked("rows", (1_000_000,), |chunk: Vec<u64>| {
+	/// 		sum += chunk.iter().sum::<u64>();
+	/// 		Ok(())
+	/// 	})?;
+	///
+	/// 	assert_eq!(sum, 499_999_500_000);
+	/// 	Ok(())
+	/// }
+	/// ```
+	///
+	/// Arguments are passed like in [`Self::call()`]. If `on_chunk` fails, the iterator is closed (running `finally` blocks of
+	/// generators) and the error is returned as [`JsError::Runtime`]. The timeout and other limits apply to producing each chunk
+	/// separately. Call hooks are not invoked for chunked calls.
+	pub fn call_chunked<A, R, F>(
+		&mut self,
+		fn_name: &str,
+		args_tuple: A,
+		mut on_chunk: F,
+	) -> Result<(), JsError>
+	where
+		A: CallArgs,
+		R: DeserializeOwned,
+		F: FnMut(R) -> Result<(), AnyError>,
+	{
+		let args = args_tuple
+			.into_args()
+			.map_err(|e| call_args::args_error(fn_name, e))?;
+		check_fn_path(fn_name)?;
+
+		let mut runtime = self.runtime()?;
+		let options = CallOptions::default();
+		let start = Instant::now();
+		let mut bytes_out = 0;
+
+		let chunks = self.run_guarded(&mut runtime, &options, |runtime| {
+			let lookups = &mut self.lexical_lookups.borrow_mut();
+			invoke::call_iterable(runtime, lookups, fn_name, Args::Serialized(&args))
+		});
+		let result = chunks.and_then(|chunks| {
+			let result = self.pull_chunks(&mut runtime, fn_name, &chunks, |value| {
+				bytes_out += usage::json_size(&value);
+				let chunk: R = call_args::from_result(value)
+					.map_err(|e| self.attributed(call_args::result_error(fn_name, e)))?;
+				on_chunk(chunk).map_err(JsError::Runtime)
+			});
+
+			// Let the generator clean up, unless it has finished anyway
+			if result.is_err() {
+				let _ = self.run_guarded(&mut runtime, &options, |runtime| {
+					invoke::close_iterator(runtime, &chunks);
+					Ok(())
+				});
+			}
+			result
+		});
+		drop(runtime);
 
-// Type that is stored inside Deno's resource table
-impl deno_core::Resource for ResultResource {
-	fn name(&self) -> Cow<str> {
-		"__rust_Result".into()
+		self.record_call(
+			start.elapsed(),
+			args.size(),
+			result.as_ref().ok().map(|()| bytes_out),
+		);
+		result
 	}
+
+	/// Requests chunks from `chunks` until it is exhausted, passing each one to `on_chunk`.
+	fn pull_chunks<F>(
+		&self,
+		runtime: &mut JsRuntime,
+		fn_name: &str,
+		chunks: &ChunkIterator,
+		mut on_chunk: F,
+	) -> Result<(), JsError>
+	where
+		F: FnMut(JsValue) -> Result<(), JsError>,
+	{
+		loop {
+			let start = Instant::now();
+			let completion = self.run_guarded(runtime, &CallOptions::default(), |runtime| {
+				let completion = invoke::next_chunk(runtime, chunks, self.result_limits)?;
+				if let Completion::Promise(promise) = &completion {
+					self.settle(runtime, Some(promise))?;
+				}
+				Ok(completion)
+			})?;
+
+			let JsValue::Object(mut step) = self.completion_result(runtime, completion, start)?
+			else {
+				return Err(JsError::Runtime(AnyError::msg(format!(
+					"{fn_name}: iterator result is not an object"
+				))));
+			};
+
+			if step.get("done").and_then(JsValue::as_bool) == Some(true) {
+				return Ok(());
+			}
+			on_chunk(step.remove("value").unwrap_or(JsValue::Null))?;
+		}
+	}
+
+	/// Evaluates a JavaScript expression in the global scope of this script, and returns the result as a JSON value.
+	///
+	/// The expression can access all globals of the script and may use `await`. See also [`eval_json()`](crate::eval_json) for
+	/// one-off evaluations in a fresh runtime.
+	pub fn eval_json(&self, js_expr: &str) -> Result<JsValue, JsError> {
+		let start = Instant::now();
+		let result = self.evaluate(js_expr);
+		self.record_call(
+			start.elapsed(),
+			0,
+			result.as_ref().ok().map(usage::json_size),
+		);
+		result
+	}
+
+	/// Like [`Self::eval_json()`], for evaluations on behalf of the library, which are not counted in the usage report.
+	pub(crate) fn evaluate(&self, js_expr: &str) -> Result<JsValue, JsError> {
+		self.execute_returning(Self::call_wrapper(&format!("({js_expr})")))
+	}
+
+	/// Lists the exports of a script loaded as ES module (see [`ScriptBuilder::as_module()`]), sorted by name.
+	///
+	/// This allows checking at load time whether a plugin implements the interface expected by the host:
+	///
+	/// ```rust
+	/// use js_sandbox::Script;
+	///
+	/// let js_code = "export function init() {} export const version = 2;";
+	/// let script = Script::builder().as_module().build_from_string(js_code).unwrap();
+	///
+	/// let exports = script.module_exports().unwrap();
+	/// assert!(exports.iter().any(|e| e.name == "init" && e.is_function));
+	/// assert!(exports.iter().any(|e| e.name == "version" && !e.is_function));
+	/// ```
+	///
+	/// Fails if the script was loaded as a classic script.
+	pub fn module_exports(&self) -> Result<Vec<ModuleExport>, JsError> {
+		if !self.module {
+			return Err(JsError::Runtime(AnyError::msg(
+				"script was not loaded as a module",
+			)));
+		}
+
+		let exports = self.evaluate(&module::exports_expr())?;
+		let exports: Vec<(String, bool)> = serde_json::from_value(exports)?;
+
+		Ok(exports
+			.into_iter()
+			.map(|(name, is_function)| ModuleExport { name, is_function })
+			.collect())
+	}
+
+	/// Moves the time observed by the script forward, firing all timers that become due in the meantime.
+	///
+	/// Timers fire in order of their due time, and each one observes its due time via `Date` and `performance.now()`. Promises
+	/// settle before the next timer fires. This allows testing time-dependent logic deterministically and without sleeping.
+	///
+	/// The script's time remains shifted by `duration` relative to its clock. Requires a clock configured via
+	/// [`ScriptBuilder::with_clock()`], typically a [`VirtualClock`](crate::VirtualClock).
+	///
+	/// ```rust
+	/// use std::time::Duration;
+	/// use js_sandbox::{Script, VirtualClock};
+	///
+	/// let js_code = "let ticks = []; setInterval(() => ticks.push(performance.now()), 100); function getTicks() { return ticks; }";
+	/// let mut script = Script::builder()
+	/// 	.with_clock(VirtualClock::new())
+	/// 	.build_from_string(js_code)
+	/// 	.unwrap();
+	///
+	/// script.advance_time(Duration::from_millis(350)).unwrap();
+	/// let ticks: Vec<f64> = script.call("getTicks", ()).unwrap();
+	/// assert_eq!(ticks, [100.0, 200.0, 300.0]);
+	/// ```
+	pub fn advance_time(&mut self, duration: Duration) -> Result<(), JsError> {
+		if !self.clock_timers {
+			return Err(JsError::Runtime(AnyError::msg(
+				"advance_time() requires a clock, see ScriptBuilder::with_clock()",
+			)));
+		}
+
+		// Timers are fired step by step afterwards, while running the event loop
+		let millis = duration.as_secs_f64() * 1000.0;
+		self.execute_with(
+			|runtime| clock::advance(runtime, millis),
+			&CallOptions::default(),
+		)?;
+		Ok(())
+	}
+
+	/// Invokes multiple JavaScript functions in one go.
+	///
+	/// Each entry consists of a function name and its arguments, one JSON value per argument like in [`Self::call_values()`], so
+	/// functions with different numbers and types of parameters can be mixed. All functions are invoked in order, within a single
+	/// run of the event loop, which reduces overhead when many small functions need to be called at once.
+	///
+	/// ```rust
+	/// use js_sandbox::Script;
+	/// use serde_json::json;
+	///
+	/// let mut script = Script::from_string("function triple(a) { return 3 * a; } function add(a, b) { return a + b; }").unwrap();
+	/// let calls = [("triple", vec![json!(7)]), ("add", vec![json!(1), json!(2)])];
+	///
+	/// let results: Vec<i32> = script.call_batch(&calls).unwrap();
+	/// assert_eq!(results, [21, 3]);
+	/// ```
+	///
+	/// Returns the results in the same order as the calls. If any function throws, the whole batch fails, and later functions
+	/// are not invoked.
+	pub fn call_batch<R>(&mut self, calls: &[(&str, Vec<JsValue>)]) -> Result<Vec<R>, JsError>
+	where
+		R: DeserializeOwned,
+	{
+		let mut invocations = String::new();
+		let mut bytes_in = 0;
+		for (fn_name, args) in calls {
+			let args = SerializedArgs::from_values(args);
+			bytes_in += args.size();
+			let invocation = Self::invocation_expr(fn_name, args.json())?;
+
+			invocations += &format!(
+				"__rust_result = {invocation};
+				__rust_results.push(typeof __rust_result === 'undefined' ? null : __rust_result);
+				"
+			);
+		}
+
+		let js_code = format!(
+			"(async () => {{
+				const __rust_results = [];
+				let __rust_result;
+				{invocations}
+				return __rust_results;
+			}})()"
+		);
+
+		let start = Instant::now();
+		let json_results = self.execute_returning(js_code);
+		self.record_call(
+			start.elapsed(),
+			bytes_in,
+			json_results.as_ref().ok().map(usage::json_size),
+		);
+
+		let json_results = match json_results? {
+			JsValue::Array(values) => values,
+			other => unreachable!("batch returns array, got {other}"),
+		};
+
+		json_results
+			.into_iter()
+			.zip(calls)
+			.map(|(json_result, (fn_name, _))| {
+				call_args::from_result(json_result)
+					.map_err(|e| self.attributed(call_args::result_error(fn_name, e)))
+			})
+			.collect()
+	}
+
+	/// Starts a chain of function calls, where each function receives the result of the previous one.
+	///
+	/// See [`Pipeline`] for details.
+	pub fn pipeline(&mut self) -> Pipeline<'_> {
+		Pipeline::new(self)
+	}
+
+	/// Registers a callback, which is invoked before every function call.
+	///
+	/// The callback receives the function name and a JSON array of all arguments. This is useful for cross-cutting concerns like
+	/// auditing or tracing: for example, a logging hook can decide which arguments to redact, without touching each call site.
+	///
+	/// Hooks apply to [`Self::call()`], [`Self::call_values()`] and everything built on top of them (such as [`js_api`](crate::js_api)
+	/// bindings), but not to batches or pipelines.
+	pub fn on_call<F>(&mut self, hook: F)
+	where
+		F: FnMut(&str, &JsValue) + 'static,
+	{
+		self.hooks.get_mut().on_call.push(Box::new(hook));
+	}
+
+	/// Registers a callback, which is invoked after every function call.
+	///
+	/// The callback receives the function name, the JSON result or error, and the duration of the call.
+	/// See [`Self::on_call()`] for which calls are covered.
+	pub fn on_result<F>(&mut self, hook: F)
+	where
+		F: FnMut(&str, Result<&JsValue, &JsError>, Duration) + 'static,
+	{
+		self.hooks.get_mut().on_result.push(Box::new(hook));
+	}
+
+	/// Registers a validator for the return values of `fn_name`, which runs before they are deserialized.
+	///
+	/// The validator receives the JSON result of each successful call, and returns a description of the problem if the value does
+	/// not have the expected shape. The call then fails with [`JsError::InvalidResult`], which tells plugin authors what their
+	/// script returned wrong, rather than surfacing as a deserialization error. Replaces any validator previously registered for the
+	/// same function. See [`Self::on_call()`] for which calls are covered.
+	///
+	/// ```rust
+	/// use js_sandbox::{JsError, Script};
+	///
+	/// let mut script = Script::from_string("function score() { return -5; }").unwrap();
+	/// script.validate_result("score", |value| match value.as_i64() {
+	/// 	Some(0..=100) => Ok(()),
+	/// 	_ => Err(format!("expected score between 0 and 100, got {value}")),
+	/// });
+	///
+	/// let result: Result<i64, JsError> = script.call("score", ());
+	/// assert!(matches!(result, Err(JsError::InvalidResult { .. })));
+	/// ```
+	pub fn validate_result<F>(&mut self, fn_name: &str, validator: F)
+	where
+		F: Fn(&JsValue) -> Result<(), String> + 'static,
+	{
+		self.hooks
+			.get_mut()
+			.validators
+			.insert(fn_name.to_string(), Box::new(validator));
+	}
+
+	/// Validates the return values of `fn_name` against a [JSON Schema](https://json-schema.org), before they are deserialized.
+	///
+	/// Like [`Self::validate_result()`], with the reason listing all violations and their location within the value.
+	/// Returns an error if `schema` itself is invalid.
+	#[cfg(feature = "json-schema")]
+	pub fn validate_result_schema(
+		&mut self,
+		fn_name: &str,
+		schema: &JsValue,
+	) -> Result<(), JsError> {
+		let schema = jsonschema::JSONSchema::compile(schema)
+			.map_err(|e| JsError::Runtime(AnyError::msg(format!("invalid JSON schema: {e}"))))?;
+
+		self.validate_result(fn_name, move |value| {
+			schema.validate(value).map_err(|errors| {
+				errors
+					.map(|e| format!("{e} (at '{}')", e.instance_path))
+					.collect::<Vec<_>>()
+					.join("; ")
+			})
+		});
+		Ok(())
+	}
+
+	/// Checks whether the script defines the JS functions called by a `#[js_api]` trait, returning all differences.
+	///
+	/// Reports functions that are missing (unless the trait provides a default implementation), names that do not refer to
+	/// functions, and functions declaring more parameters than the Rust method passes. An empty list means the script matches
+	/// the API. Types of arguments and results are not checked, as JS functions do not declare them.
+	///
+	/// ```rust
+	/// use js_sandbox::{js_api, ApiMismatch, Script};
+	///
+	/// #[js_api]
+	/// trait Plugin {
+	/// 	fn init(&mut self);
+	/// 	fn transform(&mut self, text: &str) -> String;
+	/// }
+	///
+	/// let script = Script::from_string("function transform(text, options) { return text; }").unwrap();
+	/// let mismatches = script.check_api::<Plugin>().unwrap();
+	///
+	/// assert_eq!(mismatches.len(), 2);
+	/// assert!(matches!(&mismatches[0], ApiMismatch::Missing { function } if function == "init"));
+	/// assert!(matches!(&mismatches[1], ApiMismatch::Arity { expected: 1, actual: 2, .. }));
+	/// ```
+	pub fn check_api<'a, A>(&self) -> Result<Vec<ApiMismatch>, JsError>
+	where
+		A: JsApi<'a>,
+	{
+		api_check::check(self, A::functions())
+	}
+
+	pub fn bind_api<'a, A>(&'a mut self) -> A
+	where
+		A: JsApi<'a>,
+	{
+		A::from_script(self)
+	}
+
+	pub(crate) fn call_json(&self, fn_name: &str, args: &JsValue) -> Result<JsValue, JsError> {
+		let args = SerializedArgs::from_json(args.to_string());
+		self.call_impl(fn_name, &args, &CallOptions::default())
+	}
+
+	fn call_deserialized<A, R>(
+		&self,
+		callee: Callee,
+		args_tuple: A,
+		options: &CallOptions,
+	) -> Result<R, JsError>
+	where
+		A: CallArgs,
+		R: DeserializeOwned,
+	{
+		let fn_name = callee.name();
+		let args = args_tuple
+			.into_args()
+			.map_err(|e| call_args::args_error(fn_name, e))?;
+		let json_result = self.call_callee(callee, &args, options)?;
+		let result: R = call_args::from_result(json_result)
+			.map_err(|e| self.attributed(call_args::result_error(fn_name, e)))?;
+
+		Ok(result)
+	}
+
+	pub(crate) fn call_impl(
+		&self,
+		fn_name: &str,
+		args: &SerializedArgs,
+		options: &CallOptions,
+	) -> Result<JsValue, JsError> {
+		self.call_callee(Callee::Function(fn_name), args, options)
+	}
+
+	fn call_callee(
+		&self,
+		callee: Callee,
+		args: &SerializedArgs,
+		options: &CallOptions,
+	) -> Result<JsValue, JsError> {
+		options.check()?;
+
+		let fn_name = callee.name();
+		let mut retries = Retries::new(options);
+		loop {
+			self.hooks()?.before_call(fn_name, args)?;
+
+			let start = Instant::now();
+			let result = self.call_unhooked(callee, args, options);
+			let result = self
+				.hooks
+				.borrow_mut()
+				.after_call(fn_name, result, start.elapsed());
+
+			match result {
+				Err(e) if retries.retry(&e) => continue,
+				result => return result,
+			}
+		}
+	}
+
+	fn call_unhooked(
+		&self,
+		callee: Callee,
+		args: &SerializedArgs,
+		options: &CallOptions,
+	) -> Result<JsValue, JsError> {
+		self.call_direct(callee, Args::Serialized(args), args.size(), options)
+	}
+
+	fn call_values_unhooked(&self, fn_name: &str, args: Vec<JsValue>) -> Result<JsValue, JsError> {
+		let bytes_in = args.iter().map(usage::json_size).sum::<u64>() as usize;
+		self.call_direct(
+			Callee::Function(fn_name),
+			Args::Values(args),
+			bytes_in,
+			&CallOptions::default(),
+		)
+	}
+
+	/// Invokes `callee` directly, without going through a wrapper script.
+	fn call_direct(
+		&self,
+		callee: Callee,
+		args: Args,
+		bytes_in: usize,
+		options: &CallOptions,
+	) -> Result<JsValue, JsError> {
+		if let Callee::Function(fn_name) = callee {
+			check_fn_path(fn_name)?;
+		}
+
+		let run = |runtime: &mut JsRuntime| {
+			let lookups = &mut self.lexical_lookups.borrow_mut();
+			invoke::call(runtime, lookups, callee, args, self.result_limits)
+		};
+
+		let start = Instant::now();
+		let result = self.execute_with(run, options);
+		self.record_call(
+			start.elapsed(),
+			bytes_in,
+			result.as_ref().ok().map(usage::json_size),
+		);
+		result
+	}
+
+	/// Wraps a single invocation into a script, which evaluates to a promise of its result.
+	fn call_wrapper(invocation: &str) -> String {
+		format!(
+			"(async () => {{
+				return {invocation};
+			}})()"
+		)
+	}
+
+	/// JS expression calling `fn_name`, awaiting the result if the function is async.
+	pub(crate) fn invocation_expr(fn_name: &str, json_args: &str) -> Result<String, JsError> {
+		check_fn_path(fn_name)?;
+
+		Ok(format!(
+			"({fn_name}.constructor.name === 'AsyncFunction'
+				? await {fn_name}({json_args})
+				: {fn_name}({json_args}))"
+		))
+	}
+
+	/// Executes a wrapper script, which evaluates to a promise of its result.
+	///
+	/// Not counted in the usage report; calls made on behalf of the host are recorded with `record_call()`.
+	pub(crate) fn execute_returning(&self, js_code: String) -> Result<JsValue, JsError> {
+		let run = |runtime: &mut JsRuntime| {
+			let completion =
+				runtime.execute_script(Self::DEFAULT_FILENAME, FastString::from(js_code))?;
+			invoke::wrapper_completion(runtime, completion)
+		};
+
+		self.execute_with(run, &CallOptions::default())
+	}
+
+	/// Like `execute_returning()`, for wrappers around user code that starts after `line_offset` lines of the wrapper.
+	///
+	/// Line numbers in errors then refer to the user's code rather than the wrapper.
+	fn execute_embedding(&self, js_code: &str, line_offset: i32) -> Result<JsValue, JsError> {
+		let run = |runtime: &mut JsRuntime| {
+			let completion =
+				invoke::execute_at_line(runtime, Self::DEFAULT_FILENAME, js_code, -line_offset)?;
+			invoke::wrapper_completion(runtime, completion)
+		};
+
+		self.execute_with(run, &CallOptions::default())
+	}
+
+	/// Like `execute_returning()`, with per-call settings and a custom way of starting the call.
+	///
+	/// `run` starts the call and returns how its result is obtained.
+	fn execute_with<F>(&self, run: F, options: &CallOptions) -> Result<JsValue, JsError>
+	where
+		F: FnOnce(&mut JsRuntime) -> Result<Completion, AnyError>,
+	{
+		let mut runtime = self.runtime()?;
+		let result = self.execute_returning_impl(&mut runtime, run, options);
+
+		let mut heap_stats = v8::HeapStatistics::default();
+		runtime.v8_isolate().get_heap_statistics(&mut heap_stats);
+
+		// V8 only enforces the limit on its own heap, and array buffers are capped on their own when allocated. Their sum, as well
+		// as memory reported with adjust_external_memory(), is checked here, once the call is done
+		let result = result.and_then(|value| match self.max_heap_size {
+			Some(limit) if memory_in_use(&heap_stats) > limit => Err(JsError::MemoryLimit {
+				used: memory_in_use(&heap_stats),
+				limit,
+			}),
+			_ => Ok(value),
+		});
+
+		result
+	}
+
+	/// Adds a call made by the host to the usage report, sampling the heap now that it is done.
+	///
+	/// Evaluations on behalf of the library (e.g. in `has_function()` or when loading namespaces) are not recorded. `bytes_out` is
+	/// the size of the call's results, or `None` if it failed.
+	pub(crate) fn record_call(&self, wall_time: Duration, bytes_in: usize, bytes_out: Option<u64>) {
+		let mut heap_stats = v8::HeapStatistics::default();
+		if let Ok(mut runtime) = self.runtime() {
+			runtime.v8_isolate().get_heap_statistics(&mut heap_stats);
+		}
+
+		self.usage.borrow_mut().merge(&UsageReport {
+			calls: 1,
+			failed_calls: u64::from(bytes_out.is_none()),
+			wall_time,
+			bytes_in: bytes_in as u64,
+			bytes_out: bytes_out.unwrap_or_default(),
+			peak_heap_after_call: heap_stats.used_heap_size(),
+			peak_external_memory_after_call: heap_stats.external_memory(),
+		});
+	}
+
+	fn execute_returning_impl<F>(
+		&self,
+		runtime: &mut JsRuntime,
+		run: F,
+		options: &CallOptions,
+	) -> Result<JsValue, JsError>
+	where
+		F: FnOnce(&mut JsRuntime) -> Result<Completion, AnyError>,
+	{
+		let start = Instant::now();
+		let completion = self.run_guarded(runtime, options, |runtime| {
+			let completion = run(runtime)?;
+
+			// Fast path for synchronous functions, unless they left work behind (e.g. promises or async ops)
+			let is_sync = matches!(completion, Completion::Value(_));
+			if is_sync && !self.clock_timers && task_limits::poll_once(runtime)? {
+				return Ok(completion);
+			}
+
+			self.settle(runtime, None)?;
+			Ok(completion)
+		})?;
+
+		let json_value = self.completion_result(runtime, completion, start)?;
+		if let Some(max_size) = options.max_result_size {
+			limits::check_result_size(&json_value, max_size)?;
+		}
+
+		Ok(json_value)
+	}
+
+	/// Runs `body` under the script's limits: timeout, time-slice budget, preemption and the per-call settings in `options`.
+	///
+	/// Errors of an aborted execution are converted to the variant matching the reason of termination.
+	fn run_guarded<T, F>(
+		&self,
+		runtime: &mut JsRuntime,
+		options: &CallOptions,
+		body: F,
+	) -> Result<T, JsError>
+	where
+		F: FnOnce(&mut JsRuntime) -> Result<T, AnyError>,
+	{
+		// Termination persists until cancelled. Also covers a timeout that fired just after the previous call had returned.
+		runtime.v8_isolate().cancel_terminate_execution();
+		self.terminated.reset();
+		let start = Instant::now();
+
+		// Timers are cancelled when the guards go out of scope at the end of this call
+		let _timeout_guard = options.timeout.or(self.timeout).map(|timeout| {
+			let handle = runtime.v8_isolate().thread_safe_handle();
+			let terminated = self.terminated.clone();
+
+			watchdog::schedule(timeout, move || {
+				terminated.set(Termination::Timeout);
+				handle.terminate_execution();
+				None
+			})
+		});
+
+		let _budget_guard = self.budget.map(|budget| {
+			let handle = runtime.v8_isolate().thread_safe_handle();
+			budget.start(handle, self.terminated.clone())
+		});
+
+		let _preemption_guard = self.preemption.as_ref().map(|preemptor| {
+			let handle = runtime.v8_isolate().thread_safe_handle();
+			preemptor.start(handle, self.terminated.clone())
+		});
+
+		// syncing ops is required cause they sometimes change while preparing the engine
+		// self.runtime.sync_ops_cache();
+
+		call_options::begin(&runtime.op_state(), options);
+		task_limits::begin(&runtime.op_state());
+		let result = body(runtime);
+		call_options::end(&runtime.op_state());
+
+		result.map_err(|e| self.termination_error(runtime, e, start))
+	}
+
+	/// Runs the event loop within the settle timeout, see `run_event_loop()`.
+	fn settle(
+		&self,
+		runtime: &mut JsRuntime,
+		until: Option<&v8::Global<v8::Promise>>,
+	) -> Result<(), AnyError> {
+		let settle_timer = self.settle_timeout.map(|timeout| {
+			let handle = runtime.v8_isolate().thread_safe_handle();
+			SettleTimer::start(timeout, handle, self.terminated.clone())
+		});
+
+		self.run_event_loop(runtime, settle_timer.as_ref(), until)
+	}
+
+	/// Runs the event loop until all work is done, including timers of a custom clock which have become due.
+	///
+	/// If `until` is given, returns early once that promise has settled.
+	fn run_event_loop(
+		&self,
+		runtime: &mut JsRuntime,
+		settle_timer: Option<&SettleTimer>,
+		until: Option<&v8::Global<v8::Promise>>,
+	) -> Result<(), AnyError> {
+		let mut turns = 0;
+		loop {
+			task_limits::run_event_loop(
+				runtime,
+				&mut turns,
+				self.max_event_loop_turns,
+				settle_timer,
+				until,
+				&self.terminated,
+			)?;
+
+			let settled = until.is_some_and(|promise| invoke::is_settled(runtime, promise));
+			if settled || !self.clock_timers || !clock::run_next_timer(runtime)? {
+				return Ok(());
+			}
+		}
+	}
+
+	/// Extracts the result of a call that has run, failing if its promise is still pending.
+	fn completion_result(
+		&self,
+		runtime: &mut JsRuntime,
+		completion: Completion,
+		start: Instant,
+	) -> Result<JsValue, JsError> {
+		match completion {
+			Completion::Value(json_value) => Ok(json_value),
+			Completion::Promise(promise) => {
+				match invoke::settled_result(runtime, &promise, self.result_limits) {
+					Some(result) => Ok(result?),
+					None => Err(JsError::PendingPromise {
+						elapsed: start.elapsed(),
+					}),
+				}
+			}
+		}
+	}
+
+	/// Converts an error of an aborted execution to the variant matching the reason of termination, if any.
+	fn termination_error(
+		&self,
+		runtime: &mut JsRuntime,
+		error: AnyError,
+		start: Instant,
+	) -> JsError {
+		let error = match self.terminated.reason() {
+			Some(Termination::Timeout) => JsError::Timeout {
+				elapsed: start.elapsed(),
+			},
+			Some(Termination::Preempted) => JsError::Preempted {
+				elapsed: start.elapsed(),
+			},
+			Some(Termination::Budget) => JsError::BudgetExhausted {
+				slices: self.budget.expect("budget is set").slices,
+			},
+			Some(Termination::HeapLimit) => {
+				// The limit was raised to let V8 unwind; it is restored once the call's garbage is collected
+				self.heap_exhausted.set(true);
+
+				let mut heap_stats = v8::HeapStatistics::default();
+				runtime.v8_isolate().get_heap_statistics(&mut heap_stats);
+				runtime.v8_isolate().low_memory_notification();
+
+				JsError::MemoryLimit {
+					used: memory_in_use(&heap_stats),
+					limit: self.max_heap_size.expect("heap limit is set"),
+				}
+			}
+			Some(Termination::SettleTimeout) => JsError::PendingPromise {
+				elapsed: start.elapsed(),
+			},
+			Some(Termination::MicrotaskLimit) => JsError::MicrotaskLimit {
+				limit: self.max_microtasks.expect("microtask limit is set"),
+			},
+			Some(Termination::EventLoopLimit) => JsError::EventLoopLimit {
+				limit: self
+					.max_event_loop_turns
+					.expect("event loop turn limit is set"),
+			},
+			_ => match self.recursion_budget {
+				Some(budget) => {
+					limits::recursion_error(error, budget).unwrap_or_else(JsError::from)
+				}
+				None => JsError::from(error),
+			},
+		};
+
+		self.attributed(error)
+	}
+
+	/// Wraps an error with the script's provenance, if any.
+	fn attributed(&self, error: JsError) -> JsError {
+		match &self.provenance {
+			Some(provenance) => JsError::WithProvenance {
+				provenance: provenance.clone(),
+				error: Box::new(error),
+			},
+			None => error,
+		}
+	}
+
+	/// Provenance attached with [`ScriptBuilder::with_provenance()`], if any.
+	pub fn provenance(&self) -> Option<&Provenance> {
+		self.provenance.as_ref()
+	}
+
+	/// Reports memory that the host holds on behalf of this script, e.g. buffers backing objects handed to JavaScript.
+	///
+	/// `change_in_bytes` is positive when memory is acquired and negative when it is released again. V8 takes this memory into
+	/// account when scheduling garbage collection, and it counts towards the limit set with
+	/// [`ScriptBuilder::with_max_heap_size()`]. Array buffers whose backing store is provided by the host (e.g. returned from an op)
+	/// are accounted automatically.
+	///
+	/// Returns the total amount of external memory reported for this script.
+	pub fn adjust_external_memory(&mut self, change_in_bytes: i64) -> i64 {
+		let mut runtime = Entered::new(self.runtime.get_mut());
+		runtime
+			.v8_isolate()
+			.adjust_amount_of_external_allocated_memory(change_in_bytes)
+	}
+
+	/// Asks V8 to reclaim as much memory as possible, instead of waiting for its heuristics.
+	///
+	/// Useful between calls, e.g. after a large batch was processed. This runs a full garbage collection (with a low-memory
+	/// notification to V8), which blocks the current thread and can take a while for large heaps.
+	pub fn request_gc(&mut self) {
+		let mut runtime = Entered::new(self.runtime.get_mut());
+		runtime.v8_isolate().low_memory_notification();
+	}
+
+	/// Gives direct access to the underlying `deno_core` runtime, for functionality that js-sandbox does not cover.
+	///
+	/// **This is an escape hatch for advanced use.** The runtime is shared with js-sandbox's own machinery, and the sandbox's
+	/// guarantees only hold as long as you do not undermine them: code executed through the runtime is not subject to timeouts,
+	/// budgets or usage tracking, and changes to globals, ops or the event loop may break later calls. Prefer the methods of
+	/// [`Script`] wherever they suffice. The runtime's API follows the `deno_core` version used by this crate and may change with
+	/// any release of js-sandbox.
+	///
+	/// The isolate is entered on the current thread while the returned guard lives.
+	///
+	/// ```rust
+	/// use js_sandbox::{JsError, Script};
+	///
+	/// fn main() -> Result<(), JsError> {
+	/// 	let mut script = Script::from_string("function answer() { return globalThis.answer; }")?;
+	///
+	/// 	let code = deno_core::FastString::from_static("globalThis.answer = 42;");
+	/// 	script.runtime_mut().execute_script("setup.js", code)?;
+	///
+	/// 	let answer: u32 = script.call("answer", ())?;
+	/// 	assert_eq!(answer, 42);
+	/// 	Ok(())
+	/// }
+	/// ```
+	pub fn runtime_mut(&mut self) -> impl DerefMut<Target = JsRuntime> + '_ {
+		Entered::new(self.runtime.get_mut())
+	}
+
+	/// Runs `f` with a V8 handle scope in the script's context, for conversions that go beyond JSON.
+	///
+	/// This allows working with raw V8 values or `serde_v8`, e.g. to inspect functions or symbols. Handles created in the scope
+	/// cannot escape it; convert what you need into Rust values (or `v8::Global` handles, which are only valid for this script).
+	/// Like with [`Self::runtime_mut()`], code run inside `f` is not subject to the script's limits.
+	///
+	/// ```rust
+	/// use deno_core::v8;
+	/// use js_sandbox::Script;
+	///
+	/// let mut script = Script::from_string("function greet(name) { return `Hello ${name}`; }").unwrap();
+	///
+	/// let arity = script.with_scope(|scope| {
+	/// 	let global = scope.get_current_context().global(scope);
+	/// 	let key = v8::String::new(scope, "greet").unwrap();
+	/// 	let value = global.get(scope, key.into()).unwrap();
+	/// 	let function = v8::Local::<v8::Function>::try_from(value).unwrap();
+	///
+	/// 	let key = v8::String::new(scope, "length").unwrap();
+	/// 	function.get(scope, key.into()).unwrap().uint32_value(scope)
+	/// });
+	/// assert_eq!(arity, Some(1));
+	/// ```
+	pub fn with_scope<F, R>(&mut self, f: F) -> R
+	where
+		F: FnOnce(&mut v8::HandleScope) -> R,
+	{
+		let mut runtime = Entered::new(self.runtime.get_mut());
+		let scope = &mut runtime.handle_scope();
+		f(scope)
+	}
+
+	/// Returns the resources used by this script so far.
+	///
+	/// See [`UsageReport`] for the tracked quantities.
+	pub fn usage(&self) -> UsageReport {
+		self.usage.borrow().clone()
+	}
+
+	/// Resets all usage counters to zero, e.g. at the start of a new billing period.
+	pub fn reset_usage(&mut self) {
+		*self.usage.get_mut() = UsageReport::default();
+	}
+
+	/// Borrows the runtime, with its isolate entered on the current thread.
+	fn runtime(&self) -> Result<Entered<RefMut<'_, Runtime>>, JsError> {
+		self.runtime
+			.try_borrow_mut()
+			.map(Entered::new)
+			.map_err(|_| already_executing())
+	}
+
+	fn hooks(&self) -> Result<RefMut<'_, CallHooks>, JsError> {
+		self.hooks.try_borrow_mut().map_err(|_| already_executing())
+	}
+
+	/// Whether the script is still in a reliable state.
+	///
+	/// Returns `false` once the script has hit a condition it cannot recover from: the heap limit set with
+	/// [`ScriptBuilder::with_max_heap_size()`] was reached, or a host callback panicked, possibly leaving host state inconsistent.
+	/// Such a script can still be called, but pools should discard it and create a new one. Timeouts and other limits do not
+	/// affect health, as the script recovers from them with the next call.
+	pub fn is_healthy(&self) -> bool {
+		self.fatal_condition().is_none()
+	}
+
+	/// The condition which made the script unhealthy, if any; see [`Self::is_healthy()`].
+	pub fn fatal_condition(&self) -> Option<FatalCondition> {
+		if self.heap_exhausted.get() {
+			Some(FatalCondition::HeapLimit)
+		} else if self.host_panicked.is_set() {
+			Some(FatalCondition::HostPanic)
+		} else {
+			None
+		}
+	}
+
+	/// Whether calls started with `start_call()` have not been collected yet.
+	pub(crate) fn has_calls_in_flight(&self) -> bool {
+		!self.in_flight.is_empty()
+	}
+
+	/// Whether the last call was forcibly stopped, due to a timeout, time-slice budget or heap limit.
+	pub(crate) fn was_terminated(&self) -> bool {
+		self.terminated.reason().is_some()
+	}
+
+	pub(crate) fn create_from_string(
+		js_code: &str,
+		builder: ScriptBuilder,
+	) -> Result<Self, JsError> {
+		limits::check_source_size(js_code.len() as u64, builder.max_source_size)?;
+		limits::check_nesting_depth(js_code, builder.max_nesting_depth)?;
+
+		Self::create_script(js_code.to_string(), builder)
+	}
+
+	pub(crate) fn create_from_file(
+		file: &Path,
+		expected_sha256: Option<&str>,
+		mut builder: ScriptBuilder,
+	) -> Result<Self, JsError> {
+		let js_code = limits::read_source(file, builder.max_source_size)?;
+		if let Some(expected) = expected_sha256 {
+			integrity::check_sha256(&js_code, expected)?;
+		}
+		if let Some(verifier) = &builder.module_verifier {
+			verifier(&file.display().to_string(), &js_code)?;
+		}
+
+		let js_code = String::from_utf8(js_code).map_err(AnyError::from)?;
+		limits::check_nesting_depth(&js_code, builder.max_nesting_depth)?;
+
+		// Stack traces show the file's name, unless a custom one is set
+		if builder.filename.is_none() {
+			if builder.module && builder.imports == ImportPolicy::FileSystem {
+				let path = file.canonicalize().map_err(AnyError::from)?;
+				builder.module_url =
+					Some(ModuleSpecifier::from_file_path(&path).map_err(|()| {
+						AnyError::msg(format!("invalid module path `{}`", path.display()))
+					})?);
+			}
+			builder.filename = file
+				.file_name()
+				.map(|name| name.to_string_lossy().into_owned());
+		}
+		Self::create_script(js_code, builder)
+	}
+
+	fn create_script<S>(js_code: S, builder: ScriptBuilder) -> Result<Self, JsError>
+	where
+		S: Into<FastString>,
+	{
+		// Runtimes must be created with the same extensions and ops as the snapshot they start from
+		if builder.snapshot.is_some()
+			&& (builder.web_apis || !builder.ops.is_empty() || !builder.extensions.is_empty())
+		{
+			return Err(JsError::Runtime(AnyError::msg(
+				"snapshots cannot be combined with custom ops, extensions or web APIs",
+			)));
+		}
+
+		let mut extensions = vec![sandbox_extension(builder.ops)];
+
+		#[cfg(feature = "web")]
+		if builder.web_apis {
+			extensions.extend(crate::web::extensions());
+		}
+
+		extensions.extend(builder.extensions);
+
+		let create_params = builder.max_heap_size.map(|max_heap_size| {
+			v8::CreateParams::default()
+				.heap_limits(0, max_heap_size)
+				.array_buffer_allocator(limits::BoundedAllocator::new_shared(max_heap_size))
+		});
+
+		let mut stack_size = builder
+			.max_stack_size
+			.unwrap_or(ScriptBuilder::DEFAULT_MAX_STACK_SIZE);
+		if let Some(budget) = builder.recursion_budget {
+			stack_size = stack_size.min(limits::recursion_stack_size(budget));
+		}
+
+		let audit = AuditLog::default();
+		let mut runtime = Runtime::new(
+			deno_core::RuntimeOptions {
+				module_loader: Some(Rc::new(SandboxLoader::new(
+					builder.imports,
+					builder.import_types,
+					builder.module_verifier,
+					audit.clone(),
+				))),
+				extensions,
+				startup_snapshot: builder.snapshot.map(Snapshot::Static),
+				create_params,
+				..Default::default()
+			},
+			stack_size,
+		);
+
+		let mut entered = Entered::new(&mut runtime);
+
+		let terminated = TerminationFlag::default();
+		if builder.max_heap_size.is_some() {
+			// Without this callback, V8 aborts the whole process when running out of heap
+			let handle = entered.v8_isolate().thread_safe_handle();
+			let heap_terminated = terminated.clone();
+
+			entered.add_near_heap_limit_callback(move |current_limit, initial_limit| {
+				heap_terminated.set(Termination::HeapLimit);
+				handle.terminate_execution();
+
+				// Give V8 some room to unwind the stack, once: if the limit has been raised already, keep it
+				if current_limit > initial_limit {
+					current_limit
+				} else {
+					initial_limit + initial_limit / 2
+				}
+			});
+
+			// Go back to the configured limit once the aborted call's garbage is collected
+			entered
+				.v8_isolate()
+				.automatically_restore_initial_heap_limit(Self::HEAP_RESTORE_THRESHOLD);
+		}
+
+		let host_panicked = PanicFlag::default();
+		let op_state = entered.op_state();
+		op_state.borrow_mut().put(ConsoleOutput {
+			prefix: builder
+				.provenance
+				.as_ref()
+				.map(|provenance| format!("[{provenance}] ")),
+			..ConsoleOutput::default()
+		});
+		op_state.borrow_mut().put(host_panicked.clone());
+		op_state.borrow_mut().put(audit.clone());
+
+		// With web APIs, Deno's full console is provided; otherwise install the built-in one
+		if !builder.web_apis {
+			let console_code = console::install_code(
+				"(line, isError) => Deno.core.ops.op_console_print(line + '\\n', isError)",
+			);
+			entered.execute_script(Self::DEFAULT_FILENAME, console_code.into())?;
+		}
+
+		let clock_timers = builder.clock.is_some();
+		let clock = builder
+			.clock
+			.unwrap_or_else(|| Box::new(SystemClock::new()));
+		entered.op_state().borrow_mut().put(ScriptClock(clock));
+		entered.execute_script(
+			Self::DEFAULT_FILENAME,
+			FastString::from_static(clock::INSTALL_JS),
+		)?;
+		entered.execute_script(
+			Self::DEFAULT_FILENAME,
+			FastString::from_static(call_options::INSTALL_JS),
+		)?;
+		if clock_timers {
+			clock::install(&mut entered)?;
+		}
+
+		if builder.deny_code_generation {
+			let scope = &mut entered.handle_scope();
+			scope
+				.get_current_context()
+				.set_allow_generation_from_strings(false);
+		}
+		if builder.deny_wasm {
+			entered.execute_script(
+				Self::DEFAULT_FILENAME,
+				FastString::from_static(policy::REMOVE_WASM_JS),
+			)?;
+		}
+		if builder.deny_timers {
+			entered.execute_script(
+				Self::DEFAULT_FILENAME,
+				FastString::from_static(policy::REMOVE_TIMERS_JS),
+			)?;
+		}
+
+		if let Some(max_microtasks) = builder.max_microtasks {
+			let handle = entered.v8_isolate().thread_safe_handle();
+			let counter = MicrotaskCounter::new(max_microtasks, handle, terminated.clone());
+
+			entered.op_state().borrow_mut().put(counter);
+			entered.execute_script(
+				Self::DEFAULT_FILENAME,
+				FastString::from_static(task_limits::INSTALL_JS),
+			)?;
+		}
+
+		if let Some(context) = builder.context {
+			let context_code = context::install_code(&context?);
+			entered.execute_script(Self::DEFAULT_FILENAME, context_code.into())?;
+		}
+
+		if let Some(env) = builder.env {
+			entered.op_state().borrow_mut().put(env);
+			entered.execute_script(
+				Self::DEFAULT_FILENAME,
+				FastString::from_static(env::INSTALL_JS),
+			)?;
+		}
+
+		if let Some(storage) = builder.storage {
+			entered.op_state().borrow_mut().put(storage);
+			entered.execute_script(
+				Self::DEFAULT_FILENAME,
+				FastString::from_static(storage::INSTALL_JS),
+			)?;
+		}
+
+		if let Some(output_sink) = builder.output_sink {
+			entered.op_state().borrow_mut().put(OutputSink(output_sink));
+			entered.execute_script(
+				Self::DEFAULT_FILENAME,
+				FastString::from_static(sink::INSTALL_JS),
+			)?;
+		}
+
+		host_object::install(
+			&mut entered,
+			builder.host_objects,
+			builder.async_host_fns,
+			builder.capabilities,
+		)?;
+		drop(entered);
+
+		let mut script = Script {
+			runtime: RefCell::new(runtime),
+			timeout: builder.timeout,
+			settle_timeout: None,
+			budget: None,
+			preemption: None,
+			max_heap_size: builder.max_heap_size,
+			max_microtasks: builder.max_microtasks,
+			max_event_loop_turns: builder.max_event_loop_turns,
+			recursion_budget: builder.recursion_budget,
+			result_limits: ResultLimits {
+				max_string_length: builder.max_string_length,
+				max_array_length: builder.max_array_length,
+			},
+			clock_timers,
+			module: builder.module,
+			namespaces: BTreeSet::new(),
+			terminated,
+			usage: RefCell::default(),
+			hooks: RefCell::default(),
+			lexical_lookups: RefCell::default(),
+			in_flight: HashMap::new(),
+			next_call_id: 0,
+			error_classes: BTreeSet::new(),
+			heap_exhausted: Cell::new(false),
+			host_panicked,
+			audit,
+			provenance: builder.provenance,
+		};
+
+		let filename = builder
+			.filename
+			.as_deref()
+			.map_or(Self::DEFAULT_FILENAME, static_filename);
+
+		let start = Instant::now();
+		let mut runtime = Entered::new(script.runtime.get_mut());
+		let result = if script.module {
+			let specifier = match builder.module_url {
+				Some(url) => Ok(url),
+				None => {
+					deno_core::resolve_url(&format!("file:///{filename}")).map_err(AnyError::from)
+				}
+			};
+			specifier.and_then(|specifier| module::load(&mut runtime, &specifier, js_code.into()))
+		} else {
+			runtime.execute_script(filename, js_code.into()).map(drop)
+		};
+		drop(runtime);
+
+		if let Err(e) = result {
+			let mut runtime = Entered::new(script.runtime.borrow_mut());
+			return Err(script.termination_error(&mut runtime, e, start));
+		}
+
+		// Namespaces can be part of the snapshot
+		if builder.snapshot.is_some() {
+			let names = script.evaluate(&namespace::names_expr())?;
+			script.namespaces = serde_json::from_value(names)?;
+		}
+
+		// After the script's code, which may need to install polyfills
+		if builder.freeze_intrinsics {
+			let mut runtime = Entered::new(script.runtime.get_mut());
+			runtime.execute_script(
+				Self::DEFAULT_FILENAME,
+				FastString::from_static(policy::FREEZE_INTRINSICS_JS),
+			)?;
+		}
+
+		Ok(script)
+	}
+}
+
+/// Returns `name` with a static lifetime, as required by `JsRuntime::execute_script()`.
+///
+/// Each distinct name is leaked once, so that recreating scripts from the same files does not leak memory over time.
+fn static_filename(name: &str) -> &'static str {
+	static FILENAMES: Mutex<BTreeSet<&'static str>> = Mutex::new(BTreeSet::new());
+
+	let mut filenames = FILENAMES.lock().unwrap_or_else(PoisonError::into_inner);
+	match filenames.get(name) {
+		Some(filename) => filename,
+		None => {
+			let filename: &'static str = Box::leak(name.into());
+			filenames.insert(filename);
+			filename
+		}
+	}
+}
+
+/// Extension with the ops used by js-sandbox itself, followed by `extra_ops`.
+pub(crate) fn sandbox_extension(extra_ops: Vec<OpDecl>) -> Extension {
+	let mut ops = vec![
+		console::op_console_print::DECL,
+		call_options::op_seeded_random::DECL,
+		call_options::op_call_context::DECL,
+		clock::op_performance_now::DECL,
+		clock::op_clock_now::DECL,
+		host_object::op_host_call::DECL,
+		host_object::op_host_call_async::DECL,
+		env::op_env_get::DECL,
+		env::op_env_set::DECL,
+		env::op_env_delete::DECL,
+		env::op_env_keys::DECL,
+		storage::op_storage_get::DECL,
+		storage::op_storage_set::DECL,
+		storage::op_storage_remove::DECL,
+		sink::op_sink_write::DECL,
+		sink::op_sink_write_str::DECL,
+		task_limits::op_count_microtask::DECL,
+		audit::op_audit_capability::DECL,
+	];
+	ops.extend(extra_ops);
+
+	Extension {
+		name: "js_sandbox",
+		ops: Cow::Owned(ops),
+		..Default::default()
+	}
+}
+
+fn already_executing() -> JsError {
+	JsError::Runtime(AnyError::msg("script is already executing a call"))
+}
+
+fn namespace_error(method: &str, namespace: &str, reason: &str) -> JsError {
+	JsError::Runtime(AnyError::msg(format!(
+		"{method}(\"{namespace}\"): {reason}"
+	)))
 }
 
-#[op]
-fn op_return(
-	state: &mut OpState,
-	args: JsValue,
-	_buf: Option<JsBuffer>,
-) -> Result<JsValue, deno_core::error::AnyError> {
-	let entry = ResultResource { json_value: args };
-	let resource_table = &mut state.resource_table;
-	let _rid = resource_table.add(entry);
-	Ok(serde_json::Value::Null)
+/// Memory that counts towards the heap limit: the used JS heap plus external memory.
+fn memory_in_use(heap_stats: &v8::HeapStatistics) -> usize {
+	heap_stats.used_heap_size() + heap_stats.external_memory()
 }
@@ -0,0 +1,46 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::io::Write;
+
+use deno_core::{op, JsBuffer, OpState};
+
+use crate::{panic_guard, AnyError};
+
+/// Defines `host.write(chunk)`, accepting strings (written as UTF-8) and byte arrays.
+pub(crate) const INSTALL_JS: &str = r#"
+globalThis.host = globalThis.host ?? {};
+host.write = (chunk) => {
+	if (typeof chunk === "string") {
+		Deno.core.ops.op_sink_write_str(chunk);
+	} else if (ArrayBuffer.isView(chunk)) {
+		Deno.core.ops.op_sink_write(new Uint8Array(chunk.buffer, chunk.byteOffset, chunk.byteLength));
+	} else {
+		throw new TypeError("host.write() expects a string or a typed array");
+	}
+};
+"#;
+
+/// Destination of `host.write()`, stored in the op state.
+pub(crate) struct OutputSink(pub Box<dyn Write>);
+
+// The ops are registered for every script, but only scripts built with an output sink have the state
+
+fn output_sink(state: &mut OpState) -> Result<&mut OutputSink, AnyError> {
+	state
+		.try_borrow_mut::<OutputSink>()
+		.ok_or_else(|| AnyError::msg("no output sink"))
+}
+
+#[op]
+pub(crate) fn op_sink_write(state: &mut OpState, chunk: JsBuffer) -> Result<(), AnyError> {
+	panic_guard::catch(state, |state| {
+		Ok(output_sink(state)?.0.write_all(&chunk)?)
+	})
+}
+
+#[op]
+pub(crate) fn op_sink_write_str(state: &mut OpState, chunk: String) -> Result<(), AnyError> {
+	panic_guard::catch(state, |state| {
+		Ok(output_sink(state)?.0.write_all(chunk.as_bytes())?)
+	})
+}
@@ -0,0 +1,122 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+//! Startup snapshots, which let scripts start with code that has already been parsed and evaluated.
+//!
+//! A snapshot is a serialized V8 heap. Creating one is slow, so it is typically done once in a build script, and the resulting blob
+//! is embedded in the binary:
+//!
+//! ```rust,no_run
+//! // build.rs
+//! fn main() {
+//! 	let out_dir = std::env::var("OUT_DIR").unwrap();
+//!
+//! 	js_sandbox::snapshot::Builder::new()
+//! 		.with_prelude("function clamp(x, lo, hi) { return Math.min(Math.max(x, lo), hi); }")
+//! 		.with_plugin("physics", "function speed(vx, vy) { return Math.hypot(vx, vy); }")
+//! 		.write_to(format!("{out_dir}/scripts.snap"))
+//! 		.expect("snapshot can be created");
+//! }
+//! ```
+//!
+//! At runtime, scripts are then created from the snapshot with [`ScriptBuilder::with_snapshot()`](crate::ScriptBuilder::with_snapshot):
+//!
+//! ```rust,ignore
+//! static SNAPSHOT: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/scripts.snap"));
+//!
+//! let mut script = Script::builder().with_snapshot(SNAPSHOT).build_from_string("")?;
+//! let speed: f64 = script.call_namespace("physics", "speed", (3.0, 4.0))?;
+//! ```
+//!
+//! Snapshots must be created with the same version of js-sandbox (and V8) as the one loading them.
+
+use std::path::Path;
+
+use deno_core::{JsRuntimeForSnapshot, RuntimeOptions, RuntimeSnapshotOptions};
+
+use crate::{invoke, lexer, namespace, platform, script, AnyError, JsError, Script};
+
+/// Creates a startup snapshot from JS sources.
+///
+/// Sources are evaluated in the order they were added. The snapshot is taken before js-sandbox installs its globals (`console`,
+/// `host`, `context`, ...), so these can be used inside functions, but not by code running at the top level of a source.
+#[derive(Default)]
+pub struct Builder {
+	sources: Vec<Source>,
+}
+
+enum Source {
+	Prelude(String),
+	Plugin { namespace: String, js_code: String },
+}
+
+impl Builder {
+	/// Creates a builder without any sources.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Adds code that is evaluated in the global scope, like the code passed to [`Script::from_string()`].
+	pub fn with_prelude(mut self, js_code: impl Into<String>) -> Self {
+		self.sources.push(Source::Prelude(js_code.into()));
+		self
+	}
+
+	/// Adds code whose top-level functions are registered under `namespace`, like with [`Script::add_script()`].
+	pub fn with_plugin(mut self, namespace: &str, js_code: impl Into<String>) -> Self {
+		self.sources.push(Source::Plugin {
+			namespace: namespace.to_string(),
+			js_code: js_code.into(),
+		});
+		self
+	}
+
+	/// Evaluates all sources and returns the snapshot.
+	///
+	/// Fails if a source throws or a plugin's namespace is not a valid identifier or used twice.
+	pub fn build(self) -> Result<Box<[u8]>, JsError> {
+		platform::init_platform();
+
+		// Must match the extensions of the runtimes loading the snapshot
+		let mut runtime = JsRuntimeForSnapshot::new(
+			RuntimeOptions {
+				extensions: vec![script::sandbox_extension(Vec::new())],
+				..Default::default()
+			},
+			RuntimeSnapshotOptions::default(),
+		);
+
+		let mut namespaces = Vec::new();
+		for source in self.sources {
+			let (js_code, line_offset) = match source {
+				Source::Prelude(js_code) => (js_code, 0),
+				Source::Plugin { namespace, js_code } => {
+					if !lexer::is_identifier(&namespace) || namespaces.contains(&namespace) {
+						return Err(JsError::Runtime(AnyError::msg(format!(
+							"snapshot plugin \"{namespace}\": invalid or duplicate namespace"
+						))));
+					}
+
+					let register_code = namespace::register_code(&namespace, &js_code);
+					namespaces.push(namespace);
+					register_code
+				}
+			};
+
+			invoke::execute_at_line(
+				&mut runtime,
+				Script::DEFAULT_FILENAME,
+				&js_code,
+				-line_offset,
+			)?;
+		}
+
+		Ok(runtime.snapshot().to_vec().into_boxed_slice())
+	}
+
+	/// Evaluates all sources and writes the snapshot to a file, for inclusion with `include_bytes!`.
+	pub fn write_to(self, path: impl AsRef<Path>) -> Result<(), JsError> {
+		let snapshot = self.build()?;
+		std::fs::write(path, snapshot).map_err(AnyError::from)?;
+		Ok(())
+	}
+}
@@ -0,0 +1,68 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use crate::host_object::{arg_from_json, unknown_method};
+use crate::{AnyError, HostObject, JsValue};
+
+/// Database which scripts can query with `host.db.query(sql, params)`.
+///
+/// Implementations forward queries to an actual database (e.g. SQLite or PostgreSQL), and decide which statements they accept,
+/// e.g. only reads or only certain tables. Parameters are passed separately from the SQL text, so that scripts do not need to
+/// build queries by string concatenation. Registered with [`ScriptBuilder::with_database()`](crate::ScriptBuilder::with_database);
+/// requires the `sql` feature.
+///
+/// ```rust
+/// use js_sandbox::{AnyError, JsValue, Script, SqlDatabase, JsError};
+///
+/// struct Numbers;
+///
+/// impl SqlDatabase for Numbers {
+/// 	fn query(&mut self, sql: &str, params: &[JsValue]) -> Result<Vec<JsValue>, AnyError> {
+/// 		assert_eq!(sql, "SELECT n FROM numbers WHERE n < ?");
+/// 		let limit = params[0].as_u64().unwrap_or_default();
+/// 		Ok((0..limit).map(|n| serde_json::json!({ "n": n })).collect())
+/// 	}
+/// }
+///
+/// fn main() -> Result<(), JsError> {
+/// 	let js_code = r#"function sum(limit) {
+/// 		const rows = host.db.query("SELECT n FROM numbers WHERE n < ?", [limit]);
+/// 		return rows.reduce((acc, row) => acc + row.n, 0);
+/// 	}"#;
+///
+/// 	let mut script = Script::builder()
+/// 		.with_database(Numbers)
+/// 		.build_from_string(js_code)?;
+///
+/// 	let sum: u64 = script.call("sum", (4,))?;
+/// 	assert_eq!(sum, 6);
+/// 	Ok(())
+/// }
+/// ```
+///
+/// Errors are thrown as exceptions in JS.
+pub trait SqlDatabase: 'static {
+	/// Runs the query `sql` with positional parameters `params`, returning the resulting rows (typically one JSON object per row).
+	fn query(&mut self, sql: &str, params: &[JsValue]) -> Result<Vec<JsValue>, AnyError>;
+}
+
+/// Exposes a database as host object `db`.
+pub(crate) struct DatabaseObject(pub Box<dyn SqlDatabase>);
+
+impl HostObject for DatabaseObject {
+	fn method_names(&self) -> &'static [&'static str] {
+		&["query"]
+	}
+
+	fn call_method(&mut self, method: &str, args: Vec<JsValue>) -> Result<JsValue, AnyError> {
+		if method != "query" {
+			return Err(unknown_method(method));
+		}
+
+		let mut args = args.into_iter();
+		let sql: String = arg_from_json(args.next())?;
+		let params: Option<Vec<JsValue>> = arg_from_json(args.next())?;
+
+		let rows = self.0.query(&sql, &params.unwrap_or_default())?;
+		Ok(JsValue::Array(rows))
+	}
+}
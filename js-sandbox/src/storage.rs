@@ -0,0 +1,129 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use deno_core::{op, OpState};
+
+use crate::{panic_guard, AnyError};
+
+/// Backend of the `storage` global, which lets scripts keep small persistent state across runs.
+///
+/// Scripts access the storage through a subset of the web's `localStorage` API: `storage.getItem(key)`, `storage.setItem(key, value)`
+/// and `storage.removeItem(key)`. Keys and values are strings; other values are converted to strings, like in browsers. Where the data
+/// lives (memory, files, a database, ...) is up to the implementation, which is registered with
+/// [`ScriptBuilder::with_storage()`](crate::ScriptBuilder::with_storage).
+///
+/// ```rust
+/// use std::collections::HashMap;
+/// use js_sandbox::{AnyError, Script, ScriptStorage, JsError};
+///
+/// #[derive(Default)]
+/// struct MemoryStorage(HashMap<String, String>);
+///
+/// impl ScriptStorage for MemoryStorage {
+/// 	fn get_item(&self, key: &str) -> Result<Option<String>, AnyError> {
+/// 		Ok(self.0.get(key).cloned())
+/// 	}
+///
+/// 	fn set_item(&mut self, key: &str, value: &str) -> Result<(), AnyError> {
+/// 		self.0.insert(key.to_string(), value.to_string());
+/// 		Ok(())
+/// 	}
+///
+/// 	fn remove_item(&mut self, key: &str) -> Result<(), AnyError> {
+/// 		self.0.remove(key);
+/// 		Ok(())
+/// 	}
+///
+/// 	fn size(&self) -> usize {
+/// 		self.0.iter().map(|(k, v)| k.len() + v.len()).sum()
+/// 	}
+/// }
+///
+/// fn main() -> Result<(), JsError> {
+/// 	let js_code = "function visit() { const n = Number(storage.getItem('visits') ?? 0) + 1; storage.setItem('visits', n); return n; }";
+/// 	let mut script = Script::builder()
+/// 		.with_storage(MemoryStorage::default(), 1024)
+/// 		.build_from_string(js_code)?;
+///
+/// 	script.call::<_, u32>("visit", ())?;
+/// 	let visits: u32 = script.call("visit", ())?;
+/// 	assert_eq!(visits, 2);
+/// 	Ok(())
+/// }
+/// ```
+///
+/// Errors returned by the methods are thrown as exceptions in JS.
+pub trait ScriptStorage: 'static {
+	/// Returns the value stored under `key`, or `None` if there is none.
+	fn get_item(&self, key: &str) -> Result<Option<String>, AnyError>;
+
+	/// Stores `value` under `key`, replacing any previous value.
+	fn set_item(&mut self, key: &str, value: &str) -> Result<(), AnyError>;
+
+	/// Removes the value stored under `key`, if any.
+	fn remove_item(&mut self, key: &str) -> Result<(), AnyError>;
+
+	/// Total size of all stored keys and values in bytes, which is checked against the quota.
+	fn size(&self) -> usize;
+}
+
+/// Storage of a script together with its quota, stored in the op state.
+pub(crate) struct QuotaStorage {
+	pub storage: Box<dyn ScriptStorage>,
+	pub max_size: usize,
+}
+
+/// Defines the `storage` global.
+pub(crate) const INSTALL_JS: &str = r#"
+Object.defineProperty(globalThis, "storage", {
+	value: Object.freeze({
+		getItem: (key) => Deno.core.ops.op_storage_get(String(key)),
+		setItem: (key, value) => void Deno.core.ops.op_storage_set(String(key), String(value)),
+		removeItem: (key) => void Deno.core.ops.op_storage_remove(String(key)),
+	}),
+	enumerable: true,
+});
+"#;
+
+// The ops are registered for every script, but only scripts built with storage have the state
+
+fn quota_storage(state: &mut OpState) -> Result<&mut QuotaStorage, AnyError> {
+	state
+		.try_borrow_mut::<QuotaStorage>()
+		.ok_or_else(|| AnyError::msg("script has no storage"))
+}
+
+#[op]
+pub(crate) fn op_storage_get(state: &mut OpState, key: String) -> Result<Option<String>, AnyError> {
+	panic_guard::catch(state, |state| quota_storage(state)?.storage.get_item(&key))
+}
+
+#[op]
+pub(crate) fn op_storage_set(
+	state: &mut OpState,
+	key: String,
+	value: String,
+) -> Result<(), AnyError> {
+	panic_guard::catch(state, |state| {
+		let QuotaStorage { storage, max_size } = quota_storage(state)?;
+
+		let replaced_size = storage
+			.get_item(&key)?
+			.map_or(0, |previous| key.len() + previous.len());
+		let new_size = storage.size().saturating_sub(replaced_size) + key.len() + value.len();
+
+		if new_size > *max_size {
+			return Err(AnyError::msg(format!(
+				"storage quota of {max_size} bytes exceeded"
+			)));
+		}
+
+		storage.set_item(&key, &value)
+	})
+}
+
+#[op]
+pub(crate) fn op_storage_remove(state: &mut OpState, key: String) -> Result<(), AnyError> {
+	panic_guard::catch(state, |state| {
+		quota_storage(state)?.storage.remove_item(&key)
+	})
+}
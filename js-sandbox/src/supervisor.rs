@@ -0,0 +1,120 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::ops::{Deref, DerefMut};
+
+use crate::{FatalCondition, JsError, Script, UsageReport};
+
+type ScriptFactory = Box<dyn Fn() -> Result<Script, JsError>>;
+type OnRecreate = Box<dyn FnMut(FatalCondition, Result<(), &JsError>)>;
+
+/// Script which recreates itself after a fatal error, so that long-running hosts heal themselves.
+///
+/// The script is created by a factory, which loads the source code and applies all settings. Once the script has become unhealthy
+/// (see [`Script::is_healthy()`]), the factory is invoked again to replace it, the next time the script is accessed mutably (e.g.
+/// for [`Script::call()`]). All methods of `Script` are available through `Deref`:
+///
+/// ```rust
+/// use js_sandbox::{Script, SupervisedScript, JsError};
+///
+/// fn main() -> Result<(), JsError> {
+/// 	let mut script = SupervisedScript::new(|| {
+/// 		Script::builder()
+/// 			.with_max_heap_size(32 * 1024 * 1024)
+/// 			.build_from_string("function hog() { const a = []; for (;;) a.push(new Array(1000).fill(0)); }
+/// 				function ping() { return 'pong'; }")
+/// 	})?;
+/// 	script.on_recreate(|condition, result| eprintln!("recreated after {condition:?}: {result:?}"));
+///
+/// 	assert!(script.call::<_, ()>("hog", ()).is_err());
+/// 	assert_eq!(script.call::<_, String>("ping", ())?, "pong");
+/// 	assert_eq!(script.recreations(), 1);
+/// 	Ok(())
+/// }
+/// ```
+///
+/// State held by the JS code (e.g. global variables or namespaces added later on) is lost when the script is recreated.
+pub struct SupervisedScript {
+	script: Script,
+	factory: ScriptFactory,
+	on_recreate: Vec<OnRecreate>,
+	recreations: u64,
+	// Usage of scripts that have been replaced
+	retired_usage: UsageReport,
+}
+
+impl SupervisedScript {
+	/// Creates the script using `factory`, which is invoked again whenever the script needs to be recreated.
+	pub fn new<F>(factory: F) -> Result<Self, JsError>
+	where
+		F: Fn() -> Result<Script, JsError> + 'static,
+	{
+		Ok(Self {
+			script: factory()?,
+			factory: Box::new(factory),
+			on_recreate: Vec::new(),
+			recreations: 0,
+			retired_usage: UsageReport::default(),
+		})
+	}
+
+	/// Registers a hook which is invoked whenever the script is recreated, with the condition that made it unusable.
+	///
+	/// If the factory fails, the hook receives its error; the broken script is kept, and recreation is attempted again on the next
+	/// access.
+	pub fn on_recreate<F>(&mut self, hook: F)
+	where
+		F: FnMut(FatalCondition, Result<(), &JsError>) + 'static,
+	{
+		self.on_recreate.push(Box::new(hook));
+	}
+
+	/// Number of times the script has been recreated.
+	pub fn recreations(&self) -> u64 {
+		self.recreations
+	}
+
+	/// Returns the resources used so far, including scripts that have been replaced.
+	pub fn usage(&self) -> UsageReport {
+		let mut usage = self.retired_usage.clone();
+		usage.merge(&self.script.usage());
+		usage
+	}
+
+	fn recover(&mut self) {
+		let Some(condition) = self.script.fatal_condition() else {
+			return;
+		};
+
+		match (self.factory)() {
+			Ok(script) => {
+				let old_script = std::mem::replace(&mut self.script, script);
+				self.retired_usage.merge(&old_script.usage());
+				self.recreations += 1;
+
+				for hook in self.on_recreate.iter_mut() {
+					hook(condition, Ok(()));
+				}
+			}
+			Err(e) => {
+				for hook in self.on_recreate.iter_mut() {
+					hook(condition, Err(&e));
+				}
+			}
+		}
+	}
+}
+
+impl Deref for SupervisedScript {
+	type Target = Script;
+
+	fn deref(&self) -> &Script {
+		&self.script
+	}
+}
+
+impl DerefMut for SupervisedScript {
+	fn deref_mut(&mut self) -> &mut Script {
+		self.recover();
+		&mut self.script
+	}
+}
@@ -0,0 +1,160 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+use deno_core::futures::executor::block_on;
+use deno_core::futures::future::poll_fn;
+use deno_core::futures::task::noop_waker;
+use deno_core::{op, v8, JsRuntime, OpState};
+
+use crate::termination::{Termination, TerminationFlag};
+use crate::{invoke, watchdog, AnyError};
+
+/// Counts promise reactions and `queueMicrotask()` callbacks, installed with
+/// [`ScriptBuilder::with_max_microtasks()`](crate::ScriptBuilder::with_max_microtasks).
+pub(crate) const INSTALL_JS: &str = r#"(() => {
+	const ops = Deno.core.ops;
+	Deno.core.setPromiseHooks(undefined, () => ops.op_count_microtask(), undefined, undefined);
+
+	const queueMicrotask = globalThis.queueMicrotask;
+	if (typeof queueMicrotask === "function") {
+		globalThis.queueMicrotask = (callback) => typeof callback === "function"
+			? queueMicrotask(() => { ops.op_count_microtask(); callback(); })
+			: queueMicrotask(callback);
+	}
+})();"#;
+
+/// Microtasks run during the current call, stored in the op state.
+pub(crate) struct MicrotaskCounter {
+	count: u64,
+	limit: u64,
+	handle: v8::IsolateHandle,
+	terminated: TerminationFlag,
+}
+
+impl MicrotaskCounter {
+	pub fn new(limit: u64, handle: v8::IsolateHandle, terminated: TerminationFlag) -> Self {
+		Self {
+			count: 0,
+			limit,
+			handle,
+			terminated,
+		}
+	}
+}
+
+/// Restarts counting microtasks for a new call.
+pub(crate) fn begin(state: &Rc<RefCell<OpState>>) {
+	if let Some(counter) = state.borrow_mut().try_borrow_mut::<MicrotaskCounter>() {
+		counter.count = 0;
+	}
+}
+
+#[op]
+pub(crate) fn op_count_microtask(state: &mut OpState) {
+	let Some(counter) = state.try_borrow_mut::<MicrotaskCounter>() else {
+		return;
+	};
+
+	counter.count += 1;
+	if counter.count > counter.limit {
+		// Takes effect as soon as control returns to JS
+		counter.terminated.set(Termination::MicrotaskLimit);
+		counter.handle.terminate_execution();
+	}
+}
+
+/// Aborts waiting for promises after the settle timeout, see [`Script::with_settle_timeout()`](crate::Script::with_settle_timeout).
+///
+/// Unlike terminating the isolate, this also takes effect while the event loop is idle, waiting for ops or timers.
+pub(crate) struct SettleTimer {
+	shared: Arc<SettleShared>,
+	_scheduled: watchdog::Scheduled,
+}
+
+#[derive(Default)]
+struct SettleShared {
+	expired: AtomicBool,
+	waker: Mutex<Option<Waker>>,
+}
+
+impl SettleTimer {
+	/// Starts the timer. Sets `terminated` when it expires; dropping the timer cancels it.
+	pub fn start(
+		timeout: Duration,
+		handle: v8::IsolateHandle,
+		terminated: TerminationFlag,
+	) -> Self {
+		let shared = Arc::new(SettleShared::default());
+		let watchdog_shared = shared.clone();
+
+		let scheduled = watchdog::schedule(timeout, move || {
+			watchdog_shared.expired.store(true, Ordering::SeqCst);
+			terminated.set(Termination::SettleTimeout);
+
+			// Interrupts JS code running in a callback, or wakes up the event loop if it waits
+			handle.terminate_execution();
+			if let Some(waker) = watchdog_shared.waker.lock().unwrap().take() {
+				waker.wake();
+			}
+			None
+		});
+
+		Self {
+			shared,
+			_scheduled: scheduled,
+		}
+	}
+
+	fn expired(&self, waker: &Waker) -> bool {
+		// Register first, so that expiry right after the check still wakes the event loop
+		*self.shared.waker.lock().unwrap() = Some(waker.clone());
+		self.shared.expired.load(Ordering::SeqCst)
+	}
+}
+
+/// Runs the event loop until all work is done, polling it at most `max_turns` times (counting from `turns`), and not beyond the
+/// expiry of `settle`. Stops early once `until` has settled, even if other work is still pending.
+///
+/// Fails and sets `terminated` if the work is not done by then.
+pub(crate) fn run_event_loop(
+	runtime: &mut JsRuntime,
+	turns: &mut u64,
+	max_turns: Option<u64>,
+	settle: Option<&SettleTimer>,
+	until: Option<&v8::Global<v8::Promise>>,
+	terminated: &TerminationFlag,
+) -> Result<(), AnyError> {
+	block_on(poll_fn(|cx| {
+		if settle.is_some_and(|timer| timer.expired(cx.waker())) {
+			return Poll::Ready(Err(AnyError::msg("promise did not settle in time")));
+		}
+
+		if max_turns.is_some_and(|max_turns| *turns >= max_turns) {
+			terminated.set(Termination::EventLoopLimit);
+			return Poll::Ready(Err(AnyError::msg("event loop turn limit exceeded")));
+		}
+
+		*turns += 1;
+		match runtime.poll_event_loop(cx, false) {
+			Poll::Pending if until.is_some_and(|promise| invoke::is_settled(runtime, promise)) => {
+				Poll::Ready(Ok(()))
+			}
+			poll => poll,
+		}
+	}))
+}
+
+/// Polls the event loop once without blocking, returning whether all work is done.
+pub(crate) fn poll_once(runtime: &mut JsRuntime) -> Result<bool, AnyError> {
+	let waker = noop_waker();
+	match runtime.poll_event_loop(&mut Context::from_waker(&waker), false) {
+		Poll::Ready(result) => result.map(|()| true),
+		Poll::Pending => Ok(false),
+	}
+}
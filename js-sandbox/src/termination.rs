@@ -0,0 +1,60 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+
+/// Reason why a call was forcibly stopped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub(crate) enum Termination {
+	Timeout = 1,
+	Budget = 2,
+	HeapLimit = 3,
+	Preempted = 4,
+	MicrotaskLimit = 5,
+	EventLoopLimit = 6,
+	SettleTimeout = 7,
+}
+
+/// Records why execution was terminated; shared with the watchdog thread and V8 callbacks.
+///
+/// Only the first reason is kept, as a terminated isolate may still be hit by other limits while unwinding.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct TerminationFlag(Arc<AtomicU8>);
+
+impl TerminationFlag {
+	pub fn set(&self, reason: Termination) {
+		let _ = self
+			.0
+			.compare_exchange(0, reason as u8, Ordering::SeqCst, Ordering::SeqCst);
+	}
+
+	pub fn reset(&self) {
+		self.0.store(0, Ordering::SeqCst);
+	}
+
+	pub fn reason(&self) -> Option<Termination> {
+		match self.0.load(Ordering::SeqCst) {
+			0 => None,
+			1 => Some(Termination::Timeout),
+			2 => Some(Termination::Budget),
+			3 => Some(Termination::HeapLimit),
+			4 => Some(Termination::Preempted),
+			5 => Some(Termination::MicrotaskLimit),
+			6 => Some(Termination::EventLoopLimit),
+			7 => Some(Termination::SettleTimeout),
+			other => unreachable!("invalid termination reason {other}"),
+		}
+	}
+}
+
+/// Condition a script cannot recover from, see [`Script::is_healthy()`](crate::Script::is_healthy).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FatalCondition {
+	/// The heap limit set with [`ScriptBuilder::with_max_heap_size()`](crate::ScriptBuilder::with_max_heap_size) was reached.
+	HeapLimit,
+
+	/// A host callback (such as a method of a [`HostObject`](crate::HostObject)) panicked.
+	HostPanic,
+}
@@ -0,0 +1,77 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::io;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::JsValue;
+
+/// Cumulative resource usage of a script, e.g. for billing or abuse detection.
+///
+/// Retrieved through [`Script::usage()`](crate::Script::usage) or [`SandboxManager::usage()`](crate::SandboxManager::usage).
+/// Serializable, so it can be stored or sent to a monitoring system directly.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct UsageReport {
+	/// Number of calls into JavaScript (a batch or pipeline counts as one call).
+	pub calls: u64,
+
+	/// Number of calls that returned an error.
+	pub failed_calls: u64,
+
+	/// Total wall-clock time spent in calls, including awaiting async functions.
+	///
+	/// This is not CPU time: time the thread spends descheduled, or blocked in host functions, counts as well.
+	pub wall_time: Duration,
+
+	/// Total size of JSON arguments passed to JavaScript, in bytes.
+	pub bytes_in: u64,
+
+	/// Total size of JSON results returned from JavaScript, in bytes.
+	pub bytes_out: u64,
+
+	/// Highest used heap size observed after a call, in bytes.
+	///
+	/// The heap is only sampled once calls have completed, so memory that a call allocates and releases again is not seen.
+	pub peak_heap_after_call: usize,
+
+	/// Highest external memory (such as array buffers backed by host memory) observed after a call, in bytes.
+	///
+	/// Sampled like [`Self::peak_heap_after_call`].
+	pub peak_external_memory_after_call: usize,
+}
+
+impl UsageReport {
+	/// Adds the usage of `other` to this report: counters are summed up, peaks are combined.
+	pub fn merge(&mut self, other: &UsageReport) {
+		self.calls += other.calls;
+		self.failed_calls += other.failed_calls;
+		self.wall_time += other.wall_time;
+		self.bytes_in += other.bytes_in;
+		self.bytes_out += other.bytes_out;
+		self.peak_heap_after_call = self.peak_heap_after_call.max(other.peak_heap_after_call);
+		self.peak_external_memory_after_call = self
+			.peak_external_memory_after_call
+			.max(other.peak_external_memory_after_call);
+	}
+}
+
+/// Size of a value in JSON representation, without allocating the string.
+pub(crate) fn json_size(value: &JsValue) -> u64 {
+	struct Counter(u64);
+
+	impl io::Write for Counter {
+		fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+			self.0 += buf.len() as u64;
+			Ok(buf.len())
+		}
+
+		fn flush(&mut self) -> io::Result<()> {
+			Ok(())
+		}
+	}
+
+	let mut counter = Counter(0);
+	serde_json::to_writer(&mut counter, value).expect("JSON value is always serializable");
+	counter.0
+}
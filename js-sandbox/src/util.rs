@@ -1,5 +1,8 @@
 // Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
 
+use std::mem::ManuallyDrop;
+use std::rc::Rc;
+
 use crate::{JsError, JsValue, Script};
 
 /// Evaluates a standalone Javascript expression, and returns the result as a JSON value.
@@ -7,6 +10,8 @@ use crate::{JsError, JsValue, Script};
 /// If there is an error, Err will be returned.
 /// This function is primarily useful for small standalone experiments. Usually, you would want to use the [`Script`](struct.Script.html) struct
 /// for more sophisticated Rust->JS interaction.
+///
+/// Every invocation creates and disposes a new JS runtime. To evaluate many expressions, use [`global()`] instead.
 pub fn eval_json(js_expr: &str) -> Result<JsValue, JsError> {
 	let code = format!(
 		"
@@ -16,6 +21,35 @@ pub fn eval_json(js_expr: &str) -> Result<JsValue, JsError> {
 	"
 	);
 
-	let mut script = Script::from_string(&code)?;
+	let script = Script::from_string(&code)?;
 	script.call_json("__rust_expr", &JsValue::Null)
 }
+
+/// Returns a script shared by all callers on the current thread, intended for quick ad-hoc evaluations.
+///
+/// The script is created on first use and is never disposed, so repeated evaluations via [`Script::eval_json()`] do not
+/// pay for setting up a runtime each time. Since it is shared, global state (e.g. properties assigned to `globalThis`) is visible to
+/// all subsequent evaluations on the same thread. Use a dedicated [`Script`] for untrusted code.
+///
+/// The script is deliberately leaked, also when its thread exits: dropping it in a thread-local destructor could tear down the
+/// isolate after V8 itself, e.g. on the main thread after `main()` returns. Avoid `global()` on short-lived threads.
+///
+/// ```rust
+/// let result = js_sandbox::global().eval_json("[1, 2, 3].map(x => x * 2)").unwrap();
+/// assert_eq!(result, serde_json::json!([2, 4, 6]));
+/// ```
+pub fn global() -> Rc<Script> {
+	thread_local! {
+		static GLOBAL: ManuallyDrop<Rc<Script>> =
+			ManuallyDrop::new(Rc::new(Script::from_string("").expect("empty script can be initialized")));
+	}
+
+	GLOBAL.with(|script| Rc::clone(script))
+}
+
+/// Creates the script behind a function defined with [`js_fn!`](crate::js_fn), which stores the JS function `js_code` as `fn_name`.
+pub fn js_fn_script(fn_name: &str, js_code: &str) -> Result<Script, JsError> {
+	// Parenthesized, so that declarations are evaluated as expressions. The line break ends trailing // comments.
+	let js_code = js_code.trim().trim_end_matches(';');
+	Script::from_string(&format!("const {fn_name} = ({js_code}\n);"))
+}
@@ -0,0 +1,111 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Action fired by the watchdog. Returns the delay until it should fire again, or `None` if it is done.
+type Action = Box<dyn FnMut() -> Option<Duration> + Send>;
+
+enum Command {
+	Schedule {
+		id: u64,
+		at: Instant,
+		action: Action,
+	},
+	Cancel {
+		id: u64,
+	},
+}
+
+struct Entry {
+	id: u64,
+	at: Option<Instant>,
+	action: Action,
+}
+
+/// Handle to an action scheduled on the watchdog thread. Dropping it cancels the action.
+pub(crate) struct Scheduled {
+	id: u64,
+}
+
+impl Drop for Scheduled {
+	fn drop(&mut self) {
+		send(Command::Cancel { id: self.id });
+	}
+}
+
+/// Runs `action` on the shared watchdog thread after `delay`, and repeatedly as long as it returns a new delay.
+///
+/// All scripts share a single watchdog thread, which is started lazily. Scheduled actions are cancelled when the returned handle
+/// is dropped, so no thread keeps sleeping after a call has completed.
+pub(crate) fn schedule<F>(delay: Duration, action: F) -> Scheduled
+where
+	F: FnMut() -> Option<Duration> + Send + 'static,
+{
+	static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+	let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+
+	send(Command::Schedule {
+		id,
+		at: Instant::now() + delay,
+		action: Box::new(action),
+	});
+
+	Scheduled { id }
+}
+
+fn send(command: Command) {
+	static SENDER: OnceLock<Mutex<mpsc::Sender<Command>>> = OnceLock::new();
+
+	let sender = SENDER.get_or_init(|| {
+		let (tx, rx) = mpsc::channel();
+		thread::Builder::new()
+			.name("js-sandbox-watchdog".to_string())
+			.spawn(move || run(rx))
+			.expect("spawn watchdog thread");
+
+		Mutex::new(tx)
+	});
+
+	sender
+		.lock()
+		.expect("watchdog sender not poisoned")
+		.send(command)
+		.expect("watchdog thread is running");
+}
+
+fn run(rx: mpsc::Receiver<Command>) {
+	let mut entries: Vec<Entry> = Vec::new();
+
+	loop {
+		let next = entries.iter().filter_map(|e| e.at).min();
+		let received = match next {
+			Some(at) => rx.recv_timeout(at.saturating_duration_since(Instant::now())),
+			None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+		};
+
+		match received {
+			Ok(Command::Schedule { id, at, action }) => entries.push(Entry {
+				id,
+				at: Some(at),
+				action,
+			}),
+			Ok(Command::Cancel { id }) => entries.retain(|e| e.id != id),
+			Err(RecvTimeoutError::Timeout) => {}
+			Err(RecvTimeoutError::Disconnected) => return,
+		}
+
+		// Fire due actions, also when commands keep arriving
+		let now = Instant::now();
+		for entry in entries.iter_mut() {
+			if entry.at.is_some_and(|at| at <= now) {
+				entry.at = (entry.action)().map(|delay| now + delay);
+			}
+		}
+
+		entries.retain(|e| e.at.is_some());
+	}
+}
@@ -0,0 +1,41 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use std::sync::Arc;
+
+use deno_core::{Extension, OpState};
+
+/// Permissions for the web extensions: no high-resolution time, which mitigates timing attacks.
+struct SandboxPermissions;
+
+impl deno_web::TimersPermission for SandboxPermissions {
+	fn allow_hrtime(&mut self) -> bool {
+		false
+	}
+
+	fn check_unstable(&self, _state: &OpState, _api_name: &'static str) {
+		// None of the installed globals expose unstable APIs
+	}
+}
+
+deno_core::extension!(
+	js_sandbox_web,
+	deps = [deno_webidl, deno_console, deno_url, deno_web],
+	esm_entry_point = "ext:js_sandbox_web/web.js",
+	esm = [dir "src/js", "web.js"],
+	state = |state| {
+		state.put(SandboxPermissions);
+	},
+);
+
+/// Extensions providing `console`, `URL`, `TextEncoder`/`TextDecoder`, `atob`/`btoa` and timers.
+pub(crate) fn extensions() -> Vec<Extension> {
+	let blob_store = Arc::new(deno_web::BlobStore::default());
+
+	vec![
+		deno_webidl::deno_webidl::init_ops_and_esm(),
+		deno_console::deno_console::init_ops_and_esm(),
+		deno_url::deno_url::init_ops_and_esm(),
+		deno_web::deno_web::init_ops_and_esm::<SandboxPermissions>(blob_store, None),
+		js_sandbox_web::init_ops_and_esm(),
+	]
+}
@@ -0,0 +1,11 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+const unit = 1;
+
+function area(width, height) {
+	return width * height * unit;
+}
+
+function perimeter(width, height) {
+	return 2 * (width + height);
+}
@@ -0,0 +1,65 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+use js_sandbox::{find_forbidden_apis, ForbiddenApiFinding, DEFAULT_FORBIDDEN_APIS};
+
+#[test]
+fn forbidden_apis_are_found() {
+	let src = "
+		function load(name) { return import(name); }
+		const core = Deno.core;";
+
+	let findings = find_forbidden_apis(src, DEFAULT_FORBIDDEN_APIS);
+
+	assert_eq!(
+		findings,
+		vec![
+			ForbiddenApiFinding {
+				identifier: "import".to_string(),
+				line: 2,
+				column: 32,
+			},
+			ForbiddenApiFinding {
+				identifier: "Deno".to_string(),
+				line: 3,
+				column: 16,
+			},
+		]
+	);
+}
+
+#[test]
+fn forbidden_apis_ignore_strings_comments_and_properties() {
+	let src = r#"
+		// eval is not used here
+		/* neither is Function */
+		const text = "eval('1')" + 'Function' + `Deno ${config.eval}`;
+		obj?.Function(text);"#;
+
+	let findings = find_forbidden_apis(src, DEFAULT_FORBIDDEN_APIS);
+	assert!(findings.is_empty(), "unexpected findings: {findings:?}");
+}
+
+#[test]
+fn forbidden_apis_custom_list() {
+	let src = "
+		const fetched = fetch(`${base}/api`);
+		const joined = [...fetched, ...sockets];";
+
+	let findings = find_forbidden_apis(src, &["fetch", "sockets"]);
+	let identifiers: Vec<&str> = findings.iter().map(|f| f.identifier.as_str()).collect();
+
+	assert_eq!(identifiers, ["fetch", "sockets"]);
+}
+
+#[test]
+fn forbidden_apis_around_regex_literals() {
+	let src = r#"
+		const quote = /["'(]/g; eval(code); const s = "";
+		const ratio = total / count; Function(ratio / 2);
+		const pattern = /eval\/Deno[/]/;"#;
+
+	let findings = find_forbidden_apis(src, DEFAULT_FORBIDDEN_APIS);
+	let identifiers: Vec<&str> = findings.iter().map(|f| f.identifier.as_str()).collect();
+
+	assert_eq!(identifiers, ["eval", "Function"]);
+}
@@ -0,0 +1,66 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+#![cfg(feature = "v8")]
+
+use std::thread;
+
+use deno_core::futures::executor::block_on;
+
+use js_sandbox::{AsyncScriptHandle, JsError, Script};
+
+#[test]
+fn call_from_threads() {
+	let src = "
+		var count = 0;
+		function inc(n) { count += n; return count; }
+		function get() { return count; }";
+
+	let handle = AsyncScriptHandle::from_string(src).expect("Initialization succeeds");
+
+	let threads: Vec<_> = (0..4)
+		.map(|_| {
+			let handle = handle.clone();
+			thread::spawn(move || {
+				let _: i32 = block_on(handle.call("inc", (1,))).unwrap();
+			})
+		})
+		.collect();
+
+	for t in threads {
+		t.join().unwrap();
+	}
+
+	let result: i32 = block_on(handle.call("get", ())).unwrap();
+	assert_eq!(result, 4);
+}
+
+#[test]
+fn spawn_with_builder() {
+	let handle = AsyncScriptHandle::spawn(|| {
+		Script::builder()
+			.with_max_source_size(1024)
+			.build_from_string("function triple(a) { return 3 * a; }")
+	})
+	.expect("Initialization succeeds");
+
+	let result: i32 = block_on(handle.call("triple", (7,))).unwrap();
+	assert_eq!(result, 21);
+}
+
+#[test]
+fn ctor_error_syntax() {
+	let result = AsyncScriptHandle::from_string("function triple(a) { return 3 *. a; }");
+	assert!(
+		result.is_err(),
+		"Syntax error is reported from script thread"
+	);
+}
+
+#[test]
+fn call_error_exception() {
+	let handle = AsyncScriptHandle::from_string("function fail() { throw new Error('boom'); }")
+		.expect("Initialization succeeds");
+
+	let result: Result<i32, JsError> = block_on(handle.call("fail", ()));
+	assert!(result.unwrap_err().to_string().contains("boom"));
+}
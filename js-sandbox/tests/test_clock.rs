@@ -0,0 +1,193 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+#![cfg(feature = "v8")]
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use js_sandbox::{Clock, Script, VirtualClock};
+
+#[test]
+fn performance_now_real() {
+	let js_code = "function elapsed() { const start = performance.now(); while (performance.now() - start < 5) {} return performance.now() - start; }";
+	let mut script = Script::from_string(js_code).expect("Initialization succeeds");
+
+	let elapsed: f64 = script.call("elapsed", ()).unwrap();
+	assert!(elapsed >= 5.0, "elapsed: {elapsed}");
+}
+
+#[test]
+fn performance_now_virtual() {
+	let clock = VirtualClock::new();
+	let mut script = Script::builder()
+		.with_clock(clock.clone())
+		.build_from_string("function now() { return performance.now(); }")
+		.expect("Initialization succeeds");
+
+	let now: f64 = script.call("now", ()).unwrap();
+	assert_eq!(now, 0.0);
+
+	clock.advance(Duration::from_millis(1500));
+	clock.advance(Duration::from_micros(250));
+	let now: f64 = script.call("now", ()).unwrap();
+	assert_eq!(now, 1500.25);
+
+	// Time does not move on its own
+	let now: f64 = script.call("now", ()).unwrap();
+	assert_eq!(now, 1500.25);
+}
+
+#[test]
+fn date_virtual() {
+	let start = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+	let clock = VirtualClock::starting_at(start);
+	let js_code = r#"
+	function now() {
+		return [Date.now(), new Date().getTime(), new Date(5).getTime(), new Date() instanceof Date];
+	}"#;
+	let mut script = Script::builder()
+		.with_clock(clock.clone())
+		.build_from_string(js_code)
+		.expect("Initialization succeeds");
+
+	let result: (u64, u64, u64, bool) = script.call("now", ()).unwrap();
+	assert_eq!(result, (1_700_000_000_000, 1_700_000_000_000, 5, true));
+
+	clock.advance(Duration::from_secs(60));
+	let result: (u64, u64, u64, bool) = script.call("now", ()).unwrap();
+	assert_eq!(result, (1_700_000_060_000, 1_700_000_060_000, 5, true));
+}
+
+#[test]
+fn timers_virtual() {
+	let clock = VirtualClock::new();
+	let js_code = r#"
+	let fired = [];
+	function schedule() {
+		setTimeout(() => fired.push("immediate"), 0);
+		setTimeout(() => fired.push("later"), 100);
+		const id = setTimeout(() => fired.push("cancelled"), 50);
+		clearTimeout(id);
+	}
+	function take() {
+		const result = fired;
+		fired = [];
+		return result;
+	}"#;
+	let mut script = Script::builder()
+		.with_clock(clock.clone())
+		.build_from_string(js_code)
+		.expect("Initialization succeeds");
+
+	// Zero-delay timers fire at the end of the call
+	script.call::<_, ()>("schedule", ()).unwrap();
+	let fired: Vec<String> = script.call("take", ()).unwrap();
+	assert_eq!(fired, ["immediate"]);
+
+	clock.advance(Duration::from_millis(99));
+	let fired: Vec<String> = script.call("take", ()).unwrap();
+	assert!(fired.is_empty());
+
+	// Due timers fire after the call, so they are observed by the next one
+	clock.advance(Duration::from_millis(1));
+	let fired: Vec<String> = script.call("take", ()).unwrap();
+	assert!(fired.is_empty());
+	let fired: Vec<String> = script.call("take", ()).unwrap();
+	assert_eq!(fired, ["later"]);
+}
+
+#[test]
+fn custom_clock() {
+	struct FixedClock;
+
+	impl Clock for FixedClock {
+		fn now(&self) -> SystemTime {
+			UNIX_EPOCH + Duration::from_millis(42)
+		}
+
+		fn elapsed(&self) -> Duration {
+			Duration::from_millis(7)
+		}
+	}
+
+	let mut script = Script::builder()
+		.with_clock(FixedClock)
+		.build_from_string("function now() { return [Date.now(), performance.now()]; }")
+		.expect("Initialization succeeds");
+
+	let result: (f64, f64) = script.call("now", ()).unwrap();
+	assert_eq!(result, (42.0, 7.0));
+}
+
+#[test]
+fn advance_time() {
+	let js_code = r#"
+	let log = [];
+	setInterval(() => log.push(`interval ${performance.now()}`), 10);
+	setTimeout(() => {
+		log.push(`timeout ${Date.now()}`);
+		Promise.resolve().then(() => log.push("promise"));
+		setTimeout(() => log.push(`nested ${performance.now()}`), 5);
+	}, 15);
+	function take() {
+		const result = log;
+		log = [];
+		return result;
+	}"#;
+	let mut script = Script::builder()
+		.with_clock(VirtualClock::new())
+		.build_from_string(js_code)
+		.expect("Initialization succeeds");
+
+	script.advance_time(Duration::from_millis(35)).unwrap();
+	let log: Vec<String> = script.call("take", ()).unwrap();
+	assert_eq!(
+		log,
+		[
+			"interval 10",
+			"timeout 15",
+			"promise",
+			"interval 20",
+			"nested 20",
+			"interval 30"
+		]
+	);
+
+	// Time stays shifted
+	let now: f64 = script
+		.eval_json("performance.now()")
+		.map(|v| v.as_f64().unwrap())
+		.unwrap();
+	assert_eq!(now, 35.0);
+
+	script.advance_time(Duration::from_millis(5)).unwrap();
+	let log: Vec<String> = script.call("take", ()).unwrap();
+	assert_eq!(log, ["interval 40"]);
+}
+
+#[test]
+fn advance_time_requires_clock() {
+	let mut script = Script::from_string("").expect("Initialization succeeds");
+
+	let result = script.advance_time(Duration::from_secs(1));
+	assert!(result.is_err());
+}
+
+#[test]
+fn clock_controller_hidden() {
+	let js_code = r#"
+	function controllers() {
+		return Object.getOwnPropertyNames(globalThis).filter(name => name.startsWith("__jsSandbox"));
+	}"#;
+	let mut script = Script::builder()
+		.with_clock(VirtualClock::new())
+		.build_from_string(js_code)
+		.expect("Initialization succeeds");
+
+	// Only the host can fire timers or move time
+	let controllers: Vec<String> = script.call("controllers", ()).unwrap();
+	assert!(controllers.is_empty(), "{controllers:?}");
+
+	script.advance_time(Duration::from_millis(5)).unwrap();
+	let now = script.eval_json("performance.now()").unwrap();
+	assert_eq!(now.as_f64(), Some(5.0));
+}
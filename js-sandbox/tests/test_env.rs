@@ -0,0 +1,112 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+#![cfg(feature = "v8")]
+
+use js_sandbox::{JsError, JsValue, Script, ScriptEnv};
+
+#[test]
+fn env_read_only() {
+	let src = r#"
+	function config() { return [env.HOST, env.PORT, env.MISSING, "HOST" in env, Object.keys(env).join(",")]; }
+	function modify() { env.HOST = "evil"; }
+	function remove() { delete env.HOST; }"#;
+
+	let env = ScriptEnv::new([("HOST", "localhost"), ("PORT", "8080")]);
+	let mut script = Script::builder()
+		.with_env(env)
+		.build_from_string(src)
+		.expect("Initialization succeeds");
+
+	let result: (String, String, Option<String>, bool, String) = script.call("config", ()).unwrap();
+	assert_eq!(
+		result,
+		(
+			"localhost".to_string(),
+			"8080".to_string(),
+			None,
+			true,
+			"HOST,PORT".to_string()
+		)
+	);
+
+	let result: Result<(), JsError> = script.call("modify", ());
+	assert!(result.is_err());
+
+	let result: Result<(), JsError> = script.call("remove", ());
+	assert!(result.is_err());
+	assert_eq!(script.env()["HOST"], "localhost");
+}
+
+#[test]
+fn env_writable_quota() {
+	let src = r#"
+	function set(name, value) {
+		try {
+			env[name] = value;
+			return true;
+		} catch (e) {
+			return false;
+		}
+	}
+	function remove(name) { delete env[name]; }"#;
+
+	let env = ScriptEnv::new([("A", "1")]).writable(2, 10);
+	let mut script = Script::builder()
+		.with_env(env)
+		.build_from_string(src)
+		.expect("Initialization succeeds");
+
+	// Values are converted to strings
+	assert!(script.call::<_, bool>("set", ("B", 22)).unwrap());
+	assert_eq!(script.env()["B"], "22");
+
+	// Too many variables, or too many bytes
+	assert!(!script.call::<_, bool>("set", ("C", "3")).unwrap());
+	assert!(!script.call::<_, bool>("set", ("A", "123456789")).unwrap());
+	assert!(script.call::<_, bool>("set", ("A", "12345")).unwrap());
+
+	script.call::<_, ()>("remove", ("B",)).unwrap();
+	assert!(script.call::<_, bool>("set", ("C", "3")).unwrap());
+
+	let mut vars: Vec<_> = script.env().into_iter().collect();
+	vars.sort();
+	assert_eq!(
+		vars,
+		[
+			("A".to_string(), "12345".to_string()),
+			("C".to_string(), "3".to_string())
+		]
+	);
+}
+
+#[test]
+fn env_absent() {
+	let mut script =
+		Script::from_string("function hasEnv() { return typeof env !== 'undefined'; }")
+			.expect("Initialization succeeds");
+
+	let result: bool = script.call("hasEnv", ()).unwrap();
+	assert!(!result);
+	assert!(script.env().is_empty());
+}
+
+#[test]
+fn env_ops_without_env() {
+	let src = r#"
+	function get() { return Deno.core.ops.op_env_get("HOST"); }
+	function set() { Deno.core.ops.op_env_set("HOST", "evil"); }
+	function keys() { return Deno.core.ops.op_env_keys(); }"#;
+	let mut script = Script::from_string(src).expect("Initialization succeeds");
+
+	for fn_name in ["get", "set", "keys"] {
+		let err = script.call::<_, JsValue>(fn_name, ()).unwrap_err();
+		assert!(matches!(err, JsError::Runtime(_)), "{fn_name}: {err:?}");
+		assert!(
+			err.to_string().contains("no environment"),
+			"{fn_name}: {err}"
+		);
+	}
+
+	// The script remains usable
+	assert!(script.call::<_, JsValue>("get", ()).is_err());
+}
@@ -1,5 +1,7 @@
 // Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
 
+#![cfg(feature = "v8")]
+
 use js_sandbox::JsValue;
 use util::expect_error;
 
@@ -29,3 +31,30 @@ fn syntax_error() {
 
 	expect_error(result_opt, "Syntax error");
 }
+
+#[test]
+fn global_script() {
+	let script = js_sandbox::global();
+
+	let result = script.eval_json("Math.max(3, 7)").unwrap();
+	assert_eq!(result, JsValue::from(7));
+
+	// Same script is reused on this thread, including its state
+	script.eval_json("globalThis.counter = 41").unwrap();
+	let result = js_sandbox::global().eval_json("++counter").unwrap();
+	assert_eq!(result, JsValue::from(42));
+
+	assert!(std::rc::Rc::ptr_eq(&script, &js_sandbox::global()));
+}
+
+#[test]
+fn global_script_error() {
+	let result = js_sandbox::global().eval_json("undefinedVariable + 1");
+	assert!(result.is_err());
+
+	// Remains usable after an error
+	let result = js_sandbox::global()
+		.eval_json("'still ' + 'working'")
+		.unwrap();
+	assert_eq!(result, JsValue::from("still working"));
+}
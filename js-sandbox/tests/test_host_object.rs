@@ -0,0 +1,417 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+#![cfg(feature = "v8")]
+
+use js_sandbox::{
+	js_host_object, AnyError, BlockedOperation, CapabilityRegistry, ImportPolicy, JsError, Script,
+};
+
+struct Inventory {
+	items: Vec<String>,
+}
+
+#[js_host_object]
+impl Inventory {
+	pub fn add(&mut self, item: String) -> usize {
+		self.items.push(item);
+		self.items.len()
+	}
+
+	pub fn add_all(&mut self, prefix: &str, items: &[String]) -> usize {
+		self.items
+			.extend(items.iter().map(|item| format!("{prefix}{item}")));
+		self.items.len()
+	}
+
+	pub fn list(&self) -> Vec<String> {
+		self.items.clone()
+	}
+
+	pub fn get(&self, index: usize) -> String {
+		self.items[index].clone()
+	}
+
+	pub fn take(&mut self, index: usize) -> Result<String, AnyError> {
+		if index < self.items.len() {
+			Ok(self.items.remove(index))
+		} else {
+			Err(AnyError::msg("no such item"))
+		}
+	}
+}
+
+#[test]
+fn call_host_methods() {
+	let src = r#"
+	function fill() {
+		host.inventory.add("sword");
+		return host.inventory.add("shield");
+	}
+
+	function list() {
+		return host.inventory.list().join(",");
+	}"#;
+
+	let mut script = Script::builder()
+		.with_host_object("inventory", Inventory { items: Vec::new() })
+		.build_from_string(src)
+		.expect("Initialization succeeds");
+
+	let count: usize = script.call("fill", ()).unwrap();
+	assert_eq!(count, 2);
+
+	let listed: String = script.call("list", ()).unwrap();
+	assert_eq!(listed, "sword,shield");
+}
+
+#[test]
+fn call_host_reference_params() {
+	let src = r#"
+	function fill() {
+		return host.inventory.add_all("iron ", ["sword", "shield"]);
+	}"#;
+
+	let mut script = Script::builder()
+		.with_host_object("inventory", Inventory { items: Vec::new() })
+		.build_from_string(src)
+		.expect("Initialization succeeds");
+
+	let count: usize = script.call("fill", ()).unwrap();
+	assert_eq!(count, 2);
+}
+
+#[test]
+fn call_host_error_is_catchable() {
+	let src = r#"
+	function takeMissing() {
+		try {
+			return host.inventory.take(5);
+		} catch (e) {
+			return "caught: " + e.message;
+		}
+	}
+
+	function takeUncaught() {
+		return host.inventory.take(5);
+	}"#;
+
+	let mut script = Script::builder()
+		.with_host_object("inventory", Inventory { items: Vec::new() })
+		.build_from_string(src)
+		.expect("Initialization succeeds");
+
+	let result: String = script.call("takeMissing", ()).unwrap();
+	assert_eq!(result, "caught: no such item");
+
+	let result: Result<String, JsError> = script.call("takeUncaught", ());
+	assert!(result.is_err());
+}
+
+#[test]
+fn call_host_panic_is_contained() {
+	let src = r#"
+	function getMissing() {
+		try {
+			return host.inventory.get(5);
+		} catch (e) {
+			return "caught: " + e.message;
+		}
+	}
+
+	async function firstOfNone() {
+		return await host.first();
+	}"#;
+
+	let mut script = Script::builder()
+		.with_host_object("inventory", Inventory { items: Vec::new() })
+		.with_async_host_fn("first", |args| async move { Ok(args[0].clone()) })
+		.build_from_string(src)
+		.expect("Initialization succeeds");
+	assert!(script.is_healthy());
+
+	let result: String = script.call("getMissing", ()).unwrap();
+	assert!(result.starts_with("caught: host callback panicked: index out of bounds"));
+
+	let result: Result<String, JsError> = script.call("firstOfNone", ());
+	assert!(matches!(result, Err(JsError::Runtime(e)) if e.to_string().contains("panicked")));
+
+	// The script remains usable, but host state may be inconsistent
+	let result: String = script.call("getMissing", ()).unwrap();
+	assert!(result.starts_with("caught:"));
+	assert!(!script.is_healthy());
+}
+
+#[test]
+fn call_async_host_fn() {
+	use std::thread;
+	use std::time::Duration;
+
+	use deno_core::futures::channel::oneshot;
+	use js_sandbox::JsValue;
+
+	let src = r#"
+	async function lookupBoth(a, b) {
+		const [first, second] = await Promise.all([host.lookup(a), host.lookup(b)]);
+		return first + "," + second;
+	}
+
+	async function lookupMissing() {
+		try {
+			return await host.lookup(-1);
+		} catch (e) {
+			return "caught: " + e.message;
+		}
+	}"#;
+
+	let mut script = Script::builder()
+		.with_async_host_fn("lookup", |args| async move {
+			let id = args[0].as_i64().unwrap_or_default();
+			if id < 0 {
+				return Err(AnyError::msg("no such id"));
+			}
+
+			// Completes on another thread, waking up the script's event loop
+			let (sender, receiver) = oneshot::channel();
+			thread::spawn(move || {
+				thread::sleep(Duration::from_millis(10));
+				let _ = sender.send(format!("item{id}"));
+			});
+
+			Ok(JsValue::from(receiver.await?))
+		})
+		.build_from_string(src)
+		.expect("Initialization succeeds");
+
+	let result: String = script.call("lookupBoth", (1, 2)).unwrap();
+	assert_eq!(result, "item1,item2");
+
+	let result: String = script.call("lookupMissing", ()).unwrap();
+	assert_eq!(result, "caught: no such id");
+}
+
+#[test]
+fn call_async_host_fn_absent() {
+	let src = r#"
+	async function callMissing() {
+		try {
+			return await Deno.core.opAsync("op_host_call_async", "lookup", []);
+		} catch (e) {
+			return "caught: " + e.message;
+		}
+	}"#;
+
+	let mut script = Script::from_string(src).expect("Initialization succeeds");
+
+	let result: String = script.call("callMissing", ()).unwrap();
+	assert_eq!(result, "caught: no host function `lookup`");
+}
+
+#[test]
+fn call_host_object_absent() {
+	let src = r#"
+	function callMissing() {
+		try {
+			return Deno.core.ops.op_host_call("inventory", "list", []);
+		} catch (e) {
+			return "caught: " + e.message;
+		}
+	}"#;
+
+	let mut script = Script::from_string(src).expect("Initialization succeeds");
+
+	let result: String = script.call("callMissing", ()).unwrap();
+	assert_eq!(result, "caught: no host object `inventory`");
+
+	// Not a host panic, so the script stays healthy
+	assert!(script.is_healthy());
+}
+
+#[test]
+fn call_async_fn_typed() {
+	let src = r#"
+	async function greetAll(names) {
+		const greetings = await Promise.all(names.map(name => host.greet(name, 2)));
+		return greetings.join(" ");
+	}
+
+	async function greetInvalid() {
+		try {
+			return await host.greet(42);
+		} catch (e) {
+			return "rejected";
+		}
+	}"#;
+
+	let mut script = Script::builder()
+		.with_async_fn("greet", |(name, times): (String, usize)| async move {
+			Ok(format!("hi {name}").repeat(times))
+		})
+		.build_from_string(src)
+		.expect("Initialization succeeds");
+
+	let result: String = script.call("greetAll", (vec!["a", "b"],)).unwrap();
+	assert_eq!(result, "hi ahi a hi bhi b");
+
+	let result: String = script.call("greetInvalid", ()).unwrap();
+	assert_eq!(result, "rejected");
+}
+
+#[test]
+fn call_output_sink() {
+	use std::io::{self, Write};
+	use std::sync::{Arc, Mutex};
+
+	#[derive(Clone, Default)]
+	struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+	impl Write for SharedBuffer {
+		fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+			self.0.lock().unwrap().extend_from_slice(buf);
+			Ok(buf.len())
+		}
+
+		fn flush(&mut self) -> io::Result<()> {
+			Ok(())
+		}
+	}
+
+	let src = r#"
+	function report(rows) {
+		host.write("id,value\n");
+		for (let i = 0; i < rows; i++) {
+			host.write(`${i},${i * i}\n`);
+		}
+		host.write(new TextEncoderLike().encode("end"));
+		return rows;
+	}
+
+	function writeInvalid() {
+		try {
+			host.write(42);
+		} catch (e) {
+			return e instanceof TypeError;
+		}
+		return false;
+	}
+
+	// Minimal ASCII encoder, TextEncoder is only available with web APIs
+	class TextEncoderLike {
+		encode(s) { return Uint8Array.from(s, c => c.charCodeAt(0)); }
+	}"#;
+
+	let buffer = SharedBuffer::default();
+	let mut script = Script::builder()
+		.with_output_sink(buffer.clone())
+		.build_from_string(src)
+		.expect("Initialization succeeds");
+
+	let rows: u32 = script.call("report", (3,)).unwrap();
+	assert_eq!(rows, 3);
+
+	let written = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+	assert_eq!(written, "id,value\n0,0\n1,1\n2,4\nend");
+
+	let invalid: bool = script.call("writeInvalid", ()).unwrap();
+	assert!(invalid);
+}
+
+#[test]
+fn call_output_sink_absent() {
+	let src = r#"
+	function writeStr() { Deno.core.ops.op_sink_write_str("data"); }
+	function writeBytes() { Deno.core.ops.op_sink_write(new Uint8Array([1, 2])); }"#;
+
+	let mut script = Script::from_string(src).expect("Initialization succeeds");
+
+	for fn_name in ["writeStr", "writeBytes"] {
+		let err = script.call::<_, ()>(fn_name, ()).unwrap_err();
+		assert!(
+			err.to_string().contains("no output sink"),
+			"{fn_name}: {err}"
+		);
+	}
+
+	// Not a host panic, so the script stays healthy
+	assert!(script.is_healthy());
+}
+
+#[test]
+fn call_granted_capabilities() {
+	let registry = CapabilityRegistry::new()
+		.with_capability("inventory", || Inventory { items: Vec::new() })
+		.with_capability("admin", || Inventory { items: Vec::new() });
+
+	let src = r#"
+	function use(name) {
+		try {
+			const capability = host.require(name);
+			capability.add("item");
+			return "granted";
+		} catch (e) {
+			return e.name;
+		}
+	}
+
+	function isGlobal() {
+		return typeof host.inventory;
+	}"#;
+
+	let mut script = Script::builder()
+		.with_capabilities(&registry, &["inventory"])
+		.build_from_string(src)
+		.expect("Initialization succeeds");
+
+	let result: String = script.call("use", ("inventory",)).unwrap();
+	assert_eq!(result, "granted");
+
+	let result: String = script.call("use", ("admin",)).unwrap();
+	assert_eq!(result, "CapabilityError");
+
+	let result: String = script.call("use", ("unknown",)).unwrap();
+	assert_eq!(result, "CapabilityError");
+
+	// Capabilities are not exposed as regular host objects
+	let result: String = script.call("isGlobal", ()).unwrap();
+	assert_eq!(result, "undefined");
+}
+
+#[test]
+fn audit_log_blocked_operations() {
+	let js_code = r#"
+	function useCapability(name) {
+		try { host.require(name); return "granted"; } catch (e) { return e.name; }
+	}
+	async function load() {
+		try { await import("./plugin.js"); return "loaded"; } catch (e) { return "denied"; }
+	}"#;
+
+	let registry =
+		CapabilityRegistry::new().with_capability("inventory", || Inventory { items: Vec::new() });
+	let mut script = Script::builder()
+		.with_imports(ImportPolicy::Deny)
+		.with_capabilities(&registry, &[])
+		.build_from_string(js_code)
+		.expect("Initialization succeeds");
+
+	assert!(script.take_audit_log().is_empty());
+
+	let result: String = script.call("useCapability", ("inventory",)).unwrap();
+	assert_eq!(result, "CapabilityError");
+	let result: String = script.call("load", ()).unwrap();
+	assert_eq!(result, "denied");
+
+	let events = script.take_audit_log();
+	assert_eq!(events.len(), 2);
+
+	assert_eq!(events[0].operation, BlockedOperation::Capability);
+	assert_eq!(events[0].target, "inventory");
+	assert_eq!(events[0].origin.as_deref(), Some("sandboxed.js"));
+	assert!(events[0].stack[0].starts_with("at useCapability "));
+
+	assert_eq!(events[1].operation, BlockedOperation::DynamicImport);
+	assert_eq!(events[1].target, "./plugin.js");
+	assert!(events[1].stack.is_empty());
+
+	// Events are only returned once
+	assert!(script.take_audit_log().is_empty());
+}
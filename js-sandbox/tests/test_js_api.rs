@@ -1,6 +1,31 @@
 // Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
 
-use js_sandbox::{js_api, JsResult, Script};
+#![cfg(feature = "v8")]
+
+use js_sandbox::{js_api, js_fn, ApiMismatch, JsError, JsResult, JsValue, Script, ScriptEngine};
+
+#[js_api(register = "tests/shapes.js")]
+trait Shapes {
+	fn area(&mut self, width: f64, height: f64) -> f64;
+	fn perimeter(&self, width: f64, height: f64) -> JsResult<f64>;
+}
+
+#[js_api]
+trait StyleApi {
+	#[allow(clippy::too_many_arguments)]
+	fn format(
+		&mut self,
+		text: &str,
+		bold: bool,
+		italic: bool,
+		size: u32,
+		color: &str,
+		margins: &[i32],
+		prefix: Option<&str>,
+	) -> String;
+
+	fn sum(&self, a: i32, b: i32, c: i32, d: i32, e: i32, f: i32) -> JsResult<i32>;
+}
 
 #[js_api]
 trait TripleApi {
@@ -13,6 +38,209 @@ trait SaveLoadApi {
 	fn load(&mut self) -> String;
 }
 
+#[js_api]
+trait CounterApi {
+	fn increment(&mut self) -> u32;
+	fn current(&self) -> u32;
+}
+
+#[js_api]
+trait EditorPlugin {
+	fn on_save(&mut self, file: &str) -> JsResult<String> {
+		Ok(format!("{file} saved"))
+	}
+
+	fn theme(&self) -> String {
+		"light".to_string()
+	}
+}
+
+#[js_api]
+trait DocumentApi {
+	/// Renders the document.
+	fn render(&mut self, title: &str, lines: Vec<String>, width: Option<u32>) -> JsResult<String>;
+	fn reset(&mut self);
+}
+
+#[test]
+fn test_ts_declarations() {
+	let expected = "\
+/**
+ * Renders the document.
+ */
+declare function render(title: string, lines: string[], width: number | null): string | Promise<string>;
+declare function reset(): void | Promise<void>;
+";
+	assert_eq!(<DocumentApi>::TS_DECLARATIONS, expected);
+
+	let code = "function render(title, lines, width) { return [title, ...lines].join('\\n'); } function reset() {}";
+	let mut script = Script::from_string(code).unwrap();
+	let mut api = script.bind_api::<DocumentApi>();
+	assert_eq!(api.render("T", vec!["a".into()], None).unwrap(), "T\na");
+	api.reset();
+	assert_eq!(
+		<TripleApi>::TS_DECLARATIONS,
+		"declare function triple(a: number): number | Promise<number>;\n"
+	);
+}
+
+#[test]
+fn test_check_api() {
+	let code = r#"
+		function save(value, extra) {}
+		const load = 42;
+	"#;
+	let script = Script::from_string(code).unwrap();
+
+	let mismatches = script.check_api::<SaveLoadApi>().unwrap();
+	assert_eq!(
+		mismatches,
+		vec![
+			ApiMismatch::Arity {
+				function: "save".to_string(),
+				expected: 1,
+				actual: 2
+			},
+			ApiMismatch::NotAFunction {
+				function: "load".to_string()
+			},
+		]
+	);
+
+	// Functions with a default implementation are optional
+	let script = Script::from_string("function theme() { return 'dark'; }").unwrap();
+	assert_eq!(script.check_api::<EditorPlugin>().unwrap(), vec![]);
+	let script = Script::from_string("").unwrap();
+	assert_eq!(
+		script.check_api::<TripleApi>().unwrap(),
+		vec![ApiMismatch::Missing {
+			function: "triple".to_string()
+		}]
+	);
+}
+
+#[test]
+fn test_default_fallback() {
+	let mut script = Script::from_string("function theme() { return 'dark'; }").unwrap();
+	let mut api = script.bind_api::<EditorPlugin>();
+
+	// Defined in JS
+	assert_eq!(api.theme(), "dark");
+
+	// Missing in JS, falls back to the Rust implementation
+	assert_eq!(api.on_save("notes.txt").unwrap(), "notes.txt saved");
+}
+
+#[test]
+fn test_shared_ref() {
+	let code = r#"
+		let count = 0;
+		function increment() { return ++count; }
+		function current() { return count; }
+	"#;
+
+	let mut script = Script::from_string(code).unwrap();
+	let mut api = script.bind_api::<CounterApi>();
+	api.increment();
+	api.increment();
+
+	let read = |api: &CounterApi| api.current();
+	assert_eq!(read(&api), 2);
+}
+
+#[js_api]
+trait CollectionApi {
+	fn sum(&mut self, values: &[i32]) -> i32;
+	fn join(&mut self, separator: &str, words: Vec<String>, suffix: Option<&str>) -> String;
+}
+
+#[test]
+fn test_collection_params() {
+	let code = r#"
+		function sum(values) { return values.reduce((a, b) => a + b, 0); }
+		function join(separator, words, suffix) { return words.join(separator) + (suffix ?? ""); }
+	"#;
+
+	let mut script = Script::from_string(code).unwrap();
+	let mut api = script.bind_api::<CollectionApi>();
+
+	let values = vec![1, 2, 3];
+	assert_eq!(api.sum(&values), 6);
+	assert_eq!(api.sum(&[]), 0);
+
+	let words = vec!["a".to_string(), "b".to_string()];
+	assert_eq!(api.join("-", words, Some("!")), "a-b!");
+}
+
+/// Engine that answers every call with the function name and arguments.
+struct EchoEngine;
+
+impl ScriptEngine for EchoEngine {
+	fn call_values(&mut self, fn_name: &str, args: Vec<JsValue>) -> Result<JsValue, JsError> {
+		self.call_values_ref(fn_name, args)
+	}
+
+	fn call_values_ref(&self, fn_name: &str, args: Vec<JsValue>) -> Result<JsValue, JsError> {
+		let args: Vec<String> = args.iter().map(JsValue::to_string).collect();
+		Ok(JsValue::from(format!("{fn_name}({})", args.join(","))))
+	}
+
+	fn has_function(&self, _fn_name: &str) -> Result<bool, JsError> {
+		Ok(true)
+	}
+}
+
+#[test]
+fn test_custom_engine() {
+	let mut engine = EchoEngine;
+	let mut api = CollectionApi::new(&mut engine);
+
+	assert_eq!(
+		api.join(", ", vec!["a".into()], None),
+		r#"join(", ",["a"],null)"#
+	);
+}
+
+#[test]
+fn test_register() {
+	let mut script = Script::from_string("function area() { return 'global'; }").unwrap();
+
+	{
+		let mut shapes: Shapes = script.bind_api();
+		assert_eq!(shapes.area(2.0, 3.0), 6.0);
+		assert_eq!(shapes.perimeter(2.0, 3.0).unwrap(), 10.0);
+	}
+
+	// Binding again reuses the namespace
+	let mut shapes = Shapes::register(&mut script).unwrap();
+	assert_eq!(shapes.area(1.0, 4.0), 4.0);
+
+	assert_eq!(script.list_namespaces().collect::<Vec<_>>(), vec!["Shapes"]);
+	assert!(script.check_api::<Shapes>().unwrap().is_empty());
+
+	let global: String = script.call("area", ()).unwrap();
+	assert_eq!(global, "global");
+}
+
+#[test]
+fn test_many_params() {
+	let code = r#"
+		function format(text, bold, italic, size, color, margins, prefix) {
+			return `${prefix ?? ""}${text}|${bold}|${italic}|${size}|${color}|${margins.join(",")}`;
+		}
+		function sum(...values) { return values.length === 6 ? values.reduce((a, b) => a + b) : -1; }
+	"#;
+
+	let mut script = Script::from_string(code).unwrap();
+	let mut api: StyleApi = script.bind_api();
+
+	assert_eq!(
+		api.format("hi", true, false, 12, "red", &[1, 2], Some("> ")),
+		"> hi|true|false|12|red|1,2"
+	);
+	assert_eq!(api.sum(1, 2, 3, 4, 5, 6).unwrap(), 21);
+}
+
 #[test]
 fn test_stateless() {
 	let code = r#"
@@ -46,3 +274,27 @@ fn test_stateful() {
 		assert_eq!(loaded.as_str(), "secret");
 	}
 }
+
+js_fn!(fn word_count(text: &str) -> usize = "(text) => text.split(/\\s+/).filter(Boolean).length");
+
+js_fn!(
+	/// Counts calls, to check that state persists.
+	fn next_id() -> JsResult<u32> = r#"
+		function nextId() {
+			globalThis.lastId = (globalThis.lastId ?? 0) + 1;
+			return globalThis.lastId;
+		};"#
+);
+
+js_fn!(fn broken(a: i32) -> JsResult<i32> = "function (a) {");
+
+#[test]
+fn test_js_fn() {
+	assert_eq!(word_count("one two  three"), 3);
+	assert_eq!(word_count(""), 0);
+
+	assert_eq!(next_id().unwrap(), 1);
+	assert_eq!(next_id().unwrap(), 2);
+
+	assert!(matches!(broken(1), Err(JsError::Runtime(_))));
+}
@@ -0,0 +1,284 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+#![cfg(feature = "v8")]
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use js_sandbox::{
+	js_host_object, FatalCondition, JsError, SandboxManager, Script, SupervisedScript, TenantLimits,
+};
+
+struct Checker;
+
+#[js_host_object]
+impl Checker {
+	pub fn check(&self, ok: bool) -> bool {
+		assert!(ok, "check failed");
+		ok
+	}
+}
+
+#[test]
+fn tenants_are_isolated() {
+	let src = "
+		var count = 0;
+		function inc() { return ++count; }";
+
+	let mut manager = SandboxManager::new();
+	manager
+		.add_tenant("a", src, TenantLimits::default())
+		.unwrap();
+	manager
+		.add_tenant("b", src, TenantLimits::default())
+		.unwrap();
+
+	let _: i32 = manager.call("a", "inc", ()).unwrap();
+	let a: i32 = manager.call("a", "inc", ()).unwrap();
+	let b: i32 = manager.call("b", "inc", ()).unwrap();
+
+	assert_eq!((a, b), (2, 1));
+	assert!(manager
+		.add_tenant("a", src, TenantLimits::default())
+		.is_err());
+
+	let result: Result<i32, JsError> = manager.call("c", "inc", ());
+	assert!(result.is_err(), "Unknown tenant");
+}
+
+#[test]
+fn call_rate_limited() {
+	let limits = TenantLimits {
+		call_rate: Some((2, Duration::from_secs(60))),
+		..Default::default()
+	};
+
+	let mut manager = SandboxManager::new();
+	manager
+		.add_tenant("t", "function f() { return 1; }", limits)
+		.unwrap();
+
+	let _: i32 = manager.call("t", "f", ()).unwrap();
+	let _: i32 = manager.call("t", "f", ()).unwrap();
+
+	let result: Result<i32, JsError> = manager.call("t", "f", ());
+	assert!(result
+		.unwrap_err()
+		.to_string()
+		.contains("call rate exceeded"));
+}
+
+#[test]
+fn recycle_after_timeout() {
+	let src = "
+		var count = 0;
+		function inc() { return ++count; }
+		function run_forever() { for(;;){} }";
+
+	let limits = TenantLimits {
+		timeout: Some(Duration::from_millis(100)),
+		..Default::default()
+	};
+
+	let mut manager = SandboxManager::new();
+	manager.add_tenant("t", src, limits).unwrap();
+
+	let count: i32 = manager.call("t", "inc", ()).unwrap();
+	assert_eq!(count, 1);
+
+	let result: Result<(), JsError> = manager.call("t", "run_forever", ());
+	assert!(result.is_err());
+
+	// Fresh state after recycling
+	let count: i32 = manager.call("t", "inc", ()).unwrap();
+	assert_eq!(count, 1);
+}
+
+#[test]
+fn recycle_failure_keeps_call_error() {
+	// Initialization only succeeds before the deadline, so recreating the script fails afterwards
+	let deadline = SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.unwrap()
+		.as_millis()
+		+ 200;
+	let src = format!(
+		"if (Date.now() > {deadline}) throw new Error('expired');
+		function ping() {{ return 1; }}
+		function run_forever() {{ for(;;){{}} }}"
+	);
+
+	let limits = TenantLimits {
+		timeout: Some(Duration::from_millis(100)),
+		..Default::default()
+	};
+
+	let mut manager = SandboxManager::new();
+	manager.add_tenant("t", &src, limits).unwrap();
+	assert!(manager.recycle_error("t").is_none());
+	thread::sleep(Duration::from_millis(300));
+
+	let result: Result<(), JsError> = manager.call("t", "run_forever", ());
+	assert!(matches!(result, Err(JsError::Timeout { .. })), "{result:?}");
+
+	let recycle_error = manager.recycle_error("t").expect("recreation failed");
+	assert!(
+		recycle_error.to_string().contains("expired"),
+		"{recycle_error}"
+	);
+
+	// The previous script stays in place
+	let result: i32 = manager.call("t", "ping", ()).unwrap();
+	assert_eq!(result, 1);
+}
+
+#[test]
+fn recycle_after_heap_limit() {
+	let src = "
+		function grow() { const a = []; for(;;) { a.push(new Array(1000).fill('x')); } }
+		function ok() { return 'ok'; }";
+
+	let limits = TenantLimits {
+		max_heap_size: Some(16 * 1024 * 1024),
+		..Default::default()
+	};
+
+	let mut manager = SandboxManager::new();
+	manager.add_tenant("t", src, limits).unwrap();
+
+	let result: Result<(), JsError> = manager.call("t", "grow", ());
+	assert!(result.is_err());
+
+	let result: String = manager.call("t", "ok", ()).unwrap();
+	assert_eq!(result, "ok");
+}
+
+#[test]
+fn evict_idle() {
+	let mut manager = SandboxManager::new();
+	manager
+		.add_tenant("old", "function f() {}", TenantLimits::default())
+		.unwrap();
+
+	thread::sleep(Duration::from_millis(50));
+	manager
+		.add_tenant("new", "function f() {}", TenantLimits::default())
+		.unwrap();
+
+	let evicted = manager.evict_idle(Duration::from_millis(40));
+
+	assert_eq!(evicted, vec!["old".to_string()]);
+	assert!(!manager.contains_tenant("old"));
+	assert!(manager.contains_tenant("new"));
+}
+
+#[test]
+fn usage_survives_recycling() {
+	let src = "
+		function echo(s) { return s; }
+		function run_forever() { for(;;){} }";
+
+	let limits = TenantLimits {
+		timeout: Some(Duration::from_millis(50)),
+		..Default::default()
+	};
+
+	let mut manager = SandboxManager::new();
+	manager.add_tenant("t", src, limits).unwrap();
+
+	let _: String = manager.call("t", "echo", ("abc",)).unwrap();
+	let _: Result<(), JsError> = manager.call("t", "run_forever", ());
+	let _: String = manager.call("t", "echo", ("abc",)).unwrap();
+
+	let usage = manager.usage("t").unwrap();
+	assert_eq!(usage.calls, 3);
+	assert_eq!(usage.failed_calls, 1);
+	assert!(usage.wall_time >= Duration::from_millis(50));
+
+	let reports = manager.usage_reports();
+	assert_eq!(reports.get("t"), Some(&usage));
+}
+
+#[test]
+fn recycle_by_policy() {
+	let src = "
+		var count = 0;
+		function inc() { return ++count; }";
+
+	let mut manager = SandboxManager::new();
+	let limits = TenantLimits {
+		recycle_after_calls: Some(3),
+		..Default::default()
+	};
+	manager.add_tenant("calls", src, limits).unwrap();
+
+	let counts: Vec<i32> = (0..7)
+		.map(|_| manager.call("calls", "inc", ()).unwrap())
+		.collect();
+	assert_eq!(counts, [1, 2, 3, 1, 2, 3, 1]);
+	assert_eq!(manager.usage("calls").unwrap().calls, 7);
+
+	// Any heap in use exceeds the limit, so every call starts fresh
+	let limits = TenantLimits {
+		recycle_heap_size: Some(1),
+		..Default::default()
+	};
+	manager.add_tenant("heap", src, limits).unwrap();
+
+	for _ in 0..3 {
+		let count: i32 = manager.call("heap", "inc", ()).unwrap();
+		assert_eq!(count, 1);
+	}
+
+	// Scripts with calls in flight are drained first
+	let script = manager.script_mut("calls").unwrap();
+	let call = script.start_call("inc", ()).unwrap();
+	let count: i32 = manager.call("calls", "inc", ()).unwrap();
+	assert_eq!(count, 3);
+	let count: i32 = manager.call("calls", "inc", ()).unwrap();
+	assert_eq!(count, 4);
+
+	let script = manager.script_mut("calls").unwrap();
+	assert_eq!(script.finish_call::<i32>(call).unwrap(), 2);
+	let count: i32 = manager.call("calls", "inc", ()).unwrap();
+	assert_eq!(count, 5);
+	let count: i32 = manager.call("calls", "inc", ()).unwrap();
+	assert_eq!(count, 1);
+}
+
+#[test]
+fn supervised_script_recreated() {
+	let src = "
+		var count = 0;
+		function inc() { return ++count; }
+		function check(ok) { return host.checker.check(ok); }";
+
+	let mut script = SupervisedScript::new(move || {
+		Script::builder()
+			.with_host_object("checker", Checker)
+			.build_from_string(src)
+	})
+	.unwrap();
+
+	let incidents = Rc::new(RefCell::new(Vec::new()));
+	let recorded = incidents.clone();
+	script.on_recreate(move |condition, result| {
+		recorded.borrow_mut().push((condition, result.is_ok()));
+	});
+
+	let _: i32 = script.call("inc", ()).unwrap();
+	let result: Result<bool, JsError> = script.call("check", (false,));
+	assert!(result.is_err());
+	assert!(!script.is_healthy());
+	assert_eq!(script.recreations(), 0);
+
+	// Recreated on next use, with fresh state
+	let count: i32 = script.call("inc", ()).unwrap();
+	assert_eq!(count, 1);
+	assert!(script.is_healthy());
+	assert_eq!(script.recreations(), 1);
+	assert_eq!(*incidents.borrow(), [(FatalCondition::HostPanic, true)]);
+	assert_eq!(script.usage().calls, 3);
+}
@@ -0,0 +1,114 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+#![cfg(feature = "v8")]
+
+use deno_core::futures::executor::block_on;
+use serde_json::json;
+
+use js_sandbox::{js_api, JsError, JsResult, JsValue, MockCall, MockScript, Script, ScriptEngine};
+
+#[js_api]
+trait ShopApi {
+	fn price(&mut self, item: &str, quantity: u32) -> JsResult<f64>;
+
+	fn label(&mut self, item: &str) -> String {
+		item.to_uppercase()
+	}
+}
+
+#[test]
+fn mock_returning() {
+	let mut script = MockScript::new().returning("price", 2.5);
+
+	let mut api = ShopApi::new(&mut script);
+	assert_eq!(api.price("apple", 4).unwrap(), 2.5);
+	assert_eq!(api.price("pear", 1).unwrap(), 2.5);
+
+	assert_eq!(
+		script.calls(),
+		vec![
+			MockCall {
+				fn_name: "price".to_string(),
+				args: vec![json!("apple"), json!(4)],
+			},
+			MockCall {
+				fn_name: "price".to_string(),
+				args: vec![json!("pear"), json!(1)],
+			},
+		]
+	);
+
+	script.clear_calls();
+	assert!(script.calls().is_empty());
+}
+
+#[test]
+fn mock_responding() {
+	let mut script = MockScript::new().responding("price", |args| {
+		let quantity = args[1].as_f64().unwrap();
+		Ok(JsValue::from(quantity * 1.5))
+	});
+
+	let mut api = ShopApi::new(&mut script);
+	assert_eq!(api.price("apple", 4).unwrap(), 6.0);
+}
+
+#[test]
+fn mock_errors() {
+	let mut script = MockScript::new().throwing("price", "out of stock");
+
+	let mut api = ShopApi::new(&mut script);
+	let err = api.price("apple", 1).unwrap_err();
+	assert!(err.to_string().contains("out of stock"), "{err}");
+
+	// Undefined function, without default method
+	let err = script.call::<_, f64>("tax", (1.0,)).unwrap_err();
+	assert!(matches!(err, JsError::Runtime(_)), "{err:?}");
+
+	// Wrong result type
+	let mut script = MockScript::new().returning("price", "free");
+	let err = ShopApi::new(&mut script).price("apple", 1).unwrap_err();
+	assert!(matches!(err, JsError::Json(_)), "{err:?}");
+}
+
+#[test]
+fn mock_default_method() {
+	let mut script = MockScript::new();
+	assert!(!script.has_function("label").unwrap());
+	assert_eq!(ShopApi::new(&mut script).label("apple"), "APPLE");
+
+	let mut script = MockScript::new().returning("label", "Apple");
+	assert!(script.has_function("label").unwrap());
+	assert_eq!(ShopApi::new(&mut script).label("apple"), "Apple");
+}
+
+#[test]
+fn mock_call_async() {
+	let mut script = MockScript::new().returning("price", 3.0);
+
+	let price: f64 = block_on(script.call_async("price", ("apple", 2))).unwrap();
+	assert_eq!(price, 3.0);
+	assert_eq!(script.calls().len(), 1);
+}
+
+#[test]
+fn dyn_engines() {
+	let script =
+		Script::from_string("function price(item, quantity) { return quantity * 2; }").unwrap();
+	let mock = MockScript::new().returning("price", 1.0);
+
+	let mut engines: Vec<Box<dyn ScriptEngine>> = vec![Box::new(script), Box::new(mock)];
+
+	let prices: Vec<f64> = engines
+		.iter_mut()
+		.map(|engine| ShopApi::new(engine).price("apple", 3).unwrap())
+		.collect();
+	assert_eq!(prices, vec![6.0, 1.0]);
+
+	let engine: &mut dyn ScriptEngine = engines[0].as_mut();
+	let result = engine
+		.call_values("price", vec![json!("pear"), json!(5)])
+		.unwrap();
+	assert_eq!(result, json!(10));
+	assert!(engine.has_function("price").unwrap());
+}
@@ -0,0 +1,256 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+#![cfg(feature = "v8")]
+
+use js_sandbox::{
+	BlockedOperation, ImportPolicy, ImportType, JsError, JsValue, ModuleExport, Script,
+};
+
+#[test]
+fn module_exports() {
+	let src = r#"
+	const config = await Promise.resolve({ name: "plugin" });
+
+	export default function run() {}
+	export async function init() {}
+	export class Handler {}
+	export const name = config.name;
+	"#;
+
+	let script = Script::builder()
+		.as_module()
+		.build_from_string(src)
+		.expect("Initialization succeeds");
+
+	let exports = script.module_exports().unwrap();
+	let export = |name: &str, is_function| ModuleExport {
+		name: name.to_string(),
+		is_function,
+	};
+
+	assert_eq!(
+		exports,
+		[
+			export("Handler", true),
+			export("default", true),
+			export("init", true),
+			export("name", false),
+		]
+	);
+}
+
+#[test]
+fn module_exports_requires_module() {
+	let script = Script::from_string("function run() {}").expect("Initialization succeeds");
+
+	assert!(script.module_exports().is_err());
+}
+
+#[test]
+fn module_syntax_error() {
+	let result = Script::builder()
+		.as_module()
+		.build_from_string("export function broken( {");
+
+	assert!(result.is_err());
+}
+
+#[test]
+fn call_module_exports() {
+	let src = r#"
+	let calls = 0;
+
+	export default async function run(input) {
+		calls++;
+		return await Promise.resolve(input.toUpperCase());
+	}
+
+	export function callCount() { return calls; }
+	export const notFunction = 5;
+	"#;
+
+	let mut script = Script::builder()
+		.as_module()
+		.build_from_string(src)
+		.expect("Initialization succeeds");
+
+	let result: String = script.call_export("default", ("plugin",)).unwrap();
+	assert_eq!(result, "PLUGIN");
+
+	let count: u32 = script.call_export("callCount", ()).unwrap();
+	assert_eq!(count, 1);
+
+	// Module-scoped declarations are not globals
+	let result: Result<u32, JsError> = script.call("callCount", ());
+	assert!(result.is_err());
+
+	let result: Result<u32, JsError> = script.call_export("notFunction", ());
+	let err = result.expect_err("Non-function export is not callable");
+	assert!(
+		err.to_string().contains("notFunction is not a function"),
+		"{err}"
+	);
+
+	let result: Result<u32, JsError> = script.call_export("missing", ());
+	assert!(result.is_err());
+}
+
+#[test]
+fn call_export_non_identifier() {
+	let src = r#"
+	const render = (page) => `<${page}>`;
+	export { render as "render-page" };
+	"#;
+
+	let mut script = Script::builder()
+		.as_module()
+		.build_from_string(src)
+		.expect("Initialization succeeds");
+
+	let result: String = script.call_export("render-page", ("home",)).unwrap();
+	assert_eq!(result, "<home>");
+
+	// Names are looked up, never evaluated
+	let result: Result<u32, JsError> = script.call_export("x; globalThis.hacked = 1", ());
+	assert!(result.is_err());
+	let hacked = script.eval_json("typeof hacked").unwrap();
+	assert_eq!(hacked, JsValue::from("undefined"));
+}
+
+#[test]
+fn call_export_requires_module() {
+	let mut script =
+		Script::from_string("function run() { return 1; }").expect("Initialization succeeds");
+
+	let result: Result<u32, JsError> = script.call_export("run", ());
+	assert!(result.is_err());
+}
+
+#[test]
+fn import_types() {
+	let path = std::fs::canonicalize("tests/config.json").unwrap();
+	let src = format!(
+		r#"
+	import config from "file://{}" assert {{ type: "json" }};
+	export function version() {{ return config.version; }}
+	"#,
+		path.display()
+	);
+
+	let mut script = Script::builder()
+		.as_module()
+		.build_from_string(&src)
+		.expect("Initialization succeeds");
+
+	let result: i32 = script.call("version", ()).unwrap();
+	assert_eq!(result, 3);
+
+	let result = Script::builder()
+		.as_module()
+		.with_import_types(&[])
+		.build_from_string(&src);
+	assert!(result.is_err());
+
+	let mut script = Script::builder()
+		.with_import_types(&[])
+		.build_from_string(&format!(
+			r#"async function load() {{
+				try {{ await import("file://{}", {{ assert: {{ type: "json" }} }}); return "loaded"; }}
+				catch (e) {{ return "denied"; }}
+			}}"#,
+			path.display()
+		))
+		.expect("Initialization succeeds");
+
+	let result: String = script.call("load", ()).unwrap();
+	assert_eq!(result, "denied");
+
+	let events = script.take_audit_log();
+	assert_eq!(events.len(), 1);
+	assert_eq!(events[0].operation, BlockedOperation::ImportType);
+	assert!(events[0].target.ends_with("config.json"));
+}
+
+#[test]
+fn import_from_root_directory() {
+	let dir = std::env::temp_dir().join(format!("js-sandbox-root-{}", std::process::id()));
+	let root = dir.join("plugins");
+	std::fs::create_dir_all(root.join("lib")).unwrap();
+	std::fs::write(root.join("lib/base.js"), "export const base = 40;").unwrap();
+	std::fs::write(
+		root.join("lib/util.js"),
+		"import { base } from './base.js'; export function answer() { return base + 2; }",
+	)
+	.unwrap();
+	std::fs::write(dir.join("secret.js"), "export const secret = 'leaked';").unwrap();
+	#[cfg(unix)]
+	std::os::unix::fs::symlink(dir.join("secret.js"), root.join("link.js")).unwrap();
+
+	let src = r#"
+	import { answer } from "/lib/util.js";
+	export function run() { return answer(); }
+	export async function load(path) {
+		try { await import(path); return "loaded"; } catch (e) { return "denied"; }
+	}"#;
+
+	let mut script = Script::builder()
+		.as_module()
+		.with_imports(ImportPolicy::Directory(root))
+		.build_from_string(src)
+		.expect("Initialization succeeds");
+
+	let result: i32 = script.call("run", ()).unwrap();
+	assert_eq!(result, 42);
+	let result: String = script.call("load", ("./lib/base.js",)).unwrap();
+	assert_eq!(result, "loaded");
+
+	// Files outside the root cannot be reached
+	for path in [
+		"../secret.js",
+		"/../../secret.js",
+		"https://example.com/x.js",
+		"./link.js",
+	] {
+		let result: String = script.call("load", (path,)).unwrap();
+		assert_eq!(result, "denied", "{path}");
+	}
+
+	let events = script.take_audit_log();
+	assert!(events
+		.iter()
+		.all(|event| event.operation == BlockedOperation::DynamicImport));
+	assert!(events
+		.iter()
+		.any(|event| event.target == "https://example.com/x.js"));
+	#[cfg(unix)]
+	assert!(events
+		.iter()
+		.any(|event| event.target.ends_with("/link.js")));
+
+	std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn import_relative_to_file() {
+	let dir = std::env::temp_dir().join(format!("js-sandbox-relative-{}", std::process::id()));
+	let plugins = dir.join("plugins");
+	std::fs::create_dir_all(&plugins).unwrap();
+	std::fs::write(plugins.join("util.js"), "export const answer = 42;").unwrap();
+	std::fs::write(
+		plugins.join("x.mjs"),
+		"import { answer } from './util.js'; export function run() { return answer; }",
+	)
+	.unwrap();
+
+	// Imports are resolved against the file's directory, not against the file name alone
+	let mut script = Script::builder()
+		.as_module()
+		.with_imports(ImportPolicy::FileSystem)
+		.build_from_file(plugins.join("x.mjs"))
+		.expect("Initialization succeeds");
+	let result: i32 = script.call("run", ()).unwrap();
+	assert_eq!(result, 42);
+	assert!(script.is_healthy());
+
+	std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,162 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+#![cfg(feature = "v8")]
+
+use js_sandbox::{JsError, JsValue, Script};
+
+#[test]
+fn call_namespace() {
+	let src = "let calls = 0; function count() { return calls; }";
+	let mut script = Script::from_string(src).expect("Initialization succeeds");
+
+	let math = r#"
+	function add(a, b) { calls += 1; return a + b; }
+	async function mul(a, b) { calls += 1; return a * b; }
+	function fail() { throw new Error("math failed"); }"#;
+	script.add_script("math", math).unwrap();
+
+	let text = "function add(a, b) { return a + ' ' + b; }";
+	script.add_script("text", text).unwrap();
+
+	let result: i32 = script.call_namespace("math", "add", (1, 2)).unwrap();
+	assert_eq!(result, 3);
+	let result: i32 = script.call_namespace("math", "mul", (3, 4)).unwrap();
+	assert_eq!(result, 12);
+	let result: String = script.call_namespace("text", "add", ("a", "b")).unwrap();
+	assert_eq!(result, "a b");
+
+	// Namespaced functions share the script's globals, but do not leak their own declarations
+	let calls: u32 = script.call("count", ()).unwrap();
+	assert_eq!(calls, 2);
+	let result: Result<i32, JsError> = script.call("add", (1, 2));
+	assert!(matches!(result, Err(JsError::Runtime(_))));
+
+	let result: Result<(), JsError> = script.call_namespace("math", "fail", ());
+	assert!(matches!(result, Err(JsError::Runtime(e)) if e.to_string().contains("math failed")));
+
+	let result: Result<String, JsError> = script.call_namespace("math", "add", (1, 2));
+	assert!(matches!(result, Err(JsError::Json(_))));
+
+	let result: Result<i32, JsError> = script.call_namespace("missing", "add", (1, 2));
+	assert!(matches!(result, Err(JsError::Runtime(e)) if e.to_string().contains("does not exist")));
+}
+
+#[test]
+fn add_script_errors() {
+	let mut script = Script::from_string("").expect("Initialization succeeds");
+	script.add_script("plugin", "function run() {}").unwrap();
+
+	let cases = [
+		("plugin", "function run() {}"),
+		("bad name", "function run() {}"),
+		("other", "const run = () => 5;"),
+		("other", "function run() {"),
+		("other", "throw new Error('init failed'); function run() {}"),
+	];
+
+	for (namespace, source) in cases {
+		let result = script.add_script(namespace, source);
+		assert!(
+			matches!(result, Err(JsError::Runtime(_))),
+			"{namespace} {source:?}: {result:?}"
+		);
+	}
+
+	// Failed registrations leave no trace
+	let result: Result<(), JsError> = script.call_namespace("other", "run", ());
+	assert!(matches!(result, Err(JsError::Runtime(_))));
+}
+
+#[test]
+fn add_script_top_level_functions() {
+	let mut script = Script::from_string("").expect("Initialization succeeds");
+
+	let src = r#"
+	const helper = function named() { return 1; };
+	class Counter { increment() { return 2; } }
+	function outer() {
+		function inner() { return 3; }
+		return inner() + helper();
+	}
+	async function load() { return "loaded"; }
+	const brackets = /[({]/;
+	function* range(n) { for (let i = 0; i < n; i++) yield i; }
+	function sum(n) { return [...range(n)].reduce((a, b) => a + b, 0); }"#;
+	script.add_script("plugin", src).unwrap();
+
+	let result: i32 = script.call_namespace("plugin", "outer", ()).unwrap();
+	assert_eq!(result, 4);
+	let result: String = script.call_namespace("plugin", "load", ()).unwrap();
+	assert_eq!(result, "loaded");
+
+	// Declarations after regex literals and generators are found as well
+	let result: i32 = script.call_namespace("plugin", "sum", (4,)).unwrap();
+	assert_eq!(result, 6);
+	let result: Result<JsValue, JsError> = script.call_namespace("plugin", "range", (1,));
+	assert!(result.is_ok());
+
+	for fn_name in ["named", "increment", "inner", "helper", "Counter"] {
+		let result: Result<i32, JsError> = script.call_namespace("plugin", fn_name, ());
+		assert!(result.is_err(), "{fn_name} must not be exposed");
+	}
+}
+
+#[test]
+fn namespace_management() {
+	let mut script = Script::from_string("").expect("Initialization succeeds");
+	script
+		.add_script("b", "function version() { return 1; }")
+		.unwrap();
+	script
+		.add_script("a", "function version() { return 1; }")
+		.unwrap();
+	assert_eq!(script.list_namespaces().collect::<Vec<_>>(), ["a", "b"]);
+
+	script
+		.replace_namespace("a", "function version() { return 2; }")
+		.unwrap();
+	let version: u32 = script.call_namespace("a", "version", ()).unwrap();
+	assert_eq!(version, 2);
+
+	// Failed updates keep the previous version
+	let result = script.replace_namespace("a", "function version() {");
+	assert!(matches!(result, Err(JsError::Runtime(_))));
+	let version: u32 = script.call_namespace("a", "version", ()).unwrap();
+	assert_eq!(version, 2);
+
+	let result = script.replace_namespace("c", "function version() { return 1; }");
+	assert!(matches!(result, Err(JsError::Runtime(_))));
+
+	assert!(script.remove_namespace("b").unwrap());
+	assert!(!script.remove_namespace("b").unwrap());
+	assert_eq!(script.list_namespaces().collect::<Vec<_>>(), ["a"]);
+
+	let result: Result<u32, JsError> = script.call_namespace("b", "version", ());
+	assert!(matches!(result, Err(JsError::Runtime(_))));
+	let removed: bool = script
+		.eval_json("typeof __jsSandboxNamespaces.b === 'undefined'")
+		.unwrap()
+		.as_bool()
+		.unwrap();
+	assert!(removed);
+
+	// Removed namespaces can be added again
+	script
+		.add_script("b", "function version() { return 3; }")
+		.unwrap();
+	let version: u32 = script.call_namespace("b", "version", ()).unwrap();
+	assert_eq!(version, 3);
+}
+
+#[test]
+fn namespace_error_line() {
+	let mut script = Script::from_string("function main() {}").unwrap();
+
+	let source = "function ok() {}\nfunction fail() { throw new Error('failed'); }";
+	script.add_script("plugin", source).unwrap();
+
+	// Reported at the line within the plugin's source, not within the code wrapping it
+	let result: Result<(), JsError> = script.call_namespace("plugin", "fail", ());
+	let message = result.unwrap_err().to_string();
+	assert!(message.contains("sandboxed.js:2:"), "{message}");
+}
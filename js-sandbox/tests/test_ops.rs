@@ -0,0 +1,62 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+#![cfg(feature = "v8")]
+
+use std::borrow::Cow;
+
+use deno_core::{op, Extension, Op};
+
+use js_sandbox::{AnyError, Script};
+
+#[op]
+fn op_triple(a: i32) -> Result<i32, AnyError> {
+	Ok(3 * a)
+}
+
+#[op]
+async fn op_square_async(a: i32) -> Result<i32, AnyError> {
+	Ok(a * a)
+}
+
+#[test]
+fn call_custom_ops() {
+	let src = r#"
+	function triple(a) { return Deno.core.ops.op_triple(a); }
+	async function square(a) { return await Deno.core.opAsync("op_square_async", a); }
+	"#;
+
+	let mut script = Script::builder()
+		.with_ops([op_triple::DECL, op_square_async::DECL])
+		.build_from_string(src)
+		.expect("Initialization succeeds");
+
+	let result: i32 = script.call("triple", (7,)).unwrap();
+	assert_eq!(result, 21);
+
+	let result: i32 = script.call("square", (7,)).unwrap();
+	assert_eq!(result, 49);
+}
+
+#[op]
+fn op_greet(name: String) -> Result<String, AnyError> {
+	Ok(format!("Hello {name}"))
+}
+
+#[test]
+fn call_user_extension() {
+	let ext = Extension {
+		name: "greeter",
+		ops: Cow::Owned(vec![op_greet::DECL]),
+		..Default::default()
+	};
+
+	let src = "function greet(name) { return Deno.core.ops.op_greet(name); }";
+
+	let mut script = Script::builder()
+		.with_extensions([ext])
+		.build_from_string(src)
+		.expect("Initialization succeeds");
+
+	let result: String = script.call("greet", ("JS",)).unwrap();
+	assert_eq!(result, "Hello JS");
+}
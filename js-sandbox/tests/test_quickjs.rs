@@ -0,0 +1,104 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+#![cfg(feature = "quickjs")]
+
+use std::time::Duration;
+
+use serde::Serialize;
+
+use js_sandbox::quickjs::Script;
+use js_sandbox::JsError;
+
+#[derive(Serialize)]
+struct Person {
+	name: String,
+	age: u8,
+}
+
+#[test]
+fn call() {
+	let src = r#"
+	function toString(person) {
+		return "A person named " + person.name + " with age " + person.age;
+	}"#;
+
+	let mut script = Script::from_string(src).expect("Initialization succeeds");
+
+	let person = Person {
+		name: "Roger".to_string(),
+		age: 42,
+	};
+	let result: String = script.call("toString", (person,)).unwrap();
+
+	assert_eq!(result, "A person named Roger with age 42");
+}
+
+#[test]
+fn call_async() {
+	let src = "async function async_func(a) { return new Promise((resolve) => resolve(a)); }";
+	let mut script = Script::from_string(src).expect("Initialization succeeds");
+
+	let result: i32 = script.call("async_func", (3,)).unwrap();
+	assert_eq!(result, 3);
+}
+
+#[test]
+fn ctor_error_syntax() {
+	let script = Script::from_string("function triple(a) { return 3 *. a; }");
+	assert!(script.is_err());
+}
+
+#[test]
+fn call_error_timeout() {
+	let js_code = "
+		function run_forever() { for(;;){} }
+		function triple(a) { return 3 * a; }";
+
+	let mut script = Script::from_string(js_code)
+		.expect("Initialization succeeds")
+		.with_timeout(Duration::from_millis(100));
+
+	let result: Result<String, JsError> = script.call("run_forever", ());
+	assert!(matches!(result, Err(JsError::Timeout { .. })));
+
+	// Script remains usable
+	let result: i32 = script.call("triple", (7,)).unwrap();
+	assert_eq!(result, 21);
+}
+
+#[test]
+fn call_error_invalid_name() {
+	let js_code = "
+		var injected = false;
+		function wasInjected() { return injected; }";
+
+	let mut script = Script::from_string(js_code).expect("Initialization succeeds");
+
+	let result: Result<(), JsError> = script.call("injected = true; wasInjected", ());
+	assert!(result.is_err());
+
+	// Code passed as function name never runs
+	let result: bool = script.call("wasInjected", ()).unwrap();
+	assert!(!result);
+}
+
+#[test]
+fn call_error_async_job() {
+	let js_code = "
+		async function circular() { const a = {}; a.self = a; return a; }
+		function triple(a) { return 3 * a; }";
+
+	let mut script = Script::from_string(js_code)
+		.expect("Initialization succeeds")
+		.with_timeout(Duration::from_millis(1000));
+
+	// Converting the result throws inside a promise job; the exception is reported, not taken for a termination
+	let result: Result<String, JsError> = script.call("circular", ());
+	let error = result
+		.expect_err("circular result cannot be serialized")
+		.to_string();
+	assert!(error.contains("TypeError"), "unexpected error: {error}");
+
+	let result: i32 = script.call("triple", (7,)).unwrap();
+	assert_eq!(result, 21);
+}
@@ -1,13 +1,19 @@
 // Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
 
+#![cfg(feature = "v8")]
 #![allow(clippy::let_unit_value)]
 
+use std::cell::Cell;
 use std::collections::HashMap;
+use std::rc::Rc;
 use std::time::{Duration, Instant};
 
 use serde::{Deserialize, Serialize};
 
-use js_sandbox::{AnyError, JsError, Script};
+use js_sandbox::{
+	AnyError, CallOptions, JsError, JsValue, Policy, Preemption, Provenance, RetryOn, Script,
+	VirtualClock,
+};
 use util::expect_error;
 
 mod util;
@@ -130,6 +136,30 @@ fn call_void() -> Result<(), AnyError> {
 	Ok(())
 }
 
+#[test]
+fn call_console_formatting() -> Result<(), AnyError> {
+	let js_code = r#"
+	function debug() {
+		const obj = { id: 1, tags: ["a", "b"], nested: { deeper: { deepest: {} } }, map: new Map([["k", 2]]) };
+		obj.self = obj;
+
+		console.log("object:", obj, [1, [2, [3, [4]]]]);
+		console.info(null, undefined, 10n, Symbol("s"), () => {});
+		console.warn(new Error("warning"));
+		console.dir(obj, { depth: 0 });
+		console.table([{ a: 1, b: "x" }, { a: 2, c: [] }, 3]);
+		console.table({ first: { v: 1 }, second: { v: 2 } }, ["v"]);
+		console.table("not tabular");
+		return typeof console.error;
+	}"#;
+	let mut script = Script::from_string(js_code)?;
+
+	let result: String = script.call("debug", ())?;
+	assert_eq!(result, "function");
+
+	Ok(())
+}
+
 #[test]
 fn call_from_file() {
 	let mut script = Script::from_file("tests/hello.js").expect("File can be loaded");
@@ -147,6 +177,157 @@ fn call_from_file() {
 	assert_eq!(result, exp_result);
 }
 
+#[test]
+fn call_error_filename() {
+	let mut script = Script::from_file("tests/hello.js").expect("File can be loaded");
+	let result: Result<JsValue, JsError> = script.call("extract", (JsValue::Null,));
+	let message = result.unwrap_err().to_string();
+	assert!(message.contains("hello.js:"), "{message}");
+
+	let mut script = Script::builder()
+		.with_filename("plugin.js")
+		.build_from_string("function fail() { throw new Error('failed'); }")
+		.unwrap();
+	let result: Result<JsValue, JsError> = script.call("fail", ());
+	let message = result.unwrap_err().to_string();
+	assert!(message.contains("plugin.js:"), "{message}");
+	assert!(!message.contains("sandboxed.js"), "{message}");
+}
+
+#[test]
+fn call_from_file_verified() {
+	let file = std::env::temp_dir().join(format!("js-sandbox-verified-{}.js", std::process::id()));
+	std::fs::write(&file, "function triple(a) { return 3 * a; }").unwrap();
+	let sha256 = "ec46467599c5801bc3099b6f869f2d081c51038d07dfdd80b4afda3f6b86171b";
+
+	let mut script = Script::from_file_verified(&file, sha256).expect("File matches the hash");
+	let result: i32 = script.call("triple", (5,)).unwrap();
+	assert_eq!(result, 15);
+
+	// Tampered file is not executed
+	std::fs::write(&file, "function triple(a) { return 4 * a; }").unwrap();
+	let result = Script::from_file_verified(&file, sha256);
+	assert!(matches!(result, Err(JsError::IntegrityMismatch { .. })));
+
+	let result = Script::builder()
+		.with_module_verifier(|_location, code| {
+			if code.starts_with(b"// signed") {
+				Ok(())
+			} else {
+				Err(AnyError::msg("signature missing"))
+			}
+		})
+		.build_from_file(&file);
+	assert!(result.is_err());
+
+	std::fs::remove_file(file).unwrap();
+}
+
+#[test]
+fn call_from_included_file() {
+	let js_code: &'static str = js_sandbox::js_include!("tests/hello.js");
+	let mut script = Script::from_string(js_code).expect("Initialization succeeds");
+
+	let result: i32 = script.call("triple", (5,)).unwrap();
+	assert_eq!(result, 15);
+}
+
+#[test]
+fn call_large_string() {
+	let src = r#"
+		function describe(prefix, text, suffix) {
+			return [prefix, text.length, text.slice(0, 5), text.endsWith("\n"), suffix];
+		}"#;
+	let mut script = Script::from_string(src).expect("Initialization succeeds");
+
+	let seen_len = Rc::new(Cell::new(0));
+	let hook_len = seen_len.clone();
+	script.on_call(move |_, args| hook_len.set(args[1].as_str().unwrap().len()));
+
+	// Quotes, backslashes, non-ASCII and control characters must arrive unchanged
+	let text = "\"é\\ ✓\n".repeat(100_000);
+	let result: (String, usize, String, bool, Option<u8>) =
+		script.call("describe", ("<", &text, None::<u8>)).unwrap();
+	assert_eq!(
+		result,
+		("<".to_string(), 600_000, "\"é\\ ✓".to_string(), true, None)
+	);
+	assert_eq!(seen_len.get(), text.len());
+}
+
+#[test]
+fn call_binary_result() {
+	let src = r#"
+		function bytes() { return new Uint8Array([1, 2, 250]).buffer; }
+		function header(version, length) {
+			const view = new DataView(new ArrayBuffer(8), 2, 4);
+			view.setUint8(0, version);
+			view.setUint8(1, 0xff);
+			view.setUint16(2, length);
+			return view;
+		}
+		async function empty() { return new ArrayBuffer(0); }"#;
+	let mut script = Script::from_string(src).expect("Initialization succeeds");
+
+	let result: Vec<u8> = script.call("bytes", ()).unwrap();
+	assert_eq!(result, [1, 2, 250]);
+	let result: Vec<u8> = script.call("header", (3, 0x1234)).unwrap();
+	assert_eq!(result, [3, 0xff, 0x12, 0x34]);
+	let result: Vec<u8> = script.call("empty", ()).unwrap();
+	assert!(result.is_empty());
+}
+
+#[test]
+fn call_typed_array_result() {
+	let src = r#"
+		function samples(n) { return Float64Array.from({ length: n }, (_, i) => i / 2); }
+		function offsets() { return new Int32Array([-2147483648, 0, 7]).subarray(1); }
+		function ids() { return new BigUint64Array([2n ** 64n - 1n]); }
+		function pixels() { return new Uint8ClampedArray([300, -5, 128]); }"#;
+	let mut script = Script::from_string(src).expect("Initialization succeeds");
+
+	let result: Vec<f64> = script.call("samples", (100_000,)).unwrap();
+	assert_eq!(result.len(), 100_000);
+	assert_eq!(result[99_999], 49_999.5);
+
+	let result: Vec<i32> = script.call("offsets", ()).unwrap();
+	assert_eq!(result, [0, 7]);
+	let result: Vec<u64> = script.call("ids", ()).unwrap();
+	assert_eq!(result, [u64::MAX]);
+	let result: Vec<u8> = script.call("pixels", ()).unwrap();
+	assert_eq!(result, [255, 0, 128]);
+}
+
+#[test]
+fn call_serde_bytes() {
+	#[derive(Serialize, Deserialize, Debug, PartialEq)]
+	struct Blob {
+		name: String,
+		#[serde(with = "serde_bytes")]
+		data: Vec<u8>,
+	}
+
+	let src = r#"
+		function reverse(blob) {
+			if (!(blob.data instanceof Uint8Array)) throw new TypeError("expected Uint8Array");
+			return { name: blob.name, data: blob.data.slice().reverse() };
+		}"#;
+	let mut script = Script::from_string(src).expect("Initialization succeeds");
+
+	let blob = Blob {
+		name: "pixels".to_string(),
+		data: vec![1, 2, 3, 255],
+	};
+	let result: Blob = script.call("reverse", (blob,)).unwrap();
+	assert_eq!(
+		result,
+		Blob {
+			name: "pixels".to_string(),
+			data: vec![255, 3, 2, 1],
+		}
+	);
+}
+
 #[test]
 fn call_local_state() {
 	let src = "var i = 0;
@@ -200,6 +381,216 @@ fn call_error_inexistent_function() {
 	expect_error(result, "Inexistent function");
 }
 
+#[test]
+fn call_nested_path() {
+	let src = r#"
+		const utils = {
+			math: {
+				offset: 100,
+				add(a, b) { return a + b + this.offset; },
+			},
+		};"#;
+	let mut script = Script::from_string(src).expect("Initialization succeeds");
+
+	let result: i32 = script.call("utils.math.add", (1, 2)).unwrap();
+	assert_eq!(result, 103);
+
+	let result: Result<i32, JsError> = script.call("utils.math.sub", (1, 2));
+	expect_error(result, "Inexistent nested function");
+
+	for fn_name in [
+		"utils.math.add(1, 2); utils",
+		"utils..add",
+		"",
+		"1utils.add",
+	] {
+		let result: Result<i32, JsError> = script.call(fn_name, (1, 2));
+		assert!(
+			matches!(&result, Err(JsError::Runtime(e)) if e.to_string().contains("not a valid function name")),
+			"{fn_name:?} must be rejected: {result:?}"
+		);
+	}
+}
+
+#[test]
+fn call_resolves_function_each_time() {
+	let src = r#"
+		let handler = (x) => x + 1;
+		const limit = 5;
+		function replaceHandler() { handler = (x) => x * 10; }"#;
+	let mut script = Script::from_string(src).expect("Initialization succeeds");
+
+	let result: i32 = script.call("handler", (2,)).unwrap();
+	assert_eq!(result, 3);
+
+	script.call::<_, ()>("replaceHandler", ()).unwrap();
+	let result: i32 = script.call("handler", (2,)).unwrap();
+	assert_eq!(result, 20);
+
+	let result: Result<i32, JsError> = script.call("limit", ());
+	expect_error(result, "Non-function binding");
+}
+
+#[test]
+fn call_error_after_result() {
+	let src = r#"
+		async function returnThenFail(x) {
+			setTimeout(() => { throw new Error("late failure"); }, 0);
+			return x;
+		}
+		async function echo(x) { return x; }"#;
+	let mut script = Script::builder()
+		.with_clock(VirtualClock::new())
+		.build_from_string(src)
+		.expect("Initialization succeeds");
+
+	let result: Result<i32, JsError> = script.call("returnThenFail", (1,));
+	assert!(matches!(result, Err(JsError::Runtime(e)) if e.to_string().contains("late failure")));
+
+	// The result of the failed call must not leak into later calls
+	for i in 2..5 {
+		let result: i32 = script.call("echo", (i,)).unwrap();
+		assert_eq!(result, i);
+	}
+}
+
+#[test]
+fn concurrent_calls() {
+	let src = r#"
+		const waiting = {};
+		function wait(key) { return new Promise((resolve, reject) => waiting[key] = { resolve, reject }); }
+
+		async function fetchUser(id) { return { id, name: await wait("user") }; }
+		async function fetchScore() { return await wait("score") * 2; }
+		async function fetchFailing() { await wait("failing"); throw new Error("fetch failed"); }
+		function provide(key, value) { waiting[key].resolve(value); }
+		function identity(x) { return x; }"#;
+	let mut script = Script::from_string(src).expect("Initialization succeeds");
+
+	let user = script.start_call("fetchUser", (7,)).unwrap();
+	let score = script.start_call("fetchScore", ()).unwrap();
+	let failing = script.start_call("fetchFailing", ()).unwrap();
+	let sync = script.start_call("identity", ("sync",)).unwrap();
+	assert_ne!(user, score);
+
+	// Results are routed by ID, in any order
+	let result: Result<i32, JsError> = script.finish_call(score);
+	assert!(matches!(result, Err(JsError::PendingPromise { .. })));
+
+	let score = script.start_call("fetchScore", ()).unwrap();
+	let _: () = script.call("provide", ("score", 21)).unwrap();
+	let _: () = script.call("provide", ("failing", JsValue::Null)).unwrap();
+	let _: () = script.call("provide", ("user", "Ada")).unwrap();
+
+	let result: JsValue = script.finish_call(user).unwrap();
+	assert_eq!(result, serde_json::json!({ "id": 7, "name": "Ada" }));
+	let result: String = script.finish_call(sync).unwrap();
+	assert_eq!(result, "sync");
+	let result: Result<i32, JsError> = script.finish_call(failing);
+	assert!(matches!(result, Err(JsError::Runtime(e)) if e.to_string().contains("fetch failed")));
+	let result: i32 = script.finish_call(score).unwrap();
+	assert_eq!(result, 42);
+
+	// Each ID can be used once
+	let result: Result<String, JsError> = script.finish_call(user);
+	assert!(matches!(result, Err(JsError::Runtime(_))));
+}
+
+#[test]
+fn call_chunked() {
+	let src = r#"
+		let produced = 0, closed = false;
+		function* lines(count) {
+			try {
+				for (let i = 0; i < count; ++i) { produced++; yield `line ${i}\n`; }
+			} finally {
+				closed = true;
+			}
+		}
+		async function* pages() {
+			for (const page of [[1, 2], [3]]) { await null; yield new Uint8Array(page); }
+		}
+		function notIterable() { return "abc"; }
+		function getState() { return { produced, closed }; }"#;
+	let mut script = Script::from_string(src).expect("Initialization succeeds");
+
+	// Chunks are only produced on demand, and the generator is closed when the consumer stops
+	let mut consumed = 0;
+	let result = script.call_chunked("lines", (1_000_000,), |_: String| {
+		consumed += 1;
+		match consumed {
+			3 => Err(AnyError::msg("enough")),
+			_ => Ok(()),
+		}
+	});
+	assert!(matches!(result, Err(JsError::Runtime(e)) if e.to_string() == "enough"));
+	let state: JsValue = script.call("getState", ()).unwrap();
+	assert_eq!(state, serde_json::json!({ "produced": 3, "closed": true }));
+
+	let mut output = String::new();
+	script
+		.call_chunked("lines", (3,), |chunk: String| {
+			output += &chunk;
+			Ok(())
+		})
+		.unwrap();
+	assert_eq!(output, "line 0\nline 1\nline 2\n");
+
+	let mut pages = vec![];
+	script
+		.call_chunked("pages", (), |page: Vec<u8>| {
+			pages.push(page);
+			Ok(())
+		})
+		.unwrap();
+	assert_eq!(pages, [vec![1, 2], vec![3]]);
+
+	let result = script.call_chunked("notIterable", (), |_: JsValue| Ok(()));
+	expect_error(result, "non-iterable result");
+}
+
+#[test]
+fn redefine_function() {
+	let src = r#"
+		const step = 10;
+		let ticks = [];
+		function onTick(dt) { ticks.push(dt); return ticks; }
+		function unchanged() { return ticks.length; }"#;
+	let mut script = Script::from_string(src).expect("Initialization succeeds");
+
+	let _: Vec<i32> = script.call("onTick", (1,)).unwrap();
+	script
+		.redefine_function(
+			"onTick",
+			"function onTick(dt) { ticks.push(dt * step); return ticks; };",
+		)
+		.unwrap();
+
+	let ticks: Vec<i32> = script.call("onTick", (2,)).unwrap();
+	assert_eq!(ticks, [1, 20]);
+	let count: usize = script.call("unchanged", ()).unwrap();
+	assert_eq!(count, 2);
+
+	let cases = [
+		("missing", "function missing() {}"),
+		("step", "() => 0"),
+		("onTick", "42"),
+		("onTick", "function onTick() {"),
+		("onTick(); x", "() => 0"),
+	];
+	for (fn_name, source) in cases {
+		let result = script.redefine_function(fn_name, source);
+		assert!(
+			matches!(result, Err(JsError::Runtime(_))),
+			"{fn_name} {source:?}: {result:?}"
+		);
+	}
+
+	// Failed attempts leave the function in place
+	let ticks: Vec<i32> = script.call("onTick", (3,)).unwrap();
+	assert_eq!(ticks, [1, 20, 30]);
+}
+
 #[test]
 fn call_error_exception() {
 	let src = "function triple(a) { throw \"string_error\"; }";
@@ -211,6 +602,107 @@ fn call_error_exception() {
 	expect_error(result, "Runtime exception");
 }
 
+#[test]
+fn call_error_thrown_value() {
+	#[derive(Deserialize, Debug, PartialEq)]
+	struct Failure {
+		code: u32,
+		reason: String,
+	}
+
+	let src = r#"
+		function lookup(id) { throw { code: 404, reason: `no item ${id}` }; }
+		async function lookupAsync(id) { await null; throw "unavailable"; }
+		function fail() { throw new RangeError("out of range"); }"#;
+	let mut script = Script::from_string(src).expect("Initialization succeeds");
+
+	let error = script.call::<_, JsValue>("lookup", (3,)).unwrap_err();
+	let expected = Failure {
+		code: 404,
+		reason: "no item 3".to_string(),
+	};
+	assert_eq!(error.thrown_as::<Failure>(), Some(expected));
+	assert_eq!(error.thrown_as::<String>(), None);
+
+	let error = script.call::<_, JsValue>("lookupAsync", (3,)).unwrap_err();
+	assert!(matches!(&error, JsError::Thrown { value, .. } if value == "unavailable"));
+
+	// Error objects are reported as before
+	let error = script.call::<_, JsValue>("fail", ()).unwrap_err();
+	assert!(matches!(&error, JsError::Runtime(e) if e.to_string().contains("out of range")));
+	assert_eq!(error.thrown_as::<JsValue>(), None);
+}
+
+#[test]
+fn call_expecting_errors() {
+	#[derive(Deserialize, Debug, PartialEq)]
+	struct DomainError {
+		name: String,
+		message: String,
+		code: Option<u32>,
+	}
+
+	let src = r#"
+		class DomainError extends Error {}
+		class NotFound extends DomainError {
+			constructor(id) { super(`no item ${id}`); this.name = "NotFound"; this.code = 404; }
+		}
+		async function find(id) {
+			if (id === 0) throw new NotFound(id);
+			if (id < 0) throw new RangeError("negative id");
+			return `item ${id}`;
+		}"#;
+	let mut script = Script::from_string(src).expect("Initialization succeeds");
+	assert!(script.register_error_class("not a class").is_err());
+	script.register_error_class("DomainError").unwrap();
+
+	let result = script.call_expecting_errors::<String, DomainError>("find", (1,));
+	assert_eq!(result.unwrap(), Ok("item 1".to_string()));
+
+	let result = script.call_expecting_errors::<String, DomainError>("find", (0,));
+	let expected = DomainError {
+		name: "NotFound".to_string(),
+		message: "no item 0".to_string(),
+		code: Some(404),
+	};
+	assert_eq!(result.unwrap(), Err(expected));
+
+	// Other errors are not expected
+	let result = script.call_expecting_errors::<String, DomainError>("find", (-1,));
+	assert!(matches!(result, Err(JsError::Runtime(e)) if e.to_string().contains("negative id")));
+}
+
+#[test]
+fn call_error_conversion() {
+	let src = "function render(id, options) { return 'text'; }";
+	let mut script = Script::from_string(src).expect("Initialization succeeds");
+
+	// Non-string map keys cannot be represented in JSON
+	let options = HashMap::from([(vec![1, 2], true)]);
+	let result: Result<String, JsError> = script.call("render", (7, options));
+	match result {
+		Err(JsError::Json(e)) => {
+			assert_eq!(
+				e.to_string(),
+				r#"call("render") arg #2: key must be a string"#
+			)
+		}
+		other => panic!("expected argument error, got {other:?}"),
+	}
+
+	let result: Result<i32, JsError> = script.call("render", (7, ()));
+	match result {
+		Err(JsError::Json(e)) => {
+			let message = e.to_string();
+			assert!(
+				message.starts_with(r#"call("render") return value: invalid type: string "text""#),
+				"unexpected message: {message}"
+			);
+		}
+		other => panic!("expected return value error, got {other:?}"),
+	}
+}
+
 #[test]
 fn call_error_timeout() {
 	let timeout = Duration::from_millis(200);
@@ -225,7 +717,10 @@ fn call_error_timeout() {
 	let result: Result<String, JsError> = script.call("run_forever", ());
 	let duration = start.elapsed();
 
-	expect_error(result, "Timed out");
+	match result {
+		Err(JsError::Timeout { elapsed }) => assert!(elapsed >= timeout),
+		other => panic!("expected timeout, got {other:?}"),
+	}
 	assert!(
 		duration >= timeout,
 		"Terminates before the specified timeout (at {}ms)",
@@ -239,6 +734,40 @@ fn call_error_timeout() {
 	);
 }
 
+#[test]
+fn call_after_timeout_recovers() {
+	let js_code = r#"
+	let counter = 0;
+	function increment() { return ++counter; }
+	function run_forever() { counter = 100; for(;;){} }
+	function run_forever_later() {
+		setTimeout(() => { for(;;){} }, 0);
+		return "returned before timeout";
+	}"#;
+	let mut script = Script::builder()
+		.with_clock(VirtualClock::new())
+		.build_from_string(js_code)
+		.expect("Initialization succeeds")
+		.with_timeout(Duration::from_millis(100));
+
+	let result: i32 = script.call("increment", ()).unwrap();
+	assert_eq!(result, 1);
+
+	let result: Result<(), JsError> = script.call("run_forever", ());
+	assert!(matches!(result, Err(JsError::Timeout { .. })));
+
+	// Usable again; changes made by the aborted call survive
+	let result: i32 = script.call("increment", ()).unwrap();
+	assert_eq!(result, 101);
+
+	// Result that was returned before the timeout is not mixed up with the next call
+	let result: Result<String, JsError> = script.call("run_forever_later", ());
+	assert!(matches!(result, Err(JsError::Timeout { .. })));
+
+	let result: i32 = script.call("increment", ()).unwrap();
+	assert_eq!(result, 102);
+}
+
 #[test]
 fn call_async() {
 	let src = r#"
@@ -253,3 +782,1219 @@ fn call_async() {
 
 	assert_eq!(result, 3);
 }
+
+#[test]
+fn call_sync_with_pending_work() {
+	let src = r#"
+	let log = [];
+	function schedule() {
+		Promise.resolve().then(() => log.push("microtask"));
+		log.push("sync");
+		return log.length;
+	}
+	function getLog() { return log; }
+	"#;
+
+	let mut script = Script::from_string(src).expect("Initialization succeeds");
+
+	// Work left behind by synchronous functions still completes within the call
+	let result: usize = script.call("schedule", ()).unwrap();
+	assert_eq!(result, 1);
+	let log: Vec<String> = script.call("getLog", ()).unwrap();
+	assert_eq!(log, ["sync", "microtask"]);
+}
+
+#[test]
+fn init_platform_concurrent() {
+	let threads: Vec<_> = (0..4)
+		.map(|i| {
+			std::thread::spawn(move || {
+				js_sandbox::init_platform();
+
+				let mut script = Script::builder()
+					.with_max_stack_size(256 + 128 * i)
+					.build_from_string("function inc(a) { return a + 1; }")
+					.expect("Initialization succeeds");
+
+				let result: usize = script.call("inc", (i,)).unwrap();
+				result
+			})
+		})
+		.collect();
+
+	js_sandbox::init_platform();
+
+	for (i, thread) in threads.into_iter().enumerate() {
+		assert_eq!(thread.join().unwrap(), i + 1);
+	}
+}
+
+#[test]
+fn concurrent_scripts_any_drop_order() {
+	let src = "let count = 0; function inc(a) { count += a; return count; }";
+
+	let threads: Vec<_> = (0..4)
+		.map(|_| {
+			std::thread::spawn(move || {
+				let mut total = 0;
+				for _ in 0..10 {
+					let mut a = Script::from_string(src).expect("Initialization succeeds");
+					let mut b = Script::from_string(src).expect("Initialization succeeds");
+					let _: usize = a.call("inc", (1,)).unwrap();
+
+					// Dropped in creation order, not reverse
+					drop(a);
+					let mut c = Script::from_string(src).expect("Initialization succeeds");
+					let _: usize = c.call("inc", (5,)).unwrap();
+
+					total += b.call::<_, usize>("inc", (2,)).unwrap();
+					drop(b);
+					total += c.call::<_, usize>("inc", (3,)).unwrap();
+				}
+				total
+			})
+		})
+		.collect();
+
+	for thread in threads {
+		assert_eq!(thread.join().unwrap(), 10 * (2 + 8));
+	}
+}
+
+#[test]
+fn call_error_stack_overflow() {
+	let src = r#"
+	function recurse(n) { return recurse(n + 1) + 1; }
+
+	function catchOverflow() {
+		try {
+			return recurse(0);
+		} catch (e) {
+			return e.name;
+		}
+	}"#;
+
+	let mut script = Script::builder()
+		.with_max_stack_size(128)
+		.build_from_string(src)
+		.expect("Initialization succeeds");
+
+	let caught: String = script.call("catchOverflow", ()).unwrap();
+	assert_eq!(caught, "RangeError");
+
+	let result: Result<i32, JsError> = script.call("recurse", (0,));
+	expect_error(result, "Stack overflow");
+}
+
+#[test]
+fn stack_size_per_script() {
+	let src = "function countdown(n) { return n === 0 ? 0 : countdown(n - 1) + 1; }";
+
+	let mut small = Script::builder()
+		.with_max_stack_size(128)
+		.build_from_string(src)
+		.expect("Initialization succeeds");
+	let mut large = Script::builder()
+		.with_max_stack_size(900)
+		.build_from_string(src)
+		.expect("Initialization succeeds");
+
+	// Each script keeps its own limit, regardless of the order of creation and calls
+	for _ in 0..2 {
+		let result: Result<u32, JsError> = small.call("countdown", (5000,));
+		assert!(result.is_err());
+
+		let result: u32 = large.call("countdown", (5000,)).unwrap();
+		assert_eq!(result, 5000);
+	}
+}
+
+#[test]
+fn call_error_recursion_limit() {
+	let src = r#"
+	function recurse(n) { return recurse(n + 1) + 1; }
+	function countdown(n) { return n === 0 ? 0 : countdown(n - 1) + 1; }
+	function catchOverflow() {
+		try { return recurse(0); } catch (e) { return e.name; }
+	}"#;
+
+	let budget = 4000;
+	let mut script = Script::builder()
+		.with_recursion_budget(budget)
+		.build_from_string(src)
+		.expect("Initialization succeeds");
+
+	// The budget is approximate: well below it succeeds, well above it fails
+	let result: usize = script.call("countdown", (budget / 2,)).unwrap();
+	assert_eq!(result, budget / 2);
+
+	let result: Result<usize, JsError> = script.call("countdown", (budget * 2,));
+	assert!(
+		matches!(result, Err(JsError::RecursionLimit { .. })),
+		"expected recursion limit error, got {result:?}"
+	);
+
+	// Still catchable within JS
+	let caught: String = script.call("catchOverflow", ()).unwrap();
+	assert_eq!(caught, "RangeError");
+
+	let result: Result<i32, JsError> = script.call("recurse", (0,));
+	match result {
+		Err(JsError::RecursionLimit { function, limit }) => {
+			assert_eq!(function.as_deref(), Some("recurse"));
+			assert_eq!(limit, budget);
+		}
+		other => panic!("expected recursion limit error, got {other:?}"),
+	}
+}
+
+#[test]
+fn ctor_error_source_too_large() {
+	let src = "function triple(a) { return 3 * a; }";
+
+	let script = Script::builder()
+		.with_max_source_size(16)
+		.build_from_string(src);
+	let err = script
+		.err()
+		.expect("Source exceeding the limit is rejected");
+	assert!(err.to_string().contains("exceeding the limit"), "{err}");
+
+	let script = Script::builder()
+		.with_max_source_size(src.len())
+		.build_from_string(src);
+	assert!(script.is_ok(), "Source within the limit is accepted");
+
+	// Files are not read beyond the limit
+	let script = Script::builder()
+		.with_max_source_size(16)
+		.build_from_file("tests/hello.js");
+	let err = script.err().expect("File exceeding the limit is rejected");
+	assert!(err.to_string().contains("exceeds the limit"), "{err}");
+
+	let len = std::fs::metadata("tests/hello.js").unwrap().len() as usize;
+	let script = Script::builder()
+		.with_max_source_size(len)
+		.build_from_file("tests/hello.js");
+	assert!(script.is_ok(), "File within the limit is accepted");
+}
+
+#[test]
+fn ctor_error_nesting_too_deep() {
+	let src = format!("var x = {}1{};", "[".repeat(100), "]".repeat(100));
+
+	let script = Script::builder()
+		.with_max_nesting_depth(50)
+		.build_from_string(&src);
+	let err = script
+		.err()
+		.expect("Source exceeding the limit is rejected");
+	assert!(err.to_string().contains("nests brackets"), "{err}");
+
+	// Brackets in strings and comments are not counted
+	let src = r#"
+	// ((((((((
+	var s = "[[[[[[[[";
+	function f() { return [s]; }"#;
+
+	let script = Script::builder()
+		.with_max_nesting_depth(3)
+		.build_from_string(src);
+	assert!(script.is_ok(), "Source within the limit is accepted");
+}
+
+#[test]
+fn call_error_budget_exhausted() {
+	let js_code = "
+		function run_forever() { for(;;){} }
+		function quick(a) { return a + 1; }";
+
+	let mut script = Script::from_string(js_code)
+		.expect("Initialization succeeds")
+		.with_time_slice_budget(5, Duration::from_millis(10));
+
+	let result: i32 = script.call("quick", (1,)).unwrap();
+	assert_eq!(result, 2);
+
+	// An endless loop exhausts any budget; without one, this call would never return
+	let result: Result<String, JsError> = script.call("run_forever", ());
+	assert!(matches!(
+		result,
+		Err(JsError::BudgetExhausted { slices: 5 })
+	));
+
+	// The budget is replenished for the next call
+	let result: i32 = script.call("quick", (2,)).unwrap();
+	assert_eq!(result, 3);
+}
+
+#[test]
+fn call_error_memory_limit() {
+	let limit = 32 * 1024 * 1024;
+
+	let js_code =
+		"function hog() { const chunks = []; for(;;) { chunks.push(new Array(10000).fill(1)); } }";
+	let mut script = Script::builder()
+		.with_max_heap_size(limit)
+		.build_from_string(js_code)
+		.expect("Initialization succeeds");
+
+	assert!(script.is_healthy());
+	let result: Result<(), JsError> = script.call("hog", ());
+
+	match result {
+		Err(JsError::MemoryLimit {
+			used,
+			limit: reported,
+		}) => {
+			assert_eq!(reported, limit);
+			assert!(used > 0);
+		}
+		other => panic!("expected memory limit error, got {other:?}"),
+	}
+	assert!(!script.is_healthy());
+}
+
+#[test]
+fn call_error_memory_limit_repeated() {
+	let limit = 32 * 1024 * 1024;
+
+	let js_code =
+		"function hog() { const chunks = []; for(;;) { chunks.push(new Array(10000).fill(1)); } }";
+	let mut script = Script::builder()
+		.with_max_heap_size(limit)
+		.build_from_string(js_code)
+		.expect("Initialization succeeds");
+
+	// The limit is only raised to unwind each call, not compounded across calls
+	for _ in 0..3 {
+		let result: Result<(), JsError> = script.call("hog", ());
+		match result {
+			Err(JsError::MemoryLimit { used, .. }) => assert!(used < 2 * limit, "used {used}"),
+			other => panic!("expected memory limit error, got {other:?}"),
+		}
+	}
+}
+
+#[test]
+fn call_error_external_memory_limit() {
+	let limit = 32 * 1024 * 1024;
+	let external = 2 * limit as i64;
+
+	let mut script = Script::builder()
+		.with_max_heap_size(limit)
+		.build_from_string("function ping() { return 1; }")
+		.expect("Initialization succeeds");
+
+	script.call::<_, u32>("ping", ()).unwrap();
+
+	// Memory held by the host on behalf of the script
+	assert!(script.adjust_external_memory(external) >= external);
+
+	let result: Result<u32, JsError> = script.call("ping", ());
+	match result {
+		Err(JsError::MemoryLimit { used, .. }) => assert!(used > limit),
+		other => panic!("expected memory limit error, got {other:?}"),
+	}
+	assert!(script.usage().peak_external_memory_after_call >= external as usize);
+
+	script.adjust_external_memory(-external);
+	assert_eq!(script.call::<_, u32>("ping", ()).unwrap(), 1);
+	assert!(script.is_healthy());
+}
+
+#[test]
+fn call_error_array_buffer_limit() {
+	let js_code = r#"
+	function alloc(bytes) {
+		try {
+			return new ArrayBuffer(bytes).byteLength;
+		} catch (e) {
+			return e.name;
+		}
+	}"#;
+
+	let mut script = Script::builder()
+		.with_max_heap_size(32 * 1024 * 1024)
+		.build_from_string(js_code)
+		.expect("Initialization succeeds");
+
+	// Refused when allocated, not only after the call
+	let result: JsValue = script.call("alloc", (256 * 1024 * 1024,)).unwrap();
+	assert_eq!(result, JsValue::from("RangeError"));
+
+	let result: JsValue = script.call("alloc", (1024,)).unwrap();
+	assert_eq!(result, JsValue::from(1024));
+	assert!(script.is_healthy());
+}
+
+#[test]
+fn call_error_microtask_limit() {
+	let js_code = r#"
+	async function spin() { for (;;) { await null; } }
+	async function count(n) { for (let i = 0; i < n; i++) { await null; } return n; }
+	"#;
+
+	let mut script = Script::builder()
+		.with_max_microtasks(1000)
+		.build_from_string(js_code)
+		.expect("Initialization succeeds");
+
+	assert_eq!(script.call::<_, u32>("count", (10,)).unwrap(), 10);
+
+	let result: Result<(), JsError> = script.call("spin", ());
+	match result {
+		Err(JsError::MicrotaskLimit { limit }) => assert_eq!(limit, 1000),
+		other => panic!("expected microtask limit error, got {other:?}"),
+	}
+
+	// Counted per call
+	assert_eq!(script.call::<_, u32>("count", (10,)).unwrap(), 10);
+}
+
+#[test]
+fn call_error_event_loop_limit() {
+	let js_code = r#"
+	async function ticks(n) {
+		for (let i = 0; i < n; i++) { await new Promise(resolve => setTimeout(resolve, 0)); }
+		return n;
+	}
+	"#;
+
+	let mut script = Script::builder()
+		.with_clock(VirtualClock::new())
+		.with_max_event_loop_turns(20)
+		.build_from_string(js_code)
+		.expect("Initialization succeeds");
+
+	assert_eq!(script.call::<_, u32>("ticks", (5,)).unwrap(), 5);
+
+	let result: Result<u32, JsError> = script.call("ticks", (100,));
+	match result {
+		Err(JsError::EventLoopLimit { limit }) => assert_eq!(limit, 20),
+		other => panic!("expected event loop limit error, got {other:?}"),
+	}
+}
+
+#[test]
+fn call_error_pending_promise() {
+	let js_code = r#"
+	async function never() { await new Promise(() => {}); }
+	function wait(ms) { const end = Date.now() + ms; while (Date.now() < end) {} return ms; }
+	"#;
+
+	let settle_timeout = Duration::from_millis(20);
+	let mut script = Script::from_string(js_code)
+		.expect("Initialization succeeds")
+		.with_settle_timeout(settle_timeout);
+
+	// Nothing is pending that could resolve the promise
+	let result: Result<(), JsError> = script.call("never", ());
+	assert!(matches!(result, Err(JsError::PendingPromise { .. })));
+
+	// Synchronous execution is not limited by the settle timeout
+	assert_eq!(script.call::<_, u64>("wait", (100,)).unwrap(), 100);
+
+	// Timers keep the event loop busy until the settle timeout expires
+	let js_code = r#"
+	async function poll() { for (;;) { await new Promise(resolve => setTimeout(resolve, 10)); } }
+	"#;
+
+	let mut script = Script::builder()
+		.with_clock(VirtualClock::new())
+		.build_from_string(js_code)
+		.expect("Initialization succeeds")
+		.with_settle_timeout(settle_timeout);
+
+	let result: Result<(), JsError> = script.call("poll", ());
+	match result {
+		Err(JsError::PendingPromise { elapsed }) => assert!(elapsed >= settle_timeout),
+		other => panic!("expected pending promise error, got {other:?}"),
+	}
+}
+
+#[test]
+fn runtime_mut() {
+	let mut script = Script::from_string("function get() { return globalThis.value; }").unwrap();
+
+	{
+		let mut runtime = script.runtime_mut();
+		let code = deno_core::FastString::from_static("globalThis.value = [1, 2, 3];");
+		runtime.execute_script("setup.js", code).unwrap();
+	}
+
+	let result: Vec<i32> = script.call("get", ()).unwrap();
+	assert_eq!(result, vec![1, 2, 3]);
+}
+
+#[test]
+fn with_scope() {
+	use deno_core::{serde_v8, v8};
+
+	let js_code = "const tag = Symbol('tag'); const point = { x: 1, y: 2, [tag]: true };";
+	let mut script = Script::from_string(js_code).unwrap();
+
+	let (description, point) = script.with_scope(|scope| {
+		let tag = eval_in_scope(scope, "tag");
+		let tag = v8::Local::<v8::Symbol>::try_from(tag).unwrap();
+		let description = tag.description(scope).to_rust_string_lossy(scope);
+
+		let point = eval_in_scope(scope, "point");
+		let point: HashMap<String, i32> = serde_v8::from_v8(scope, point).unwrap();
+		(description, point)
+	});
+
+	assert_eq!(description, "tag");
+	assert_eq!(
+		point,
+		HashMap::from([("x".to_string(), 1), ("y".to_string(), 2)])
+	);
+}
+
+fn eval_in_scope<'s>(
+	scope: &mut deno_core::v8::HandleScope<'s>,
+	expr: &str,
+) -> deno_core::v8::Local<'s, deno_core::v8::Value> {
+	use deno_core::v8;
+
+	let source = v8::String::new(scope, expr).unwrap();
+	let script = v8::Script::compile(scope, source, None).unwrap();
+	script.run(scope).unwrap()
+}
+
+#[test]
+fn request_gc() {
+	let js_code = r#"
+	let data = null;
+	function fill(n) { data = new Array(n).fill("x").map((s, i) => s + i); return data.length; }
+	function release() { data = null; }
+	"#;
+
+	let mut script = Script::from_string(js_code).expect("Initialization succeeds");
+
+	let len: usize = script.call("fill", (100_000,)).unwrap();
+	assert_eq!(len, 100_000);
+	script.call::<_, ()>("release", ()).unwrap();
+
+	script.request_gc();
+
+	let len: usize = script.call("fill", (10,)).unwrap();
+	assert_eq!(len, 10);
+}
+
+#[test]
+fn call_timeout_cancelled_after_return() {
+	let timeout = Duration::from_millis(100);
+
+	let js_code =
+		"function wait(ms) { const end = Date.now() + ms; while (Date.now() < end) {} return ms; }";
+	let mut script = Script::from_string(js_code)
+		.expect("Initialization succeeds")
+		.with_timeout(timeout);
+
+	// Second call overlaps with the deadline of the first one; it must not be terminated by it
+	let result: i32 = script.call("wait", (60,)).unwrap();
+	assert_eq!(result, 60);
+
+	let result: i32 = script.call("wait", (60,)).unwrap();
+	assert_eq!(result, 60);
+}
+
+#[test]
+fn call_kwargs() {
+	#[derive(Serialize)]
+	struct Size {
+		width: i32,
+		height: i32,
+	}
+
+	let src = "function area({ width, height }) { return width * height; }";
+	let mut script = Script::from_string(src).expect("Initialization succeeds");
+
+	let result: i32 = script
+		.call_kwargs("area", serde_json::json!({ "width": 10, "height": 20 }))
+		.unwrap();
+	assert_eq!(result, 200);
+
+	let result: i32 = script
+		.call_kwargs(
+			"area",
+			Size {
+				width: 3,
+				height: 4,
+			},
+		)
+		.unwrap();
+	assert_eq!(result, 12);
+
+	let result: Result<i32, JsError> = script.call_kwargs("area", 10);
+	assert!(matches!(result, Err(JsError::Json(_))));
+}
+
+#[test]
+fn call_js() {
+	use deno_core::v8;
+	use js_sandbox::{FromJs, ToJs};
+
+	// Passed to JS as Float64Array
+	#[derive(Debug, PartialEq)]
+	struct Vec2(f64, f64);
+
+	impl ToJs for Vec2 {
+		fn to_js<'s>(
+			&self,
+			scope: &mut v8::HandleScope<'s>,
+		) -> Result<v8::Local<'s, v8::Value>, AnyError> {
+			let buffer = v8::ArrayBuffer::new(scope, 16);
+			let array = v8::Float64Array::new(scope, buffer, 0, 2).unwrap();
+			for (i, value) in [self.0, self.1].into_iter().enumerate() {
+				let value = v8::Number::new(scope, value);
+				array.set_index(scope, i as u32, value.into());
+			}
+			Ok(array.into())
+		}
+	}
+
+	impl FromJs for Vec2 {
+		fn from_js(
+			scope: &mut v8::HandleScope,
+			value: v8::Local<v8::Value>,
+		) -> Result<Self, AnyError> {
+			let array = v8::Local::<v8::Float64Array>::try_from(value)?;
+			let mut get = |i| {
+				let element = array.get_index(scope, i).unwrap();
+				f64::from_js(scope, element)
+			};
+			Ok(Vec2(get(0)?, get(1)?))
+		}
+	}
+
+	let js_code = "
+		function add(a, b) { return new Float64Array([a[0] + b[0], a[1] + b[1]]); }
+		async function label(v, name) { return `${name}: ${v.constructor.name}`; }
+		function count(items) { return items.length; }";
+	let mut script = Script::from_string(js_code).unwrap();
+
+	let sum: Vec2 = script
+		.call_js("add", (Vec2(1.0, 2.0), Vec2(0.5, 0.5)))
+		.unwrap();
+	assert_eq!(sum, Vec2(1.5, 2.5));
+
+	let label: String = script.call_js("label", (Vec2(0.0, 0.0), "origin")).unwrap();
+	assert_eq!(label, "origin: Float64Array");
+
+	let count: u32 = script
+		.call_js("count", (vec![Some(1), None, Some(3)],))
+		.unwrap();
+	assert_eq!(count, 3);
+
+	// Conversions are strict
+	let result: Result<String, JsError> = script.call_js("count", (vec![true],));
+	assert!(matches!(result, Err(JsError::Json(e)) if e.to_string().contains("expected string")));
+}
+
+#[test]
+fn call_values() {
+	let src = "function describe(rule, threshold) { return `${rule.name}: ${rule.values.filter(v => v > threshold).length}`; }";
+	let mut script = Script::from_string(src).expect("Initialization succeeds");
+
+	let rule = serde_json::json!({ "name": "large", "values": [1, 5, 10, 50] });
+	let result: String = script
+		.call_values("describe", vec![rule, JsValue::from(4)])
+		.unwrap();
+	assert_eq!(result, "large: 3");
+
+	let result: Result<String, JsError> = script.call_values("missing", vec![]);
+	assert!(result.is_err());
+}
+
+#[test]
+fn call_ref() {
+	fn classify(script: &Script, value: i32) -> String {
+		script.call_ref("classify", (value,)).unwrap()
+	}
+
+	let src = "function classify(v) { return v < 0 ? 'negative' : 'non-negative'; }";
+	let script = Script::from_string(src).expect("Initialization succeeds");
+
+	let shared = &script;
+	assert_eq!(classify(shared, -3), "negative");
+	assert_eq!(classify(shared, 5), "non-negative");
+	assert_eq!(script.usage().calls, 2);
+}
+
+#[test]
+fn call_async() {
+	use deno_core::futures::executor::block_on;
+	use js_sandbox::ScriptEngine;
+
+	let src = "async function double(v) { await null; return 2 * v; }";
+	let mut script = Script::from_string(src).expect("Initialization succeeds");
+
+	let result: i32 = block_on(script.call_async("double", (21,))).unwrap();
+	assert_eq!(result, 42);
+}
+
+#[test]
+fn call_ref_reentrant_fails() {
+	use std::cell::{OnceCell, RefCell};
+	use std::rc::{Rc, Weak};
+
+	let src = "function id(v) { return v; }";
+	let mut script = Script::from_string(src).expect("Initialization succeeds");
+
+	let this: Rc<OnceCell<Weak<Script>>> = Rc::default();
+	let nested_result = Rc::new(RefCell::new(None));
+
+	let hook_this = this.clone();
+	let hook_result = nested_result.clone();
+	script.on_call(move |_, _| {
+		let script = hook_this.get().and_then(Weak::upgrade).unwrap();
+		let result: Result<i32, JsError> = script.call_ref("id", (2,));
+		*hook_result.borrow_mut() = Some(result.is_err());
+	});
+
+	let script = Rc::new(script);
+	this.set(Rc::downgrade(&script)).unwrap();
+
+	let result: i32 = script.call_ref("id", (1,)).unwrap();
+	assert_eq!(result, 1);
+	assert_eq!(*nested_result.borrow(), Some(true));
+}
+
+#[test]
+fn call_batch() {
+	let src = r#"
+	var log = [];
+	function triple(a) { log.push("triple"); return 3 * a; }
+	function add(a, b) { log.push("add"); return a + b; }
+	async function getLog() { return log.join(","); }
+	"#;
+
+	let mut script = Script::from_string(src).expect("Initialization succeeds");
+
+	let calls = [
+		("triple", vec![JsValue::from(7)]),
+		("add", vec![JsValue::from(1), JsValue::from(2)]),
+		("getLog", vec![]),
+	];
+	let results: Vec<JsValue> = script.call_batch(&calls).unwrap();
+
+	assert_eq!(
+		results,
+		vec![
+			JsValue::from(21),
+			JsValue::from(3),
+			JsValue::from("triple,add")
+		]
+	);
+}
+
+#[test]
+fn call_pipeline() {
+	let src = r#"
+	function parse(text) { return { words: text.split(" ") }; }
+	async function transform(doc, suffix) { return doc.words.map(w => w + suffix); }
+	function render(words) { return words.join("|"); }
+	"#;
+
+	let mut script = Script::from_string(src).expect("Initialization succeeds");
+
+	let result: String = script
+		.pipeline()
+		.call("parse", ("hello pipeline world",))
+		.call("transform", ("!",))
+		.call("render", ())
+		.run()
+		.unwrap();
+
+	assert_eq!(result, "hello!|pipeline!|world!");
+}
+
+#[test]
+fn usage_report() {
+	let src = r#"
+	function echo(s) { return s; }
+	function fail() { throw new Error("fail"); }
+	"#;
+
+	let mut script = Script::from_string(src).expect("Initialization succeeds");
+
+	let _: String = script.call("echo", ("hello",)).unwrap();
+	let _: Result<(), JsError> = script.call("fail", ());
+
+	let usage = script.usage();
+	assert_eq!(usage.calls, 2);
+	assert_eq!(usage.failed_calls, 1);
+	assert_eq!(usage.bytes_in, "\"hello\"".len() as u64);
+	assert_eq!(usage.bytes_out, "\"hello\"".len() as u64);
+	assert!(usage.peak_heap_after_call > 0);
+
+	let json = serde_json::to_value(usage).unwrap();
+	assert_eq!(json["calls"], 2);
+
+	// Internal evaluations are not counted, started calls count once
+	assert!(script.has_function("echo").unwrap());
+	let id = script.start_call("echo", ("again",)).unwrap();
+	let _: String = script.finish_call(id).unwrap();
+
+	let usage = script.usage();
+	assert_eq!(usage.calls, 3);
+	assert_eq!(usage.bytes_in, 2 * "\"hello\"".len() as u64);
+	assert_eq!(usage.bytes_out, 2 * "\"hello\"".len() as u64);
+
+	script.reset_usage();
+	assert_eq!(script.usage().calls, 0);
+}
+
+#[test]
+fn call_hooks() {
+	use std::cell::RefCell;
+	use std::rc::Rc;
+
+	let src = r#"
+	function login(user, password) { return user.length; }
+	function fail() { throw new Error("fail"); }
+	"#;
+
+	let mut script = Script::from_string(src).expect("Initialization succeeds");
+
+	let log = Rc::new(RefCell::new(Vec::<String>::new()));
+
+	let call_log = log.clone();
+	script.on_call(move |fn_name, args| {
+		// Redact second argument
+		call_log
+			.borrow_mut()
+			.push(format!("call {fn_name}({}, ***)", args[0]));
+	});
+
+	let result_log = log.clone();
+	script.on_result(move |fn_name, result, _duration| {
+		let outcome = match result {
+			Ok(value) => value.to_string(),
+			Err(_) => "error".to_string(),
+		};
+		result_log
+			.borrow_mut()
+			.push(format!("result {fn_name}: {outcome}"));
+	});
+
+	let _: usize = script.call("login", ("admin", "secret")).unwrap();
+	let _: Result<(), JsError> = script.call("fail", ());
+
+	assert_eq!(
+		*log.borrow(),
+		vec![
+			"call login(\"admin\", ***)",
+			"result login: 5",
+			"call fail(null, ***)",
+			"result fail: error",
+		]
+	);
+}
+
+#[test]
+fn validate() {
+	// Top-level code must not run: this would throw
+	let src = r#"
+	throw new Error("must not run");
+	function triple(a) { return 3 * a; }
+	"#;
+	assert!(Script::validate(src).is_ok());
+
+	let src = "function triple(a) { return 3 *. a; }";
+	expect_error(Script::validate(src), "Syntax error");
+}
+
+#[test]
+fn call_result_validation() {
+	let js_code = r#"
+	function point(valid) { return valid ? { x: 1, y: 2 } : { x: 1 }; }
+	function other() { return "unchecked"; }"#;
+	let mut script = Script::from_string(js_code).expect("Initialization succeeds");
+
+	script.validate_result("point", |value| {
+		for key in ["x", "y"] {
+			if !value[key].is_number() {
+				return Err(format!("missing number '{key}'"));
+			}
+		}
+		Ok(())
+	});
+
+	let result: JsValue = script.call("point", (true,)).unwrap();
+	assert_eq!(result, serde_json::json!({ "x": 1, "y": 2 }));
+
+	let result: Result<JsValue, JsError> = script.call("point", (false,));
+	match result {
+		Err(JsError::InvalidResult { function, reason }) => {
+			assert_eq!(function, "point");
+			assert_eq!(reason, "missing number 'y'");
+		}
+		other => panic!("unexpected result: {other:?}"),
+	}
+
+	let result: String = script.call("other", ()).unwrap();
+	assert_eq!(result, "unchecked");
+}
+
+#[cfg(feature = "path-to-error")]
+#[test]
+fn call_result_error_path() {
+	#[derive(Deserialize, Debug)]
+	#[allow(dead_code)]
+	struct Item {
+		name: String,
+	}
+
+	#[derive(Deserialize, Debug)]
+	#[allow(dead_code)]
+	struct Order {
+		items: Vec<Item>,
+	}
+
+	let js_code =
+		"function order() { return { items: [{ name: 'a' }, { name: 'b' }, { name: 5 }] }; }";
+	let mut script = Script::from_string(js_code).expect("Initialization succeeds");
+
+	let result: Result<Order, JsError> = script.call("order", ());
+	match result {
+		Err(JsError::Json(e)) => assert!(e.to_string().contains("items[2].name: invalid type")),
+		other => panic!("unexpected result: {other:?}"),
+	}
+}
+
+#[cfg(feature = "json-schema")]
+#[test]
+fn call_result_validation_schema() {
+	let js_code = "function user(age) { return { name: 'Ann', age }; }";
+	let mut script = Script::from_string(js_code).expect("Initialization succeeds");
+
+	let schema = serde_json::json!({
+		"type": "object",
+		"properties": {
+			"name": { "type": "string" },
+			"age": { "type": "integer", "minimum": 0 }
+		},
+		"required": ["name", "age"]
+	});
+	script.validate_result_schema("user", &schema).unwrap();
+
+	let result: JsValue = script.call("user", (30,)).unwrap();
+	assert_eq!(result["age"], 30);
+
+	let result: Result<JsValue, JsError> = script.call("user", (-1,));
+	match result {
+		Err(JsError::InvalidResult { reason, .. }) => assert!(reason.contains("/age"), "{reason}"),
+		other => panic!("unexpected result: {other:?}"),
+	}
+
+	let invalid_schema = serde_json::json!({ "type": 12 });
+	assert!(script
+		.validate_result_schema("user", &invalid_schema)
+		.is_err());
+}
+
+#[test]
+fn call_with_context() {
+	#[derive(Serialize)]
+	struct Config {
+		name: String,
+		flags: Vec<String>,
+	}
+
+	let src = r#"
+	function describe() { return context.name + ":" + context.flags.join(","); }
+	function modify() {
+		"use strict";
+		try { context.flags.push("hacked"); } catch (e) { return e instanceof TypeError; }
+		return false;
+	}"#;
+
+	let config = Config {
+		name: "plugin".to_string(),
+		flags: vec!["a".to_string(), "b".to_string()],
+	};
+
+	let mut script = Script::builder()
+		.with_context(&config)
+		.build_from_string(src)
+		.expect("Initialization succeeds");
+
+	let result: String = script.call("describe", ()).unwrap();
+	assert_eq!(result, "plugin:a,b");
+
+	let frozen: bool = script.call("modify", ()).unwrap();
+	assert!(frozen);
+
+	let result: String = script.call("describe", ()).unwrap();
+	assert_eq!(result, "plugin:a,b");
+}
+
+#[test]
+fn call_with_options() {
+	let js_code = r#"
+	function random() { return [Math.random(), Math.random()]; }
+	function text(len) { return "x".repeat(len); }
+	function log(text) { console.log(text); console.error("multi\nline"); }
+	function run_forever() { for(;;){} }
+	function request() { return callContext === null ? "none" : callContext.user + "/" + callContext.id; }"#;
+	let mut script = Script::from_string(js_code).expect("Initialization succeeds");
+
+	// Seed
+	let seeded = CallOptions::new().with_seed(7);
+	let first: Vec<f64> = script.call_with_options("random", (), &seeded).unwrap();
+	let second: Vec<f64> = script.call_with_options("random", (), &seeded).unwrap();
+	assert_eq!(first, second);
+	assert_ne!(first[0], first[1]);
+	assert!(first.iter().all(|x| (0.0..1.0).contains(x)));
+
+	let other: Vec<f64> = script
+		.call_with_options("random", (), &CallOptions::new().with_seed(8))
+		.unwrap();
+	assert_ne!(first, other);
+
+	// Max result size (JSON string including quotes)
+	let limited = CallOptions::new().with_max_result_size(10);
+	let result: String = script.call_with_options("text", (8,), &limited).unwrap();
+	assert_eq!(result.len(), 8);
+	let result: Result<String, JsError> = script.call_with_options("text", (9,), &limited);
+	assert!(result.is_err());
+
+	// Console capture
+	let capture = CallOptions::new().with_console_capture(true);
+	script
+		.call_with_options::<_, ()>("log", ("captured",), &capture)
+		.unwrap();
+	script.call::<_, ()>("log", ("printed",)).unwrap();
+	assert_eq!(script.take_console_output(), ["captured", "multi", "line"]);
+	assert!(script.take_console_output().is_empty());
+
+	// Call context, only visible during the call
+	let context = serde_json::json!({ "user": "alice", "id": 17 });
+	let with_context = CallOptions::new().with_call_context(&context);
+	let result: String = script
+		.call_with_options("request", (), &with_context)
+		.unwrap();
+	assert_eq!(result, "alice/17");
+	let result: String = script.call("request", ()).unwrap();
+	assert_eq!(result, "none");
+
+	// Context without JSON representation (non-string map keys)
+	let context: std::collections::HashMap<(i32, i32), i32> = [((1, 2), 3)].into();
+	let invalid = CallOptions::new().with_call_context(&context);
+	let result: Result<String, JsError> = script.call_with_options("request", (), &invalid);
+	assert!(matches!(result, Err(JsError::Json(_))));
+
+	// Timeout
+	let timeout = Duration::from_millis(100);
+	let result: Result<(), JsError> =
+		script.call_with_options("run_forever", (), &CallOptions::new().with_timeout(timeout));
+	assert!(matches!(result, Err(JsError::Timeout { .. })));
+}
+
+#[test]
+fn call_preemption() {
+	use std::cell::Cell;
+	use std::rc::Rc;
+
+	let js_code = r#"
+	function busy(ms) { const end = Date.now() + ms; while (Date.now() < end) {} return "done"; }
+	function spin() { for(;;){} }"#;
+
+	let checkpoints = Rc::new(Cell::new(0));
+	let counter = checkpoints.clone();
+	let mut script = Script::from_string(js_code)
+		.expect("Initialization succeeds")
+		.with_preemption(Duration::from_millis(10), move |checkpoint| {
+			counter.set(checkpoint.count);
+			if checkpoint.elapsed < Duration::from_millis(300) {
+				Preemption::Continue
+			} else {
+				Preemption::Abort
+			}
+		});
+
+	// Interrupted several times, but allowed to finish
+	let result: String = script.call("busy", (100,)).unwrap();
+	assert_eq!(result, "done");
+	assert!(checkpoints.get() >= 2, "checkpoints: {}", checkpoints.get());
+
+	let result: Result<(), JsError> = script.call("spin", ());
+	match result {
+		Err(JsError::Preempted { elapsed }) => assert!(elapsed >= Duration::from_millis(300)),
+		other => panic!("expected preemption, got {other:?}"),
+	}
+}
+
+#[test]
+fn call_with_retries() {
+	let js_code = r#"
+	let attempts = 0;
+	function flaky() {
+		attempts++;
+		if (attempts < 3) { for(;;){} }
+		return attempts;
+	}
+	function throws() { attempts++; throw new Error("not retried"); }"#;
+	let mut script = Script::from_string(js_code).expect("Initialization succeeds");
+
+	let options = CallOptions::new()
+		.with_timeout(Duration::from_millis(50))
+		.with_retries(2, Duration::from_millis(10));
+
+	let result: i32 = script.call_with_options("flaky", (), &options).unwrap();
+	assert_eq!(result, 3);
+
+	// Exceptions are not retried by default
+	let result: Result<(), JsError> = script.call_with_options("throws", (), &options);
+	assert!(result.is_err());
+	assert_eq!(script.call::<_, i32>("flaky", ()).unwrap(), 5);
+
+	let options = options.with_retry_on(&[RetryOn::Error]);
+	let result: Result<(), JsError> = script.call_with_options("throws", (), &options);
+	assert!(result.is_err());
+	assert_eq!(script.call::<_, i32>("flaky", ()).unwrap(), 9);
+}
+
+#[test]
+fn policy_presets() {
+	let js_code = r#"
+	function evaluate(code) { return eval(code); }
+	function globals() { return [typeof WebAssembly, typeof setTimeout]; }
+	async function load() {
+		try { await import("./hello.js"); return "loaded"; } catch (e) { return "denied"; }
+	}"#;
+
+	let mut script = Script::builder()
+		.with_policy(Policy::strict())
+		.with_clock(VirtualClock::new())
+		.build_from_string(js_code)
+		.expect("Initialization succeeds");
+
+	let result: Result<i32, JsError> = script.call("evaluate", ("1 + 2",));
+	assert!(result.is_err());
+	let result: Vec<String> = script.call("globals", ()).unwrap();
+	assert_eq!(result, ["undefined", "undefined"]);
+	let result: String = script.call("load", ()).unwrap();
+	assert_eq!(result, "denied");
+
+	let mut script = Script::builder()
+		.with_policy(Policy::permissive())
+		.with_clock(VirtualClock::new())
+		.build_from_string(js_code)
+		.expect("Initialization succeeds");
+
+	let result: i32 = script.call("evaluate", ("1 + 2",)).unwrap();
+	assert_eq!(result, 3);
+	let result: Vec<String> = script.call("globals", ()).unwrap();
+	assert_eq!(result, ["object", "function"]);
+
+	// Source limits apply before the code runs
+	let result = Script::builder()
+		.with_policy(Policy::strict())
+		.build_from_string(&format!("{}1{}", "[".repeat(300), "]".repeat(300)));
+	assert!(result.is_err());
+}
+
+#[test]
+fn frozen_intrinsics() {
+	let js_code = r#"
+	// Polyfills can still be installed during initialization
+	Array.prototype.sum = function () { return this.reduce((a, b) => a + b, 0); };
+
+	function pollute() {
+		Array.prototype.push = () => 0;
+		Object.prototype.polluted = true;
+		return [1, 2].sum();
+	}
+
+	function check() {
+		const error = new Error("failed");
+		error.name = "CustomError";
+		return [({}).polluted === undefined, [1].push(2), error.name, Object.isFrozen(Object.prototype)];
+	}"#;
+
+	let mut script = Script::builder()
+		.with_frozen_intrinsics()
+		.build_from_string(js_code)
+		.expect("Initialization succeeds");
+
+	let result: i32 = script.call("pollute", ()).unwrap();
+	assert_eq!(result, 3);
+
+	let result: (bool, i32, String, bool) = script.call("check", ()).unwrap();
+	assert_eq!(result, (true, 2, "CustomError".to_string(), true));
+}
+
+#[test]
+fn call_result_length_limits() {
+	let js_code = r#"
+	function text(n) { return "x".repeat(n); }
+	function nested(n) { return { items: [1, 2, 3], text: "x".repeat(n) }; }
+	function list(n) { return Array(n).fill(0); }
+	function bytes(n) { return new Uint8Array(n); }
+	async function later(n) { return "x".repeat(n); }"#;
+
+	let mut script = Script::builder()
+		.with_max_string_length(10)
+		.with_max_array_length(5)
+		.build_from_string(js_code)
+		.expect("Initialization succeeds");
+
+	let result: String = script.call("text", (10,)).unwrap();
+	assert_eq!(result.len(), 10);
+	let result: Result<String, JsError> = script.call("text", (11,));
+	assert!(result.is_err());
+	let result: Result<JsValue, JsError> = script.call("nested", (11,));
+	assert!(result.is_err());
+	let result: Result<String, JsError> = script.call("later", (11,));
+	assert!(result.is_err());
+
+	let result: Vec<i32> = script.call("list", (5,)).unwrap();
+	assert_eq!(result.len(), 5);
+	let result: Result<Vec<i32>, JsError> = script.call("list", (6,));
+	assert!(result.is_err());
+	let result: Result<Vec<u8>, JsError> = script.call("bytes", (6,));
+	assert!(result.is_err());
+}
+
+#[test]
+fn call_with_provenance() {
+	let js_code = r#"
+	function fail() { throw { code: 42 }; }
+	function spin() { for (;;) {} }
+	function log() { console.log("hello\nworld"); }"#;
+
+	let provenance = Provenance::new("geometry")
+		.with_version("1.2.0")
+		.with_author("Jane");
+	let mut script = Script::builder()
+		.with_provenance(provenance.clone())
+		.build_from_string(js_code)
+		.expect("Initialization succeeds")
+		.with_timeout(Duration::from_millis(50));
+
+	assert_eq!(script.provenance(), Some(&provenance));
+
+	let err = script.call::<_, ()>("fail", ()).unwrap_err();
+	assert_eq!(err.provenance(), Some(&provenance));
+	assert_eq!(err.thrown_as::<HashMap<String, i32>>().unwrap()["code"], 42);
+	assert!(err.to_string().starts_with("[geometry@1.2.0 by Jane] "));
+
+	let err = script.call::<_, ()>("spin", ()).unwrap_err();
+	assert!(matches!(err.inner(), JsError::Timeout { .. }));
+
+	let capture = CallOptions::new().with_console_capture(true);
+	script
+		.call_with_options::<_, ()>("log", (), &capture)
+		.unwrap();
+	assert_eq!(
+		script.take_console_output(),
+		[
+			"[geometry@1.2.0 by Jane] hello",
+			"[geometry@1.2.0 by Jane] world"
+		]
+	);
+}
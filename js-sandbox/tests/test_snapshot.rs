@@ -0,0 +1,50 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+#![cfg(feature = "v8")]
+
+use js_sandbox::{snapshot, JsError, Script};
+
+#[test]
+fn script_from_snapshot() {
+	let snapshot = snapshot::Builder::new()
+		.with_prelude("let calls = 0; function scale(x) { calls += 1; return x * 10; }")
+		.with_plugin("math", "function add(a, b) { return scale(a) + b; }")
+		.build()
+		.expect("snapshot can be created");
+	let snapshot: &'static [u8] = Box::leak(snapshot);
+
+	let mut script = Script::builder()
+		.with_snapshot(snapshot)
+		.build_from_string("function getCalls() { return calls; }")
+		.expect("Initialization succeeds");
+
+	let result: i32 = script.call("scale", (2,)).unwrap();
+	assert_eq!(result, 20);
+	let result: i32 = script.call_namespace("math", "add", (1, 2)).unwrap();
+	assert_eq!(result, 12);
+	let calls: u32 = script.call("getCalls", ()).unwrap();
+	assert_eq!(calls, 2);
+	assert_eq!(script.list_namespaces().collect::<Vec<_>>(), ["math"]);
+
+	// Every script starts from the state in the snapshot
+	let mut other = Script::builder()
+		.with_snapshot(snapshot)
+		.build_from_string("")
+		.expect("Initialization succeeds");
+	let result: i32 = other.eval_json("calls").unwrap().as_i64().unwrap() as i32;
+	assert_eq!(result, 0);
+}
+
+#[test]
+fn snapshot_errors() {
+	let result = snapshot::Builder::new()
+		.with_prelude("throw new Error('prelude failed');")
+		.build();
+	assert!(matches!(result, Err(JsError::Runtime(_))));
+
+	let result = snapshot::Builder::new()
+		.with_plugin("a", "function f() {}")
+		.with_plugin("a", "function g() {}")
+		.build();
+	assert!(matches!(result, Err(JsError::Runtime(_))));
+}
@@ -0,0 +1,64 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+#![cfg(feature = "sql")]
+
+use serde_json::json;
+
+use js_sandbox::{AnyError, JsValue, Script, SqlDatabase};
+
+/// Table of users, answering a fixed set of queries.
+struct Users;
+
+impl SqlDatabase for Users {
+	fn query(&mut self, sql: &str, params: &[JsValue]) -> Result<Vec<JsValue>, AnyError> {
+		match sql {
+			"SELECT name FROM users WHERE age >= ?" => {
+				let min_age = params[0].as_u64().unwrap_or_default();
+				let users = [("alice", 31), ("bob", 17), ("carol", 45)];
+
+				Ok(users
+					.iter()
+					.filter(|(_, age)| *age >= min_age)
+					.map(|(name, _)| json!({ "name": name }))
+					.collect())
+			}
+			"SELECT COUNT(*) AS count FROM users" => Ok(vec![json!({ "count": 3 })]),
+			_ => Err(AnyError::msg("query not allowed")),
+		}
+	}
+}
+
+#[test]
+fn query_database() {
+	let src = r#"
+	function adults() {
+		return host.db.query("SELECT name FROM users WHERE age >= ?", [18]).map(row => row.name);
+	}
+
+	function count() {
+		return host.db.query("SELECT COUNT(*) AS count FROM users")[0].count;
+	}
+
+	function dropTable() {
+		try {
+			host.db.query("DROP TABLE users");
+			return "dropped";
+		} catch (e) {
+			return e.message;
+		}
+	}"#;
+
+	let mut script = Script::builder()
+		.with_database(Users)
+		.build_from_string(src)
+		.expect("Initialization succeeds");
+
+	let names: Vec<String> = script.call("adults", ()).unwrap();
+	assert_eq!(names, ["alice", "carol"]);
+
+	let count: u32 = script.call("count", ()).unwrap();
+	assert_eq!(count, 3);
+
+	let message: String = script.call("dropTable", ()).unwrap();
+	assert_eq!(message, "query not allowed");
+}
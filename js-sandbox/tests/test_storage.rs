@@ -0,0 +1,113 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+#![cfg(feature = "v8")]
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use js_sandbox::{AnyError, JsError, JsValue, Script, ScriptStorage};
+
+/// Storage shared with the test, to inspect it and to reuse it across scripts.
+#[derive(Clone, Default)]
+struct SharedStorage(Rc<RefCell<HashMap<String, String>>>);
+
+impl ScriptStorage for SharedStorage {
+	fn get_item(&self, key: &str) -> Result<Option<String>, AnyError> {
+		Ok(self.0.borrow().get(key).cloned())
+	}
+
+	fn set_item(&mut self, key: &str, value: &str) -> Result<(), AnyError> {
+		self.0
+			.borrow_mut()
+			.insert(key.to_string(), value.to_string());
+		Ok(())
+	}
+
+	fn remove_item(&mut self, key: &str) -> Result<(), AnyError> {
+		self.0.borrow_mut().remove(key);
+		Ok(())
+	}
+
+	fn size(&self) -> usize {
+		self.0.borrow().iter().map(|(k, v)| k.len() + v.len()).sum()
+	}
+}
+
+const SRC: &str = r#"
+function increment() {
+	const count = Number(storage.getItem("count") ?? 0) + 1;
+	storage.setItem("count", count);
+	return count;
+}
+
+function store(key, value) {
+	try {
+		storage.setItem(key, value);
+		return true;
+	} catch (e) {
+		return false;
+	}
+}
+
+function remove(key) { storage.removeItem(key); }
+"#;
+
+#[test]
+fn storage_persists_across_scripts() {
+	let backend = SharedStorage::default();
+
+	for expected in 1..=3 {
+		let mut script = Script::builder()
+			.with_storage(backend.clone(), 100)
+			.build_from_string(SRC)
+			.expect("Initialization succeeds");
+
+		let count: u32 = script.call("increment", ()).unwrap();
+		assert_eq!(count, expected);
+	}
+
+	assert_eq!(backend.0.borrow()["count"], "3");
+}
+
+#[test]
+fn storage_quota() {
+	let backend = SharedStorage::default();
+	let mut script = Script::builder()
+		.with_storage(backend.clone(), 10)
+		.build_from_string(SRC)
+		.expect("Initialization succeeds");
+
+	assert!(script.call::<_, bool>("store", ("a", "12345678")).unwrap());
+	assert!(!script.call::<_, bool>("store", ("b", "12")).unwrap());
+
+	// Replacing a value only counts the difference
+	assert!(script.call::<_, bool>("store", ("a", "123456789")).unwrap());
+	assert!(!script
+		.call::<_, bool>("store", ("a", "1234567890"))
+		.unwrap());
+
+	script.call::<_, ()>("remove", ("a",)).unwrap();
+	assert!(script.call::<_, bool>("store", ("b", "12")).unwrap());
+
+	let stored = backend.0.borrow().clone();
+	assert_eq!(stored, HashMap::from([("b".to_string(), "12".to_string())]));
+}
+
+#[test]
+fn storage_ops_without_storage() {
+	let src = r#"
+	function get() { return Deno.core.ops.op_storage_get("count"); }
+	function set() { Deno.core.ops.op_storage_set("count", "1"); }
+	function remove() { Deno.core.ops.op_storage_remove("count"); }"#;
+	let mut script = Script::from_string(src).expect("Initialization succeeds");
+
+	for fn_name in ["get", "set", "remove"] {
+		let err = script.call::<_, JsValue>(fn_name, ()).unwrap_err();
+		assert!(matches!(err, JsError::Runtime(_)), "{fn_name}: {err:?}");
+		assert!(err.to_string().contains("no storage"), "{fn_name}: {err}");
+	}
+
+	// Not a host panic, so the script stays healthy
+	assert!(script.is_healthy());
+}
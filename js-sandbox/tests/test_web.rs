@@ -0,0 +1,46 @@
+// Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
+
+#![cfg(feature = "web")]
+
+use js_sandbox::Script;
+
+fn web_script(src: &str) -> Script {
+	Script::builder()
+		.with_web_apis()
+		.build_from_string(src)
+		.expect("Initialization succeeds")
+}
+
+#[test]
+fn call_with_timer() {
+	let src = r#"
+	async function delayed(value) {
+		return new Promise(resolve => setTimeout(() => resolve(value * 2), 10));
+	}"#;
+
+	let mut script = web_script(src);
+	let result: i32 = script.call("delayed", (21,)).unwrap();
+
+	assert_eq!(result, 42);
+}
+
+#[test]
+fn call_with_url_and_encoding() {
+	let src = r#"
+	function host(href) { return new URL(href).host; }
+	function byteLength(text) { return new TextEncoder().encode(text).length; }
+	function roundtrip(text) { console.log(text); return atob(btoa(text)); }"#;
+
+	let mut script = web_script(src);
+
+	let result: String = script
+		.call("host", ("https://example.com:8080/path",))
+		.unwrap();
+	assert_eq!(result, "example.com:8080");
+
+	let result: usize = script.call("byteLength", ("äb",)).unwrap();
+	assert_eq!(result, 3);
+
+	let result: String = script.call("roundtrip", ("sandbox",)).unwrap();
+	assert_eq!(result, "sandbox");
+}
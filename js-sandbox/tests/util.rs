@@ -1,5 +1,7 @@
 // Copyright (c) 2020-2023 js-sandbox contributors. Zlib license.
 
+#![cfg(feature = "v8")]
+
 use js_sandbox::JsError;
 
 pub fn expect_error<T>(result: Result<T, JsError>, error_type: &str) {